@@ -3,9 +3,9 @@ extern crate liboxyjs;
 
 use liboxyjs::parser::ast::AstNode;
 use liboxyjs::parser::ast_builder::AstBuilder;
-use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::parser::input_stream::ReadInputStream;
+use liboxyjs::vm::interpreter::{Interpreter, RunError};
 use std::io;
-use std::io::Read;
 use std::fmt::Debug;
 use std::fs::File;
 use std::path::Path;
@@ -13,14 +13,28 @@ use std::path::Path;
 fn main() {
     liboxyjs::initialize_library();
 
-    let mut stdin = io::stdin();
-    let mut buf: Vec<u8> = Vec::new();
-    stdin.read_to_end(&mut buf).unwrap();
-
-    // Create an AstBuilder
-    let stream = VecInputStream::new(buf);
+    // Create an AstBuilder reading directly from stdin.
+    let stream = ReadInputStream::new(io::stdin());
     let mut builder = AstBuilder::new(stream);
+    builder.set_log_debug(true);
 
     let program = builder.parse_program().unwrap();
-    println!("Parsed program: {}", program.tree_string())
+    println!("Parsed program: {}", program.tree_string());
+
+    let mut interp = Interpreter::new();
+    match interp.run_program(&program) {
+        Ok(value) => println!("Result: {}", interp.display_string(value)),
+        Err(RunError::Throw(exception)) => {
+            // Errors are shown by their toString, which may itself throw.
+            let text = match interp.string_value(exception.value()) {
+                Ok(text) => interp.display_string(text),
+                Err(_) => interp.display_string(exception.value())
+            };
+            println!("Uncaught exception: {}", text);
+            for frame in exception.stack_trace() {
+                println!("    {}", frame);
+            }
+        }
+        Err(RunError::Compile(error)) => panic!("Compile error: {:?}", error)
+    }
 }
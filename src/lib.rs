@@ -1,11 +1,11 @@
 
 pub mod parser;
+pub mod runtime;
 pub mod vm;
 
 /**
- * Initializer for library that must be called exactly once at the
- * beginning of program execution.
+ * Does nothing; kept for compatibility.  Parsing needs no initialization, so
+ * this may be called any number of times, from any thread, or not at all.
  */
 pub fn initialize_library() {
-    parser::initialize_module();
 }
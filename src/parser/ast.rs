@@ -2,9 +2,9 @@
 use std::fmt;
 
 use parser::ast_builder::FullToken;
-use parser::tokenizer::Token;
+use parser::tokenizer::{Token, TokenLocation};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AstKind {
     Program,
     BlockStmt,
@@ -12,6 +12,18 @@ pub enum AstKind {
     EmptyStmt,
     IfStmt,
     ExprStmt,
+    WithStmt,
+    ReturnStmt,
+    ThrowStmt,
+    TryStmt,
+    WhileStmt,
+    DoWhileStmt,
+    ForStmt,
+    ForInStmt,
+    BreakStmt,
+    ContinueStmt,
+    LabelledStmt,
+    FunctionDecl,
 
     CallExpr,
     ElementExpr,
@@ -23,6 +35,9 @@ pub enum AstKind {
     CondExpr,
     AssignExpr,
     CommaExpr,
+    FunctionExpr,
+    ObjectLiteralExpr,
+    ArrayLiteralExpr,
     AtomicExpr
 }
 impl AstKind {
@@ -37,6 +52,31 @@ pub trait AstNode where Self: fmt::Debug {
     fn is_expression(&self) -> bool;
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error>;
 
+    /**
+     * Whether this node may appear as the target of an assignment, compound
+     * assignment, or prefix/postfix increment/decrement.  Only identifier
+     * references, property accesses and element accesses qualify.
+     */
+    fn is_assignment_target(&self) -> bool {
+        false
+    }
+
+    /** The identifier token, if this node is a bare identifier reference. */
+    fn identifier(&self) -> Option<&FullToken> {
+        None
+    }
+
+    /**
+     * Comments attached to this node, when the AstBuilder collects them.
+     * Only statements and the program carry comments.
+     */
+    fn comments(&self) -> Option<&NodeComments> {
+        None
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        None
+    }
+
     fn tree_string(&self) -> String {
         let mut str = String::new();
         self.write_tree(&mut str).unwrap();
@@ -44,16 +84,157 @@ pub trait AstNode where Self: fmt::Debug {
     }
 }
 
+/** Node types of a single kind, which node references can be downcast to. */
+pub trait KindedAstNode: AstNode {
+    const KIND: AstKind;
+}
+impl<'a> dyn AstNode + 'a {
+    pub fn downcast_ref<T: KindedAstNode>(&self) -> Option<&T> {
+        if self.kind() == T::KIND {
+            Some(unsafe { &*(self as *const dyn AstNode as *const T) })
+        } else {
+            None
+        }
+    }
+}
+impl KindedAstNode for ProgramNode { const KIND: AstKind = AstKind::Program; }
+impl KindedAstNode for BlockStmtNode { const KIND: AstKind = AstKind::BlockStmt; }
+impl KindedAstNode for VarStmtNode { const KIND: AstKind = AstKind::VarStmt; }
+impl KindedAstNode for EmptyStmtNode { const KIND: AstKind = AstKind::EmptyStmt; }
+impl KindedAstNode for IfStmtNode { const KIND: AstKind = AstKind::IfStmt; }
+impl KindedAstNode for ExprStmtNode { const KIND: AstKind = AstKind::ExprStmt; }
+impl KindedAstNode for WithStmtNode { const KIND: AstKind = AstKind::WithStmt; }
+impl KindedAstNode for ReturnStmtNode { const KIND: AstKind = AstKind::ReturnStmt; }
+impl KindedAstNode for ThrowStmtNode { const KIND: AstKind = AstKind::ThrowStmt; }
+impl KindedAstNode for TryStmtNode { const KIND: AstKind = AstKind::TryStmt; }
+impl KindedAstNode for WhileStmtNode { const KIND: AstKind = AstKind::WhileStmt; }
+impl KindedAstNode for DoWhileStmtNode { const KIND: AstKind = AstKind::DoWhileStmt; }
+impl KindedAstNode for ForStmtNode { const KIND: AstKind = AstKind::ForStmt; }
+impl KindedAstNode for ForInStmtNode { const KIND: AstKind = AstKind::ForInStmt; }
+impl KindedAstNode for BreakStmtNode { const KIND: AstKind = AstKind::BreakStmt; }
+impl KindedAstNode for ContinueStmtNode { const KIND: AstKind = AstKind::ContinueStmt; }
+impl KindedAstNode for LabelledStmtNode { const KIND: AstKind = AstKind::LabelledStmt; }
+impl KindedAstNode for FunctionDeclNode { const KIND: AstKind = AstKind::FunctionDecl; }
+impl KindedAstNode for CallExprNode { const KIND: AstKind = AstKind::CallExpr; }
+impl KindedAstNode for ElementExprNode { const KIND: AstKind = AstKind::ElementExpr; }
+impl KindedAstNode for PropertyExprNode { const KIND: AstKind = AstKind::PropertyExpr; }
+impl KindedAstNode for ConstructExprNode { const KIND: AstKind = AstKind::ConstructExpr; }
+impl KindedAstNode for PostfixOpExprNode { const KIND: AstKind = AstKind::PostfixOpExpr; }
+impl KindedAstNode for UnaryOpExprNode { const KIND: AstKind = AstKind::UnaryOpExpr; }
+impl KindedAstNode for BinaryOpExprNode { const KIND: AstKind = AstKind::BinaryOpExpr; }
+impl KindedAstNode for CondExprNode { const KIND: AstKind = AstKind::CondExpr; }
+impl KindedAstNode for AssignExprNode { const KIND: AstKind = AstKind::AssignExpr; }
+impl KindedAstNode for CommaExprNode { const KIND: AstKind = AstKind::CommaExpr; }
+impl KindedAstNode for FunctionExprNode { const KIND: AstKind = AstKind::FunctionExpr; }
+impl KindedAstNode for ObjectLiteralExprNode { const KIND: AstKind = AstKind::ObjectLiteralExpr; }
+impl KindedAstNode for ArrayLiteralExprNode { const KIND: AstKind = AstKind::ArrayLiteralExpr; }
+impl KindedAstNode for AtomicExprNode { const KIND: AstKind = AstKind::AtomicExpr; }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind {
+    Line,
+    Block
+}
+
+/** A comment from the source text, with its delimiters. */
+#[derive(Debug, Clone)]
+pub struct Comment {
+    kind: CommentKind,
+    location: TokenLocation,
+    text: String,
+    follows_newline: bool
+}
+impl Comment {
+    pub fn new(kind: CommentKind, location: TokenLocation, text: String, follows_newline: bool)
+        -> Comment
+    {
+        Comment { kind, location, text, follows_newline }
+    }
+
+    pub fn kind(&self) -> CommentKind {
+        self.kind
+    }
+    pub fn location(&self) -> &TokenLocation {
+        &self.location
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /**
+     * Whether a line terminator separates this comment from the preceding token.
+     * Comments which don't start a line trail the code before them.
+     */
+    pub fn follows_newline(&self) -> bool {
+        self.follows_newline
+    }
+}
+
+/**
+ * Comments attached to a node.  Leading comments precede the node, trailing
+ * comments follow it on the same line (or end the enclosing block), and inner
+ * comments fall within the node's text without belonging to any nested statement.
+ */
+#[derive(Debug, Clone)]
+pub struct NodeComments {
+    leading: Vec<Comment>,
+    trailing: Vec<Comment>,
+    inner: Vec<Comment>
+}
+impl NodeComments {
+    pub fn new() -> NodeComments {
+        NodeComments { leading: Vec::new(), trailing: Vec::new(), inner: Vec::new() }
+    }
+
+    pub fn leading(&self) -> &Vec<Comment> {
+        &self.leading
+    }
+    pub fn trailing(&self) -> &Vec<Comment> {
+        &self.trailing
+    }
+    pub fn inner(&self) -> &Vec<Comment> {
+        &self.inner
+    }
+
+    pub fn add_leading(&mut self, comment: Comment) {
+        self.leading.push(comment);
+    }
+    pub fn add_trailing(&mut self, comment: Comment) {
+        self.trailing.push(comment);
+    }
+    pub fn add_inner(&mut self, comment: Comment) {
+        self.inner.push(comment);
+    }
+}
+impl Default for NodeComments {
+    fn default() -> NodeComments {
+        NodeComments::new()
+    }
+}
+
 /*****************************************************************************
  **** ProgramNode ************************************************************
  *****************************************************************************/
 #[derive(Debug)]
 pub struct ProgramNode {
-    source_elements: Vec<Box<AstNode>>
+    source_elements: Vec<Box<dyn AstNode>>,
+    strict: bool,
+    comments: NodeComments
 }
 impl ProgramNode {
     pub fn new() -> ProgramNode {
-        ProgramNode { source_elements: Vec::with_capacity(3) }
+        ProgramNode {
+            source_elements: Vec::with_capacity(3),
+            strict: false,
+            comments: NodeComments::new()
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
     }
 
     pub fn source_elements(&self) -> &Vec<Box<AstNode>> {
@@ -62,6 +243,9 @@ impl ProgramNode {
     pub fn add_source_element(&mut self, source_element: Box<AstNode>) {
         self.source_elements.push(source_element);
     }
+    pub fn last_source_element_mut(&mut self) -> Option<&mut Box<dyn AstNode>> {
+        self.source_elements.last_mut()
+    }
 }
 impl AstNode for ProgramNode {
     fn kind(&self) -> AstKind {
@@ -73,9 +257,15 @@ impl AstNode for ProgramNode {
     fn is_expression(&self) -> bool {
         false
     }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
 
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
-        w.write_str("ProgramNode{")?;
+        w.write_str(if self.strict { "ProgramNode(strict){" } else { "ProgramNode{" })?;
         let mut first = true;
         for source_element in &self.source_elements {
             if ! first {
@@ -94,11 +284,16 @@ impl AstNode for ProgramNode {
  *****************************************************************************/
 #[derive(Debug)]
 pub struct BlockStmtNode {
-    statements: Vec<Box<AstNode>>
+    statements: Vec<Box<dyn AstNode>>,
+    comments: NodeComments
 }
 impl BlockStmtNode {
     pub fn new(statements: Vec<Box<AstNode>>) -> BlockStmtNode {
-        BlockStmtNode { statements }
+        BlockStmtNode { statements, comments: NodeComments::new() }
+    }
+
+    pub fn statements(&self) -> &[Box<dyn AstNode>] {
+        &self.statements
     }
 }
 impl AstNode for BlockStmtNode {
@@ -111,6 +306,12 @@ impl AstNode for BlockStmtNode {
     fn is_expression(&self) -> bool {
         false
     }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("Block{")?;
         for stmt in &self.statements {
@@ -127,11 +328,12 @@ impl AstNode for BlockStmtNode {
  *****************************************************************************/
 #[derive(Debug)]
 pub struct VarStmtNode {
-    var_decls: Vec<VarDecl>
+    var_decls: Vec<VarDecl>,
+    comments: NodeComments
 }
 impl VarStmtNode {
     pub fn new() -> VarStmtNode {
-        VarStmtNode { var_decls: Vec::with_capacity(1) }
+        VarStmtNode { var_decls: Vec::with_capacity(1), comments: NodeComments::new() }
     }
 
     pub fn var_decls(&self) -> &Vec<VarDecl> {
@@ -155,6 +357,12 @@ impl AstNode for VarStmtNode {
     fn is_expression(&self) -> bool {
         false
     }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
 
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("Var{")?;
@@ -213,10 +421,11 @@ impl VarDecl {
  *****************************************************************************/
 #[derive(Debug)]
 pub struct EmptyStmtNode {
+    comments: NodeComments
 }
 impl EmptyStmtNode {
     pub fn new() -> EmptyStmtNode {
-        EmptyStmtNode {}
+        EmptyStmtNode { comments: NodeComments::new() }
     }
 }
 impl AstNode for EmptyStmtNode {
@@ -229,6 +438,12 @@ impl AstNode for EmptyStmtNode {
     fn is_expression(&self) -> bool {
         false
     }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
 
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("Empty{}")
@@ -242,7 +457,8 @@ impl AstNode for EmptyStmtNode {
 pub struct IfStmtNode {
     cond_expr: Box<AstNode>,
     if_true_stmt: Box<AstNode>,
-    if_false_stmt: Option<Box<AstNode>>
+    if_false_stmt: Option<Box<dyn AstNode>>,
+    comments: NodeComments
 }
 impl IfStmtNode {
     pub fn new_if(cond_expr: Box<AstNode>, if_true_stmt: Box<AstNode>)
@@ -250,7 +466,7 @@ impl IfStmtNode {
     {
         assert!(cond_expr.is_expression());
         assert!(if_true_stmt.is_statement());
-        IfStmtNode { cond_expr, if_true_stmt, if_false_stmt: None }
+        IfStmtNode { cond_expr, if_true_stmt, if_false_stmt: None, comments: NodeComments::new() }
     }
     pub fn new_if_else(cond_expr: Box<AstNode>,
                        if_true_stmt: Box<AstNode>,
@@ -260,7 +476,12 @@ impl IfStmtNode {
         assert!(cond_expr.is_expression());
         assert!(if_true_stmt.is_statement());
         assert!(if_false_stmt.is_statement());
-        IfStmtNode { cond_expr, if_true_stmt, if_false_stmt: Some(if_false_stmt) }
+        IfStmtNode {
+            cond_expr,
+            if_true_stmt,
+            if_false_stmt: Some(if_false_stmt),
+            comments: NodeComments::new()
+        }
     }
 
     pub fn cond_expr(&self) -> &AstNode {
@@ -290,6 +511,12 @@ impl AstNode for IfStmtNode {
     fn is_expression(&self) -> bool {
         false
     }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
 
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("If(")?;
@@ -311,12 +538,13 @@ impl AstNode for IfStmtNode {
  *****************************************************************************/
 #[derive(Debug)]
 pub struct ExprStmtNode {
-    expr: Box<AstNode>
+    expr: Box<dyn AstNode>,
+    comments: NodeComments
 }
 impl ExprStmtNode {
     pub fn new(expr: Box<AstNode>) -> ExprStmtNode {
         assert!(expr.is_expression());
-        ExprStmtNode { expr }
+        ExprStmtNode { expr, comments: NodeComments::new() }
     }
 
     pub fn expression(&self) -> &AstNode {
@@ -333,6 +561,12 @@ impl AstNode for ExprStmtNode {
     fn is_expression(&self) -> bool {
         false
     }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("ExprStmt{")?;
         self.expr.write_tree(w)?;
@@ -341,6 +575,695 @@ impl AstNode for ExprStmtNode {
     }
 }
 
+/*****************************************************************************
+ **** WithStmtNode ***********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct WithStmtNode {
+    object_expr: Box<dyn AstNode>,
+    body_stmt: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl WithStmtNode {
+    pub fn new(object_expr: Box<dyn AstNode>, body_stmt: Box<dyn AstNode>) -> WithStmtNode {
+        assert!(object_expr.is_expression());
+        assert!(body_stmt.is_statement());
+        WithStmtNode { object_expr, body_stmt, comments: NodeComments::new() }
+    }
+
+    pub fn object_expr(&self) -> &dyn AstNode {
+        self.object_expr.as_ref()
+    }
+    pub fn body_stmt(&self) -> &dyn AstNode {
+        self.body_stmt.as_ref()
+    }
+}
+impl AstNode for WithStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::WithStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("With(")?;
+        self.object_expr.write_tree(w)?;
+        w.write_str("){")?;
+        self.body_stmt.write_tree(w)?;
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** ReturnStmtNode *********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct ReturnStmtNode {
+    expr: Option<Box<dyn AstNode>>,
+    comments: NodeComments
+}
+impl ReturnStmtNode {
+    pub fn new(expr: Option<Box<dyn AstNode>>) -> ReturnStmtNode {
+        assert!(expr.as_ref().is_none_or(|expr| expr.is_expression()));
+        ReturnStmtNode { expr, comments: NodeComments::new() }
+    }
+
+    pub fn expression(&self) -> Option<&dyn AstNode> {
+        self.expr.as_ref().map(|expr| expr.as_ref())
+    }
+}
+impl AstNode for ReturnStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ReturnStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Return")?;
+        if let Some(ref expr) = self.expr {
+            w.write_str("{")?;
+            expr.write_tree(w)?;
+            w.write_str("}")?;
+        }
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** ThrowStmtNode **********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct ThrowStmtNode {
+    expr: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl ThrowStmtNode {
+    pub fn new(expr: Box<dyn AstNode>) -> ThrowStmtNode {
+        assert!(expr.is_expression());
+        ThrowStmtNode { expr, comments: NodeComments::new() }
+    }
+
+    pub fn expression(&self) -> &dyn AstNode {
+        self.expr.as_ref()
+    }
+}
+impl AstNode for ThrowStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ThrowStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Throw{")?;
+        self.expr.write_tree(w)?;
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** TryStmtNode ************************************************************
+ *****************************************************************************/
+/** A try statement, which has a catch block, a finally block, or both. */
+#[derive(Debug)]
+pub struct TryStmtNode {
+    try_block: Box<dyn AstNode>,
+    catch_param: Option<FullToken>,
+    catch_block: Option<Box<dyn AstNode>>,
+    finally_block: Option<Box<dyn AstNode>>,
+    comments: NodeComments
+}
+impl TryStmtNode {
+    pub fn new(try_block: Box<dyn AstNode>, catch: Option<(FullToken, Box<dyn AstNode>)>,
+               finally_block: Option<Box<dyn AstNode>>)
+        -> TryStmtNode
+    {
+        assert!(try_block.kind() == AstKind::BlockStmt);
+        assert!(catch.is_some() || finally_block.is_some());
+        let (catch_param, catch_block) = match catch {
+            Some((param, block)) => {
+                assert!(param.kind().is_identifier());
+                assert!(block.kind() == AstKind::BlockStmt);
+                (Some(param), Some(block))
+            }
+            None => (None, None)
+        };
+        assert!(finally_block.as_ref().is_none_or(|block| block.kind() == AstKind::BlockStmt));
+        TryStmtNode { try_block, catch_param, catch_block, finally_block, comments: NodeComments::new() }
+    }
+
+    pub fn try_block(&self) -> &dyn AstNode {
+        self.try_block.as_ref()
+    }
+    pub fn catch_param(&self) -> Option<&FullToken> {
+        self.catch_param.as_ref()
+    }
+    pub fn catch_block(&self) -> Option<&dyn AstNode> {
+        self.catch_block.as_ref().map(|block| block.as_ref())
+    }
+    pub fn finally_block(&self) -> Option<&dyn AstNode> {
+        self.finally_block.as_ref().map(|block| block.as_ref())
+    }
+}
+impl AstNode for TryStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::TryStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Try")?;
+        self.try_block.write_tree(w)?;
+        if let (Some(ref param), Some(ref block)) = (&self.catch_param, &self.catch_block) {
+            w.write_str("Catch(")?;
+            param.write_token(w)?;
+            w.write_str(")")?;
+            block.write_tree(w)?;
+        }
+        if let Some(ref block) = self.finally_block {
+            w.write_str("Finally")?;
+            block.write_tree(w)?;
+        }
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** WhileStmtNode **********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct WhileStmtNode {
+    cond_expr: Box<dyn AstNode>,
+    body_stmt: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl WhileStmtNode {
+    pub fn new(cond_expr: Box<dyn AstNode>, body_stmt: Box<dyn AstNode>) -> WhileStmtNode {
+        assert!(cond_expr.is_expression());
+        assert!(body_stmt.is_statement());
+        WhileStmtNode { cond_expr, body_stmt, comments: NodeComments::new() }
+    }
+
+    pub fn cond_expr(&self) -> &dyn AstNode {
+        self.cond_expr.as_ref()
+    }
+    pub fn body_stmt(&self) -> &dyn AstNode {
+        self.body_stmt.as_ref()
+    }
+}
+impl AstNode for WhileStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::WhileStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("While(")?;
+        self.cond_expr.write_tree(w)?;
+        w.write_str("){")?;
+        self.body_stmt.write_tree(w)?;
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** DoWhileStmtNode ********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct DoWhileStmtNode {
+    body_stmt: Box<dyn AstNode>,
+    cond_expr: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl DoWhileStmtNode {
+    pub fn new(body_stmt: Box<dyn AstNode>, cond_expr: Box<dyn AstNode>) -> DoWhileStmtNode {
+        assert!(body_stmt.is_statement());
+        assert!(cond_expr.is_expression());
+        DoWhileStmtNode { body_stmt, cond_expr, comments: NodeComments::new() }
+    }
+
+    pub fn body_stmt(&self) -> &dyn AstNode {
+        self.body_stmt.as_ref()
+    }
+    pub fn cond_expr(&self) -> &dyn AstNode {
+        self.cond_expr.as_ref()
+    }
+}
+impl AstNode for DoWhileStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::DoWhileStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Do{")?;
+        self.body_stmt.write_tree(w)?;
+        w.write_str("}While(")?;
+        self.cond_expr.write_tree(w)?;
+        w.write_str(")")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** ForStmtNode ************************************************************
+ *****************************************************************************/
+/**
+ * A `for (init; cond; update)` loop.  The initializer is a var statement or an
+ * expression, and each of the three parts may be missing.
+ */
+#[derive(Debug)]
+pub struct ForStmtNode {
+    init: Option<Box<dyn AstNode>>,
+    cond_expr: Option<Box<dyn AstNode>>,
+    update_expr: Option<Box<dyn AstNode>>,
+    body_stmt: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl ForStmtNode {
+    pub fn new(init: Option<Box<dyn AstNode>>, cond_expr: Option<Box<dyn AstNode>>,
+               update_expr: Option<Box<dyn AstNode>>, body_stmt: Box<dyn AstNode>)
+        -> ForStmtNode
+    {
+        assert!(init.as_ref().is_none_or(|init| init.is_expression() || init.kind() == AstKind::VarStmt));
+        assert!(cond_expr.as_ref().is_none_or(|expr| expr.is_expression()));
+        assert!(update_expr.as_ref().is_none_or(|expr| expr.is_expression()));
+        assert!(body_stmt.is_statement());
+        ForStmtNode { init, cond_expr, update_expr, body_stmt, comments: NodeComments::new() }
+    }
+
+    pub fn init(&self) -> Option<&dyn AstNode> {
+        self.init.as_ref().map(|init| init.as_ref())
+    }
+    pub fn cond_expr(&self) -> Option<&dyn AstNode> {
+        self.cond_expr.as_ref().map(|expr| expr.as_ref())
+    }
+    pub fn update_expr(&self) -> Option<&dyn AstNode> {
+        self.update_expr.as_ref().map(|expr| expr.as_ref())
+    }
+    pub fn body_stmt(&self) -> &dyn AstNode {
+        self.body_stmt.as_ref()
+    }
+}
+impl AstNode for ForStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ForStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("For(")?;
+        for (idx, part) in [&self.init, &self.cond_expr, &self.update_expr].iter().enumerate() {
+            if idx > 0 {
+                w.write_str(";")?;
+            }
+            if let Some(ref part) = **part {
+                part.write_tree(w)?;
+            }
+        }
+        w.write_str("){")?;
+        self.body_stmt.write_tree(w)?;
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** ForInStmtNode **********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct ForInStmtNode {
+    target: Box<dyn AstNode>,
+    object_expr: Box<dyn AstNode>,
+    body_stmt: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl ForInStmtNode {
+    /**
+     * The target is either a var statement declaring a single variable, or an
+     * expression which may be assigned to.
+     */
+    pub fn new(target: Box<dyn AstNode>, object_expr: Box<dyn AstNode>, body_stmt: Box<dyn AstNode>)
+        -> ForInStmtNode
+    {
+        assert!(target.is_assignment_target() ||
+                target.downcast_ref::<VarStmtNode>().is_some_and(|var_stmt| var_stmt.var_decls().len() == 1));
+        assert!(object_expr.is_expression());
+        assert!(body_stmt.is_statement());
+        ForInStmtNode { target, object_expr, body_stmt, comments: NodeComments::new() }
+    }
+
+    pub fn target(&self) -> &dyn AstNode {
+        self.target.as_ref()
+    }
+    pub fn object_expr(&self) -> &dyn AstNode {
+        self.object_expr.as_ref()
+    }
+    pub fn body_stmt(&self) -> &dyn AstNode {
+        self.body_stmt.as_ref()
+    }
+}
+impl AstNode for ForInStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ForInStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("ForIn(")?;
+        self.target.write_tree(w)?;
+        w.write_str(" in ")?;
+        self.object_expr.write_tree(w)?;
+        w.write_str("){")?;
+        self.body_stmt.write_tree(w)?;
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** BreakStmtNode **********************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct BreakStmtNode {
+    label: Option<FullToken>,
+    comments: NodeComments
+}
+impl BreakStmtNode {
+    pub fn new(label: Option<FullToken>) -> BreakStmtNode {
+        assert!(label.as_ref().is_none_or(|label| label.kind().is_identifier()));
+        BreakStmtNode { label, comments: NodeComments::new() }
+    }
+
+    pub fn label(&self) -> Option<&FullToken> {
+        self.label.as_ref()
+    }
+}
+impl AstNode for BreakStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::BreakStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Break")?;
+        if let Some(ref label) = self.label {
+            w.write_str("(")?;
+            label.write_token(w)?;
+            w.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** ContinueStmtNode *******************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct ContinueStmtNode {
+    label: Option<FullToken>,
+    comments: NodeComments
+}
+impl ContinueStmtNode {
+    pub fn new(label: Option<FullToken>) -> ContinueStmtNode {
+        assert!(label.as_ref().is_none_or(|label| label.kind().is_identifier()));
+        ContinueStmtNode { label, comments: NodeComments::new() }
+    }
+
+    pub fn label(&self) -> Option<&FullToken> {
+        self.label.as_ref()
+    }
+}
+impl AstNode for ContinueStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ContinueStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Continue")?;
+        if let Some(ref label) = self.label {
+            w.write_str("(")?;
+            label.write_token(w)?;
+            w.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** LabelledStmtNode *******************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct LabelledStmtNode {
+    label: FullToken,
+    body_stmt: Box<dyn AstNode>,
+    comments: NodeComments
+}
+impl LabelledStmtNode {
+    pub fn new(label: FullToken, body_stmt: Box<dyn AstNode>) -> LabelledStmtNode {
+        assert!(label.kind().is_identifier());
+        assert!(body_stmt.is_statement());
+        LabelledStmtNode { label, body_stmt, comments: NodeComments::new() }
+    }
+
+    pub fn label(&self) -> &FullToken {
+        &self.label
+    }
+    pub fn body_stmt(&self) -> &dyn AstNode {
+        self.body_stmt.as_ref()
+    }
+}
+impl AstNode for LabelledStmtNode {
+    fn kind(&self) -> AstKind {
+        AstKind::LabelledStmt
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("Labelled(")?;
+        self.label.write_token(w)?;
+        w.write_str("){")?;
+        self.body_stmt.write_tree(w)?;
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
+/*****************************************************************************
+ **** FunctionDeclNode *******************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct FunctionDeclNode {
+    func: FunctionDef,
+    comments: NodeComments
+}
+impl FunctionDeclNode {
+    pub fn new(func: FunctionDef) -> FunctionDeclNode {
+        assert!(func.name().is_some());
+        FunctionDeclNode { func, comments: NodeComments::new() }
+    }
+
+    pub fn func(&self) -> &FunctionDef {
+        &self.func
+    }
+}
+impl AstNode for FunctionDeclNode {
+    fn kind(&self) -> AstKind {
+        AstKind::FunctionDecl
+    }
+    fn is_statement(&self) -> bool {
+        true
+    }
+    fn is_expression(&self) -> bool {
+        false
+    }
+    fn comments(&self) -> Option<&NodeComments> {
+        Some(&self.comments)
+    }
+    fn comments_mut(&mut self) -> Option<&mut NodeComments> {
+        Some(&mut self.comments)
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("FunctionDecl")?;
+        self.func.write_tree(w)
+    }
+}
+
+/**
+ * The parts shared by function declarations and function expressions.
+ * Strictness is that of the function's own code: inherited from the
+ * enclosing code, or established by the function's directive prologue.
+ */
+#[derive(Debug)]
+pub struct FunctionDef {
+    name: Option<FullToken>,
+    params: Vec<FullToken>,
+    body: Vec<Box<dyn AstNode>>,
+    strict: bool
+}
+impl FunctionDef {
+    pub fn new(name: Option<FullToken>, params: Vec<FullToken>, body: Vec<Box<dyn AstNode>>,
+               strict: bool)
+        -> FunctionDef
+    {
+        assert!(name.as_ref().is_none_or(|tok| tok.kind().is_identifier()));
+        assert!(params.iter().all(|tok| tok.kind().is_identifier()));
+        assert!(body.iter().all(|stmt| stmt.is_statement()));
+        FunctionDef { name, params, body, strict }
+    }
+
+    pub fn name(&self) -> Option<&FullToken> {
+        self.name.as_ref()
+    }
+    pub fn params(&self) -> &Vec<FullToken> {
+        &self.params
+    }
+    pub fn body(&self) -> &Vec<Box<dyn AstNode>> {
+        &self.body
+    }
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        if self.strict {
+            w.write_str("(strict)")?;
+        }
+        w.write_str("(")?;
+        if let Some(ref name) = self.name {
+            name.write_token(w)?;
+        }
+        w.write_str(")(")?;
+        let mut first = true;
+        for param in &self.params {
+            if ! first {
+                w.write_str(", ")?;
+            }
+            first = false;
+            param.write_token(w)?;
+        }
+        w.write_str("){")?;
+        for stmt in &self.body {
+            stmt.write_tree(w)?;
+            w.write_str(";")?;
+        }
+        w.write_str("}")?;
+        Ok(())
+    }
+}
+
 /*****************************************************************************
  **** BinaryExprNode *********************************************************
  *****************************************************************************/
@@ -455,8 +1378,7 @@ impl AssignExprNode {
     pub fn new(assign_op: FullToken, left_expr: Box<AstNode>, right_expr: Box<AstNode>)
         -> AssignExprNode
     {
-        // FIXME: assert that left_expr is a valid lvalue expression.
-        assert!(left_expr.is_expression());
+        assert!(left_expr.is_assignment_target());
         assert!(right_expr.is_expression());
         assert!(assign_op.kind().is_assignment_op());
         AssignExprNode { assign_op, left_expr, right_expr }
@@ -547,8 +1469,7 @@ pub struct PostfixOpExprNode {
 impl PostfixOpExprNode {
     pub fn new(postfix_op: FullToken, sub_expr: Box<AstNode>) -> PostfixOpExprNode {
         assert!(postfix_op.kind().is_plus_plus() || postfix_op.kind().is_minus_minus());
-        assert!(sub_expr.is_expression());
-        // FIXME: assert that sub_expr is a valid LVALUE expr.
+        assert!(sub_expr.is_assignment_target());
         PostfixOpExprNode { postfix_op, sub_expr }
     }
 
@@ -591,6 +1512,8 @@ impl UnaryOpExprNode {
     pub fn new(unary_op: FullToken, sub_expr: Box<AstNode>) -> UnaryOpExprNode {
         assert!(unary_op.kind().is_unary_op());
         assert!(sub_expr.is_expression());
+        assert!(!(unary_op.kind().is_plus_plus() || unary_op.kind().is_minus_minus()) ||
+                sub_expr.is_assignment_target());
         UnaryOpExprNode { unary_op, sub_expr }
     }
 
@@ -766,6 +1689,9 @@ impl AstNode for ElementExprNode {
     fn is_expression(&self) -> bool {
         true
     }
+    fn is_assignment_target(&self) -> bool {
+        true
+    }
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("ElementExpr{")?;
         self.target_expr.write_tree(w)?;
@@ -787,7 +1713,7 @@ pub struct PropertyExprNode {
 impl PropertyExprNode {
     pub fn new(target_expr: Box<AstNode>, property_name: FullToken) -> PropertyExprNode {
         assert!(target_expr.is_expression());
-        assert!(property_name.kind().is_identifier());
+        assert!(property_name.kind().is_identifier_name());
         PropertyExprNode { target_expr, property_name }
     }
 
@@ -808,6 +1734,9 @@ impl AstNode for PropertyExprNode {
     fn is_expression(&self) -> bool {
         true
     }
+    fn is_assignment_target(&self) -> bool {
+        true
+    }
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("PropertyExpr{")?;
         self.target_expr.write_tree(w)?;
@@ -818,6 +1747,166 @@ impl AstNode for PropertyExprNode {
     }
 }
 
+/*****************************************************************************
+ **** FunctionExprNode *******************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub struct FunctionExprNode {
+    func: FunctionDef
+}
+impl FunctionExprNode {
+    pub fn new(func: FunctionDef) -> FunctionExprNode {
+        FunctionExprNode { func }
+    }
+
+    pub fn func(&self) -> &FunctionDef {
+        &self.func
+    }
+}
+impl AstNode for FunctionExprNode {
+    fn kind(&self) -> AstKind {
+        AstKind::FunctionExpr
+    }
+    fn is_statement(&self) -> bool {
+        false
+    }
+    fn is_expression(&self) -> bool {
+        true
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("FunctionExpr")?;
+        self.func.write_tree(w)
+    }
+}
+
+/*****************************************************************************
+ **** ObjectLiteralExprNode **************************************************
+ *****************************************************************************/
+#[derive(Debug)]
+pub enum PropertyDefinitionKind {
+    Init(Box<dyn AstNode>),
+    Getter(FunctionDef),
+    Setter(FunctionDef)
+}
+
+/**
+ * A property in an object literal.  The name token is an identifier name, a
+ * string literal or a numeric literal.
+ */
+#[derive(Debug)]
+pub struct PropertyDefinition {
+    name: FullToken,
+    kind: PropertyDefinitionKind
+}
+impl PropertyDefinition {
+    pub fn new(name: FullToken, kind: PropertyDefinitionKind) -> PropertyDefinition {
+        PropertyDefinition { name, kind }
+    }
+
+    pub fn name(&self) -> &FullToken {
+        &self.name
+    }
+    pub fn kind(&self) -> &PropertyDefinitionKind {
+        &self.kind
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectLiteralExprNode {
+    properties: Vec<PropertyDefinition>
+}
+impl ObjectLiteralExprNode {
+    pub fn new(properties: Vec<PropertyDefinition>) -> ObjectLiteralExprNode {
+        ObjectLiteralExprNode { properties }
+    }
+
+    pub fn properties(&self) -> &[PropertyDefinition] {
+        &self.properties
+    }
+}
+impl AstNode for ObjectLiteralExprNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ObjectLiteralExpr
+    }
+    fn is_statement(&self) -> bool {
+        false
+    }
+    fn is_expression(&self) -> bool {
+        true
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("ObjectLiteral{")?;
+        let mut first = true;
+        for property in &self.properties {
+            if ! first {
+                w.write_str(", ")?;
+            }
+            first = false;
+            match property.kind {
+                PropertyDefinitionKind::Init(ref expr) => {
+                    property.name.write_token(w)?;
+                    w.write_str(": ")?;
+                    expr.write_tree(w)?;
+                }
+                PropertyDefinitionKind::Getter(ref func) => {
+                    w.write_str("get ")?;
+                    property.name.write_token(w)?;
+                    func.write_tree(w)?;
+                }
+                PropertyDefinitionKind::Setter(ref func) => {
+                    w.write_str("set ")?;
+                    property.name.write_token(w)?;
+                    func.write_tree(w)?;
+                }
+            }
+        }
+        w.write_str("}")
+    }
+}
+
+/*****************************************************************************
+ **** ArrayLiteralExprNode ***************************************************
+ *****************************************************************************/
+/** An array literal.  Elided elements are `None`. */
+#[derive(Debug)]
+pub struct ArrayLiteralExprNode {
+    elements: Vec<Option<Box<dyn AstNode>>>
+}
+impl ArrayLiteralExprNode {
+    pub fn new(elements: Vec<Option<Box<dyn AstNode>>>) -> ArrayLiteralExprNode {
+        ArrayLiteralExprNode { elements }
+    }
+
+    pub fn elements(&self) -> &[Option<Box<dyn AstNode>>] {
+        &self.elements
+    }
+}
+impl AstNode for ArrayLiteralExprNode {
+    fn kind(&self) -> AstKind {
+        AstKind::ArrayLiteralExpr
+    }
+    fn is_statement(&self) -> bool {
+        false
+    }
+    fn is_expression(&self) -> bool {
+        true
+    }
+    fn write_tree(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        w.write_str("ArrayLiteral[")?;
+        let mut first = true;
+        for element in &self.elements {
+            if ! first {
+                w.write_str(", ")?;
+            }
+            first = false;
+            if let Some(ref expr) = *element {
+                expr.write_tree(w)?;
+            }
+        }
+        w.write_str("]")
+    }
+}
+
 /*****************************************************************************
  **** AtomicExprNode *********************************************************
  *****************************************************************************/
@@ -845,6 +1934,16 @@ impl AstNode for AtomicExprNode {
     fn is_expression(&self) -> bool {
         true
     }
+    fn is_assignment_target(&self) -> bool {
+        self.name.kind().is_identifier()
+    }
+    fn identifier(&self) -> Option<&FullToken> {
+        if self.name.kind().is_identifier() {
+            Some(&self.name)
+        } else {
+            None
+        }
+    }
     fn write_tree(&self, w: &mut fmt::Write) -> Result<(), fmt::Error> {
         w.write_str("AtomicExpr{")?;
         self.name.write_token(w);
@@ -1,9 +1,11 @@
 
 use std::borrow::Borrow;
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
 
 use parser::ast;
-use parser::ast::{AstKind, AstNode};
+use parser::ast::{AstKind, AstNode, Comment, CommentKind};
 use parser::input_stream::{InputStream, StreamPosition};
 use parser::precedence::Precedence;
 use parser::token_kind::TokenKind;
@@ -13,16 +15,32 @@ use parser::tokenizer::{Token, TokenError, TokenLocation, Tokenizer, TokenizerMo
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FullToken {
     kind: TokenKind,
-    location: TokenLocation
+    location: TokenLocation,
+    text: Option<Rc<str>>
 }
 impl FullToken {
     pub fn new(kind: TokenKind, location: TokenLocation) -> FullToken {
-        FullToken { kind, location }
+        FullToken { kind, location, text: None }
     }
 
     pub fn location(&self) -> &TokenLocation {
         &self.location
     }
+
+    /**
+     * The source text of identifier, keyword and literal tokens.  Other
+     * tokens do not carry their text, as it is implied by their kind.
+     */
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_ref().map(|text| text.as_ref())
+    }
+    fn set_text(&mut self, text: String) {
+        self.text = Some(Rc::from(text));
+    }
+
+    pub fn is_identifier_named(&self, name: &str) -> bool {
+        self.kind.is_identifier() && (self.text() == Some(name))
+    }
 }
 impl Token for FullToken {
     fn make(kind: TokenKind, location: TokenLocation) -> FullToken {
@@ -64,28 +82,118 @@ pub enum ParseError {
     ExpectedCommaOrCloseParen,
     ExpectedExpression,
     ExpectedStatement,
-    ExpectedEndOfStatement
+    ExpectedEndOfStatement,
+    ExpectedFunctionName,
+    ExpectedParameterName,
+    ExpectedPropertyName,
+    ExpectedCommaOrCloseBrace,
+    ExpectedCommaOrCloseBracket,
+    InvalidAccessorParameters(TokenLocation),
+    DuplicateProperty(TokenLocation),
+    ExpectedCatchOrFinally,
+    ExpectedCatchParameter,
+    ReturnOutsideFunction(TokenLocation),
+    NewlineAfterThrow(TokenLocation),
+    IllegalBreak(TokenLocation),
+    IllegalContinue(TokenLocation),
+    UndefinedLabel(TokenLocation),
+    DuplicateLabel(TokenLocation),
+    InvalidAssignmentTarget(TokenLocation),
+    StrictModeWith(TokenLocation),
+    StrictModeOctalLiteral(TokenLocation),
+    StrictModeOctalEscape(TokenLocation),
+    StrictModeDeleteIdentifier(TokenLocation),
+    StrictModeEvalOrArguments(TokenLocation),
+    StrictModeDuplicateParameter(TokenLocation),
+    StrictModeReservedWord(TokenLocation)
 }
 pub type ParseResult<T> = Result<T, ParseError>;
 pub type MaybeParseResult<T> = ParseResult<Option<T>>;
 
+/**
+ * A position the parser can rewind to.  Besides the tokenizer's position, it
+ * records whether the last token read was a newline, which decides whether the
+ * next comment read follows one, and where the last token other than a newline
+ * ended, which is where an expression ending there ends.
+ */
+#[derive(Debug, Clone, Copy)]
+struct ParserPosition {
+    tokenizer_position: TokenizerPosition,
+    after_newline: bool,
+    last_token_end: StreamPosition
+}
+impl ParserPosition {
+    fn stream_position(self) -> StreamPosition {
+        self.tokenizer_position.stream_position()
+    }
+}
+
 pub struct AstBuilder<STREAM: InputStream> {
     tokenizer: Tokenizer<STREAM, FullTokenizerMode>,
-    skipped_newline: bool
+    skipped_newline: bool,
+    after_newline: bool,
+    last_token_end: StreamPosition,
+    strict: bool,
+    in_function: bool,
+
+    // Whether the 'in' operator is excluded from expressions, as in the first
+    // part of a for statement's head, outside any brackets.
+    no_in: bool,
+
+    // The loops and labels enclosing the statement being parsed, within the
+    // current function, for checking break and continue.  Each label records
+    // whether it labels a loop.
+    loop_depth: u32,
+    labels: Vec<(String, bool)>,
+
+    // Comment collection.
+    collect_comments: bool,
+    pending_comments: Vec<Comment>,
+    last_comment_start: Option<StreamPosition>,
+
+    // Whether to print a trace of the parse to stdout.
+    log_debug: bool
 }
 impl<STREAM: InputStream> AstBuilder<STREAM> {
     pub fn new(stream: STREAM) -> AstBuilder<STREAM> {
         AstBuilder {
             tokenizer: Tokenizer::new(stream, FullTokenizerMode{}),
-            skipped_newline: false
+            skipped_newline: false,
+            after_newline: true,
+            last_token_end: StreamPosition::default(),
+            in_function: false,
+            strict: false,
+            no_in: false,
+            loop_depth: 0,
+            labels: Vec::new(),
+            collect_comments: false,
+            pending_comments: Vec::new(),
+            last_comment_start: None,
+            log_debug: false
         }
     }
 
+    /**
+     * Collect comments while parsing, and attach them to the statements
+     * (or the program) they are nearest to.  Off by default.
+     */
+    pub fn set_collect_comments(&mut self, collect_comments: bool) {
+        self.collect_comments = collect_comments;
+    }
+
+    /**
+     * Print a trace of the tokens and productions parsed to stdout, for
+     * debugging the parser.  Off by default.
+     */
+    pub fn set_log_debug(&mut self, log_debug: bool) {
+        self.log_debug = log_debug;
+    }
+
     pub fn read_and_print_tokens(&mut self) {
         // Just read tokens and print them out until we're done, then return Error.
         loop {
             let token = self.next_token().unwrap();
-            self.log_debug(format!("Token: {}", token.kind().name()));
+            println!("Token: {}", token.kind().name());
             if token.kind().is_error() {
                 panic!("Got token error: {:?}", self.tokenizer.get_error());
             }
@@ -98,10 +206,11 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
     pub fn parse_program(&mut self) -> ParseResult<Box<ast::ProgramNode>> {
         self.log_debug(format!("parse_program() BEGIN"));
         let mut program_node = ast::ProgramNode::new();
+        self.parse_directive_prologue()?;
         loop {
             self.log_debug(format!("parse_program() LOOP"));
             let position = self.mark_position();
-            match self.try_parse_statement()? {
+            match self.try_parse_list_statement(program_node.last_source_element_mut())? {
                 Some(boxed_source_element) => {
                     self.log_debug(format!("parse_program() GOT SOURCE ELEMENT"));
                     program_node.add_source_element(boxed_source_element);
@@ -115,12 +224,156 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
 
         // Must have reached end of stream.
         self.log_debug(format!("parse_program() CHECK FOR END"));
+        let end_position = self.mark_position();
         self.must_expect_token(TokenKind::end())?;
+        program_node.set_strict(self.strict);
+        if self.collect_comments {
+            self.attach_list_end_comments(program_node.last_source_element_mut(), end_position);
+            for comment in self.pending_comments.drain(..) {
+                program_node.comments_mut().unwrap().add_inner(comment);
+            }
+        }
 
         self.log_debug(format!("parse_program() END"));
         Ok(Box::new(program_node))
     }
 
+    /**
+     * Scan the directive prologue at the start of a program or function body, and
+     * enter strict mode if it contains a Use Strict Directive.  The stream is rewound
+     * afterwards, so the directives are parsed again as ordinary expression statements,
+     * subject to the strict mode restrictions if those now apply.
+     */
+    fn parse_directive_prologue(&mut self) -> ParseResult<()> {
+        let position = self.mark_position();
+        loop {
+            let tok = self.next_token()?;
+            if ! tok.kind().is_string_literal() {
+                break;
+            }
+            let end_tok = self.next_token_keep_newline()?;
+            let end_kind = end_tok.kind();
+            let ends_statement = end_kind.is_semicolon() || end_kind.is_newline();
+            if ! (ends_statement || end_kind.is_close_brace() || end_kind.is_end()) {
+                // The string literal begins a longer expression, so it's not a directive.
+                break;
+            }
+            if is_use_strict_directive(&tok) {
+                self.log_debug("parse_directive_prologue() USE STRICT");
+                self.strict = true;
+            }
+            if ! ends_statement {
+                break;
+            }
+        }
+        self.rewind_position(position);
+        Ok(())
+    }
+
+    /**
+     * Parse a statement in a statement list (program, block or function body), and
+     * attach the comments preceding and within it.  Comments before the statement
+     * lead it, unless they trail the previous statement on its last line.  Comments
+     * within it not claimed by a nested statement list are its inner comments.
+     */
+    fn try_parse_list_statement(&mut self, prev_stmt: Option<&mut Box<dyn AstNode>>)
+        -> MaybeParseResult<Box<dyn AstNode>>
+    {
+        // Nothing before the start of a list statement is rewound to once it has
+        // been reached, so the input stream need not retain it.
+        let position = self.mark_position();
+        self.tokenizer.pin_window_start(position.tokenizer_position);
+
+        if ! self.collect_comments {
+            return self.try_parse_statement();
+        }
+
+        let list_position = self.mark_position().stream_position();
+        let stmt_start = self.peek_token_start()?;
+        let mut stmt = match self.try_parse_statement()? {
+            Some(stmt) => stmt,
+            None => { return Ok(None); }
+        };
+
+        let stmt_end = self.mark_position().stream_position();
+        let mut prev_stmt = prev_stmt;
+        let mut seen_leading = false;
+        for comment in self.take_comments_between(list_position, stmt_end) {
+            if comment.location().start_offset() >= stmt_start {
+                stmt.comments_mut().unwrap().add_inner(comment);
+                continue;
+            }
+            if ! seen_leading && ! comment.follows_newline() {
+                if let Some(ref mut prev) = prev_stmt {
+                    prev.comments_mut().unwrap().add_trailing(comment);
+                    continue;
+                }
+            }
+            seen_leading = true;
+            stmt.comments_mut().unwrap().add_leading(comment);
+        }
+        Ok(Some(stmt))
+    }
+
+    /**
+     * Attach the comments at the end of a statement list which share a line with
+     * its last statement.  Any others are left pending, and become inner comments
+     * of the enclosing statement (or the program).
+     */
+    fn attach_list_end_comments(&mut self, last_stmt: Option<&mut Box<dyn AstNode>>,
+                                list_position: ParserPosition)
+    {
+        let last_stmt = match last_stmt {
+            Some(stmt) => stmt,
+            None => { return; }
+        };
+        let list_end = self.mark_position().stream_position();
+        let comments = self.take_comments_between(list_position.stream_position(), list_end);
+        let mut trailing = true;
+        for comment in comments {
+            trailing = trailing && ! comment.follows_newline();
+            if trailing {
+                last_stmt.comments_mut().unwrap().add_trailing(comment);
+            } else {
+                self.return_pending_comment(comment);
+            }
+        }
+    }
+
+    fn take_comments_between(&mut self, start: StreamPosition, end: StreamPosition)
+        -> Vec<Comment>
+    {
+        let first = self.pending_comments.iter()
+                        .take_while(|c| c.location().start_offset() < start)
+                        .count();
+        let count = self.pending_comments[first..].iter()
+                        .take_while(|c| c.location().start_offset() < end)
+                        .count();
+        self.pending_comments.drain(first..(first + count)).collect()
+    }
+
+    fn return_pending_comment(&mut self, comment: Comment) {
+        let start = comment.location().start_offset();
+        let index = self.pending_comments.iter()
+                        .take_while(|c| c.location().start_offset() < start)
+                        .count();
+        self.pending_comments.insert(index, comment);
+    }
+
+    fn note_comment(&mut self, token: &FullToken, follows_newline: bool) -> CommentKind {
+        let text = self.tokenizer.extract_text(token.location());
+        let kind = if text.starts_with("//") { CommentKind::Line } else { CommentKind::Block };
+
+        // Comments are read again when the parser backtracks, so only keep new ones.
+        let start = token.start_offset();
+        if self.last_comment_start.is_none_or(|last| start > last) {
+            self.last_comment_start = Some(start);
+            self.pending_comments.push(Comment::new(kind, token.location().clone(), text,
+                                                    follows_newline));
+        }
+        kind
+    }
+
     fn try_parse_statement(&mut self) -> MaybeParseResult<Box<AstNode>> {
         self.log_debug(format!("try_parse_statement() BEGIN"));
         let tok = self.next_token()?;
@@ -130,7 +383,7 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         }
         if tok.kind().is_var_keyword() {
             self.log_debug(format!("try_parse_statement() VAR"));
-            return Ok(Some(self.parse_var_statement()?));
+            return Ok(Some(self.parse_var_statement(/* for_init = */ false)?));
         }
         if tok.kind().is_semicolon() {
             self.log_debug(format!("try_parse_statement() SEMICOLON"));
@@ -140,59 +393,117 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             self.log_debug(format!("try_parse_statement() IF"));
             return Ok(Some(self.parse_if_statement()?));
         }
+        if tok.kind().is_function_keyword() {
+            self.log_debug("try_parse_statement() FUNCTION");
+            return Ok(Some(self.parse_function_declaration()?));
+        }
+        if tok.kind().is_with_keyword() {
+            self.log_debug("try_parse_statement() WITH");
+            return Ok(Some(self.parse_with_statement(tok)?));
+        }
+        if tok.kind().is_return_keyword() {
+            self.log_debug("try_parse_statement() RETURN");
+            return Ok(Some(self.parse_return_statement(tok)?));
+        }
+        if tok.kind().is_throw_keyword() {
+            self.log_debug("try_parse_statement() THROW");
+            return Ok(Some(self.parse_throw_statement(tok)?));
+        }
+        if tok.kind().is_try_keyword() {
+            self.log_debug("try_parse_statement() TRY");
+            return Ok(Some(self.parse_try_statement()?));
+        }
+        if tok.kind().is_while_keyword() {
+            self.log_debug("try_parse_statement() WHILE");
+            return Ok(Some(self.parse_while_statement()?));
+        }
+        if tok.kind().is_do_keyword() {
+            self.log_debug("try_parse_statement() DO");
+            return Ok(Some(self.parse_do_while_statement()?));
+        }
+        if tok.kind().is_for_keyword() {
+            self.log_debug("try_parse_statement() FOR");
+            return Ok(Some(self.parse_for_statement()?));
+        }
+        if tok.kind().is_break_keyword() || tok.kind().is_continue_keyword() {
+            self.log_debug("try_parse_statement() BREAK OR CONTINUE");
+            return Ok(Some(self.parse_break_or_continue_statement(tok)?));
+        }
+        if tok.kind().is_identifier() {
+            let position = self.mark_position();
+            if self.expect_token(TokenKind::colon())? {
+                self.log_debug("try_parse_statement() LABEL");
+                return Ok(Some(self.parse_labelled_statement(tok)?));
+            }
+            self.rewind_position(position);
+        }
 
         self.log_debug(format!("try_parse_statement() CHECKING FOR EXPRESSION"));
         if let Some(boxed_expr) = self.try_parse_expression_with(tok, Precedence::lowest())? {
             self.log_debug(format!("try_parse_statement() GOT EXPRESSION. CHECK FOR SEMICOLON"));
-            // Check for semicolon or newline after expression statement.
-            let posn = self.mark_position();
-            let end_tok = self.next_token_keep_newline()?;
-            if end_tok.kind().is_newline() || end_tok.kind().is_semicolon() {
-                return Ok(Some(Box::new(ast::ExprStmtNode::new(boxed_expr))));
-            }
-
-            self.rewind_position(posn);
-            if end_tok.kind().is_close_brace() {
-                return Ok(Some(Box::new(ast::ExprStmtNode::new(boxed_expr))));
-            } else {
-                return Err(ParseError::ExpectedEndOfStatement);
-            }
+            self.parse_end_of_statement()?;
+            return Ok(Some(Box::new(ast::ExprStmtNode::new(boxed_expr))));
         }
 
         self.log_debug(format!("try_parse_statement() END (FAILED)"));
         Ok(None)
     }
 
+    /**
+     * Parse the end of a statement: a semicolon, or a newline, closing brace or
+     * the end of input.  Only a semicolon or newline is consumed.
+     */
+    fn parse_end_of_statement(&mut self) -> ParseResult<()> {
+        let posn = self.mark_position();
+        let end_tok = self.next_token_keep_newline()?;
+        if end_tok.kind().is_newline() || end_tok.kind().is_semicolon() {
+            return Ok(());
+        }
+
+        self.rewind_position(posn);
+        if end_tok.kind().is_close_brace() || end_tok.kind().is_end() {
+            Ok(())
+        } else {
+            Err(ParseError::ExpectedEndOfStatement)
+        }
+    }
+
     fn parse_block_or_object_expression(&mut self) -> ParseResult<Box<AstNode>> {
         // Check for empty block.
         if self.expect_token(TokenKind::close_brace())? {
             return Ok(Box::new(ast::BlockStmtNode::new(Vec::new())));
         }
 
-        let position = self.mark_position();
-
         // Try parsing statements in block.
         let mut statements: Vec<Box<AstNode>> = Vec::new();
         loop {
+            let end_position = self.mark_position();
             if self.expect_token(TokenKind::close_brace())? {
+                if self.collect_comments {
+                    self.attach_list_end_comments(statements.last_mut(), end_position);
+                }
                 return Ok(Box::new(ast::BlockStmtNode::new(statements)));
             }
 
-            if let Some(stmt) = self.try_parse_statement()? {
+            if let Some(stmt) = self.try_parse_list_statement(statements.last_mut())? {
                 statements.push(stmt);
                 continue;
             }
 
-            self.rewind_position(position);
             break;
         }
 
-        // If that failed, try parsing an object literal.
-        // FIXME: Actually try parsing an object literal.
+        // A brace at the start of a statement always begins a block.  Object
+        // literals are only parsed in expression position.
         return Err(ParseError::ExpectedStatement);
     }
 
-    fn parse_var_statement(&mut self) -> ParseResult<Box<ast::VarStmtNode>> {
+    /**
+     * Parse the declarations of a var statement, after the 'var' keyword.  In
+     * the initializer of a for statement, they end at a semicolon, or an 'in'
+     * after a single declaration, which is left for the for statement.
+     */
+    fn parse_var_statement(&mut self, for_init: bool) -> ParseResult<Box<ast::VarStmtNode>> {
         let mut var_statement = Box::new(ast::VarStmtNode::new());
         loop {
             // Parse var name.
@@ -200,29 +511,45 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
                 Some(token) => token,
                 None => { return Err(ParseError::ExpectedVariableName); }
             };
+            if self.strict {
+                self.check_strict_binding_identifier(&name_token)?;
+            }
 
-            let next_tok = self.next_token()?;
+            let mut position = self.mark_position();
+            let mut next_tok = self.next_token()?;
             if next_tok.kind().is_assign() {
                 // Parse an initializer.
                 let boxed_expr = self.parse_expression(Precedence::assignment())?;
                 self.log_debug(format!("Got init expr: {}", boxed_expr.tree_string()));
                 var_statement.add_var_decl_with_init(name_token, boxed_expr);
 
-                let next_tok = self.next_token()?;
-                if next_tok.kind().is_comma() {
-                    continue;
-                }
-                if next_tok.kind().is_semicolon() || self.skipped_newline {
-                    break;
-                }
-                return Err(ParseError::ExpectedCommaOrSemicolon);
+                position = self.mark_position();
+                next_tok = self.next_token()?;
+            } else {
+                var_statement.add_var_decl(name_token);
             }
+
             if next_tok.kind().is_comma() {
-                var_statement.add_var_decl(name_token);
                 continue;
             }
-            // If there was a semicolon, or newline, end of statement.
-            if next_tok.kind().is_semicolon() || self.skipped_newline {
+            // In a for statement's head, the semicolon (or the 'in' following a
+            // single declaration) is left for the for statement.
+            if for_init {
+                let ends_init = next_tok.kind().is_semicolon() ||
+                    (next_tok.kind().is_in_keyword() && var_statement.var_decls().len() == 1);
+                if ! ends_init {
+                    return Err(ParseError::ExpectedCommaOrSemicolon);
+                }
+                self.rewind_position(position);
+                break;
+            }
+            if next_tok.kind().is_semicolon() {
+                break;
+            }
+            // A newline, closing brace or end of input also ends the statement,
+            // but the token belongs to what follows.
+            if self.skipped_newline || next_tok.kind().is_close_brace() || next_tok.kind().is_end() {
+                self.rewind_position(position);
                 break;
             }
 
@@ -242,14 +569,13 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         self.log_debug("parse_if_statement() PARSE IF STATEMENT");
         let if_true_stmt = {
             let post_if_position = self.mark_position();
-            let stmt = match self.try_parse_statement()? {
+            match self.try_parse_statement()? {
                 Some(stmt) => stmt,
                 None => {
                     self.rewind_position(post_if_position);
                     return Err(ParseError::ExpectedStatement);
                 }
-            };
-            stmt
+            }
         };
 
         // Check for 'else'
@@ -268,6 +594,364 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         }
     }
 
+    fn parse_with_statement(&mut self, with_tok: FullToken) -> ParseResult<Box<dyn AstNode>> {
+        if self.strict {
+            return Err(ParseError::StrictModeWith(with_tok.location().clone()));
+        }
+        self.must_expect_token(TokenKind::open_paren())?;
+        let object_expr = self.parse_expression(Precedence::lowest())?;
+        self.must_expect_token(TokenKind::close_paren())?;
+        let post_with_position = self.mark_position();
+        let body_stmt = match self.try_parse_statement()? {
+            Some(stmt) => stmt,
+            None => {
+                self.rewind_position(post_with_position);
+                return Err(ParseError::ExpectedStatement);
+            }
+        };
+        Ok(Box::new(ast::WithStmtNode::new(object_expr, body_stmt)))
+    }
+
+    fn parse_return_statement(&mut self, return_tok: FullToken) -> ParseResult<Box<dyn AstNode>> {
+        if ! self.in_function {
+            return Err(ParseError::ReturnOutsideFunction(return_tok.location().clone()));
+        }
+
+        // A newline after "return" ends the statement.
+        let position = self.mark_position();
+        let tok = self.next_token_keep_newline()?;
+        if tok.kind().is_newline() || tok.kind().is_semicolon() {
+            return Ok(Box::new(ast::ReturnStmtNode::new(None)));
+        }
+        self.rewind_position(position);
+        if tok.kind().is_close_brace() || tok.kind().is_end() {
+            return Ok(Box::new(ast::ReturnStmtNode::new(None)));
+        }
+
+        let expr = self.parse_expression(Precedence::lowest())?;
+        self.parse_end_of_statement()?;
+        Ok(Box::new(ast::ReturnStmtNode::new(Some(expr))))
+    }
+
+    fn parse_throw_statement(&mut self, throw_tok: FullToken) -> ParseResult<Box<dyn AstNode>> {
+        // No newline may separate "throw" from its expression.
+        let position = self.mark_position();
+        if self.next_token_keep_newline()?.kind().is_newline() {
+            return Err(ParseError::NewlineAfterThrow(throw_tok.location().clone()));
+        }
+        self.rewind_position(position);
+
+        let expr = self.parse_expression(Precedence::lowest())?;
+        self.parse_end_of_statement()?;
+        Ok(Box::new(ast::ThrowStmtNode::new(expr)))
+    }
+
+    fn parse_try_statement(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        let try_block = self.parse_block()?;
+
+        let catch = if self.expect_token(TokenKind::catch_keyword())? {
+            self.must_expect_token(TokenKind::open_paren())?;
+            let param = match self.expect_get_token(TokenKind::identifier())? {
+                Some(token) => token,
+                None => { return Err(ParseError::ExpectedCatchParameter); }
+            };
+            if self.strict {
+                self.check_strict_binding_identifier(&param)?;
+            }
+            self.must_expect_token(TokenKind::close_paren())?;
+            Some((param, self.parse_block()?))
+        } else {
+            None
+        };
+
+        let finally_block = if self.expect_token(TokenKind::finally_keyword())? {
+            Some(self.parse_block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally_block.is_none() {
+            return Err(ParseError::ExpectedCatchOrFinally);
+        }
+        Ok(Box::new(ast::TryStmtNode::new(try_block, catch, finally_block)))
+    }
+
+    // Parse a block which must follow, as in a try statement.
+    fn parse_block(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        self.must_expect_token(TokenKind::open_brace())?;
+        self.parse_block_or_object_expression()
+    }
+
+    fn parse_while_statement(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        self.must_expect_token(TokenKind::open_paren())?;
+        let cond_expr = self.parse_expression(Precedence::lowest())?;
+        self.must_expect_token(TokenKind::close_paren())?;
+        let body_stmt = self.parse_loop_body()?;
+        Ok(Box::new(ast::WhileStmtNode::new(cond_expr, body_stmt)))
+    }
+
+    fn parse_do_while_statement(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        let body_stmt = self.parse_loop_body()?;
+        self.must_expect_token(TokenKind::while_keyword())?;
+        self.must_expect_token(TokenKind::open_paren())?;
+        let cond_expr = self.parse_expression(Precedence::lowest())?;
+        self.must_expect_token(TokenKind::close_paren())?;
+        // A semicolon is inserted after a do-while statement if it's missing.
+        self.expect_token(TokenKind::semicolon())?;
+        Ok(Box::new(ast::DoWhileStmtNode::new(body_stmt, cond_expr)))
+    }
+
+    /**
+     * Parse a for or for-in statement.  The first part of the head is parsed
+     * without the 'in' operator, so that an 'in' following it makes the
+     * statement a for-in.
+     */
+    fn parse_for_statement(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        self.must_expect_token(TokenKind::open_paren())?;
+        let init: Option<Box<dyn AstNode>> = if self.expect_token(TokenKind::semicolon())? {
+            None
+        } else {
+            let init_start = self.peek_token_start()?;
+            let no_in = mem::replace(&mut self.no_in, true);
+            let init: ParseResult<Box<dyn AstNode>> = if self.expect_token(TokenKind::var_keyword())? {
+                self.parse_var_statement(/* for_init = */ true).map(|var_stmt| var_stmt as Box<dyn AstNode>)
+            } else {
+                self.parse_expression(Precedence::lowest())
+            };
+            self.no_in = no_in;
+            let init = init?;
+
+            let init_location = self.location_from(init_start);
+            if self.expect_token(TokenKind::in_keyword())? {
+                return self.parse_for_in_rest(init, &init_location);
+            }
+            self.must_expect_token(TokenKind::semicolon())?;
+            Some(init)
+        };
+
+        let cond_expr = if self.expect_token(TokenKind::semicolon())? {
+            None
+        } else {
+            let cond_expr = self.parse_expression(Precedence::lowest())?;
+            self.must_expect_token(TokenKind::semicolon())?;
+            Some(cond_expr)
+        };
+
+        let update_expr = if self.expect_token(TokenKind::close_paren())? {
+            None
+        } else {
+            let update_expr = self.parse_expression(Precedence::lowest())?;
+            self.must_expect_token(TokenKind::close_paren())?;
+            Some(update_expr)
+        };
+
+        let body_stmt = self.parse_loop_body()?;
+        Ok(Box::new(ast::ForStmtNode::new(init, cond_expr, update_expr, body_stmt)))
+    }
+
+    // Parse the rest of a for-in statement, after the 'in' of its head.  Its
+    // target is a single variable declaration, or an assignable expression.
+    fn parse_for_in_rest(&mut self, target: Box<dyn AstNode>, target_location: &TokenLocation)
+        -> ParseResult<Box<dyn AstNode>>
+    {
+        if target.kind() != AstKind::VarStmt {
+            self.check_assignment_target(target.as_ref(), target_location)?;
+        }
+        let object_expr = self.parse_expression(Precedence::lowest())?;
+        self.must_expect_token(TokenKind::close_paren())?;
+        let body_stmt = self.parse_loop_body()?;
+        Ok(Box::new(ast::ForInStmtNode::new(target, object_expr, body_stmt)))
+    }
+
+    fn parse_loop_body(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        self.loop_depth += 1;
+        let body_stmt = self.parse_sub_statement();
+        self.loop_depth -= 1;
+        body_stmt
+    }
+
+    // Parse a statement which must follow, as the body of a compound statement.
+    fn parse_sub_statement(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        let position = self.mark_position();
+        match self.try_parse_statement()? {
+            Some(stmt) => Ok(stmt),
+            None => {
+                self.rewind_position(position);
+                Err(ParseError::ExpectedStatement)
+            }
+        }
+    }
+
+    /**
+     * Parse a break or continue statement.  An unlabelled one must be within a
+     * loop, and a labelled one within a statement with its label, which for
+     * continue must be a loop.
+     */
+    fn parse_break_or_continue_statement(&mut self, tok: FullToken) -> ParseResult<Box<dyn AstNode>> {
+        let is_break = tok.kind().is_break_keyword();
+
+        // The label must be on the same line.
+        let position = self.mark_position();
+        let label_tok = self.next_token_keep_newline()?;
+        let label = if label_tok.kind().is_identifier() {
+            Some(label_tok)
+        } else {
+            self.rewind_position(position);
+            None
+        };
+
+        match label {
+            Some(ref label) => {
+                let name = label.text().unwrap();
+                match self.labels.iter().rev().find(|(l, _)| l == name) {
+                    None => return Err(ParseError::UndefinedLabel(label.location().clone())),
+                    Some(&(_, false)) if ! is_break => {
+                        return Err(ParseError::IllegalContinue(tok.location().clone()));
+                    }
+                    _ => {}
+                }
+            }
+            None if self.loop_depth == 0 => {
+                return Err(if is_break {
+                    ParseError::IllegalBreak(tok.location().clone())
+                } else {
+                    ParseError::IllegalContinue(tok.location().clone())
+                });
+            }
+            None => {}
+        }
+        self.parse_end_of_statement()?;
+
+        Ok(if is_break {
+            Box::new(ast::BreakStmtNode::new(label))
+        } else {
+            Box::new(ast::ContinueStmtNode::new(label))
+        })
+    }
+
+    fn parse_labelled_statement(&mut self, label: FullToken) -> ParseResult<Box<dyn AstNode>> {
+        if self.strict {
+            self.check_strict_identifier(&label)?;
+        }
+        let name = label.text().unwrap().to_string();
+        if self.labels.iter().any(|(l, _)| *l == name) {
+            return Err(ParseError::DuplicateLabel(label.location().clone()));
+        }
+
+        let labels_loop = self.next_statement_is_loop()?;
+        self.labels.push((name, labels_loop));
+        let body_stmt = self.parse_sub_statement();
+        self.labels.pop();
+        Ok(Box::new(ast::LabelledStmtNode::new(label, body_stmt?)))
+    }
+
+    // Whether the next statement, after any further labels, is a loop.
+    fn next_statement_is_loop(&mut self) -> ParseResult<bool> {
+        let position = self.mark_position();
+        let is_loop = loop {
+            let tok = self.next_token()?;
+            if tok.kind().is_identifier() && self.expect_token(TokenKind::colon())? {
+                continue;
+            }
+            let kind = tok.kind();
+            break kind.is_while_keyword() || kind.is_do_keyword() || kind.is_for_keyword();
+        };
+        self.rewind_position(position);
+        Ok(is_loop)
+    }
+
+    fn parse_function_declaration(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        let name_tok = match self.expect_get_token(TokenKind::identifier())? {
+            Some(token) => token,
+            None => { return Err(ParseError::ExpectedFunctionName); }
+        };
+        let func = self.parse_function_rest(Some(name_tok))?;
+        Ok(Box::new(ast::FunctionDeclNode::new(func)))
+    }
+
+    /**
+     * Parse the parameter list and body of a function, after the 'function'
+     * keyword and optional name.
+     */
+    fn parse_function_rest(&mut self, name: Option<FullToken>) -> ParseResult<ast::FunctionDef> {
+        self.must_expect_token(TokenKind::open_paren())?;
+        let mut params = Vec::new();
+        if ! self.expect_token(TokenKind::close_paren())? {
+            loop {
+                match self.expect_get_token(TokenKind::identifier())? {
+                    Some(token) => params.push(token),
+                    None => { return Err(ParseError::ExpectedParameterName); }
+                }
+                let next_tok = self.next_token()?;
+                if next_tok.kind().is_close_paren() {
+                    break;
+                }
+                if ! next_tok.kind().is_comma() {
+                    return Err(ParseError::ExpectedCommaOrCloseParen);
+                }
+            }
+        }
+        self.must_expect_token(TokenKind::open_brace())?;
+
+        // The function body is strict if the enclosing code is, or if it
+        // begins with a Use Strict Directive.
+        let outer_strict = self.strict;
+        let outer_in_function = self.in_function;
+        let outer_loop_depth = self.loop_depth;
+        let outer_labels = mem::take(&mut self.labels);
+        let outer_no_in = self.no_in;
+        self.in_function = true;
+        self.loop_depth = 0;
+        self.no_in = false;
+        let body = self.parse_function_body();
+        let strict = self.strict;
+        self.strict = outer_strict;
+        self.in_function = outer_in_function;
+        self.loop_depth = outer_loop_depth;
+        self.labels = outer_labels;
+        self.no_in = outer_no_in;
+        let body = body?;
+
+        // The name and parameters precede the directive prologue, so they can
+        // only be checked once the strictness of the function is known.
+        if strict {
+            if let Some(ref name_tok) = name {
+                self.check_strict_binding_identifier(name_tok)?;
+            }
+            for (i, param) in params.iter().enumerate() {
+                self.check_strict_binding_identifier(param)?;
+                if params[..i].iter().any(|prev| prev.text() == param.text()) {
+                    return Err(ParseError::StrictModeDuplicateParameter(param.location().clone()));
+                }
+            }
+        }
+        Ok(ast::FunctionDef::new(name, params, body, strict))
+    }
+
+    // Parse the directive prologue and statements of a function body, up to
+    // its closing brace.
+    fn parse_function_body(&mut self) -> ParseResult<Vec<Box<dyn AstNode>>> {
+        self.parse_directive_prologue()?;
+        let mut body = Vec::new();
+        loop {
+            let end_position = self.mark_position();
+            if self.expect_token(TokenKind::close_brace())? {
+                if self.collect_comments {
+                    self.attach_list_end_comments(body.last_mut(), end_position);
+                }
+                return Ok(body);
+            }
+            let position = self.mark_position();
+            match self.try_parse_list_statement(body.last_mut())? {
+                Some(stmt) => body.push(stmt),
+                None => {
+                    self.rewind_position(position);
+                    return Err(ParseError::ExpectedStatement);
+                }
+            }
+        }
+    }
+
     fn parse_expression(&mut self, precedence: Precedence) -> ParseResult<Box<AstNode>> {
         let position = self.mark_position();
         if let Some(boxed_expr) = self.try_parse_expression(precedence)? {
@@ -277,6 +961,14 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             Err(ParseError::ExpectedExpression)
         }
     }
+    // Parse an expression within brackets, parentheses or braces, where 'in'
+    // is an operator even in the head of a for statement.
+    fn parse_expression_allowing_in(&mut self, precedence: Precedence) -> ParseResult<Box<dyn AstNode>> {
+        let no_in = mem::replace(&mut self.no_in, false);
+        let expr = self.parse_expression(precedence);
+        self.no_in = no_in;
+        expr
+    }
     fn try_parse_expression(&mut self, precedence: Precedence) -> MaybeParseResult<Box<AstNode>> {
         let tok = self.next_token()?;
         self.try_parse_expression_with(tok, precedence)
@@ -286,16 +978,59 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         -> MaybeParseResult<Box<AstNode>>
     {
         self.log_debug("try_parse_expression_with() BEGIN");
+        let start = tok.start_offset();
         if tok.kind().is_atomic_expr() {
             self.log_debug("try_parse_expression_with() HANDLE ATOMIC EXPR");
+            if self.strict && tok.kind().is_identifier() {
+                self.check_strict_identifier(&tok)?;
+            }
             let atomic_expr = Box::new(ast::AtomicExprNode::new(tok));
-            return Ok(Some(self.parse_rest_of_expression(atomic_expr, precedence)?));
+            return Ok(Some(self.parse_rest_of_expression(atomic_expr, start, precedence)?));
+        }
+        if tok.kind().is_slash() || tok.kind().is_slash_assign() {
+            self.log_debug("try_parse_expression_with() HANDLE REGEXP LITERAL");
+            let regexp_tok = self.read_regexp_literal(&tok)?;
+            let atomic_expr = Box::new(ast::AtomicExprNode::new(regexp_tok));
+            return Ok(Some(self.parse_rest_of_expression(atomic_expr, start, precedence)?));
+        }
+        if tok.kind().is_open_brace() {
+            self.log_debug("try_parse_expression_with() HANDLE OBJECT LITERAL");
+            let object_expr = self.parse_object_literal()?;
+            return Ok(Some(self.parse_rest_of_expression(object_expr, start, precedence)?));
+        }
+        if tok.kind().is_open_bracket() {
+            self.log_debug("try_parse_expression_with() HANDLE ARRAY LITERAL");
+            let array_expr = self.parse_array_literal()?;
+            return Ok(Some(self.parse_rest_of_expression(array_expr, start, precedence)?));
+        }
+        if tok.kind().is_open_paren() {
+            self.log_debug("try_parse_expression_with() HANDLE PARENTHESIZED EXPR");
+            // Grouping leaves no node in the tree: the inner expression is
+            // itself a valid operand, call target or assignment target.
+            let inner_expr = self.parse_expression_allowing_in(Precedence::lowest())?;
+            self.must_expect_token(TokenKind::close_paren())?;
+            return Ok(Some(self.parse_rest_of_expression(inner_expr, start, precedence)?));
         }
         if tok.kind().is_unary_op() {
             self.log_debug("try_parse_expression_with() HANDLE UNARY OP");
+            let sub_start = self.peek_token_start()?;
             let sub_expr = self.parse_expression(Precedence::unary())?;
+            if tok.kind().is_plus_plus() || tok.kind().is_minus_minus() {
+                let sub_location = self.location_from(sub_start);
+                self.check_assignment_target(sub_expr.as_ref(), &sub_location)?;
+            }
+            if self.strict && tok.kind().is_delete_keyword() && sub_expr.identifier().is_some() {
+                return Err(ParseError::StrictModeDeleteIdentifier(tok.location().clone()));
+            }
             let unary_expr = Box::new(ast::UnaryOpExprNode::new(tok, sub_expr));
-            return Ok(Some(self.parse_rest_of_expression(unary_expr, precedence)?));
+            return Ok(Some(self.parse_rest_of_expression(unary_expr, start, precedence)?));
+        }
+        if tok.kind().is_function_keyword() {
+            self.log_debug("try_parse_expression_with() HANDLE FUNCTION");
+            let name_tok = self.expect_get_token(TokenKind::identifier())?;
+            let func = self.parse_function_rest(name_tok)?;
+            let func_expr = Box::new(ast::FunctionExprNode::new(func));
+            return Ok(Some(self.parse_rest_of_expression(func_expr, start, precedence)?));
         }
         if tok.kind().is_new_keyword() {
             self.log_debug("try_parse_expression_with() HANDLE NEW");
@@ -308,7 +1043,7 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
                     // expression.
                     match self.try_parse_expression_with(next_tok, Precedence::member())? {
                         Some(member_expr) => {
-                            return Ok(Some(self.parse_new_tail(new_count, member_expr, precedence)?));
+                            return Ok(Some(self.parse_new_tail(new_count, member_expr, start, precedence)?));
                         }
                         None => {
                             return Err(ParseError::ExpectedExpression);
@@ -321,13 +1056,110 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         Ok(None)
     }
 
+    fn parse_object_literal(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        let mut properties: Vec<ast::PropertyDefinition> = Vec::new();
+        let mut seen: Vec<(PropertyKey, PropertyKind)> = Vec::new();
+        loop {
+            // A trailing comma may precede the closing brace.
+            if self.expect_token(TokenKind::close_brace())? {
+                break;
+            }
+
+            let mut name_tok = self.next_token()?;
+            if ! is_property_name(&name_tok) {
+                return Err(ParseError::ExpectedPropertyName);
+            }
+            let is_getter = name_tok.is_identifier_named("get");
+            let is_setter = name_tok.is_identifier_named("set");
+            let definition = if (is_getter || is_setter) && ! self.expect_token(TokenKind::colon())? {
+                name_tok = self.next_token()?;
+                if ! is_property_name(&name_tok) {
+                    return Err(ParseError::ExpectedPropertyName);
+                }
+                let func = self.parse_function_rest(None)?;
+                if func.params().len() != (if is_getter { 0 } else { 1 }) {
+                    return Err(ParseError::InvalidAccessorParameters(name_tok.location().clone()));
+                }
+                if is_getter {
+                    ast::PropertyDefinitionKind::Getter(func)
+                } else {
+                    ast::PropertyDefinitionKind::Setter(func)
+                }
+            } else {
+                if ! (is_getter || is_setter) {
+                    self.must_expect_token(TokenKind::colon())?;
+                }
+                ast::PropertyDefinitionKind::Init(self.parse_expression_allowing_in(Precedence::assignment())?)
+            };
+
+            // Data properties can't share a name with accessors, or with each
+            // other in strict code.  Accessors can't be defined twice.
+            let key = property_key(&name_tok);
+            let kind = match definition {
+                ast::PropertyDefinitionKind::Init(_) => PropertyKind::Data,
+                ast::PropertyDefinitionKind::Getter(_) => PropertyKind::Getter,
+                ast::PropertyDefinitionKind::Setter(_) => PropertyKind::Setter
+            };
+            for &(ref prev_key, prev_kind) in &seen {
+                if *prev_key != key {
+                    continue;
+                }
+                let conflicts = match (prev_kind, kind) {
+                    (PropertyKind::Data, PropertyKind::Data) => self.strict,
+                    (PropertyKind::Getter, PropertyKind::Setter) |
+                    (PropertyKind::Setter, PropertyKind::Getter) => false,
+                    _ => true
+                };
+                if conflicts {
+                    return Err(ParseError::DuplicateProperty(name_tok.location().clone()));
+                }
+            }
+            seen.push((key, kind));
+            properties.push(ast::PropertyDefinition::new(name_tok, definition));
+
+            let next_tok = self.next_token()?;
+            if next_tok.kind().is_close_brace() {
+                break;
+            }
+            if ! next_tok.kind().is_comma() {
+                return Err(ParseError::ExpectedCommaOrCloseBrace);
+            }
+        }
+        Ok(Box::new(ast::ObjectLiteralExprNode::new(properties)))
+    }
+
+    fn parse_array_literal(&mut self) -> ParseResult<Box<dyn AstNode>> {
+        let mut elements: Vec<Option<Box<dyn AstNode>>> = Vec::new();
+        loop {
+            if self.expect_token(TokenKind::close_bracket())? {
+                break;
+            }
+            // A comma with no element before it is an elision.  A comma after
+            // the last element isn't.
+            if self.expect_token(TokenKind::comma())? {
+                elements.push(None);
+                continue;
+            }
+            elements.push(Some(self.parse_expression_allowing_in(Precedence::assignment())?));
+
+            let next_tok = self.next_token()?;
+            if next_tok.kind().is_close_bracket() {
+                break;
+            }
+            if ! next_tok.kind().is_comma() {
+                return Err(ParseError::ExpectedCommaOrCloseBracket);
+            }
+        }
+        Ok(Box::new(ast::ArrayLiteralExprNode::new(elements)))
+    }
+
     fn parse_new_tail(&mut self, new_count: usize, member_expr: Box<AstNode>,
-                      precedence: Precedence)
+                      start: StreamPosition, precedence: Precedence)
         -> ParseResult<Box<AstNode>>
     {
         assert!(precedence <= Precedence::left_hand_side());
         let mut cur_expr: Box<AstNode> = member_expr;
-        let mut cur_new_count: usize = new_count;
+        let mut cur_new_count: usize = 0;
         loop {
             // Check for following "(", up to new_count.
             let position = self.mark_position();
@@ -338,18 +1170,19 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             }
             let mut args_vec = Vec::with_capacity(2);
             self.parse_arguments_list(&mut args_vec)?;
-            self.must_expect_token(TokenKind::close_paren())?;
             cur_expr = Box::new(ast::ConstructExprNode::new_with_arguments(cur_expr, args_vec));
             cur_new_count += 1;
             if cur_new_count == new_count {
                 break;
             }
+            // The member expression of the next 'new' may continue.
+            cur_expr = self.parse_rest_of_expression(cur_expr, start, Precedence::member())?;
         }
 
         if cur_new_count == new_count {
             // If all the 'new's matched with arguments, then we have a MemberExpr
             // that we just parsed.  Finish the parse with call expr precedence.
-            cur_expr = self.parse_rest_of_expression(cur_expr, Precedence::call())?;
+            cur_expr = self.parse_rest_of_expression(cur_expr, start, Precedence::call())?;
         } else {
             // Wrap up the remaining "bare" new expressions.
             while cur_new_count < new_count {
@@ -359,7 +1192,7 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         }
 
         // Parse the rest of the expression with the given precedence.
-        self.parse_rest_of_expression(cur_expr, precedence)
+        self.parse_rest_of_expression(cur_expr, start, precedence)
     }
 
     fn parse_arguments_list(&mut self, args_vec: &mut Vec<Box<AstNode>>) -> ParseResult<()> {
@@ -372,7 +1205,7 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
 
         // Otherwise, parse argument expressions.
         loop {
-            args_vec.push(self.parse_expression(Precedence::assignment())?);
+            args_vec.push(self.parse_expression_allowing_in(Precedence::assignment())?);
             let next_tok = self.next_token()?;
             if next_tok.kind().is_close_paren() {
                 break;
@@ -384,7 +1217,12 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         Ok(())
     }
 
-    fn parse_rest_of_expression(&mut self, left_expr: Box<AstNode>, precedence: Precedence)
+    /**
+     * Parse the operators and operands following the left operand of an
+     * expression, which begins at `start`.
+     */
+    fn parse_rest_of_expression(&mut self, left_expr: Box<dyn AstNode>, start: StreamPosition,
+                                precedence: Precedence)
         -> ParseResult<Box<AstNode>>
     {
         assert!(left_expr.is_expression());
@@ -408,9 +1246,6 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             }
 
             if tok.kind().is_assignment_op() {
-                // FIXME: Check that cur_expr is a valid lvalue expression.
-                // Return syntax error if not.
-
                 // Assignment associates right-to-left, so we use '>' for precedence
                 // instead of '>='.
                 if precedence > Precedence::assignment() {
@@ -418,6 +1253,9 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
                     return Ok(cur_expr);
                 }
 
+                let target_location = TokenLocation::new(start, position.last_token_end);
+                self.check_assignment_target(cur_expr.as_ref(), &target_location)?;
+
                 let right_expr = self.parse_expression(Precedence::assignment())?;
                 cur_expr = Box::new(ast::AssignExprNode::new(tok, cur_expr, right_expr));
                 continue;
@@ -429,7 +1267,7 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
                     return Ok(cur_expr);
                 }
 
-                let if_expr = self.parse_expression(Precedence::assignment())?;
+                let if_expr = self.parse_expression_allowing_in(Precedence::assignment())?;
                 self.must_expect_token(TokenKind::colon())?;
                 let else_expr = self.parse_expression(Precedence::assignment())?;
                 cur_expr = Box::new(ast::CondExprNode::new(cur_expr, if_expr, else_expr));
@@ -503,7 +1341,7 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             }
 
             if tok.kind().is_relational_op() {
-                if precedence >= Precedence::relational() {
+                if precedence >= Precedence::relational() || (self.no_in && tok.kind().is_in_keyword()) {
                     self.rewind_position(position);
                     return Ok(cur_expr);
                 }
@@ -554,8 +1392,8 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
                     return Ok(cur_expr);
                 }
 
-                // FIXME: Check that cur_expr is a proper LVALUE expression.
-
+                let target_location = TokenLocation::new(start, position.last_token_end);
+                self.check_assignment_target(cur_expr.as_ref(), &target_location)?;
                 cur_expr = Box::new(ast::PostfixOpExprNode::new(tok, cur_expr));
                 continue;
             }
@@ -563,7 +1401,13 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             if tok.kind().is_dot() {
                 // We should only ever see "dot" with precedence levels <= member.
                 assert!(precedence <= Precedence::member());
-                let name_tok = self.must_expect_get_token(TokenKind::identifier())?;
+                let name_tok = self.next_token()?;
+                if ! name_tok.kind().is_identifier_name() {
+                    return Err(ParseError::UnexpectedToken {
+                        expected: TokenKind::identifier(),
+                        got: name_tok.kind()
+                    });
+                }
                 cur_expr = Box::new(ast::PropertyExprNode::new(cur_expr, name_tok));
                 continue;
             }
@@ -571,16 +1415,20 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
             if tok.kind().is_open_bracket() {
                 // We should only ever see "[]" with precedence levels <= member.
                 assert!(precedence <= Precedence::member());
-                let rest_expr = self.parse_expression(Precedence::lowest())?;
+                let rest_expr = self.parse_expression_allowing_in(Precedence::lowest())?;
                 self.must_expect_token(TokenKind::close_bracket())?;
                 cur_expr = Box::new(ast::ElementExprNode::new(cur_expr, rest_expr));
                 continue;
             }
 
             if tok.kind().is_open_paren() {
-                // We should only ever see "()" with precedence levels <= member.
+                // A member expression under 'new' ends before its arguments,
+                // which belong to the 'new'.
                 self.log_debug("parse_rest_of_expression() HANDLE OPEN PAREN");
-                assert!(precedence <= Precedence::member());
+                if precedence > Precedence::call() {
+                    self.rewind_position(position);
+                    return Ok(cur_expr);
+                }
                 let mut args_vec = Vec::with_capacity(2);
                 self.parse_arguments_list(&mut args_vec)?;
                 cur_expr = Box::new(ast::CallExprNode::new(cur_expr, args_vec));
@@ -596,24 +1444,64 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         Ok(cur_expr)
     }
 
-    fn must_expect_token(&mut self, kind: TokenKind) -> ParseResult<()> {
-        // Mark the position so we can backtrack.
-        let position = self.mark_position();
-        let token = self.next_token()?;
-        if token.kind() == kind {
-            Ok(())
-        } else {
-            self.rewind_position(position);
-            Err(ParseError::UnexpectedToken {expected: kind, got: token.kind()})
+    /**
+     * Early error check for the left-hand side of assignments, compound assignments,
+     * prefix/postfix increments and decrements, and for-in heads.  The location is
+     * that of the target expression itself.
+     */
+    fn check_assignment_target(&self, target_expr: &dyn AstNode, location: &TokenLocation)
+        -> ParseResult<()>
+    {
+        if ! target_expr.is_assignment_target() {
+            return Err(ParseError::InvalidAssignmentTarget(location.clone()));
+        }
+        if self.strict {
+            if let Some(ident_tok) = target_expr.identifier() {
+                if is_eval_or_arguments(ident_tok) {
+                    return Err(ParseError::StrictModeEvalOrArguments(ident_tok.location().clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /** Strict mode restrictions on identifiers used as references. */
+    fn check_strict_identifier(&self, ident_tok: &FullToken) -> ParseResult<()> {
+        if is_strict_reserved_word(ident_tok) {
+            return Err(ParseError::StrictModeReservedWord(ident_tok.location().clone()));
+        }
+        Ok(())
+    }
+
+    /**
+     * Strict mode restrictions on identifiers being declared: variable, function
+     * and parameter names.
+     */
+    fn check_strict_binding_identifier(&self, ident_tok: &FullToken) -> ParseResult<()> {
+        self.check_strict_identifier(ident_tok)?;
+        if is_eval_or_arguments(ident_tok) {
+            return Err(ParseError::StrictModeEvalOrArguments(ident_tok.location().clone()));
         }
+        Ok(())
     }
 
-    fn must_expect_get_token(&mut self, kind: TokenKind) -> ParseResult<FullToken> {
+    /** Strict mode restrictions on octal literals and octal escapes in strings. */
+    fn check_strict_token(&self, tok: &FullToken) -> ParseResult<()> {
+        if tok.kind().is_oct_integer_literal() {
+            return Err(ParseError::StrictModeOctalLiteral(tok.location().clone()));
+        }
+        if tok.kind().is_string_literal() && tok.text().is_some_and(has_octal_escape) {
+            return Err(ParseError::StrictModeOctalEscape(tok.location().clone()));
+        }
+        Ok(())
+    }
+
+    fn must_expect_token(&mut self, kind: TokenKind) -> ParseResult<()> {
         // Mark the position so we can backtrack.
         let position = self.mark_position();
         let token = self.next_token()?;
         if token.kind() == kind {
-            Ok(token)
+            Ok(())
         } else {
             self.rewind_position(position);
             Err(ParseError::UnexpectedToken {expected: kind, got: token.kind()})
@@ -643,39 +1531,91 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
         }
     }
 
-    fn mark_position(&mut self) -> TokenizerPosition {
-        self.tokenizer.mark_position()
+    fn mark_position(&mut self) -> ParserPosition {
+        ParserPosition {
+            tokenizer_position: self.tokenizer.mark_position(),
+            after_newline: self.after_newline,
+            last_token_end: self.last_token_end
+        }
     }
-    fn rewind_position(&mut self, position: TokenizerPosition) {
-        self.tokenizer.rewind_position(position)
+    fn rewind_position(&mut self, position: ParserPosition) {
+        self.tokenizer.rewind_position(position.tokenizer_position);
+        self.after_newline = position.after_newline;
+        self.last_token_end = position.last_token_end;
     }
 
     fn next_token(&mut self) -> ParseResult<FullToken> {
         self.next_token_impl(/* check_kw = */ true, /* want_newlines = */ false)
     }
-    fn next_token_no_keywords(&mut self) -> ParseResult<FullToken> {
-        self.next_token_impl(/* check_kw = */ true, /* want_newlines = */ false)
-    }
     fn next_token_keep_newline(&mut self) -> ParseResult<FullToken> {
         self.next_token_impl(/* check_kw = */ true, /* want_newlines = */ true)
     }
 
+    // A slash where an expression starts begins a regular expression
+    // literal, which the tokenizer re-reads from the slash.
+    fn read_regexp_literal(&mut self, slash_tok: &FullToken) -> ParseResult<FullToken> {
+        let mut token = self.tokenizer.read_regexp_literal(slash_tok.location());
+        if token.kind().is_error() {
+            return Err(ParseError::ErrorToken(token));
+        }
+        let text = self.tokenizer.extract_text(token.location());
+        token.set_text(text);
+        self.after_newline = false;
+        self.last_token_end = token.end_offset();
+        Ok(token)
+    }
+
+    // The location from a start position to the end of the last token read.
+    fn location_from(&self, start: StreamPosition) -> TokenLocation {
+        TokenLocation::new(start, self.last_token_end)
+    }
+
+    fn peek_token_start(&mut self) -> ParseResult<StreamPosition> {
+        let position = self.mark_position();
+        let token = self.next_token()?;
+        self.rewind_position(position);
+        Ok(token.start_offset())
+    }
+
     fn next_token_impl(&mut self, check_kw: bool, want_newlines: bool) -> ParseResult<FullToken> {
         self.skipped_newline = false;
+        let mut after_newline = self.after_newline;
         loop {
-            let token = self.tokenizer.next_token(/* check_kw = */ true);
+            let mut token = self.tokenizer.next_token(/* check_kw = */ true);
             let kind = token.kind();
             // Ignore whitespace and comment and newline tokens.
-            if kind.is_whitespace() || kind.is_comment() {
+            if kind.is_whitespace() {
+                continue;
+            }
+            if kind.is_comment() {
+                if self.collect_comments {
+                    // Line comments include their terminating newline.
+                    let kind = self.note_comment(&token, after_newline);
+                    after_newline = after_newline || (kind == CommentKind::Line);
+                }
                 continue;
             }
+            if kind.is_newline() {
+                after_newline = true;
+            }
             if !want_newlines && kind.is_newline() {
                 self.skipped_newline = true;
                 continue;
             }
+            self.after_newline = kind.is_newline();
+            if ! kind.is_newline() {
+                self.last_token_end = token.end_offset();
+            }
             if kind.is_error() {
                 return Err(ParseError::ErrorToken(token));
             }
+            if kind.is_identifier_or_literal() || kind.is_keyword() {
+                let text = self.tokenizer.extract_text(token.location());
+                token.set_text(text);
+            }
+            if self.strict {
+                self.check_strict_token(&token)?;
+            }
             let kw_str = if check_kw { "kw" } else { "no-kw" };
             let nl_str = if want_newlines { "nl" } else { "no-nl" };
             self.log_debug(format!("next_token({}, {}): {}", kw_str, nl_str, token.token_string()));
@@ -684,6 +1624,91 @@ impl<STREAM: InputStream> AstBuilder<STREAM> {
     }
 
     fn log_debug<'a, STR: Borrow<str>>(&self, str: STR) {
-        println!("DEBUG: {}", str.borrow());
+        if self.log_debug {
+            println!("DEBUG: {}", str.borrow());
+        }
+    }
+}
+
+fn is_use_strict_directive(tok: &FullToken) -> bool {
+    // The directive must be exactly "use strict" or 'use strict', without
+    // escapes or line continuations.
+    match tok.text() {
+        Some(text) => (text == "\"use strict\"") || (text == "'use strict'"),
+        None => false
+    }
+}
+
+fn is_eval_or_arguments(tok: &FullToken) -> bool {
+    tok.is_identifier_named("eval") || tok.is_identifier_named("arguments")
+}
+
+fn is_strict_reserved_word(tok: &FullToken) -> bool {
+    const STRICT_RESERVED_WORDS: [&str; 9] = [
+        "implements", "interface", "let", "package", "private",
+        "protected", "public", "static", "yield"
+    ];
+    STRICT_RESERVED_WORDS.iter().any(|word| tok.is_identifier_named(word))
+}
+
+fn is_property_name(tok: &FullToken) -> bool {
+    let kind = tok.kind();
+    kind.is_identifier_name() || kind.is_string_literal() || kind.is_numeric_literal()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PropertyKind {
+    Data,
+    Getter,
+    Setter
+}
+
+/**
+ * A property name, normalized for detecting duplicates.  Numeric names are
+ * compared by value, and other names by their raw source text, with the
+ * quotes of string literals removed.  So names spelled differently, with
+ * escapes or once as a number and once as a string, aren't found to be
+ * duplicates.
+ */
+#[derive(Debug, PartialEq)]
+enum PropertyKey {
+    Name(String),
+    Number(f64)
+}
+
+fn property_key(tok: &FullToken) -> PropertyKey {
+    let text = tok.text().unwrap();
+    let kind = tok.kind();
+    if kind.is_string_literal() {
+        PropertyKey::Name(text[1..(text.len() - 1)].to_string())
+    } else if kind.is_hex_integer_literal() {
+        PropertyKey::Number(u64::from_str_radix(&text[2..], 16).map_or(f64::INFINITY, |v| v as f64))
+    } else if kind.is_oct_integer_literal() {
+        PropertyKey::Number(u64::from_str_radix(&text[1..], 8).map_or(f64::INFINITY, |v| v as f64))
+    } else if kind.is_numeric_literal() {
+        PropertyKey::Number(text.parse::<f64>().unwrap_or(f64::NAN))
+    } else {
+        PropertyKey::Name(text.to_string())
+    }
+}
+
+fn has_octal_escape(text: &str) -> bool {
+    // Octal escapes are '\1' through '\7', or '\0' followed by a digit.
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        if bytes[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+        let escaped = bytes[i + 1];
+        if (b'1'..=b'7').contains(&escaped) {
+            return true;
+        }
+        if (escaped == b'0') && bytes.get(i + 2).is_some_and(|b| b.is_ascii_digit()) {
+            return true;
+        }
+        i += 2;
     }
+    false
 }
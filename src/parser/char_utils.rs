@@ -20,6 +20,11 @@ impl AsciiChar {
     pub fn end() -> AsciiChar {
         AsciiChar(-1)
     }
+    // Stands in for a non-ascii code unit read from a stream with code units
+    // wider than 8 bits.
+    pub fn non_ascii() -> AsciiChar {
+        AsciiChar(0xFF)
+    }
     pub fn is_valid(self) -> bool {
         (self.0 == -1) || (self.0 <= 0xFF)
     }
@@ -89,8 +94,15 @@ impl AsciiChar {
 static SURROGATE_PAIR_A_START : i32 = 0xD800;
 static SURROGATE_PAIR_A_END : i32 = 0xDBFF;
 
-static SURROGATE_PAIR_B_START : i32 = 0xD800;
-static SURROGATE_PAIR_B_END : i32 = 0xDBFF;
+static SURROGATE_PAIR_B_START : i32 = 0xDC00;
+static SURROGATE_PAIR_B_END : i32 = 0xDFFF;
+
+pub fn is_surrogate_pair_a(unit: u16) -> bool {
+    ((unit as i32) >= SURROGATE_PAIR_A_START) && ((unit as i32) <= SURROGATE_PAIR_A_END)
+}
+pub fn is_surrogate_pair_b(unit: u16) -> bool {
+    ((unit as i32) >= SURROGATE_PAIR_B_START) && ((unit as i32) <= SURROGATE_PAIR_B_END)
+}
 
 impl NonAsciiChar {
     pub fn new(code: i32) -> NonAsciiChar {
@@ -102,10 +114,15 @@ impl NonAsciiChar {
     pub fn end() -> NonAsciiChar {
         NonAsciiChar(-1)
     }
+    pub fn from_surrogate_pair(unit_a: u16, unit_b: u16) -> NonAsciiChar {
+        assert!(is_surrogate_pair_a(unit_a) && is_surrogate_pair_b(unit_b));
+        let high = (unit_a as i32) - SURROGATE_PAIR_A_START;
+        let low = (unit_b as i32) - SURROGATE_PAIR_B_START;
+        NonAsciiChar(0x10000 + ((high << 10) | low))
+    }
 
+    // Unpaired surrogates from UTF-16 input are valid chars.
     pub fn is_valid(self) -> bool {
-        // Note: to be proper, this method should make sure that
-        // self.0 does not fall within the surrogate pair codespace.
         (self.0 == -2) || (self.0 == -1) || (self.0 < 0x10FFFF)
     }
 
@@ -115,4 +132,27 @@ impl NonAsciiChar {
     pub fn is_error(self) -> bool {
         self.0 == -2
     }
+
+    pub fn code_point(self) -> u32 {
+        assert!(self.0 >= 0);
+        self.0 as u32
+    }
+
+    // The number of code units needed to encode this char in UTF-8 and UTF-16.
+    pub fn utf8_length(self) -> u32 {
+        match self.code_point() {
+            0..=0x7F => 1,
+            0x80..=0x7FF => 2,
+            0x800..=0xFFFF => 3,
+            _ => 4
+        }
+    }
+    pub fn utf16_length(self) -> u32 {
+        if self.code_point() >= 0x10000 { 2 } else { 1 }
+    }
+
+    // LINE SEPARATOR and PARAGRAPH SEPARATOR terminate lines like LF and CR.
+    pub fn is_line_terminator(self) -> bool {
+        (self.0 == 0x2028) || (self.0 == 0x2029)
+    }
 }
@@ -1,7 +1,14 @@
 
-use parser::char_utils::{AsciiChar, NonAsciiChar};
+use parser::char_utils::{self, AsciiChar, NonAsciiChar};
+use std::io;
 use std::slice;
 
+/**
+ * A position in an input stream, counted in code units of the stream's
+ * encoding: bytes for UTF-8 streams, and 16-bit units for UTF-16 streams.
+ * Source text between two positions is only ever extracted by the stream
+ * itself, so locations mean the same thing whichever encoding is used.
+ */
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StreamPosition(u32);
 impl StreamPosition {
@@ -28,7 +35,8 @@ pub trait InputStream {
     fn read_ascii(&mut self) -> AsciiChar;
     fn unread_ascii(&mut self, ch: AsciiChar);
 
-    // Read/unread the next unicode char.
+    // Read/unread the next unicode char.  A char read as non-ascii must be
+    // unread before it can be read as unicode.
     fn read_non_ascii(&mut self) -> NonAsciiChar;
     fn unread_non_ascii(&mut self, ch: NonAsciiChar);
 
@@ -36,8 +44,16 @@ pub trait InputStream {
     fn mark(&self) -> StreamPosition;
     fn rewind(&mut self, posn: StreamPosition);
 
+    // Pin the start of the retained window.  The caller will no longer rewind to,
+    // or inspect the text of, any position before it, so streams which don't
+    // hold their entire input may discard it.
+    fn pin_window_start(&mut self, _posn: StreamPosition) {}
+
     // Check for the given ascii text at the given position.
     fn check_ascii_text(&self, text: &[char], posn: StreamPosition) -> bool;
+
+    // Extract the source text between two positions.
+    fn extract_text(&self, start: StreamPosition, end: StreamPosition) -> String;
 }
 
 pub struct VecInputStream {
@@ -94,11 +110,20 @@ impl InputStream for VecInputStream {
     }
 
     fn read_non_ascii(&mut self) -> NonAsciiChar {
-        panic!("Reading non-ascii unicode chars not yet supported!");
+        let remaining = (self.end_offset() - self.current_offset()) as usize;
+        let bytes = unsafe { slice::from_raw_parts(self.data_cur, remaining) };
+        let (ch, length) = decode_utf8(bytes);
+        self.data_cur = unsafe { self.data_cur.add(length) };
+        ch
     }
 
     fn unread_non_ascii(&mut self, ch: NonAsciiChar) {
-        panic!("Reading non-ascii unicode chars not yet supported!");
+        assert!(! ch.is_error());
+        if ! ch.is_end() {
+            let length = ch.utf8_length();
+            assert!(length <= self.current_offset());
+            self.data_cur = unsafe { self.data_cur.offset(-(length as isize)) };
+        }
     }
 
     fn mark(&self) -> StreamPosition {
@@ -124,4 +149,331 @@ impl InputStream for VecInputStream {
         }
         true
     }
+
+    fn extract_text(&self, start: StreamPosition, end: StreamPosition) -> String {
+        assert!(start <= end);
+        assert!(self.is_valid_position(end));
+        let bytes = &self.data[(start.0 as usize)..(end.0 as usize)];
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+/**
+ * An input stream which reads its input incrementally from an io::Read, in
+ * chunks.  Only the window of input from the pinned window start onwards is
+ * retained, so memory use is bounded by the span the parser may still rewind
+ * over, rather than by the size of the input.
+ *
+ * Read errors end the stream.  They can be retrieved with `read_error`.
+ */
+pub struct ReadInputStream<R: io::Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    buffer_start: u32,
+    cursor: usize,
+    window_start: u32,
+    reader_done: bool,
+    read_error: Option<io::Error>
+}
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+impl<R: io::Read> ReadInputStream<R> {
+    pub fn new(reader: R) -> ReadInputStream<R> {
+        ReadInputStream {
+            reader,
+            buffer: Vec::with_capacity(READ_CHUNK_SIZE),
+            buffer_start: 0,
+            cursor: 0,
+            window_start: 0,
+            reader_done: false,
+            read_error: None
+        }
+    }
+
+    pub fn read_error(&self) -> Option<&io::Error> {
+        self.read_error.as_ref()
+    }
+
+    pub fn current_offset(&self) -> u32 {
+        self.buffer_start + (self.cursor as u32)
+    }
+    pub fn buffered_end_offset(&self) -> u32 {
+        self.buffer_start + (self.buffer.len() as u32)
+    }
+    pub fn is_retained_position(&self, posn: StreamPosition) -> bool {
+        (posn.0 >= self.buffer_start) && (posn.0 <= self.buffered_end_offset())
+    }
+
+    // Read another chunk of input onto the end of the buffer, discarding the
+    // input before the window start first.  Returns false at the end of input.
+    fn fill_buffer(&mut self) -> bool {
+        if self.reader_done {
+            return false;
+        }
+
+        let discard = (self.window_start - self.buffer_start) as usize;
+        if discard >= READ_CHUNK_SIZE {
+            self.buffer.drain(..discard);
+            self.buffer_start = self.window_start;
+            self.cursor -= discard;
+        }
+
+        let old_len = self.buffer.len();
+        self.buffer.resize(old_len + READ_CHUNK_SIZE, 0);
+        loop {
+            match self.reader.read(&mut self.buffer[old_len..]) {
+                Ok(count) => {
+                    self.buffer.truncate(old_len + count);
+                    if count == 0 {
+                        self.reader_done = true;
+                    }
+                    return count > 0;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {
+                    continue;
+                }
+                Err(err) => {
+                    self.buffer.truncate(old_len);
+                    self.reader_done = true;
+                    self.read_error = Some(err);
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn retained_slice(&self, start: StreamPosition, end: StreamPosition) -> &[u8] {
+        assert!(start <= end);
+        assert!(self.is_retained_position(start) && self.is_retained_position(end));
+        let start_idx = (start.0 - self.buffer_start) as usize;
+        let end_idx = (end.0 - self.buffer_start) as usize;
+        &self.buffer[start_idx..end_idx]
+    }
+}
+impl<R: io::Read> InputStream for ReadInputStream<R> {
+    fn read_ascii(&mut self) -> AsciiChar {
+        if (self.cursor == self.buffer.len()) && ! self.fill_buffer() {
+            return AsciiChar::end();
+        }
+        let ch = self.buffer[self.cursor];
+        self.cursor += 1;
+        AsciiChar::new(ch)
+    }
+
+    fn unread_ascii(&mut self, ch: AsciiChar) {
+        assert!(ch.is_valid());
+        if ! ch.is_end() {
+            assert!(self.cursor > 0);
+            self.cursor -= 1;
+        }
+    }
+
+    fn read_non_ascii(&mut self) -> NonAsciiChar {
+        // Make sure the longest possible encoding is buffered.
+        while (self.buffer.len() - self.cursor) < 4 {
+            if ! self.fill_buffer() {
+                break;
+            }
+        }
+        let (ch, length) = decode_utf8(&self.buffer[self.cursor..]);
+        self.cursor += length;
+        ch
+    }
+
+    fn unread_non_ascii(&mut self, ch: NonAsciiChar) {
+        assert!(! ch.is_error());
+        if ! ch.is_end() {
+            let length = ch.utf8_length() as usize;
+            assert!(length <= self.cursor);
+            self.cursor -= length;
+        }
+    }
+
+    fn mark(&self) -> StreamPosition {
+        StreamPosition(self.current_offset())
+    }
+    fn rewind(&mut self, posn: StreamPosition) {
+        assert!(posn.0 >= self.window_start);
+        assert!(posn.0 <= self.current_offset());
+        self.cursor = (posn.0 - self.buffer_start) as usize;
+    }
+
+    fn pin_window_start(&mut self, posn: StreamPosition) {
+        assert!(posn.0 >= self.window_start);
+        assert!(posn.0 <= self.current_offset());
+        self.window_start = posn.0;
+    }
+
+    fn check_ascii_text(&self, text: &[char], posn: StreamPosition) -> bool {
+        let bytes = self.retained_slice(posn, posn.offset_forward(text.len() as u32));
+        bytes.iter().zip(text).all(|(b, c)| {
+            assert!(*b < 0x80);
+            *b == (*c as u8)
+        })
+    }
+
+    fn extract_text(&self, start: StreamPosition, end: StreamPosition) -> String {
+        String::from_utf8_lossy(self.retained_slice(start, end)).into_owned()
+    }
+}
+
+/**
+ * An input stream over UTF-16 source text, such as the contents of a string
+ * already held by the engine.  Positions count 16-bit code units.
+ *
+ * Engine strings may hold unpaired surrogates, which are read as chars of
+ * their own.  They're only allowed where any char is, in string literals,
+ * regular expressions and comments.  As extracted text can't hold them, they
+ * are written there as `\u` escapes, which mean the same code unit.
+ */
+pub struct Utf16InputStream<'a> {
+    data: &'a [u16],
+    cursor: usize
+}
+
+impl<'a> Utf16InputStream<'a> {
+    pub fn new(data: &'a [u16]) -> Utf16InputStream<'a> {
+        assert!(data.len() <= (u32::MAX as usize));
+        Utf16InputStream { data, cursor: 0 }
+    }
+
+    pub fn current_offset(&self) -> u32 {
+        self.cursor as u32
+    }
+    pub fn end_offset(&self) -> u32 {
+        self.data.len() as u32
+    }
+    pub fn is_valid_position(&self, posn: StreamPosition) -> bool {
+        posn.0 <= self.end_offset()
+    }
+    pub fn is_rewind_position(&self, posn: StreamPosition) -> bool {
+        posn.0 <= self.current_offset()
+    }
+}
+impl<'a> InputStream for Utf16InputStream<'a> {
+    fn read_ascii(&mut self) -> AsciiChar {
+        match self.data.get(self.cursor) {
+            Some(&unit) => {
+                self.cursor += 1;
+                if unit < 0x80 {
+                    AsciiChar::new(unit as u8)
+                } else {
+                    AsciiChar::non_ascii()
+                }
+            }
+            None => AsciiChar::end()
+        }
+    }
+
+    fn unread_ascii(&mut self, ch: AsciiChar) {
+        assert!(ch.is_valid());
+        if ! ch.is_end() {
+            assert!(self.cursor > 0);
+            self.cursor -= 1;
+        }
+    }
+
+    fn read_non_ascii(&mut self) -> NonAsciiChar {
+        let unit_a = match self.data.get(self.cursor) {
+            Some(&unit) => unit,
+            None => { return NonAsciiChar::end(); }
+        };
+        self.cursor += 1;
+
+        // A leading surrogate followed by a trailing one is a pair.
+        if char_utils::is_surrogate_pair_a(unit_a) {
+            if let Some(&unit_b) = self.data.get(self.cursor).filter(|&&u| char_utils::is_surrogate_pair_b(u)) {
+                self.cursor += 1;
+                return NonAsciiChar::from_surrogate_pair(unit_a, unit_b);
+            }
+        }
+        NonAsciiChar::new(unit_a as i32)
+    }
+
+    fn unread_non_ascii(&mut self, ch: NonAsciiChar) {
+        assert!(! ch.is_error());
+        if ! ch.is_end() {
+            let length = ch.utf16_length() as usize;
+            assert!(length <= self.cursor);
+            self.cursor -= length;
+        }
+    }
+
+    fn mark(&self) -> StreamPosition {
+        StreamPosition(self.current_offset())
+    }
+    fn rewind(&mut self, posn: StreamPosition) {
+        assert!(self.is_rewind_position(posn));
+        self.cursor = posn.0 as usize;
+    }
+
+    fn check_ascii_text(&self, text: &[char], posn: StreamPosition) -> bool {
+        let end = posn.offset_forward(text.len() as u32);
+        assert!(self.is_valid_position(end));
+        let units = &self.data[(posn.0 as usize)..(end.0 as usize)];
+        units.iter().zip(text).all(|(u, c)| {
+            assert!(*u < 0x80);
+            *u == (*c as u16)
+        })
+    }
+
+    fn extract_text(&self, start: StreamPosition, end: StreamPosition) -> String {
+        assert!(start <= end);
+        assert!(self.is_valid_position(end));
+        let mut text = String::with_capacity((end.0 - start.0) as usize);
+        let mut backslashes = 0;
+        for ch in char::decode_utf16(self.data[(start.0 as usize)..(end.0 as usize)].iter().cloned()) {
+            match ch {
+                Ok(ch) => {
+                    text.push(ch);
+                    backslashes = if ch == '\\' { backslashes + 1 } else { 0 };
+                }
+                // An escaped surrogate is already preceded by its backslash.
+                Err(err) => {
+                    let escape = if backslashes % 2 == 1 { "" } else { "\\" };
+                    text.push_str(&format!("{}u{:04x}", escape, err.unpaired_surrogate()));
+                    backslashes = 0;
+                }
+            }
+        }
+        text
+    }
+}
+
+/**
+ * Decode the UTF-8 encoded char at the start of `bytes`, returning it along
+ * with the number of bytes consumed.  Malformed sequences decode to an error
+ * char, consuming their first byte.
+ */
+fn decode_utf8(bytes: &[u8]) -> (NonAsciiChar, usize) {
+    let lead = match bytes.first() {
+        Some(&lead) => lead,
+        None => { return (NonAsciiChar::end(), 0); }
+    };
+
+    let (length, min_code, lead_bits) = match lead {
+        0x00..=0x7F => (1, 0, lead as u32),
+        0xC0..=0xDF => (2, 0x80, (lead & 0x1F) as u32),
+        0xE0..=0xEF => (3, 0x800, (lead & 0x0F) as u32),
+        0xF0..=0xF7 => (4, 0x10000, (lead & 0x07) as u32),
+        _ => { return (NonAsciiChar::error(), 1); }
+    };
+    if bytes.len() < length {
+        return (NonAsciiChar::error(), 1);
+    }
+
+    let mut code = lead_bits;
+    for &byte in &bytes[1..length] {
+        if (byte & 0xC0) != 0x80 {
+            return (NonAsciiChar::error(), 1);
+        }
+        code = (code << 6) | ((byte & 0x3F) as u32);
+    }
+
+    // Reject overlong encodings, surrogates, and chars beyond the unicode range.
+    if (code < min_code) || ((0xD800..=0xDFFF).contains(&code)) || (code > 0x10FFFF) {
+        return (NonAsciiChar::error(), 1);
+    }
+    (NonAsciiChar::new(code as i32), length)
 }
@@ -1,27 +1,15 @@
 
 // TODO: Use macros to make this file not suck so much.
 
-static mut MODULE_INITIALIZED: bool = false;
-pub fn initialize_module() {
-    unsafe {
-        assert!(!MODULE_INITIALIZED);
-        init_tok_strings();
-        MODULE_INITIALIZED = true;
-    }
-}
-
 /** The enum of all token kinds. */
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TokenKind(pub u8);
 impl TokenKind {
     pub fn name(&self) -> &'static str {
-        unsafe {
-            assert!(MODULE_INITIALIZED);
-            TOKEN_STRINGS[self.0 as usize]
-        }
+        TOKEN_STRINGS[self.0 as usize]
     }
 
-    pub fn error() -> TokenKind {
+    pub const fn error() -> TokenKind {
          TokenKind(TOK_ERROR.0)
     }
     pub fn is_error(&self) -> bool {
@@ -100,44 +88,51 @@ impl TokenKind {
         self.0 == TOK_STRING_LITERAL.0
     }
 
+    pub fn regexp_literal() -> TokenKind {
+        TokenKind(TOK_REGEXP_LITERAL.0)
+    }
+    pub fn is_regexp_literal(&self) -> bool {
+        self.0 == TOK_REGEXP_LITERAL.0
+    }
+
 
 
-    pub fn open_paren() -> TokenKind {
+    pub const fn open_paren() -> TokenKind {
         TokenKind(TOK_OPEN_PAREN.0)
     }
     pub fn is_open_paren(&self) -> bool {
         self.0 == TOK_OPEN_PAREN.0
     }
 
-    pub fn close_paren() -> TokenKind {
+    pub const fn close_paren() -> TokenKind {
         TokenKind(TOK_CLOSE_PAREN.0)
     }
     pub fn is_close_paren(&self) -> bool {
         self.0 == TOK_CLOSE_PAREN.0
     }
 
-    pub fn open_bracket() -> TokenKind {
+    pub const fn open_bracket() -> TokenKind {
         TokenKind(TOK_OPEN_BRACKET.0)
     }
     pub fn is_open_bracket(&self) -> bool {
         self.0 == TOK_OPEN_BRACKET.0
     }
 
-    pub fn close_bracket() -> TokenKind {
+    pub const fn close_bracket() -> TokenKind {
         TokenKind(TOK_CLOSE_BRACKET.0)
     }
     pub fn is_close_bracket(&self) -> bool {
         self.0 == TOK_CLOSE_BRACKET.0
     }
 
-    pub fn open_brace() -> TokenKind {
+    pub const fn open_brace() -> TokenKind {
         TokenKind(TOK_OPEN_BRACE.0)
     }
     pub fn is_open_brace(&self) -> bool {
         self.0 == TOK_OPEN_BRACE.0
     }
 
-    pub fn close_brace() -> TokenKind {
+    pub const fn close_brace() -> TokenKind {
         TokenKind(TOK_CLOSE_BRACE.0)
     }
     pub fn is_close_brace(&self) -> bool {
@@ -152,28 +147,28 @@ impl TokenKind {
         self.0 == TOK_DOT.0
     }
 
-    pub fn semicolon() -> TokenKind {
+    pub const fn semicolon() -> TokenKind {
         TokenKind(TOK_SEMICOLON.0)
     }
     pub fn is_semicolon(&self) -> bool {
         self.0 == TOK_SEMICOLON.0
     }
 
-    pub fn comma() -> TokenKind {
+    pub const fn comma() -> TokenKind {
         TokenKind(TOK_COMMA.0)
     }
     pub fn is_comma(&self) -> bool {
         self.0 == TOK_COMMA.0
     }
 
-    pub fn question() -> TokenKind {
+    pub const fn question() -> TokenKind {
         TokenKind(TOK_QUESTION.0)
     }
     pub fn is_question(&self) -> bool {
         self.0 == TOK_QUESTION.0
     }
 
-    pub fn colon() -> TokenKind {
+    pub const fn colon() -> TokenKind {
         TokenKind(TOK_COLON.0)
     }
     pub fn is_colon(&self) -> bool {
@@ -243,11 +238,11 @@ impl TokenKind {
 
     pub fn is_relational_op(&self) -> bool {
         ((self.0 >= TOK_LESS.0) && (self.0 <= TOK_GREATER_EQUAL.0)) ||
-            (self.0 == TOK_INSTANCEOF_KEYWORD.0)
+            (self.0 == TOK_INSTANCEOF_KEYWORD.0) || (self.0 == TOK_IN_KEYWORD.0)
     }
 
 
-    pub fn tilde() -> TokenKind {
+    pub const fn tilde() -> TokenKind {
         TokenKind(TOK_TILDE.0)
     }
     pub fn is_tilde(&self) -> bool {
@@ -648,12 +643,33 @@ impl TokenKind {
         self.0 == TOK_WHILE_KEYWORD.0
     }
 
+    pub fn with_keyword() -> TokenKind {
+        TokenKind(TOK_WITH_KEYWORD.0)
+    }
+    pub fn is_with_keyword(&self) -> bool {
+        self.0 == TOK_WITH_KEYWORD.0
+    }
+
     pub fn is_keyword(&self) -> bool {
         (self.0 >= MIN_KEYWORD_TOK_ID) && (self.0 <= MAX_KEYWORD_TOK_ID)
     }
 
+    // Identifier names include reserved words, and name properties.
+    pub fn is_identifier_name(&self) -> bool {
+        self.is_identifier() || self.is_keyword()
+    }
+
+    pub fn is_numeric_literal(&self) -> bool {
+        self.is_integer_literal() || self.is_hex_integer_literal() ||
+            self.is_oct_integer_literal() || self.is_float_literal()
+    }
+
+    pub fn is_identifier_or_literal(&self) -> bool {
+        (self.0 >= TOK_IDENTIFIER.0) && (self.0 <= TOK_REGEXP_LITERAL.0)
+    }
+
     pub fn is_atomic_expr(&self) -> bool {
-        ((self.0 >= TOK_IDENTIFIER.0) && (self.0 <= TOK_STRING_LITERAL.0)) ||
+        ((self.0 >= TOK_IDENTIFIER.0) && (self.0 <= TOK_REGEXP_LITERAL.0)) ||
             (self.0 == TOK_THIS_KEYWORD.0) || (self.0 == TOK_NULL_KEYWORD.0) ||
             (self.0 == TOK_TRUE_KEYWORD.0) || (self.0 == TOK_FALSE_KEYWORD.0)
     }
@@ -679,9 +695,10 @@ const TOK_HEX_INTEGER_LITERAL: (u8, &'static str) = (TOK_INTEGER_LITERAL.0 + 1,
 const TOK_OCT_INTEGER_LITERAL: (u8, &'static str) = (TOK_HEX_INTEGER_LITERAL.0 + 1, "oct_integer_literal");
 const TOK_FLOAT_LITERAL: (u8, &'static str) = (TOK_OCT_INTEGER_LITERAL.0 + 1, "float_literal");
 const TOK_STRING_LITERAL: (u8, &'static str) = (TOK_FLOAT_LITERAL.0 + 1, "string_literal");
+const TOK_REGEXP_LITERAL: (u8, &str) = (TOK_STRING_LITERAL.0 + 1, "regexp_literal");
 
 // Braces.
-const TOK_OPEN_PAREN: (u8, &'static str) = (TOK_STRING_LITERAL.0 + 1, "open_paren");
+const TOK_OPEN_PAREN: (u8, &str) = (TOK_REGEXP_LITERAL.0 + 1, "open_paren");
 const TOK_CLOSE_PAREN: (u8, &'static str) = (TOK_OPEN_PAREN.0 + 1, "close_paren");
 const TOK_OPEN_BRACKET: (u8, &'static str) = (TOK_CLOSE_PAREN.0 + 1, "open_bracket");
 const TOK_CLOSE_BRACKET: (u8, &'static str) = (TOK_OPEN_BRACKET.0 + 1, "close_bracket");
@@ -769,109 +786,40 @@ const TOK_TYPEOF_KEYWORD: (u8, &'static str) = (TOK_TRY_KEYWORD.0 + 1, "typeof_k
 const TOK_VAR_KEYWORD: (u8, &'static str) = (TOK_TYPEOF_KEYWORD.0 + 1, "var_keyword");
 const TOK_VOID_KEYWORD: (u8, &'static str) = (TOK_VAR_KEYWORD.0 + 1, "void_keyword");
 const TOK_WHILE_KEYWORD: (u8, &'static str) = (TOK_VOID_KEYWORD.0 + 1, "while_keyword");
+const TOK_WITH_KEYWORD: (u8, &str) = (TOK_WHILE_KEYWORD.0 + 1, "with_keyword");
 
 const MIN_KEYWORD_TOK_ID: u8 = TOK_BREAK_KEYWORD.0;
-const MAX_KEYWORD_TOK_ID: u8 = TOK_WHILE_KEYWORD.0;
-
-const MAX_TOK_ID: u8 = TOK_WHILE_KEYWORD.0;
-
-static mut TOKEN_STRINGS: [&'static str; MAX_TOK_ID as usize + 1] = [""; MAX_TOK_ID as usize  + 1];
-unsafe fn init_tok_strings() {
-    let update_array = |tok_info: &(u8, &'static str)| {
-        assert!(tok_info.0 <= MAX_TOK_ID);
-        TOKEN_STRINGS[tok_info.0 as usize] = tok_info.1;
-    };
-    update_array(&TOK_ERROR);
-    update_array(&TOK_END);
-
-    update_array(&TOK_WHITESPACE);
-    update_array(&TOK_NEWLINE);
-    update_array(&TOK_COMMENT);
-
-    update_array(&TOK_IDENTIFIER);
-    update_array(&TOK_INTEGER_LITERAL);
-    update_array(&TOK_HEX_INTEGER_LITERAL);
-    update_array(&TOK_OCT_INTEGER_LITERAL);
-    update_array(&TOK_FLOAT_LITERAL);
-
-    update_array(&TOK_OPEN_PAREN);
-    update_array(&TOK_CLOSE_PAREN);
-    update_array(&TOK_OPEN_BRACKET);
-    update_array(&TOK_CLOSE_BRACKET);
-    update_array(&TOK_OPEN_BRACE);
-    update_array(&TOK_CLOSE_BRACE);
-
-    update_array(&TOK_DOT);
-    update_array(&TOK_SEMICOLON);
-    update_array(&TOK_COMMA);
-    update_array(&TOK_QUESTION);
-    update_array(&TOK_COLON);
-
-    update_array(&TOK_EQUAL);
-    update_array(&TOK_STRICT_EQUAL);
-    update_array(&TOK_NOT_EQUAL);
-    update_array(&TOK_STRICT_NOT_EQUAL);
-    update_array(&TOK_LESS);
-    update_array(&TOK_LESS_EQUAL);
-    update_array(&TOK_GREATER);
-    update_array(&TOK_GREATER_EQUAL);
-
-    update_array(&TOK_TILDE);
-    update_array(&TOK_BANG);
-    update_array(&TOK_PLUS);
-    update_array(&TOK_PLUS_PLUS);
-    update_array(&TOK_MINUS);
-    update_array(&TOK_MINUS_MINUS);
-    update_array(&TOK_STAR);
-    update_array(&TOK_SLASH);
-    update_array(&TOK_PERCENT);
-    update_array(&TOK_SHIFT_LEFT);
-    update_array(&TOK_SHIFT_RIGHT);
-    update_array(&TOK_ARITHMETIC_SHIFT_RIGHT);
-    update_array(&TOK_BIT_AND);
-    update_array(&TOK_BIT_OR);
-    update_array(&TOK_BIT_XOR);
-    update_array(&TOK_LOGICAL_AND);
-    update_array(&TOK_LOGICAL_OR);
-
-    update_array(&TOK_ASSIGN);
-    update_array(&TOK_PLUS_ASSIGN);
-    update_array(&TOK_MINUS_ASSIGN);
-    update_array(&TOK_STAR_ASSIGN);
-    update_array(&TOK_SLASH_ASSIGN);
-    update_array(&TOK_PERCENT_ASSIGN);
-    update_array(&TOK_SHIFT_LEFT_ASSIGN);
-    update_array(&TOK_SHIFT_RIGHT_ASSIGN);
-    update_array(&TOK_ARITHMETIC_SHIFT_RIGHT_ASSIGN);
-    update_array(&TOK_BIT_AND_ASSIGN);
-    update_array(&TOK_BIT_OR_ASSIGN);
-    update_array(&TOK_BIT_XOR_ASSIGN);
-
-    update_array(&TOK_BREAK_KEYWORD);
-    update_array(&TOK_CASE_KEYWORD);
-    update_array(&TOK_CATCH_KEYWORD);
-    update_array(&TOK_CONTINUE_KEYWORD);
-    update_array(&TOK_DEFAULT_KEYWORD);
-    update_array(&TOK_DELETE_KEYWORD);
-    update_array(&TOK_DO_KEYWORD);
-    update_array(&TOK_ELSE_KEYWORD);
-    update_array(&TOK_FALSE_KEYWORD);
-    update_array(&TOK_FINALLY_KEYWORD);
-    update_array(&TOK_FOR_KEYWORD);
-    update_array(&TOK_FUNCTION_KEYWORD);
-    update_array(&TOK_IF_KEYWORD);
-    update_array(&TOK_IN_KEYWORD);
-    update_array(&TOK_INSTANCEOF_KEYWORD);
-    update_array(&TOK_NEW_KEYWORD);
-    update_array(&TOK_NULL_KEYWORD);
-    update_array(&TOK_RETURN_KEYWORD);
-    update_array(&TOK_SWITCH_KEYWORD);
-    update_array(&TOK_THIS_KEYWORD);
-    update_array(&TOK_THROW_KEYWORD);
-    update_array(&TOK_TRUE_KEYWORD);
-    update_array(&TOK_TRY_KEYWORD);
-    update_array(&TOK_TYPEOF_KEYWORD);
-    update_array(&TOK_VAR_KEYWORD);
-    update_array(&TOK_VOID_KEYWORD);
-    update_array(&TOK_WHILE_KEYWORD);
+const MAX_KEYWORD_TOK_ID: u8 = TOK_WITH_KEYWORD.0;
+
+const MAX_TOK_ID: u8 = TOK_WITH_KEYWORD.0;
+
+// The names of the token kinds, indexed by kind.
+const TOKEN_STRINGS: [&str; MAX_TOK_ID as usize + 1] = token_strings();
+const fn token_strings() -> [&'static str; MAX_TOK_ID as usize + 1] {
+    let tok_infos = [
+        TOK_ERROR, TOK_END, TOK_WHITESPACE, TOK_NEWLINE, TOK_COMMENT, TOK_IDENTIFIER,
+        TOK_INTEGER_LITERAL, TOK_HEX_INTEGER_LITERAL, TOK_OCT_INTEGER_LITERAL, TOK_FLOAT_LITERAL,
+        TOK_STRING_LITERAL, TOK_REGEXP_LITERAL, TOK_OPEN_PAREN, TOK_CLOSE_PAREN, TOK_OPEN_BRACKET,
+        TOK_CLOSE_BRACKET, TOK_OPEN_BRACE, TOK_CLOSE_BRACE, TOK_DOT, TOK_SEMICOLON, TOK_COMMA,
+        TOK_QUESTION, TOK_COLON, TOK_EQUAL, TOK_STRICT_EQUAL, TOK_NOT_EQUAL, TOK_STRICT_NOT_EQUAL,
+        TOK_LESS, TOK_LESS_EQUAL, TOK_GREATER, TOK_GREATER_EQUAL, TOK_TILDE, TOK_BANG, TOK_PLUS,
+        TOK_PLUS_PLUS, TOK_MINUS, TOK_MINUS_MINUS, TOK_STAR, TOK_SLASH, TOK_PERCENT, TOK_SHIFT_LEFT,
+        TOK_SHIFT_RIGHT, TOK_ARITHMETIC_SHIFT_RIGHT, TOK_BIT_AND, TOK_BIT_OR, TOK_BIT_XOR,
+        TOK_LOGICAL_AND, TOK_LOGICAL_OR, TOK_ASSIGN, TOK_PLUS_ASSIGN, TOK_MINUS_ASSIGN, TOK_STAR_ASSIGN,
+        TOK_SLASH_ASSIGN, TOK_PERCENT_ASSIGN, TOK_SHIFT_LEFT_ASSIGN, TOK_SHIFT_RIGHT_ASSIGN,
+        TOK_ARITHMETIC_SHIFT_RIGHT_ASSIGN, TOK_BIT_AND_ASSIGN, TOK_BIT_OR_ASSIGN, TOK_BIT_XOR_ASSIGN,
+        TOK_BREAK_KEYWORD, TOK_CASE_KEYWORD, TOK_CATCH_KEYWORD, TOK_CONTINUE_KEYWORD,
+        TOK_DEFAULT_KEYWORD, TOK_DELETE_KEYWORD, TOK_DO_KEYWORD, TOK_ELSE_KEYWORD, TOK_FALSE_KEYWORD,
+        TOK_FINALLY_KEYWORD, TOK_FOR_KEYWORD, TOK_FUNCTION_KEYWORD, TOK_IF_KEYWORD, TOK_IN_KEYWORD,
+        TOK_INSTANCEOF_KEYWORD, TOK_NEW_KEYWORD, TOK_NULL_KEYWORD, TOK_RETURN_KEYWORD,
+        TOK_SWITCH_KEYWORD, TOK_THIS_KEYWORD, TOK_THROW_KEYWORD, TOK_TRUE_KEYWORD, TOK_TRY_KEYWORD,
+        TOK_TYPEOF_KEYWORD, TOK_VAR_KEYWORD, TOK_VOID_KEYWORD, TOK_WHILE_KEYWORD, TOK_WITH_KEYWORD
+    ];
+    let mut strings = [""; MAX_TOK_ID as usize + 1];
+    let mut i = 0;
+    while i < tok_infos.len() {
+        strings[tok_infos[i].0 as usize] = tok_infos[i].1;
+        i += 1;
+    }
+    strings
 }
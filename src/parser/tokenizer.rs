@@ -1,32 +1,25 @@
 
 use std::fmt;
 use std::ptr;
-use parser::char_utils::AsciiChar;
+use parser::char_utils::{AsciiChar, NonAsciiChar};
 use parser::input_stream::{InputStream, StreamPosition};
 use parser::token_kind::TokenKind;
 
-/**
- * Initializer for tokenizer module that must be called exactly once at the
- * beginning of program execution.
- */
-static mut MODULE_INITIALIZED: bool = false;
-pub fn initialize_module() {
-    unsafe {
-        assert!(!MODULE_INITIALIZED);
-        init_single_char_tokens();
-        MODULE_INITIALIZED = true;
-    }
-}
-
 #[derive(Debug, Clone)]
 pub enum TokenError {
     PrematureEnd(TokenKind),
     CantHandleUnicodeYet,
+    InvalidUnicode,
     BadNumber,
     UnrecognizedChar(char)
 }
 
-/** Raw information required to extract the token from the source text. */
+/**
+ * Raw information required to extract the token from the source text.  The
+ * offsets are positions in the input stream, so count code units of its
+ * encoding: bytes of UTF-8 input, or 16-bit units of UTF-16 input.  Use the
+ * tokenizer to extract the text between them.
+ */
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TokenLocation {
     start_offset: StreamPosition,
@@ -94,6 +87,11 @@ pub trait TokenizerMode {
  */
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TokenizerPosition(StreamPosition);
+impl TokenizerPosition {
+    pub fn stream_position(self) -> StreamPosition {
+        self.0
+    }
+}
 
 /**
  * The actual tokenizer is parameterized on the input stream type and the
@@ -140,6 +138,68 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
         self.input_stream.rewind(position.0);
     }
 
+    pub fn pin_window_start(&mut self, position: TokenizerPosition) {
+        self.input_stream.pin_window_start(position.0);
+    }
+
+    pub fn extract_text(&self, location: &TokenLocation) -> String {
+        self.input_stream.extract_text(location.start_offset(), location.end_offset())
+    }
+
+    /**
+     * Re-read a `/` or `/=` token as the start of a regular expression
+     * literal.  Only the parser knows where a slash begins an expression, so
+     * it asks for this on the tokens it gets there.
+     */
+    pub fn read_regexp_literal(&mut self, slash_location: &TokenLocation) -> MODE::Tok {
+        assert!(self.token_error.is_none());
+        self.token_start_position = slash_location.start_offset();
+        self.input_stream.rewind(self.token_start_position);
+        let slash = self.read_ascii_char();
+        assert!(slash.is_char('/'));
+
+        // The body ends at a `/` which isn't escaped or in a class.  Neither
+        // escapes nor classes can span lines.
+        let mut in_class = false;
+        loop {
+            let mut ch = self.read_ascii_char();
+            if ch.is_char('\\') {
+                ch = self.read_ascii_char();
+            } else if ch.is_char('/') && ! in_class {
+                break;
+            } else if ch.is_char('[') {
+                in_class = true;
+            } else if ch.is_char(']') {
+                in_class = false;
+            }
+
+            if ! ch.is_ascii_or_end() {
+                match self.read_non_ascii_char(ch) {
+                    Ok(uch) => {
+                        if uch.is_line_terminator() {
+                            return self.emit_error(TokenError::PrematureEnd(TokenKind::regexp_literal()));
+                        }
+                    }
+                    Err(e) => { return self.emit_error(e); }
+                }
+                continue;
+            }
+            if ch.is_end() || ch.is_line_feed() || ch.is_carriage_return() {
+                return self.emit_error(TokenError::PrematureEnd(TokenKind::regexp_literal()));
+            }
+        }
+
+        // Flags are identifier parts; whether they're valid is checked later.
+        loop {
+            let ch = self.read_ascii_char();
+            if ! ch.is_identifier_continue() {
+                self.unread_ascii_char(ch);
+                break;
+            }
+        }
+        self.emit_token(TokenKind::regexp_literal())
+    }
+
     fn read_token(&mut self, check_kw: bool) -> MODE::Tok {
         // TODO: Order this according to token occurrence probability.
         self.token_start_position = self.input_stream.mark();
@@ -386,8 +446,9 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
     }
 
     fn read_identifier_or_keyword(&mut self, ch0: AsciiChar) -> MODE::Tok {
-        // Keep a track of the last 4 bytes of the identifier.
+        // Keep a track of the last 4 bytes of the identifier, and its length.
         let mut tail_word = ch0.ascii_value() as u32;
+        let mut length = 1;
         loop {
             let ch = self.read_ascii_char();
             if ! ch.is_identifier_continue() {
@@ -397,8 +458,9 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             }
 
             tail_word = (tail_word << 8) | (ch.ascii_value() as u32);
+            length += 1;
         }
-        self.emit_keyword_or_identifier(tail_word)
+        self.emit_keyword_or_identifier(tail_word, length)
     }
 
     #[inline(always)]
@@ -413,19 +475,24 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
     fn make_tail_word_4(ch0: char, ch1: char, ch2: char, ch3: char) -> u32 {
         ((ch0 as u32) << 24) | ((ch1 as u32) << 16) | ((ch2 as u32) << 8) | (ch3 as u32)
     }
-    fn emit_keyword_or_identifier(&mut self, tail_word: u32) -> MODE::Tok {
+    fn emit_keyword_or_identifier(&mut self, tail_word: u32, length: usize) -> MODE::Tok {
         // Keyword frequencies in JS (from esprima blog post):
         //      this - 3229, function - 3108, if - 3063, return - 2878, var - 2116
         //      else - 562, for - 436, new - 232, in - 225, typeof - 188
         //      while - 143, case - 122, break - 115,
         //      try, catch, delete, throw, switch, continue, default, instanceof
-        //      do, void, finally
+        //      do, void, finally, with
+        //
+        // The tail word only holds the last 4 bytes, so keywords of 4 bytes or
+        // more also check the identifier's length.
 
         if tail_word == Self::make_tail_word_4('t', 'h', 'i', 's') {
-            return self.emit_token_check_kw(TokenKind::this_keyword());
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::this_keyword());
+            }
 
         } else if tail_word == Self::make_tail_word_4('t', 'i', 'o', 'n') {
-            if self.input_stream.check_ascii_text(&['f','u','n','c'], self.token_start_position) {
+            if length == 8 && self.input_stream.check_ascii_text(&['f','u','n','c'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::function_keyword());
             }
 
@@ -433,7 +500,7 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             return self.emit_token_check_kw(TokenKind::if_keyword());
 
         } else if tail_word == Self::make_tail_word_4('t', 'u', 'r', 'n') {
-            if self.input_stream.check_ascii_text(&['r','e','t'], self.token_start_position) {
+            if length == 6 && self.input_stream.check_ascii_text(&['r','e','t'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::return_keyword());
             }
 
@@ -441,7 +508,9 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             return self.emit_token_check_kw(TokenKind::var_keyword());
 
         } else if tail_word == Self::make_tail_word_4('e', 'l', 's', 'e') {
-            return self.emit_token_check_kw(TokenKind::else_keyword());
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::else_keyword());
+            }
 
         } else if tail_word == Self::make_tail_word_3('f', 'o', 'r') {
             return self.emit_token_check_kw(TokenKind::for_keyword());
@@ -453,31 +522,37 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             return self.emit_token_check_kw(TokenKind::in_keyword());
 
         } else if tail_word == Self::make_tail_word_4('n', 'u', 'l', 'l') {
-            return self.emit_token_check_kw(TokenKind::null_keyword());
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::null_keyword());
+            }
 
         } else if tail_word == Self::make_tail_word_4('t', 'r', 'u', 'e') {
-            return self.emit_token_check_kw(TokenKind::true_keyword());
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::true_keyword());
+            }
 
         } else if tail_word == Self::make_tail_word_4('a', 'l', 's', 'e') {
-            if self.input_stream.check_ascii_text(&['f'], self.token_start_position) {
+            if length == 5 && self.input_stream.check_ascii_text(&['f'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::false_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('p', 'e', 'o', 'f') {
-            if self.input_stream.check_ascii_text(&['t','y'], self.token_start_position) {
+            if length == 6 && self.input_stream.check_ascii_text(&['t','y'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::typeof_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('h', 'i', 'l', 'e') {
-            if self.input_stream.check_ascii_text(&['w'], self.token_start_position) {
+            if length == 5 && self.input_stream.check_ascii_text(&['w'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::while_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('c', 'a', 's', 'e') {
-            return self.emit_token_check_kw(TokenKind::case_keyword());
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::case_keyword());
+            }
 
         } else if tail_word == Self::make_tail_word_4('r', 'e', 'a', 'k') {
-            if self.input_stream.check_ascii_text(&['b'], self.token_start_position) {
+            if length == 5 && self.input_stream.check_ascii_text(&['b'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::break_keyword());
             }
 
@@ -485,38 +560,38 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             return self.emit_token_check_kw(TokenKind::try_keyword());
 
         } else if tail_word == Self::make_tail_word_4('a', 't', 'c', 'h') {
-            if self.input_stream.check_ascii_text(&['c'], self.token_start_position) {
+            if length == 5 && self.input_stream.check_ascii_text(&['c'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::catch_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('l', 'e', 't', 'e') {
-            if self.input_stream.check_ascii_text(&['d', 'e'], self.token_start_position) {
+            if length == 6 && self.input_stream.check_ascii_text(&['d', 'e'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::delete_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('h', 'r', 'o', 'w') {
-            if self.input_stream.check_ascii_text(&['t'], self.token_start_position) {
+            if length == 5 && self.input_stream.check_ascii_text(&['t'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::throw_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('i', 't', 'c', 'h') {
-            if self.input_stream.check_ascii_text(&['s', 'w'], self.token_start_position) {
+            if length == 6 && self.input_stream.check_ascii_text(&['s', 'w'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::switch_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('i', 'n', 'u', 'e') {
-            if self.input_stream.check_ascii_text(&['c','o','n','t'], self.token_start_position) {
+            if length == 8 && self.input_stream.check_ascii_text(&['c','o','n','t'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::continue_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('a', 'u', 'l', 't') {
-            if self.input_stream.check_ascii_text(&['d','e','f'], self.token_start_position) {
+            if length == 7 && self.input_stream.check_ascii_text(&['d','e','f'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::default_keyword());
             }
 
         } else if tail_word == Self::make_tail_word_4('c', 'e', 'o', 'f') {
-            if self.input_stream.check_ascii_text(&['i','n','s','t','a','n'],
-                                                  self.token_start_position) {
+            if length == 10 && self.input_stream.check_ascii_text(&['i','n','s','t','a','n'],
+                                                                  self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::instanceof_keyword());
             }
 
@@ -524,10 +599,17 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             return self.emit_token_check_kw(TokenKind::do_keyword());
 
         } else if tail_word == Self::make_tail_word_4('v', 'o', 'i', 'd') {
-            return self.emit_token_check_kw(TokenKind::void_keyword());
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::void_keyword());
+            }
+
+        } else if tail_word == Self::make_tail_word_4('w', 'i', 't', 'h') {
+            if length == 4 {
+                return self.emit_token_check_kw(TokenKind::with_keyword());
+            }
 
         } else if tail_word == Self::make_tail_word_4('a', 'l', 'l', 'y') {
-            if self.input_stream.check_ascii_text(&['f','i','n'], self.token_start_position) {
+            if length == 7 && self.input_stream.check_ascii_text(&['f','i','n'], self.token_start_position) {
                 return self.emit_token_check_kw(TokenKind::finally_keyword());
             }
         }
@@ -655,8 +737,13 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
             }
 
             if ! ch.is_ascii_or_end() {
-                // TODO: Handle non-ascii chars.
-                return self.emit_error(TokenError::CantHandleUnicodeYet);
+                if let Err(e) = self.read_non_ascii_char(ch) {
+                    return self.emit_error(e);
+                }
+            }
+
+            if ch.is_end() {
+                return self.emit_error(TokenError::PrematureEnd(TokenKind::string_literal()));
             }
             continue;
         }
@@ -668,8 +755,7 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
         let ch = self.read_ascii_char();
         // TODO: handle all proper escape sequences, including unicode and hex.
         if ! ch.is_ascii_or_end() {
-            // TODO: Handle non-ascii chars.
-            return Err(TokenError::CantHandleUnicodeYet);
+            self.read_non_ascii_char(ch)?;
         }
         if ch.is_end() {
             return Err(TokenError::PrematureEnd(TokenKind::string_literal()));
@@ -765,8 +851,16 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
 
             // If char is not ascii or end-of-input, unread it and read a unicode char.
             if ! ch.is_ascii_or_end() {
-                // TODO: Handle unicode chars.  Remember to check for unicode newlines.
-                return self.emit_error(TokenError::CantHandleUnicodeYet);
+                match self.read_non_ascii_char(ch) {
+                    Ok(uch) => {
+                        if uch.is_line_terminator() {
+                            self.tokenizer_mode.note_newline();
+                            break;
+                        }
+                    }
+                    Err(e) => { return self.emit_error(e); }
+                }
+                continue;
             }
 
             // End-of-input in a line-comment terminates the comment.
@@ -802,8 +896,15 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
 
             // If char is not ascii or end-of-input, unread it and read a unicode char.
             if ! ch.is_ascii_or_end() {
-                // TODO: Handle unicode chars.
-                return self.emit_error(TokenError::CantHandleUnicodeYet);
+                match self.read_non_ascii_char(ch) {
+                    Ok(uch) => {
+                        if uch.is_line_terminator() && self.tokenizer_mode.cares_about_newline() {
+                            self.tokenizer_mode.note_newline();
+                        }
+                    }
+                    Err(e) => { return self.emit_error(e); }
+                }
+                continue;
             }
 
             // Check for end of input in the middle of a block comment, which is an error.
@@ -862,33 +963,37 @@ impl<STREAM, MODE> Tokenizer<STREAM, MODE>
     fn unread_ascii_char(&mut self, ch: AsciiChar) {
         self.input_stream.unread_ascii(ch);
     }
+
+    // Re-read the non-ascii char just read as `ch` as a unicode char.
+    fn read_non_ascii_char(&mut self, ch: AsciiChar) -> Result<NonAsciiChar, TokenError> {
+        self.unread_ascii_char(ch);
+        let uch = self.input_stream.read_non_ascii();
+        if uch.is_error() {
+            return Err(TokenError::InvalidUnicode);
+        }
+        Ok(uch)
+    }
 }
 
 fn check_single_char_token(ch: AsciiChar) -> TokenKind {
-    unsafe {
-        *(&SINGLE_CHAR_TOKENS as &[TokenKind]).get_unchecked(ch.octet_value_or_0xff() as usize)
-    }
+    SINGLE_CHAR_TOKENS[ch.octet_value_or_0xff() as usize]
 }
 
-static mut SINGLE_CHAR_TOKENS: [TokenKind; 256] = [TokenKind(0); 256];
-unsafe fn init_single_char_tokens() {
-    for i in 0..255 {
-        SINGLE_CHAR_TOKENS[i] = TokenKind::error();
-    }
-
-    let update_array = |ch, kind| {
-        assert!((ch as usize) <= 256);
-        SINGLE_CHAR_TOKENS[ch as usize] = kind;
-    };
-    update_array('(', TokenKind::open_paren());
-    update_array(')', TokenKind::close_paren());
-    update_array('[', TokenKind::open_bracket());
-    update_array(']', TokenKind::close_bracket());
-    update_array('{', TokenKind::open_brace());
-    update_array('}', TokenKind::close_brace());
-    update_array(';', TokenKind::semicolon());
-    update_array(',', TokenKind::comma());
-    update_array('?', TokenKind::question());
-    update_array(':', TokenKind::colon());
-    update_array('~', TokenKind::tilde());
+// The kinds of the tokens which are always a single character, indexed by the
+// character, and errors for every other byte.
+const SINGLE_CHAR_TOKENS: [TokenKind; 256] = single_char_tokens();
+const fn single_char_tokens() -> [TokenKind; 256] {
+    let mut tokens = [TokenKind::error(); 256];
+    tokens[b'(' as usize] = TokenKind::open_paren();
+    tokens[b')' as usize] = TokenKind::close_paren();
+    tokens[b'[' as usize] = TokenKind::open_bracket();
+    tokens[b']' as usize] = TokenKind::close_bracket();
+    tokens[b'{' as usize] = TokenKind::open_brace();
+    tokens[b'}' as usize] = TokenKind::close_brace();
+    tokens[b';' as usize] = TokenKind::semicolon();
+    tokens[b',' as usize] = TokenKind::comma();
+    tokens[b'?' as usize] = TokenKind::question();
+    tokens[b':' as usize] = TokenKind::colon();
+    tokens[b'~' as usize] = TokenKind::tilde();
+    tokens
 }
@@ -0,0 +1,528 @@
+/*!
+ * The embedding API.
+ *
+ * A `Runtime` owns an interpreter and its heap.  Rust code works with it
+ * through a `Context`: evaluating source text, reading and writing
+ * properties, calling functions, and defining global functions implemented
+ * by Rust closures.
+ *
+ * Values given to Rust are `JsValue`s, which keep what they refer to alive
+ * until they are dropped, so they can be held across calls into script.
+ * They convert to and from Rust types through the `FromJs` and `IntoJs`
+ * traits, which convert as the language does: a number is read from a string
+ * by `ToNumber`, and so on.
+ *
+ * Whatever goes wrong is an `Error`.  A host function which returns one
+ * throws it back into script, so exceptions pass through Rust code and out
+ * again unchanged.
+ *
+ * Runtimes share nothing: each has its own heap, atoms and globals.  A
+ * `JsValue` records the runtime it came from, and using it with any other
+ * is an `Error::ForeignValue`.  A runtime can be sent to another thread while it isn't running,
+ * which is why the closures it's given must be `Send`.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use parser::ast_builder::{AstBuilder, ParseError};
+use parser::input_stream::VecInputStream;
+use vm::builtins;
+use vm::builtins::error::ErrorKind;
+use vm::bytecode::compiler::CompileError;
+use vm::function::CallArgs;
+use vm::heap::{Trace, Tracer};
+use vm::interpreter::{Exception, Interpreter, RunError, StackFrame, VmResult};
+use vm::object;
+use vm::string;
+use vm::time::{Clock, TimeZone};
+use vm::value::Value;
+
+// Runtimes are numbered, so that values can be checked against the runtime
+// they're used with.  Numbers are never reused.
+static NEXT_RUNTIME_ID: AtomicUsize = AtomicUsize::new(1);
+
+/** An interpreter, with its own heap and global object. */
+pub struct Runtime {
+    interp: Interpreter,
+    handles: Rc<Handles>
+}
+
+/*
+ * Every Rc in a runtime is reachable only from the runtime itself: the heap,
+ * its roots and shapes, the handle table, and the host functions.  JsValues
+ * share only Arcs with it, and the closures given to it must be Send.  So the
+ * whole can move between threads together.
+ */
+unsafe impl Send for Runtime {}
+
+impl Runtime {
+    pub fn new() -> Runtime {
+        let mut interp = Interpreter::new();
+        let id = NEXT_RUNTIME_ID.fetch_add(1, Ordering::Relaxed);
+        let handles = Rc::new(Handles { runtime: id, values: RefCell::new(Vec::new()),
+                                        limit: Cell::new(MIN_HANDLES_LIMIT) });
+        interp.heap_mut().register_root(&handles);
+        Runtime { interp, handles }
+    }
+
+    pub fn context(&mut self) -> Context<'_> {
+        Context { interp: &mut self.interp, handles: &self.handles }
+    }
+
+    // The clock `Date` reads the current time from.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.interp.set_clock(clock);
+    }
+    // The zone of dates' local times.  `TimeZone::Utc` gives every machine
+    // the same results.
+    pub fn set_time_zone(&mut self, time_zone: TimeZone) {
+        self.interp.set_time_zone(time_zone);
+    }
+    // Seed `Math.random`, so that a run can be repeated.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.interp.seed_random(seed);
+    }
+    // The instructions a single regular expression match may run.
+    pub fn set_regexp_step_limit(&mut self, limit: u64) {
+        self.interp.set_regexp_step_limit(limit);
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<JsValue, Error> {
+        self.context().eval(source)
+    }
+
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+        where F: Fn(&mut Context, &[JsValue]) -> Result<JsValue, Error> + Send + 'static
+    {
+        self.context().register_function(name, function)
+    }
+    // The handles kept for JsValues, counting dead ones not yet forgotten.
+    pub fn handle_count(&self) -> usize {
+        self.handles.values.borrow().len()
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
+
+/**
+ * A value held by Rust code, which is rooted until it's dropped.  It can
+ * only be used with the runtime it came from.
+ */
+#[derive(Clone)]
+pub struct JsValue {
+    runtime: usize,
+    handle: Arc<Value>
+}
+impl fmt::Debug for JsValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "JsValue({:?} in runtime {})", *self.handle, self.runtime)
+    }
+}
+
+// The fewest handles at which dead ones are pruned between collections.
+const MIN_HANDLES_LIMIT: usize = 256;
+
+/**
+ * The values a runtime's JsValues hold.  Its handles are weak, so that a
+ * JsValue can be dropped on any thread.  The dead ones are forgotten as the
+ * heap is collected, and whenever the table doubles in size, as host calls
+ * root their arguments and results whether or not script allocates.
+ */
+struct Handles {
+    runtime: usize,
+    values: RefCell<Vec<Weak<Value>>>,
+    limit: Cell<usize>
+}
+impl Handles {
+    fn push(&self, handle: &Arc<Value>) {
+        let mut values = self.values.borrow_mut();
+        if values.len() >= self.limit.get() {
+            values.retain(|handle| handle.upgrade().is_some());
+            self.limit.set((values.len() * 2).max(MIN_HANDLES_LIMIT));
+        }
+        values.push(Arc::downgrade(handle));
+    }
+}
+impl Trace for Handles {
+    fn trace(&self, tracer: &mut Tracer) {
+        let mut values = self.values.borrow_mut();
+        values.retain(|handle| {
+            match handle.upgrade() {
+                Some(value) => {
+                    tracer.trace_value(*value);
+                    true
+                }
+                None => false
+            }
+        });
+        self.limit.set((values.len() * 2).max(MIN_HANDLES_LIMIT));
+    }
+}
+
+/** Why a call into the runtime failed. */
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Compile(CompileError),
+    // A value thrown by script, or to throw to it.
+    Throw { value: JsValue, stack_trace: Vec<StackFrame> },
+    // A value which couldn't be converted to a Rust type.  It's thrown to
+    // script as a TypeError.
+    Type(String),
+    // A JsValue of another runtime was used with this one.  It's thrown to
+    // script as a TypeError.
+    ForeignValue { value_runtime: usize, runtime: usize }
+}
+
+/**
+ * A view of a runtime, through which Rust code works with it.  Host
+ * functions are given one for the runtime calling them.
+ */
+pub struct Context<'a> {
+    interp: &'a mut Interpreter,
+    handles: &'a Rc<Handles>
+}
+
+impl<'a> Context<'a> {
+    // Root a value, so that Rust code can keep it.
+    fn root(&mut self, value: Value) -> JsValue {
+        let handle = Arc::new(value);
+        self.handles.push(&handle);
+        JsValue { runtime: self.handles.runtime, handle }
+    }
+
+    // The value a JsValue holds, if it's one of this runtime's.
+    fn raw(&self, value: &JsValue) -> Result<Value, Error> {
+        if value.runtime != self.handles.runtime {
+            return Err(Error::ForeignValue { value_runtime: value.runtime, runtime: self.handles.runtime });
+        }
+        Ok(*value.handle)
+    }
+
+    fn raw_values(&self, values: &[JsValue]) -> Result<Vec<Value>, Error> {
+        values.iter().map(|value| self.raw(value)).collect()
+    }
+
+    // Root the value of an interpreter result, or its exception.
+    fn result(&mut self, result: VmResult<Value>) -> Result<JsValue, Error> {
+        match result {
+            Ok(value) => Ok(self.root(value)),
+            Err(exception) => Err(self.thrown(exception))
+        }
+    }
+
+    fn thrown(&mut self, exception: Exception) -> Error {
+        let value = self.root(exception.value());
+        Error::Throw { value, stack_trace: exception.stack_trace().to_vec() }
+    }
+
+    /**
+     * Run source text as a program, in the runtime's global scope.  Its
+     * result is the value of the last expression statement it ran.
+     */
+    pub fn eval(&mut self, source: &str) -> Result<JsValue, Error> {
+        let mut builder = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec()));
+        let program = builder.parse_program().map_err(Error::Parse)?;
+        match self.interp.run_program(&program) {
+            Ok(value) => Ok(self.root(value)),
+            Err(RunError::Compile(error)) => Err(Error::Compile(error)),
+            Err(RunError::Throw(exception)) => Err(self.thrown(exception))
+        }
+    }
+
+    pub fn global_object(&mut self) -> JsValue {
+        let global = self.interp.global_object();
+        self.root(global)
+    }
+
+    /** Convert a Rust value to a value of the runtime. */
+    pub fn value<T: IntoJs>(&mut self, value: T) -> Result<JsValue, Error> {
+        value.into_js(self)
+    }
+
+    /** Convert a value of the runtime to a Rust value. */
+    pub fn convert<T: FromJs>(&mut self, value: &JsValue) -> Result<T, Error> {
+        T::from_js(self, value)
+    }
+
+    pub fn undefined(&mut self) -> JsValue {
+        self.root(Value::new_imm_undef())
+    }
+
+    pub fn new_object(&mut self) -> JsValue {
+        let prototype = self.interp.object_prototype();
+        let obj = object::new_object(self.interp.heap_mut(), prototype);
+        self.root(obj)
+    }
+
+    pub fn new_array(&mut self, values: &[JsValue]) -> Result<JsValue, Error> {
+        let values = self.raw_values(values)?;
+        let array = builtins::array::new_array_from(self.interp, &values);
+        Ok(self.root(array))
+    }
+
+    /** A property of a value, with getters called as script would call them. */
+    pub fn get(&mut self, object: &JsValue, key: &str) -> Result<JsValue, Error> {
+        let object = self.raw(object)?;
+        if object.is_imm_undef_null() {
+            let message = format!("Cannot read property '{}' of {}", key, self.interp.display_string(object));
+            return Err(self.type_error(&message));
+        }
+        let key = string::atomize_str(self.interp.heap_mut(), key);
+        let result = self.interp.get_property(object, key);
+        self.result(result)
+    }
+
+    /** Set a property of an object, as strict mode code would. */
+    pub fn set<T: IntoJs>(&mut self, object: &JsValue, key: &str, value: T) -> Result<(), Error> {
+        let value = value.into_js(self)?;
+        let value = self.raw(&value)?;
+        let object = self.raw(object)?;
+        if ! object::is_object(self.interp.heap(), object) {
+            let message = format!("Cannot set property '{}' of {}", key, self.interp.display_string(object));
+            return Err(self.type_error(&message));
+        }
+        let key = string::atomize_str(self.interp.heap_mut(), key);
+        match self.interp.set_property(object, key, value, /* strict = */ true) {
+            Ok(()) => Ok(()),
+            Err(exception) => Err(self.thrown(exception))
+        }
+    }
+
+    pub fn get_global(&mut self, name: &str) -> Result<JsValue, Error> {
+        let global = self.global_object();
+        self.get(&global, name)
+    }
+
+    pub fn set_global<T: IntoJs>(&mut self, name: &str, value: T) -> Result<(), Error> {
+        let global = self.global_object();
+        self.set(&global, name, value)
+    }
+
+    pub fn call(&mut self, function: &JsValue, this: &JsValue, args: &[JsValue]) -> Result<JsValue, Error> {
+        let (function, this, args) = (self.raw(function)?, self.raw(this)?, self.raw_values(args)?);
+        let result = self.interp.call(function, this, &args);
+        self.result(result)
+    }
+
+    /**
+     * A function implemented by a closure.  It's called with its arguments,
+     * and an error it returns is thrown to its caller.
+     */
+    pub fn new_function<F>(&mut self, name: &str, function: F) -> JsValue
+        where F: Fn(&mut Context, &[JsValue]) -> Result<JsValue, Error> + Send + 'static
+    {
+        let handles = self.handles.clone();
+        let call = move |interp: &mut Interpreter, args: &CallArgs| -> VmResult<Value> {
+            let mut cx = Context { interp, handles: &handles };
+            let args: Vec<JsValue> = args.args().iter().map(|&arg| cx.root(arg)).collect();
+            function(&mut cx, &args).and_then(|result| cx.raw(&result)).map_err(|error| cx.exception(error))
+        };
+        let func = self.interp.new_host_function(name, 0, Rc::new(call));
+        self.root(func)
+    }
+
+    /** Define a global function, implemented by a closure. */
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+        where F: Fn(&mut Context, &[JsValue]) -> Result<JsValue, Error> + Send + 'static
+    {
+        let func = self.new_function(name, function);
+        let global = self.interp.global_object();
+        // The function was just made by this runtime.
+        let func = *func.handle;
+        builtins::define_value(self.interp, global, name, func);
+    }
+
+    /** An error which throws a new error object of some kind. */
+    pub fn throw_error(&mut self, kind: ErrorKind, message: &str) -> Error {
+        let exception = self.interp.error(kind, message);
+        self.thrown(exception)
+    }
+
+    fn type_error(&mut self, message: &str) -> Error {
+        self.throw_error(ErrorKind::TypeError, message)
+    }
+
+    // The exception to throw to script for an error.
+    fn exception(&mut self, error: Error) -> Exception {
+        match error {
+            Error::Throw { value, .. } => match self.raw(&value) {
+                Ok(value) => Exception::new(value),
+                Err(error) => self.exception(error)
+            },
+            Error::Type(message) => self.interp.error(ErrorKind::TypeError, &message),
+            Error::ForeignValue { value_runtime, runtime } => {
+                let message = format!("A value of runtime {} was used with runtime {}", value_runtime, runtime);
+                self.interp.error(ErrorKind::TypeError, &message)
+            }
+            Error::Parse(error) => self.interp.error(ErrorKind::SyntaxError, &format!("{:?}", error)),
+            Error::Compile(error) => self.interp.error(ErrorKind::SyntaxError, &format!("{:?}", error))
+        }
+    }
+
+    /**
+     * A value as a string, as the language would convert it.  Objects are
+     * converted by their `toString`, so an array shows its elements joined.
+     */
+    pub fn display(&mut self, value: &JsValue) -> Result<String, Error> {
+        let value = self.raw(value)?;
+        let text = self.interp.string_value(value).map_err(|exception| self.thrown(exception))?;
+        Ok(self.interp.display_string(text))
+    }
+}
+
+/*****************************************************************************
+ **** Conversions ************************************************************
+ *****************************************************************************/
+
+/** A Rust type which can be given to the runtime as a value. */
+pub trait IntoJs {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error>;
+}
+
+/** A Rust type which can be taken from a value of the runtime. */
+pub trait FromJs: Sized {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<Self, Error>;
+}
+
+impl IntoJs for JsValue {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        cx.raw(&self)?;
+        Ok(self)
+    }
+}
+impl IntoJs for &JsValue {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        self.clone().into_js(cx)
+    }
+}
+impl IntoJs for () {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        Ok(cx.undefined())
+    }
+}
+impl IntoJs for bool {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        Ok(cx.root(Value::new_imm_bool(self)))
+    }
+}
+impl IntoJs for f64 {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        let value = Value::new_number(cx.interp.heap_mut(), self);
+        Ok(cx.root(value))
+    }
+}
+impl IntoJs for i32 {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        Ok(cx.root(Value::new_imm_i32(self)))
+    }
+}
+impl IntoJs for u32 {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        (self as f64).into_js(cx)
+    }
+}
+impl IntoJs for &str {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        let value = string::new_string(cx.interp.heap_mut(), self);
+        Ok(cx.root(value))
+    }
+}
+impl IntoJs for String {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        self.as_str().into_js(cx)
+    }
+}
+// None is null.
+impl<T: IntoJs> IntoJs for Option<T> {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        match self {
+            Some(value) => value.into_js(cx),
+            None => Ok(cx.root(Value::new_imm_null()))
+        }
+    }
+}
+// Vectors are arrays.
+impl<T: IntoJs> IntoJs for Vec<T> {
+    fn into_js(self, cx: &mut Context) -> Result<JsValue, Error> {
+        let mut values: Vec<JsValue> = Vec::with_capacity(self.len());
+        for value in self {
+            values.push(value.into_js(cx)?);
+        }
+        cx.new_array(&values)
+    }
+}
+
+impl FromJs for JsValue {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<JsValue, Error> {
+        value.into_js(cx)
+    }
+}
+impl FromJs for () {
+    fn from_js(_cx: &mut Context, _value: &JsValue) -> Result<(), Error> {
+        Ok(())
+    }
+}
+impl FromJs for bool {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<bool, Error> {
+        Ok(cx.raw(value)?.to_boolean(cx.interp.heap()))
+    }
+}
+impl FromJs for f64 {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<f64, Error> {
+        let value = cx.raw(value)?;
+        cx.interp.number_value(value).map_err(|exception| cx.thrown(exception))
+    }
+}
+impl FromJs for i32 {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<i32, Error> {
+        let value = cx.raw(value)?;
+        cx.interp.int32_value(value).map_err(|exception| cx.thrown(exception))
+    }
+}
+impl FromJs for u32 {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<u32, Error> {
+        let value = cx.raw(value)?;
+        cx.interp.uint32_value(value).map_err(|exception| cx.thrown(exception))
+    }
+}
+impl FromJs for String {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<String, Error> {
+        let value = cx.raw(value)?;
+        let text = cx.interp.string_value(value).map_err(|exception| cx.thrown(exception))?;
+        let units: Vec<u16> = string::code_units(cx.interp.heap(), text).collect();
+        Ok(String::from_utf16_lossy(&units))
+    }
+}
+// Undefined and null are None.
+impl<T: FromJs> FromJs for Option<T> {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<Option<T>, Error> {
+        if cx.raw(value)?.is_imm_undef_null() {
+            return Ok(None);
+        }
+        T::from_js(cx, value).map(Some)
+    }
+}
+// Vectors are taken from objects with a length, such as arrays.
+impl<T: FromJs> FromJs for Vec<T> {
+    fn from_js(cx: &mut Context, value: &JsValue) -> Result<Vec<T>, Error> {
+        if ! object::is_object(cx.interp.heap(), cx.raw(value)?) {
+            return Err(Error::Type(format!("{} is not an array", cx.display(value)?)));
+        }
+        let length = cx.get(value, "length")?;
+        let length: u32 = cx.convert(&length)?;
+        let mut items: Vec<T> = Vec::with_capacity(length as usize);
+        for index in 0..length {
+            let item = cx.get(value, &index.to_string())?;
+            items.push(cx.convert(&item)?);
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,234 @@
+/*!
+ * Arrays.
+ *
+ * An array is an object with an elements store, which holds the properties
+ * named by array indices, and the array's length.  Elements have the default
+ * attributes; an indexed property with other attributes is kept in the
+ * object's shape instead, like any other property.
+ *
+ * Elements are usually dense: a vector of values indexed from zero, with
+ * holes marking missing elements, and elements past the end of the vector
+ * missing too.  A store which would leave a hole of more than
+ * `MAX_DENSE_HOLE` elements, and more than there are elements already,
+ * switches the array to a sparse map from indices to values instead.
+ */
+
+use std::collections::BTreeMap;
+use std::mem;
+use vm::heap::{Heap, Tracer};
+use vm::object::{self, JsObject};
+use vm::string;
+use vm::value::Value;
+
+pub const MAX_DENSE_HOLE: u32 = 1024;
+
+// The largest array length.
+pub const MAX_LENGTH: u32 = 0xFFFF_FFFF;
+
+enum ElementStorage {
+    Dense(Vec<Value>),
+    Sparse(BTreeMap<u32, Value>)
+}
+
+/** The elements and length of an array. */
+pub struct ArrayElements {
+    length: u32,
+    length_writable: bool,
+    storage: ElementStorage
+}
+impl ArrayElements {
+    pub fn new() -> ArrayElements {
+        ArrayElements { length: 0, length_writable: true, storage: ElementStorage::Dense(Vec::new()) }
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+    pub fn is_length_writable(&self) -> bool {
+        self.length_writable
+    }
+    pub fn set_length_writable(&mut self, writable: bool) {
+        self.length_writable = writable;
+    }
+    pub fn is_dense(&self) -> bool {
+        matches!(self.storage, ElementStorage::Dense(_))
+    }
+
+    pub fn get(&self, index: u32) -> Option<Value> {
+        let value = match self.storage {
+            ElementStorage::Dense(ref values) => values.get(index as usize).cloned(),
+            ElementStorage::Sparse(ref values) => values.get(&index).cloned()
+        };
+        value.filter(|value| ! value.is_imm_hole())
+    }
+    pub fn has(&self, index: u32) -> bool {
+        self.get(index).is_some()
+    }
+
+    // Whether any element is present.
+    pub fn has_elements(&self) -> bool {
+        match self.storage {
+            ElementStorage::Dense(ref values) => values.iter().any(|value| ! value.is_imm_hole()),
+            ElementStorage::Sparse(ref values) => ! values.is_empty()
+        }
+    }
+
+    // Store an element, extending the length if needed.  The index must be
+    // below `MAX_LENGTH`.
+    pub fn set(&mut self, index: u32, value: Value) {
+        assert!(index < MAX_LENGTH);
+        debug_assert!(! value.is_imm_hole());
+        if let ElementStorage::Dense(ref values) = self.storage {
+            let stored = values.len() as u32;
+            if index > stored && (index - stored) > MAX_DENSE_HOLE.max(stored) {
+                self.make_sparse();
+            }
+        }
+        match self.storage {
+            ElementStorage::Dense(ref mut values) => {
+                if (index as usize) >= values.len() {
+                    values.resize(index as usize, Value::new_imm_hole());
+                    values.push(value);
+                } else {
+                    values[index as usize] = value;
+                }
+            }
+            ElementStorage::Sparse(ref mut values) => {
+                values.insert(index, value);
+            }
+        }
+        self.length = self.length.max(index + 1);
+    }
+
+    // Append an element, or a hole, at the end of the array.
+    pub fn push(&mut self, value: Value) {
+        let index = self.length;
+        self.set(index, value);
+    }
+    pub fn push_hole(&mut self) {
+        assert!(self.length < MAX_LENGTH);
+        self.length += 1;
+    }
+
+    pub fn delete(&mut self, index: u32) {
+        match self.storage {
+            ElementStorage::Dense(ref mut values) => {
+                if (index as usize) + 1 == values.len() {
+                    values.pop();
+                } else if (index as usize) < values.len() {
+                    values[index as usize] = Value::new_imm_hole();
+                }
+            }
+            ElementStorage::Sparse(ref mut values) => {
+                values.remove(&index);
+            }
+        }
+    }
+
+    // Change the length, removing the elements at or after a smaller length.
+    pub fn set_length(&mut self, length: u32) {
+        match self.storage {
+            ElementStorage::Dense(ref mut values) => values.truncate(length as usize),
+            ElementStorage::Sparse(ref mut values) => {
+                values.split_off(&length);
+            }
+        }
+        self.length = length;
+    }
+
+    // The indices of the elements present, in ascending order.
+    pub fn indices(&self) -> Vec<u32> {
+        match self.storage {
+            ElementStorage::Dense(ref values) => {
+                (0..values.len()).filter(|&idx| ! values[idx].is_imm_hole()).map(|idx| idx as u32).collect()
+            }
+            ElementStorage::Sparse(ref values) => values.keys().cloned().collect()
+        }
+    }
+
+    fn make_sparse(&mut self) {
+        let values = match self.storage {
+            ElementStorage::Dense(ref mut values) => mem::take(values),
+            ElementStorage::Sparse(_) => return
+        };
+        let sparse = values.into_iter().enumerate()
+                           .filter(|&(_, value)| ! value.is_imm_hole())
+                           .map(|(idx, value)| (idx as u32, value))
+                           .collect();
+        self.storage = ElementStorage::Sparse(sparse);
+    }
+
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self.storage {
+            ElementStorage::Dense(ref values) => tracer.trace_values(values),
+            ElementStorage::Sparse(ref values) => {
+                for value in values.values() {
+                    tracer.trace_value(*value);
+                }
+            }
+        }
+    }
+
+    pub fn extra_size(&self) -> usize {
+        match self.storage {
+            ElementStorage::Dense(ref values) => values.capacity() * mem::size_of::<Value>(),
+            // An estimate of the map's nodes.
+            ElementStorage::Sparse(ref values) => values.len() * 2 * mem::size_of::<Value>()
+        }
+    }
+}
+impl Default for ArrayElements {
+    fn default() -> ArrayElements {
+        ArrayElements::new()
+    }
+}
+
+// The key of `length`.  Short strings are immediate, so it needs no atom
+// table.
+pub fn length_key() -> Value {
+    Value::new_imm_str(b"length")
+}
+
+// A length as a number value.  All lengths fit in immediates.
+pub fn length_value(length: u32) -> Value {
+    if length <= (i32::MAX as u32) {
+        Value::new_imm_i32(length as i32)
+    } else {
+        Value::new_imm_f64(length as f64)
+    }
+}
+
+// Whether a number is a valid array length: an integer in `0..=MAX_LENGTH`.
+pub fn is_valid_length(number: f64) -> bool {
+    (number >= 0.0) && (number <= (MAX_LENGTH as f64)) && (number.trunc() == number)
+}
+
+pub fn new_array(heap: &mut Heap, prototype: Value) -> Value {
+    let shape = heap.root_shape().clone();
+    heap.alloc(JsObject::new_array(shape, prototype))
+}
+
+pub fn is_array(heap: &Heap, value: Value) -> bool {
+    heap.get::<JsObject>(value).is_some_and(|obj| obj.elements().is_some())
+}
+
+/**
+ * Whether an object or its prototypes have properties named by array indices,
+ * other than in the object's own elements.  Stores of elements which
+ * find no such properties can skip looking up their index as a string.
+ */
+pub fn chain_has_indexed_properties(heap: &Heap, object: Value) -> bool {
+    let mut holder = object;
+    while ! holder.is_imm_null() {
+        let obj = heap.get::<JsObject>(holder).unwrap();
+        let has_index_keys = obj.shape().has_index_keys(|key| object::array_index(heap, key).is_some());
+        if has_index_keys || ((holder != object) && obj.elements().is_some_and(ArrayElements::has_elements)) {
+            return true;
+        }
+        if obj.string_value().is_some_and(|string| string::string_length(heap, string).unwrap() > 0) {
+            return true;
+        }
+        holder = obj.prototype();
+    }
+    false
+}
@@ -0,0 +1,636 @@
+/*!
+ * `Array` and `Array.prototype`.
+ *
+ * The prototype's methods work on any object with a `length`, as the spec's
+ * are generic, reading and writing indexed properties through the usual
+ * property paths.  Arrays' own elements are read and written directly where
+ * that can't be told apart.  Writes that fail throw, as they would in strict
+ * code.
+ *
+ * Arrays the methods build, and values they hold while calling back into
+ * script, are rooted for the length of the call.
+ */
+
+use std::cmp::Ordering;
+use vm::array;
+use vm::builtins::{self, coercible_this, integer_value, relative_index};
+use vm::builtins::error::ErrorKind;
+use vm::function::CallArgs;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, JsObject};
+use vm::string;
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.array_prototype();
+    let constructor = builtins::define_constructor(interp, "Array", 1, construct_array, prototype);
+    builtins::define_method(interp, constructor, "isArray", 1, is_array);
+
+    builtins::define_method(interp, prototype, "toString", 0, array_to_string);
+    builtins::define_method(interp, prototype, "join", 1, join);
+    builtins::define_method(interp, prototype, "push", 1, push);
+    builtins::define_method(interp, prototype, "pop", 0, pop);
+    builtins::define_method(interp, prototype, "shift", 0, shift);
+    builtins::define_method(interp, prototype, "unshift", 1, unshift);
+    builtins::define_method(interp, prototype, "slice", 2, slice);
+    builtins::define_method(interp, prototype, "splice", 2, splice);
+    builtins::define_method(interp, prototype, "concat", 1, concat);
+    builtins::define_method(interp, prototype, "reverse", 0, reverse);
+    builtins::define_method(interp, prototype, "sort", 1, sort);
+    builtins::define_method(interp, prototype, "indexOf", 1, index_of);
+    builtins::define_method(interp, prototype, "lastIndexOf", 1, last_index_of);
+    builtins::define_method(interp, prototype, "every", 1, every);
+    builtins::define_method(interp, prototype, "some", 1, some);
+    builtins::define_method(interp, prototype, "forEach", 1, for_each);
+    builtins::define_method(interp, prototype, "map", 1, map);
+    builtins::define_method(interp, prototype, "filter", 1, filter);
+    builtins::define_method(interp, prototype, "reduce", 1, reduce);
+    builtins::define_method(interp, prototype, "reduceRight", 1, reduce_right);
+}
+
+/** A new array holding some values, which need not be rooted. */
+pub fn new_array_from(interp: &mut Interpreter, values: &[Value]) -> Value {
+    let prototype = interp.array_prototype();
+    let array = array::new_array(interp.heap_mut(), prototype);
+    let elements = interp.heap_mut().get_mut::<JsObject>(array).unwrap().elements_mut().unwrap();
+    for &value in values {
+        elements.push(value);
+    }
+    array
+}
+
+// Store an element of an array the method made, which has no properties
+// but its elements.
+fn create_index(interp: &mut Interpreter, array: Value, index: u32, value: Value) {
+    interp.heap_mut().get_mut::<JsObject>(array).unwrap().elements_mut().unwrap().set(index, value);
+}
+
+fn set_created_length(interp: &mut Interpreter, array: Value, length: u32) {
+    interp.heap_mut().get_mut::<JsObject>(array).unwrap().elements_mut().unwrap().set_length(length);
+}
+
+/*****************************************************************************
+ **** Indexed properties of array-likes **************************************
+ *****************************************************************************/
+
+// The atom naming an index.
+pub fn index_key(interp: &mut Interpreter, index: u32) -> Value {
+    string::atomize_str(interp.heap_mut(), &index.to_string())
+}
+
+// The `length` of an array-like, by ToUint32.
+pub fn length_of(interp: &mut Interpreter, object: Value) -> VmResult<u32> {
+    let length = interp.get_property(object, array::length_key())?;
+    interp.uint32_value(length)
+}
+
+fn set_length(interp: &mut Interpreter, object: Value, length: u32) -> VmResult<()> {
+    interp.set_property(object, array::length_key(), array::length_value(length), true)
+}
+
+// Whether an array-like has a property at an index, as the `in` operator
+// says.  Strings have their characters.  Arrays whose prototype chain has
+// no indexed properties have only their elements.
+pub fn has_index(interp: &mut Interpreter, object: Value, index: u32) -> bool {
+    if let Some(obj) = interp.heap().get::<JsObject>(object) {
+        if let Some(elements) = obj.elements() {
+            if elements.has(index) {
+                return true;
+            }
+            if ! array::chain_has_indexed_properties(interp.heap(), object) {
+                return false;
+            }
+        }
+        let key = index_key(interp, index);
+        return object::has_property(interp.heap(), object, key);
+    }
+    string::string_length(interp.heap(), object).is_some_and(|length| index < length)
+}
+
+pub fn get_index(interp: &mut Interpreter, object: Value, index: u32) -> VmResult<Value> {
+    if let Some(elements) = interp.heap().get::<JsObject>(object).and_then(JsObject::elements) {
+        if let Some(value) = elements.get(index) {
+            return Ok(value);
+        }
+        if ! array::chain_has_indexed_properties(interp.heap(), object) {
+            return Ok(Value::new_imm_undef());
+        }
+    }
+    let key = index_key(interp, index);
+    interp.get_property(object, key)
+}
+
+pub fn set_index(interp: &mut Interpreter, object: Value, index: u32, value: Value) -> VmResult<()> {
+    if index <= (i32::MAX as u32) && interp.set_element_fast(object, Value::new_imm_i32(index as i32), value) {
+        return Ok(());
+    }
+    let key = index_key(interp, index);
+    interp.set_property(object, key, value, true)
+}
+
+fn delete_index(interp: &mut Interpreter, object: Value, index: u32) -> VmResult<()> {
+    if let Some(elements) = interp.heap().get::<JsObject>(object).and_then(JsObject::elements) {
+        if ! elements.has(index) && ! array::chain_has_indexed_properties(interp.heap(), object) {
+            return Ok(());
+        }
+    }
+    let key = index_key(interp, index);
+    interp.delete_property(object, key, true)?;
+    Ok(())
+}
+
+// Move the property at one index to another, or delete the other if there's
+// none.
+fn move_index(interp: &mut Interpreter, object: Value, from: u32, to: u32) -> VmResult<()> {
+    if has_index(interp, object, from) {
+        let value = get_index(interp, object, from)?;
+        set_index(interp, object, to, value)
+    } else {
+        delete_index(interp, object, to)
+    }
+}
+
+// The callback argument of an iterating method.
+fn callback_arg(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let callback = args.arg(0);
+    if ! callback.is_callable(interp.heap()) {
+        let message = format!("{} is not a function", interp.display_string(callback));
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    Ok(callback)
+}
+
+/*****************************************************************************
+ **** Constructor ************************************************************
+ *****************************************************************************/
+
+// A single number argument is the new array's length, and any other
+// arguments its elements.
+fn construct_array(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    if args.len() == 1 {
+        if let Some(length) = args.arg(0).to_f64(interp.heap()) {
+            if ! array::is_valid_length(length) {
+                return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+            }
+            let array = new_array_from(interp, &[]);
+            set_created_length(interp, array, length as u32);
+            return Ok(array);
+        }
+    }
+    Ok(new_array_from(interp, args.args()))
+}
+
+fn is_array(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    Ok(Value::new_imm_bool(array::is_array(interp.heap(), args.arg(0))))
+}
+
+/*****************************************************************************
+ **** Prototype methods ******************************************************
+ *****************************************************************************/
+
+// Arrays are converted by `join`, if they have one.
+fn array_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.toString")?;
+    let join_key = string::atomize_str(interp.heap_mut(), "join");
+    let join = interp.get_property(this, join_key)?;
+    if join.is_callable(interp.heap()) {
+        return interp.call(join, this, &[]);
+    }
+    builtins::object::object_to_string(interp, args)
+}
+
+fn join(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.join")?;
+    let length = length_of(interp, this)?;
+    let separator: Vec<u16> = if args.arg(0).is_imm_undef() {
+        vec![b',' as u16]
+    } else {
+        let separator = interp.string_value(args.arg(0))?;
+        string::code_units(interp.heap(), separator).collect()
+    };
+    let mut units: Vec<u16> = Vec::new();
+    for index in 0..length {
+        if index > 0 {
+            units.extend_from_slice(&separator);
+        }
+        let element = get_index(interp, this, index)?;
+        if ! element.is_imm_undef_null() {
+            let text = interp.string_value(element)?;
+            units.extend(string::code_units(interp.heap(), text));
+        }
+        if units.len() > string::MAX_STRING_LENGTH as usize {
+            return Err(interp.error(ErrorKind::RangeError, "Invalid string length"));
+        }
+    }
+    Ok(string::new_string_from_units(interp.heap_mut(), &units))
+}
+
+fn push(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.push")?;
+    let length = length_of(interp, this)?;
+    if (length as u64) + (args.len() as u64) > (array::MAX_LENGTH as u64) {
+        return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+    }
+    for (offset, &value) in args.args().iter().enumerate() {
+        set_index(interp, this, length + offset as u32, value)?;
+    }
+    let new_length = length + args.len() as u32;
+    set_length(interp, this, new_length)?;
+    Ok(array::length_value(new_length))
+}
+
+fn pop(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.pop")?;
+    let length = length_of(interp, this)?;
+    if length == 0 {
+        set_length(interp, this, 0)?;
+        return Ok(Value::new_imm_undef());
+    }
+    let element = get_index(interp, this, length - 1)?;
+    let element = interp.heap_mut().root(element);
+    delete_index(interp, this, length - 1)?;
+    set_length(interp, this, length - 1)?;
+    Ok(element.get())
+}
+
+fn shift(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.shift")?;
+    let length = length_of(interp, this)?;
+    if length == 0 {
+        set_length(interp, this, 0)?;
+        return Ok(Value::new_imm_undef());
+    }
+    let first = get_index(interp, this, 0)?;
+    let first = interp.heap_mut().root(first);
+    for index in 1..length {
+        move_index(interp, this, index, index - 1)?;
+    }
+    delete_index(interp, this, length - 1)?;
+    set_length(interp, this, length - 1)?;
+    Ok(first.get())
+}
+
+fn unshift(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.unshift")?;
+    let length = length_of(interp, this)?;
+    let count = args.len() as u32;
+    if (length as u64) + (count as u64) > (array::MAX_LENGTH as u64) {
+        return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+    }
+    for index in (0..length).rev() {
+        move_index(interp, this, index, index + count)?;
+    }
+    for (index, &value) in args.args().iter().enumerate() {
+        set_index(interp, this, index as u32, value)?;
+    }
+    set_length(interp, this, length + count)?;
+    Ok(array::length_value(length + count))
+}
+
+fn slice(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.slice")?;
+    let length = length_of(interp, this)?;
+    let start = relative_index(interp, args.arg(0), length, 0)?;
+    let end = relative_index(interp, args.arg(1), length, length)?;
+    let result = new_array_from(interp, &[]);
+    let result = interp.heap_mut().root(result);
+    for index in start..end {
+        if has_index(interp, this, index) {
+            let element = get_index(interp, this, index)?;
+            create_index(interp, result.get(), index - start, element);
+        }
+    }
+    set_created_length(interp, result.get(), end.saturating_sub(start));
+    Ok(result.get())
+}
+
+// A missing delete count deletes to the end, as browsers do.
+fn splice(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.splice")?;
+    let length = length_of(interp, this)?;
+    let start = relative_index(interp, args.arg(0), length, 0)?;
+    let delete_count = if args.len() == 1 {
+        length - start
+    } else {
+        integer_value(interp, args.arg(1))?.max(0.0).min((length - start) as f64) as u32
+    };
+    let items = if args.len() > 2 { &args.args()[2..] } else { &[][..] };
+    let item_count = items.len() as u32;
+    if (length as u64) - (delete_count as u64) + (item_count as u64) > (array::MAX_LENGTH as u64) {
+        return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+    }
+
+    let removed = new_array_from(interp, &[]);
+    let removed = interp.heap_mut().root(removed);
+    for offset in 0..delete_count {
+        if has_index(interp, this, start + offset) {
+            let element = get_index(interp, this, start + offset)?;
+            create_index(interp, removed.get(), offset, element);
+        }
+    }
+    set_created_length(interp, removed.get(), delete_count);
+
+    if item_count < delete_count {
+        for index in start..(length - delete_count) {
+            move_index(interp, this, index + delete_count, index + item_count)?;
+        }
+        for index in ((length - delete_count + item_count)..length).rev() {
+            delete_index(interp, this, index)?;
+        }
+    } else if item_count > delete_count {
+        for index in (start..(length - delete_count)).rev() {
+            move_index(interp, this, index + delete_count, index + item_count)?;
+        }
+    }
+    for (offset, &item) in items.iter().enumerate() {
+        set_index(interp, this, start + offset as u32, item)?;
+    }
+    set_length(interp, this, length - delete_count + item_count)?;
+    Ok(removed.get())
+}
+
+// Arrays among the this value and arguments are spread into the result,
+// keeping their holes.  Other values are added as they are.
+fn concat(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.concat")?;
+    let result = new_array_from(interp, &[]);
+    let result = interp.heap_mut().root(result);
+    let mut length: u64 = 0;
+    for &item in Some(&this).into_iter().chain(args.args()) {
+        if array::is_array(interp.heap(), item) {
+            let item_length = length_of(interp, item)?;
+            if length + (item_length as u64) > (array::MAX_LENGTH as u64) {
+                return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+            }
+            for index in 0..item_length {
+                if has_index(interp, item, index) {
+                    let element = get_index(interp, item, index)?;
+                    create_index(interp, result.get(), length as u32 + index, element);
+                }
+            }
+            length += item_length as u64;
+        } else {
+            if length >= (array::MAX_LENGTH as u64) {
+                return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+            }
+            create_index(interp, result.get(), length as u32, item);
+            length += 1;
+        }
+    }
+    set_created_length(interp, result.get(), length as u32);
+    Ok(result.get())
+}
+
+fn reverse(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.reverse")?;
+    let length = length_of(interp, this)?;
+    let values = interp.heap_mut().root_vec(Vec::new());
+    for lower in 0..(length / 2) {
+        let upper = length - lower - 1;
+        let lower_value = if has_index(interp, this, lower) { Some(get_index(interp, this, lower)?) } else { None };
+        values.push(lower_value.unwrap_or(Value::new_imm_undef()));
+        let upper_value = if has_index(interp, this, upper) { Some(get_index(interp, this, upper)?) } else { None };
+        match upper_value {
+            Some(value) => set_index(interp, this, lower, value)?,
+            None => delete_index(interp, this, lower)?
+        }
+        match lower_value {
+            Some(value) => set_index(interp, this, upper, value)?,
+            None => delete_index(interp, this, upper)?
+        }
+    }
+    Ok(this)
+}
+
+/**
+ * A stable merge sort.  Undefined elements are sorted after all others, and
+ * holes after them.  Without a compare function, elements are ordered by
+ * the code units of their strings, which are computed once beforehand.
+ */
+fn sort(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.sort")?;
+    let compare_fn = args.arg(0);
+    if ! compare_fn.is_imm_undef() && ! compare_fn.is_callable(interp.heap()) {
+        return Err(interp.error(ErrorKind::TypeError,
+                                "The comparison function must be either a function or undefined"));
+    }
+    let length = length_of(interp, this)?;
+    let values = interp.heap_mut().root_vec(Vec::new());
+    let mut undefined_count = 0;
+    for index in 0..length {
+        if has_index(interp, this, index) {
+            let value = get_index(interp, this, index)?;
+            if value.is_imm_undef() {
+                undefined_count += 1;
+            } else {
+                values.push(value);
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    let mut scratch: Vec<usize> = Vec::with_capacity(order.len());
+    if compare_fn.is_imm_undef() {
+        let mut keys: Vec<Vec<u16>> = Vec::with_capacity(values.len());
+        for idx in 0..values.len() {
+            let key = interp.string_value(values.get(idx))?;
+            keys.push(string::code_units(interp.heap(), key).collect());
+        }
+        merge_sort(&mut order, &mut scratch, &mut |a, b| Ok(keys[a].cmp(&keys[b])))?;
+    } else {
+        merge_sort(&mut order, &mut scratch, &mut |a, b| {
+            let result = interp.call(compare_fn, Value::new_imm_undef(), &[values.get(a), values.get(b)])?;
+            let result = interp.number_value(result)?;
+            Ok(result.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+        })?;
+    }
+
+    let sorted_count = order.len() as u32;
+    for (index, &idx) in order.iter().enumerate() {
+        set_index(interp, this, index as u32, values.get(idx))?;
+    }
+    for index in sorted_count..(sorted_count + undefined_count) {
+        set_index(interp, this, index, Value::new_imm_undef())?;
+    }
+    for index in (sorted_count + undefined_count)..length {
+        delete_index(interp, this, index)?;
+    }
+    Ok(this)
+}
+
+fn merge_sort<F>(items: &mut [usize], scratch: &mut Vec<usize>, compare: &mut F) -> VmResult<()>
+    where F: FnMut(usize, usize) -> VmResult<Ordering>
+{
+    if items.len() <= 1 {
+        return Ok(());
+    }
+    let middle = items.len() / 2;
+    merge_sort(&mut items[..middle], scratch, compare)?;
+    merge_sort(&mut items[middle..], scratch, compare)?;
+
+    scratch.clear();
+    let (mut left, mut right) = (0, middle);
+    while left < middle && right < items.len() {
+        // Equal elements keep their order.
+        if compare(items[right], items[left])? == Ordering::Less {
+            scratch.push(items[right]);
+            right += 1;
+        } else {
+            scratch.push(items[left]);
+            left += 1;
+        }
+    }
+    scratch.extend_from_slice(&items[left..middle]);
+    scratch.extend_from_slice(&items[right..]);
+    items.copy_from_slice(scratch);
+    Ok(())
+}
+
+fn index_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.indexOf")?;
+    let length = length_of(interp, this)?;
+    let start = relative_index(interp, args.arg(1), length, 0)?;
+    for index in start..length {
+        if has_index(interp, this, index) {
+            let element = get_index(interp, this, index)?;
+            if element.strict_equals(args.arg(0), interp.heap()) {
+                return Ok(array::length_value(index));
+            }
+        }
+    }
+    Ok(Value::new_imm_i32(-1))
+}
+
+fn last_index_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Array.prototype.lastIndexOf")?;
+    let length = length_of(interp, this)?;
+    let not_found = Value::new_imm_i32(-1);
+    if length == 0 {
+        return Ok(not_found);
+    }
+    let start = if args.len() < 2 { (length - 1) as f64 } else { integer_value(interp, args.arg(1))? };
+    let start = if start < 0.0 { (length as f64) + start } else { start.min((length - 1) as f64) };
+    if start < 0.0 {
+        return Ok(not_found);
+    }
+    for index in (0..=(start as u32)).rev() {
+        if has_index(interp, this, index) {
+            let element = get_index(interp, this, index)?;
+            if element.strict_equals(args.arg(0), interp.heap()) {
+                return Ok(array::length_value(index));
+            }
+        }
+    }
+    Ok(not_found)
+}
+
+/**
+ * Call a callback with each present element, its index and the array, until
+ * it returns a result `stop` says to stop at.  Returns the array's length,
+ * and whether it stopped.
+ */
+fn each_element<F>(interp: &mut Interpreter, args: &CallArgs, method: &str, mut stop: F) -> VmResult<(u32, bool)>
+    where F: FnMut(&mut Interpreter, u32, Value, Value) -> bool
+{
+    let this = coercible_this(interp, args, method)?;
+    let length = length_of(interp, this)?;
+    let callback = callback_arg(interp, args)?;
+    for index in 0..length {
+        if has_index(interp, this, index) {
+            let element = get_index(interp, this, index)?;
+            let element = interp.heap_mut().root(element);
+            let result = interp.call(callback, args.arg(1), &[element.get(), array::length_value(index), this])?;
+            if stop(interp, index, element.get(), result) {
+                return Ok((length, true));
+            }
+        }
+    }
+    Ok((length, false))
+}
+
+fn every(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let (_, stopped) = each_element(interp, args, "Array.prototype.every", |interp, _, _, result| {
+        ! result.to_boolean(interp.heap())
+    })?;
+    Ok(Value::new_imm_bool(! stopped))
+}
+
+fn some(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let (_, stopped) = each_element(interp, args, "Array.prototype.some", |interp, _, _, result| {
+        result.to_boolean(interp.heap())
+    })?;
+    Ok(Value::new_imm_bool(stopped))
+}
+
+fn for_each(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    each_element(interp, args, "Array.prototype.forEach", |_, _, _, _| false)?;
+    Ok(Value::new_imm_undef())
+}
+
+// The results of the callback, at the indices of the elements it was called
+// with.
+fn map(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let result = new_array_from(interp, &[]);
+    let result = interp.heap_mut().root(result);
+    let (length, _) = each_element(interp, args, "Array.prototype.map", |interp, index, _, mapped| {
+        create_index(interp, result.get(), index, mapped);
+        false
+    })?;
+    set_created_length(interp, result.get(), length);
+    Ok(result.get())
+}
+
+fn filter(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let result = new_array_from(interp, &[]);
+    let result = interp.heap_mut().root(result);
+    let mut count = 0;
+    each_element(interp, args, "Array.prototype.filter", |interp, _, element, selected| {
+        if selected.to_boolean(interp.heap()) {
+            create_index(interp, result.get(), count, element);
+            count += 1;
+        }
+        false
+    })?;
+    Ok(result.get())
+}
+
+fn reduce(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    reduce_elements(interp, args, "Array.prototype.reduce", false)
+}
+
+fn reduce_right(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    reduce_elements(interp, args, "Array.prototype.reduceRight", true)
+}
+
+// Fold the present elements from either end, starting from the initial value
+// if there is one, or else the first element.
+fn reduce_elements(interp: &mut Interpreter, args: &CallArgs, method: &str, from_right: bool) -> VmResult<Value> {
+    let this = coercible_this(interp, args, method)?;
+    let length = length_of(interp, this)?;
+    let callback = callback_arg(interp, args)?;
+    let indices: Box<dyn Iterator<Item = u32>> = if from_right {
+        Box::new((0..length).rev())
+    } else {
+        Box::new(0..length)
+    };
+    let accumulator = interp.heap_mut().root(args.arg(1));
+    let mut has_accumulator = args.len() >= 2;
+    for index in indices {
+        if ! has_index(interp, this, index) {
+            continue;
+        }
+        let element = get_index(interp, this, index)?;
+        if ! has_accumulator {
+            accumulator.set(element);
+            has_accumulator = true;
+            continue;
+        }
+        let call_args = [accumulator.get(), element, array::length_value(index), this];
+        let result = interp.call(callback, Value::new_imm_undef(), &call_args)?;
+        accumulator.set(result);
+    }
+    if ! has_accumulator {
+        return Err(interp.error(ErrorKind::TypeError, "Reduce of empty array with no initial value"));
+    }
+    Ok(accumulator.get())
+}
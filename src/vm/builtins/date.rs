@@ -0,0 +1,453 @@
+/*!
+ * `Date` and `Date.prototype`.
+ *
+ * A Date object holds a time value.  The calendar arithmetic, parsing and
+ * formatting are done by `vm::time`; the methods here convert their
+ * arguments and find the fields of a date in UTC or in local time.
+ *
+ * Local time is the interpreter's time zone, and the current time comes from
+ * its clock.  An embedder can set either, so that scripts which use dates
+ * give the same results on every machine.
+ */
+
+use std::f64;
+use vm::builtins;
+use vm::builtins::error::ErrorKind;
+use vm::conversions::PreferredType;
+use vm::function::CallArgs;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, JsObject};
+use vm::string;
+use vm::time::{self, TimeZone};
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.date_prototype();
+    let constructor = builtins::define_constructor(interp, "Date", 7, construct_date, prototype);
+    builtins::define_method(interp, constructor, "parse", 1, parse);
+    builtins::define_method(interp, constructor, "UTC", 7, utc);
+    builtins::define_method(interp, constructor, "now", 0, now);
+
+    builtins::define_method(interp, prototype, "toString", 0, date_to_string);
+    builtins::define_method(interp, prototype, "toDateString", 0, to_date_string);
+    builtins::define_method(interp, prototype, "toTimeString", 0, to_time_string);
+    builtins::define_method(interp, prototype, "toLocaleString", 0, date_to_string);
+    builtins::define_method(interp, prototype, "toLocaleDateString", 0, to_date_string);
+    builtins::define_method(interp, prototype, "toLocaleTimeString", 0, to_time_string);
+    builtins::define_method(interp, prototype, "toISOString", 0, to_iso_string);
+    builtins::define_method(interp, prototype, "toJSON", 1, to_json);
+    builtins::define_method(interp, prototype, "valueOf", 0, get_time);
+    builtins::define_method(interp, prototype, "getTime", 0, get_time);
+    builtins::define_method(interp, prototype, "getTimezoneOffset", 0, get_timezone_offset);
+    // Annex B's toGMTString is the same function as toUTCString.
+    let to_utc_string_function = interp.new_native_function("toUTCString", 0, to_utc_string);
+    builtins::define_value(interp, prototype, "toUTCString", to_utc_string_function);
+    builtins::define_value(interp, prototype, "toGMTString", to_utc_string_function);
+
+    builtins::define_method(interp, prototype, "getFullYear", 0, get_full_year);
+    builtins::define_method(interp, prototype, "getUTCFullYear", 0, get_utc_full_year);
+    builtins::define_method(interp, prototype, "getYear", 0, get_year);
+    builtins::define_method(interp, prototype, "getMonth", 0, get_month);
+    builtins::define_method(interp, prototype, "getUTCMonth", 0, get_utc_month);
+    builtins::define_method(interp, prototype, "getDate", 0, get_date);
+    builtins::define_method(interp, prototype, "getUTCDate", 0, get_utc_date);
+    builtins::define_method(interp, prototype, "getDay", 0, get_day);
+    builtins::define_method(interp, prototype, "getUTCDay", 0, get_utc_day);
+    builtins::define_method(interp, prototype, "getHours", 0, get_hours);
+    builtins::define_method(interp, prototype, "getUTCHours", 0, get_utc_hours);
+    builtins::define_method(interp, prototype, "getMinutes", 0, get_minutes);
+    builtins::define_method(interp, prototype, "getUTCMinutes", 0, get_utc_minutes);
+    builtins::define_method(interp, prototype, "getSeconds", 0, get_seconds);
+    builtins::define_method(interp, prototype, "getUTCSeconds", 0, get_utc_seconds);
+    builtins::define_method(interp, prototype, "getMilliseconds", 0, get_milliseconds);
+    builtins::define_method(interp, prototype, "getUTCMilliseconds", 0, get_utc_milliseconds);
+
+    builtins::define_method(interp, prototype, "setTime", 1, set_time);
+    builtins::define_method(interp, prototype, "setFullYear", 3, set_full_year);
+    builtins::define_method(interp, prototype, "setUTCFullYear", 3, set_utc_full_year);
+    builtins::define_method(interp, prototype, "setYear", 1, set_year);
+    builtins::define_method(interp, prototype, "setMonth", 2, set_month);
+    builtins::define_method(interp, prototype, "setUTCMonth", 2, set_utc_month);
+    builtins::define_method(interp, prototype, "setDate", 1, set_date);
+    builtins::define_method(interp, prototype, "setUTCDate", 1, set_utc_date);
+    builtins::define_method(interp, prototype, "setHours", 4, set_hours);
+    builtins::define_method(interp, prototype, "setUTCHours", 4, set_utc_hours);
+    builtins::define_method(interp, prototype, "setMinutes", 3, set_minutes);
+    builtins::define_method(interp, prototype, "setUTCMinutes", 3, set_utc_minutes);
+    builtins::define_method(interp, prototype, "setSeconds", 2, set_seconds);
+    builtins::define_method(interp, prototype, "setUTCSeconds", 2, set_utc_seconds);
+    builtins::define_method(interp, prototype, "setMilliseconds", 1, set_milliseconds);
+    builtins::define_method(interp, prototype, "setUTCMilliseconds", 1, set_utc_milliseconds);
+}
+
+/** A new Date object with a time value. */
+pub fn new_date(interp: &mut Interpreter, time_value: f64) -> Value {
+    let prototype = interp.date_prototype();
+    object::new_date(interp.heap_mut(), prototype, time_value)
+}
+
+/** The time value of a value, if it's a Date object. */
+pub fn time_value_of(interp: &Interpreter, value: Value) -> Option<f64> {
+    interp.heap().get::<JsObject>(value).and_then(JsObject::time_value)
+}
+
+// The fields of a date, in the order the constructor and `Date.UTC` take
+// them.
+const YEAR: usize = 0;
+const MONTH: usize = 1;
+const DATE: usize = 2;
+const HOURS: usize = 3;
+const MINUTES: usize = 4;
+const SECONDS: usize = 5;
+const MILLISECONDS: usize = 6;
+
+fn date_fields(t: f64) -> [f64; 7] {
+    [time::year_from_time(t), time::month_from_time(t), time::date_from_time(t), time::hour_from_time(t),
+     time::min_from_time(t), time::sec_from_time(t), time::ms_from_time(t)]
+}
+
+fn make_date_from_fields(fields: &[f64; 7]) -> f64 {
+    let day = time::make_day(fields[YEAR], fields[MONTH], fields[DATE]);
+    let time_of_day = time::make_time(fields[HOURS], fields[MINUTES], fields[SECONDS], fields[MILLISECONDS]);
+    time::make_date(day, time_of_day)
+}
+
+/**
+ * The fields the constructor and `Date.UTC` are given.  A missing date is
+ * the first of the month, and missing times are zero.  Years from 0 to 99
+ * are in the 1900s.
+ */
+fn fields_from_args(interp: &mut Interpreter, args: &CallArgs) -> VmResult<[f64; 7]> {
+    let mut fields = [f64::NAN, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+    for (index, field) in fields.iter_mut().enumerate().take(args.len()) {
+        *field = interp.number_value(args.arg(index))?;
+    }
+    let year = fields[YEAR];
+    if ! year.is_nan() && (0.0..=99.0).contains(&year.trunc()) {
+        fields[YEAR] = 1900.0 + year.trunc();
+    }
+    Ok(fields)
+}
+
+fn number_result(interp: &mut Interpreter, fval: f64) -> VmResult<Value> {
+    Ok(Value::new_number(interp.heap_mut(), fval))
+}
+
+fn string_result(interp: &mut Interpreter, text: &str) -> VmResult<Value> {
+    Ok(string::new_string(interp.heap_mut(), text))
+}
+
+/*****************************************************************************
+ **** Constructor ************************************************************
+ *****************************************************************************/
+
+/**
+ * Called as a function, `Date` gives the current time as a string.  `new
+ * Date` takes a time value, a string to parse, another Date or the fields of
+ * a local time, and defaults to the current time.
+ */
+fn construct_date(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    if ! args.is_construct() {
+        let now = interp.current_time();
+        let text = time::to_date_time_string(now, interp.time_zone());
+        return string_result(interp, &text);
+    }
+    let time_value = match args.len() {
+        0 => interp.current_time(),
+        1 => {
+            let value = args.arg(0);
+            match time_value_of(interp, value) {
+                Some(time_value) => time_value,
+                None => {
+                    let value = interp.primitive_value(value, PreferredType::Default)?;
+                    if value.is_string(interp.heap()) {
+                        parse_string(interp, value)
+                    } else {
+                        time::time_clip(interp.number_value(value)?)
+                    }
+                }
+            }
+        }
+        _ => {
+            let fields = fields_from_args(interp, args)?;
+            time::time_clip(interp.time_zone().utc_time(make_date_from_fields(&fields)))
+        }
+    };
+    Ok(new_date(interp, time_value))
+}
+
+fn parse_string(interp: &Interpreter, text: Value) -> f64 {
+    let units: Vec<u16> = string::code_units(interp.heap(), text).collect();
+    time::parse(&String::from_utf16_lossy(&units), interp.time_zone())
+}
+
+fn parse(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let text = interp.string_value(args.arg(0))?;
+    let time_value = parse_string(interp, text);
+    number_result(interp, time_value)
+}
+
+// The time value of the fields of a UTC time.
+fn utc(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fields = fields_from_args(interp, args)?;
+    number_result(interp, time::time_clip(make_date_from_fields(&fields)))
+}
+
+fn now(interp: &mut Interpreter, _args: &CallArgs) -> VmResult<Value> {
+    let now = interp.current_time();
+    number_result(interp, now)
+}
+
+/*****************************************************************************
+ **** Prototype methods ******************************************************
+ *****************************************************************************/
+
+// The time value of the this value, which must be a Date.
+fn this_time_value(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<f64> {
+    match time_value_of(interp, args.this()) {
+        Some(time_value) => Ok(time_value),
+        None => {
+            let message = format!("Date.prototype.{} called on incompatible receiver {}", method,
+                                  interp.display_string(args.this()));
+            Err(interp.error(ErrorKind::TypeError, &message))
+        }
+    }
+}
+
+// A string of the this value's time value, or "Invalid Date".
+fn format_this(interp: &mut Interpreter, args: &CallArgs, method: &str, format: fn(f64, &TimeZone) -> String)
+    -> VmResult<Value>
+{
+    let time_value = this_time_value(interp, args, method)?;
+    if time_value.is_nan() {
+        return string_result(interp, "Invalid Date");
+    }
+    let text = format(time_value, interp.time_zone());
+    string_result(interp, &text)
+}
+
+fn date_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    format_this(interp, args, "toString", time::to_date_time_string)
+}
+
+fn to_date_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    format_this(interp, args, "toDateString", |t, zone| time::to_date_string(zone.local_time(t)))
+}
+
+fn to_time_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    format_this(interp, args, "toTimeString", time::to_time_string)
+}
+
+fn to_utc_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    format_this(interp, args, "toUTCString", |t, _| time::to_utc_string(t))
+}
+
+fn to_iso_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let time_value = this_time_value(interp, args, "toISOString")?;
+    if time_value.is_nan() {
+        return Err(interp.error(ErrorKind::RangeError, "Invalid time value"));
+    }
+    string_result(interp, &time::to_iso_string(time_value))
+}
+
+/**
+ * `JSON.stringify`'s hook: the result of `toISOString`, or null for a date
+ * which isn't valid.  It works on any object with a `toISOString` method.
+ */
+fn to_json(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = args.this();
+    let primitive = interp.primitive_value(this, PreferredType::Number)?;
+    if let Some(fval) = primitive.to_f64(interp.heap()) {
+        if ! fval.is_finite() {
+            return Ok(Value::new_imm_null());
+        }
+    }
+    let key = string::atomize_str(interp.heap_mut(), "toISOString");
+    let method = interp.get_property(this, key)?;
+    if ! method.is_callable(interp.heap()) {
+        return Err(interp.error(ErrorKind::TypeError, "toISOString is not a function"));
+    }
+    interp.call(method, this, &[])
+}
+
+fn get_time(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let time_value = this_time_value(interp, args, "getTime")?;
+    number_result(interp, time_value)
+}
+
+// The minutes UTC is ahead of local time.
+fn get_timezone_offset(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let time_value = this_time_value(interp, args, "getTimezoneOffset")?;
+    let offset = (time_value - interp.time_zone().local_time(time_value)) / time::MS_PER_MINUTE;
+    number_result(interp, offset)
+}
+
+/****
+ **** Getters
+ ****/
+
+// A field of the this value's time value, in local time or UTC.
+fn get_field(interp: &mut Interpreter, args: &CallArgs, method: &str, local: bool, field: fn(f64) -> f64)
+    -> VmResult<Value>
+{
+    let time_value = this_time_value(interp, args, method)?;
+    if time_value.is_nan() {
+        return number_result(interp, f64::NAN);
+    }
+    let time_value = if local { interp.time_zone().local_time(time_value) } else { time_value };
+    number_result(interp, field(time_value))
+}
+
+fn get_full_year(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getFullYear", true, time::year_from_time)
+}
+fn get_utc_full_year(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCFullYear", false, time::year_from_time)
+}
+// Annex B: the year less 1900.
+fn get_year(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getYear", true, |t| time::year_from_time(t) - 1900.0)
+}
+fn get_month(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getMonth", true, time::month_from_time)
+}
+fn get_utc_month(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCMonth", false, time::month_from_time)
+}
+fn get_date(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getDate", true, time::date_from_time)
+}
+fn get_utc_date(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCDate", false, time::date_from_time)
+}
+fn get_day(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getDay", true, time::week_day)
+}
+fn get_utc_day(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCDay", false, time::week_day)
+}
+fn get_hours(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getHours", true, time::hour_from_time)
+}
+fn get_utc_hours(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCHours", false, time::hour_from_time)
+}
+fn get_minutes(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getMinutes", true, time::min_from_time)
+}
+fn get_utc_minutes(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCMinutes", false, time::min_from_time)
+}
+fn get_seconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getSeconds", true, time::sec_from_time)
+}
+fn get_utc_seconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCSeconds", false, time::sec_from_time)
+}
+fn get_milliseconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getMilliseconds", true, time::ms_from_time)
+}
+fn get_utc_milliseconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    get_field(interp, args, "getUTCMilliseconds", false, time::ms_from_time)
+}
+
+/****
+ **** Setters
+ ****/
+
+fn set_this_time_value(interp: &mut Interpreter, args: &CallArgs, time_value: f64) -> VmResult<Value> {
+    interp.heap_mut().get_mut::<JsObject>(args.this()).unwrap().set_time_value(time_value);
+    number_result(interp, time_value)
+}
+
+fn set_time(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    this_time_value(interp, args, "setTime")?;
+    let time_value = interp.number_value(args.arg(0))?;
+    set_this_time_value(interp, args, time::time_clip(time_value))
+}
+
+/**
+ * Set some of the fields of the this value's time value, in local time or
+ * UTC: those from `first`, up to `count` of them, one for each argument
+ * given.  Every setter but the year's leaves an invalid date invalid, though
+ * it still converts its arguments.
+ */
+fn set_fields(interp: &mut Interpreter, args: &CallArgs, method: &str, local: bool, first: usize, count: usize)
+    -> VmResult<Value>
+{
+    let time_value = this_time_value(interp, args, method)?;
+    let mut values = Vec::with_capacity(count);
+    for index in 0..args.len().clamp(1, count) {
+        values.push(interp.number_value(args.arg(index))?);
+    }
+    let time_value = match (time_value.is_nan(), first) {
+        (true, YEAR) => 0.0,
+        (true, _) => { return number_result(interp, f64::NAN); }
+        (false, _) if local => interp.time_zone().local_time(time_value),
+        (false, _) => time_value
+    };
+    let mut fields = date_fields(time_value);
+    fields[first..(first + values.len())].copy_from_slice(&values);
+    let new_time_value = make_date_from_fields(&fields);
+    let new_time_value = if local { interp.time_zone().utc_time(new_time_value) } else { new_time_value };
+    set_this_time_value(interp, args, time::time_clip(new_time_value))
+}
+
+fn set_full_year(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setFullYear", true, YEAR, 3)
+}
+fn set_utc_full_year(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCFullYear", false, YEAR, 3)
+}
+fn set_month(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setMonth", true, MONTH, 2)
+}
+fn set_utc_month(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCMonth", false, MONTH, 2)
+}
+fn set_date(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setDate", true, DATE, 1)
+}
+fn set_utc_date(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCDate", false, DATE, 1)
+}
+fn set_hours(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setHours", true, HOURS, 4)
+}
+fn set_utc_hours(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCHours", false, HOURS, 4)
+}
+fn set_minutes(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setMinutes", true, MINUTES, 3)
+}
+fn set_utc_minutes(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCMinutes", false, MINUTES, 3)
+}
+fn set_seconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setSeconds", true, SECONDS, 2)
+}
+fn set_utc_seconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCSeconds", false, SECONDS, 2)
+}
+fn set_milliseconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setMilliseconds", true, MILLISECONDS, 1)
+}
+fn set_utc_milliseconds(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    set_fields(interp, args, "setUTCMilliseconds", false, MILLISECONDS, 1)
+}
+
+// Annex B: years from 0 to 99 are in the 1900s, and an invalid date becomes
+// valid.
+fn set_year(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let time_value = this_time_value(interp, args, "setYear")?;
+    let year = interp.number_value(args.arg(0))?;
+    if year.is_nan() {
+        return set_this_time_value(interp, args, f64::NAN);
+    }
+    let year = if (0.0..=99.0).contains(&year.trunc()) { 1900.0 + year.trunc() } else { year };
+    let local = if time_value.is_nan() { 0.0 } else { interp.time_zone().local_time(time_value) };
+    let mut fields = date_fields(local);
+    fields[YEAR] = year;
+    let new_time_value = interp.time_zone().utc_time(make_date_from_fields(&fields));
+    set_this_time_value(interp, args, time::time_clip(new_time_value))
+}
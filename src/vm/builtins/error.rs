@@ -0,0 +1,142 @@
+/*!
+ * Error objects.
+ *
+ * `Error` and its native subclasses each have a constructor and a prototype,
+ * with the subclasses' prototypes inheriting from `Error.prototype`.  Errors
+ * have the class `Error`, an own `message` when they're made with one, and a
+ * `stack`: their kind and message, followed by the script frames which were
+ * running when they were made.
+ */
+
+use vm::builtins::{self, coercible_this};
+use vm::function::CallArgs;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, JsObject, ObjectClass};
+use vm::string;
+use vm::value::Value;
+
+/** The kinds of error the runtime throws, and scripts can construct. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Error,
+    TypeError,
+    RangeError,
+    SyntaxError,
+    ReferenceError
+}
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 5] = [
+        ErrorKind::Error,
+        ErrorKind::TypeError,
+        ErrorKind::RangeError,
+        ErrorKind::SyntaxError,
+        ErrorKind::ReferenceError
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Error => "Error",
+            ErrorKind::TypeError => "TypeError",
+            ErrorKind::RangeError => "RangeError",
+            ErrorKind::SyntaxError => "SyntaxError",
+            ErrorKind::ReferenceError => "ReferenceError"
+        }
+    }
+}
+
+pub fn install(interp: &mut Interpreter) {
+    let constructors = [construct_error, construct_type_error, construct_range_error,
+                        construct_syntax_error, construct_reference_error];
+    for (&kind, &call) in ErrorKind::ALL.iter().zip(constructors.iter()) {
+        let prototype = interp.error_prototype(kind);
+        builtins::define_constructor(interp, kind.name(), 1, call, prototype);
+        let name = string::new_string(interp.heap_mut(), kind.name());
+        builtins::define_value(interp, prototype, "name", name);
+        let message = string::new_string(interp.heap_mut(), "");
+        builtins::define_value(interp, prototype, "message", message);
+    }
+    let error_prototype = interp.error_prototype(ErrorKind::Error);
+    builtins::define_method(interp, error_prototype, "toString", 0, error_to_string);
+}
+
+/**
+ * A new error, with the message unless it's undefined.  Its stack is taken
+ * from the interpreter's frames.
+ */
+pub fn new_error(interp: &mut Interpreter, kind: ErrorKind, message: Value) -> Value {
+    let prototype = interp.error_prototype(kind);
+    let error = object::new_object(interp.heap_mut(), prototype);
+    interp.heap_mut().get_mut::<JsObject>(error).unwrap().set_class(ObjectClass::Error);
+
+    let mut stack = kind.name().to_string();
+    if ! message.is_imm_undef() {
+        builtins::define_value(interp, error, "message", message);
+        let message = string::to_rust_string(interp.heap(), message).unwrap();
+        if ! message.is_empty() {
+            stack = format!("{}: {}", stack, message);
+        }
+    }
+    for frame in interp.stack_trace() {
+        stack = format!("{}\n    {}", stack, frame);
+    }
+    let stack = string::new_string(interp.heap_mut(), &stack);
+    builtins::define_value(interp, error, "stack", stack);
+    error
+}
+
+// Error constructors make a new error whether or not they're called by `new`.
+fn construct(interp: &mut Interpreter, args: &CallArgs, kind: ErrorKind) -> VmResult<Value> {
+    let message = if args.arg(0).is_imm_undef() {
+        args.arg(0)
+    } else {
+        interp.string_value(args.arg(0))?
+    };
+    Ok(new_error(interp, kind, message))
+}
+fn construct_error(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    construct(interp, args, ErrorKind::Error)
+}
+fn construct_type_error(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    construct(interp, args, ErrorKind::TypeError)
+}
+fn construct_range_error(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    construct(interp, args, ErrorKind::RangeError)
+}
+fn construct_syntax_error(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    construct(interp, args, ErrorKind::SyntaxError)
+}
+fn construct_reference_error(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    construct(interp, args, ErrorKind::ReferenceError)
+}
+
+// Error.prototype.toString: the name and message, either of which may be
+// empty.
+fn error_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Error.prototype.toString")?;
+    let this = builtins::object_arg(interp, this, "Error.prototype.toString")?;
+    let name = string_property(interp, this, "name", "Error")?;
+    let name = interp.heap_mut().root(name);
+    let message = string_property(interp, this, "message", "")?;
+
+    let heap = interp.heap_mut();
+    let name = name.get();
+    if string::string_length(heap, name) == Some(0) {
+        return Ok(message);
+    }
+    if string::string_length(heap, message) == Some(0) {
+        return Ok(name);
+    }
+    let separator = string::new_string(heap, ": ");
+    let prefix = string::concat(heap, name, separator);
+    Ok(string::concat(heap, prefix, message))
+}
+
+// A property converted to a string, or a default if it's undefined.
+fn string_property(interp: &mut Interpreter, object: Value, name: &str, default: &str) -> VmResult<Value> {
+    let key = string::atomize_str(interp.heap_mut(), name);
+    let value = interp.get_property(object, key)?;
+    if value.is_imm_undef() {
+        return Ok(string::new_string(interp.heap_mut(), default));
+    }
+    interp.string_value(value)
+}
@@ -0,0 +1,96 @@
+/*!
+ * `Function` and `Function.prototype`: `call`, `apply` and `bind`.
+ *
+ * Functions can't be made from source text at run time, so the `Function`
+ * constructor only throws.
+ */
+
+use vm::array;
+use vm::builtins;
+use vm::builtins::error::ErrorKind;
+use vm::function::{self, BoundFunction, CallArgs, Function};
+use vm::heap::RootedVec;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, JsObject, Property};
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.function_prototype();
+    builtins::define_constructor(interp, "Function", 1, construct_function, prototype);
+    builtins::define_method(interp, prototype, "toString", 0, function_to_string);
+    builtins::define_method(interp, prototype, "call", 1, call);
+    builtins::define_method(interp, prototype, "apply", 2, apply);
+    builtins::define_method(interp, prototype, "bind", 1, bind);
+}
+
+fn construct_function(interp: &mut Interpreter, _args: &CallArgs) -> VmResult<Value> {
+    Err(interp.error(ErrorKind::TypeError, "Code generation from strings is not supported"))
+}
+
+// The this value of a method which only works on functions.
+fn callable_this(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<Value> {
+    let this = args.this();
+    if ! this.is_callable(interp.heap()) {
+        let message = format!("{} called on non-function {}", method, interp.display_string(this));
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    Ok(this)
+}
+
+fn function_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = callable_this(interp, args, "Function.prototype.toString")?;
+    Ok(this.to_string(interp.heap_mut()))
+}
+
+fn call(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = callable_this(interp, args, "Function.prototype.call")?;
+    let rest = if args.is_empty() { &[][..] } else { &args.args()[1..] };
+    interp.call(this, args.arg(0), rest)
+}
+
+fn apply(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = callable_this(interp, args, "Function.prototype.apply")?;
+    let call_args = list_from_array_like(interp, args.arg(1))?;
+    let call_args = call_args.values().borrow().clone();
+    interp.call(this, args.arg(0), &call_args)
+}
+
+/**
+ * The elements of an array-like object, for `apply`: the values of its
+ * indexed properties below its `length`.  Undefined and null give no values.
+ */
+pub fn list_from_array_like(interp: &mut Interpreter, value: Value) -> VmResult<RootedVec> {
+    let list = interp.heap_mut().root_vec(Vec::new());
+    if value.is_imm_undef_null() {
+        return Ok(list);
+    }
+    if ! object::is_object(interp.heap(), value) {
+        return Err(interp.error(ErrorKind::TypeError, "CreateListFromArrayLike called on non-object"));
+    }
+    let length = interp.get_property(value, array::length_key())?;
+    let length = interp.uint32_value(length)?;
+    for index in 0..length {
+        let element = builtins::array::get_index(interp, value, index)?;
+        list.push(element);
+    }
+    Ok(list)
+}
+
+// A bound function's length is what's left of its target's after the bound
+// arguments.
+fn bind(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let target = callable_this(interp, args, "Function.prototype.bind")?;
+    let bound_args = if args.is_empty() { Vec::new() } else { args.args()[1..].to_vec() };
+    let target_length = match object::get_own_property(interp.heap_mut(), target, array::length_key()) {
+        Some(Property::Data { value, .. }) => value.to_f64(interp.heap()).unwrap_or(0.0),
+        _ => 0.0
+    };
+    let length = (target_length - bound_args.len() as f64).max(0.0);
+
+    let constructor = interp.heap().get::<JsObject>(target).unwrap().function().unwrap().is_constructor();
+    let bound = BoundFunction::new(target, args.arg(0), bound_args, constructor);
+    let prototype = interp.function_prototype();
+    let func = function::new_function(interp.heap_mut(), prototype, Function::Bound(bound));
+    interp.define_function_length(func, length as u32);
+    Ok(func)
+}
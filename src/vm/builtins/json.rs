@@ -0,0 +1,576 @@
+/*!
+ * The `JSON` object.
+ *
+ * `JSON.parse` reads JSON text straight into values: objects, arrays,
+ * strings and numbers are made as it goes, with no intermediate tree.  It
+ * runs no script until the text is read, so nothing it makes can be
+ * collected before then.  A reviver is then called on each value, innermost
+ * first, as the spec's Walk does.
+ *
+ * `JSON.stringify` writes the text as code units, calling `toJSON` methods
+ * and the replacer function on the way.  The objects being written are kept
+ * on a rooted stack, which also finds cycles.
+ *
+ * Both nest at most `MAX_DEPTH` deep, throwing a RangeError beyond that
+ * rather than exhausting the native stack.
+ */
+
+use std::char;
+use vm::array;
+use vm::builtins::{self, integer_value};
+use vm::builtins::array::{get_index, index_key, length_of};
+use vm::builtins::error::ErrorKind;
+use vm::builtins::object::{define_own_property, enumerable_own_keys, PropertyDescriptor};
+use vm::conversions::{number_to_string, string_to_number};
+use vm::function::CallArgs;
+use vm::heap::RootedVec;
+use vm::interpreter::{Exception, Interpreter, VmResult};
+use vm::object::{self, JsObject, ObjectClass, Property};
+use vm::shape::PropertyAttributes;
+use vm::string;
+use vm::value::Value;
+
+const MAX_DEPTH: usize = 2000;
+
+// The most chars of indentation a `space` argument gives.
+const MAX_GAP: usize = 10;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.object_prototype();
+    let json = object::new_object(interp.heap_mut(), prototype);
+    interp.heap_mut().get_mut::<JsObject>(json).unwrap().set_class(ObjectClass::Json);
+    let global = interp.global_object();
+    builtins::define_value(interp, global, "JSON", json);
+
+    builtins::define_method(interp, json, "parse", 2, parse);
+    builtins::define_method(interp, json, "stringify", 3, stringify);
+}
+
+fn too_deep(interp: &mut Interpreter) -> Exception {
+    interp.error(ErrorKind::RangeError, "Maximum call stack size exceeded")
+}
+
+/*****************************************************************************
+ **** JSON.parse *************************************************************
+ *****************************************************************************/
+
+struct Parser<'a> {
+    units: &'a [u16],
+    position: usize,
+    depth: usize
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u16> {
+        self.units.get(self.position).cloned()
+    }
+
+    fn skip_white_space(&mut self) {
+        while let Some(unit) = self.peek() {
+            if unit != 0x09 && unit != 0x0A && unit != 0x0D && unit != 0x20 {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    // Whether the next code unit is `unit`, consuming it if so.
+    fn eat(&mut self, unit: u8) -> bool {
+        if self.peek() == Some(unit as u16) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_digits(&mut self) -> usize {
+        let start = self.position;
+        while self.peek().is_some_and(|unit| (0x30..=0x39).contains(&unit)) {
+            self.position += 1;
+        }
+        self.position - start
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let units = word.as_bytes();
+        let end = self.position + units.len();
+        if end > self.units.len() || ! self.units[self.position..end].iter().zip(units).all(|(&a, &b)| a == b as u16) {
+            return false;
+        }
+        self.position = end;
+        true
+    }
+}
+
+// The error for the code unit at the parser's position, or the end of input.
+fn unexpected(interp: &mut Interpreter, parser: &Parser) -> Exception {
+    let message = match parser.peek() {
+        None => "Unexpected end of JSON input".to_string(),
+        Some(unit) => {
+            let ch = char::from_u32(unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+            format!("Unexpected token {} in JSON at position {}", ch, parser.position)
+        }
+    };
+    interp.error(ErrorKind::SyntaxError, &message)
+}
+
+fn parse_value(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<Value> {
+    parser.skip_white_space();
+    match parser.peek() {
+        Some(0x7B) => parse_object(interp, parser),
+        Some(0x5B) => parse_array(interp, parser),
+        Some(0x22) => {
+            let units = parse_string(interp, parser)?;
+            Ok(string::new_string_from_units(interp.heap_mut(), &units))
+        }
+        Some(0x2D) | Some(0x30..=0x39) => parse_number(interp, parser),
+        _ if parser.eat_word("true") => Ok(Value::new_imm_bool(true)),
+        _ if parser.eat_word("false") => Ok(Value::new_imm_bool(false)),
+        _ if parser.eat_word("null") => Ok(Value::new_imm_null()),
+        _ => Err(unexpected(interp, parser))
+    }
+}
+
+fn enter(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<()> {
+    parser.depth += 1;
+    if parser.depth > MAX_DEPTH {
+        return Err(too_deep(interp));
+    }
+    parser.position += 1;
+    parser.skip_white_space();
+    Ok(())
+}
+
+fn parse_object(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<Value> {
+    enter(interp, parser)?;
+    let prototype = interp.object_prototype();
+    let result = object::new_object(interp.heap_mut(), prototype);
+    if ! parser.eat(b'}') {
+        loop {
+            parser.skip_white_space();
+            if parser.peek() != Some(0x22) {
+                return Err(unexpected(interp, parser));
+            }
+            let name = parse_string(interp, parser)?;
+            parser.skip_white_space();
+            if ! parser.eat(b':') {
+                return Err(unexpected(interp, parser));
+            }
+            let value = parse_value(interp, parser)?;
+            // Later duplicates replace earlier ones.
+            let key = string::atomize_units(interp.heap_mut(), &name);
+            object::define_own_property(interp.heap_mut(), result, key,
+                                        Property::Data { value, attrs: PropertyAttributes::DEFAULT });
+            parser.skip_white_space();
+            if parser.eat(b'}') {
+                break;
+            }
+            if ! parser.eat(b',') {
+                return Err(unexpected(interp, parser));
+            }
+        }
+    }
+    parser.depth -= 1;
+    Ok(result)
+}
+
+fn parse_array(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<Value> {
+    enter(interp, parser)?;
+    let mut elements: Vec<Value> = Vec::new();
+    if ! parser.eat(b']') {
+        loop {
+            elements.push(parse_value(interp, parser)?);
+            parser.skip_white_space();
+            if parser.eat(b']') {
+                break;
+            }
+            if ! parser.eat(b',') {
+                return Err(unexpected(interp, parser));
+            }
+        }
+    }
+    parser.depth -= 1;
+    Ok(builtins::array::new_array_from(interp, &elements))
+}
+
+// A string's code units, from its opening quote to its closing one.
+fn parse_string(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<Vec<u16>> {
+    parser.position += 1;
+    let mut units: Vec<u16> = Vec::new();
+    loop {
+        let unit = match parser.peek() {
+            Some(unit) if unit >= 0x20 => unit,
+            _ => { return Err(unexpected(interp, parser)); }
+        };
+        parser.position += 1;
+        match unit {
+            0x22 => { return Ok(units); }
+            0x5C => {
+                match parser.peek().and_then(escaped_unit) {
+                    Some(escaped) => {
+                        parser.position += 1;
+                        units.push(escaped);
+                    }
+                    None if parser.eat(b'u') => units.push(parse_hex4(interp, parser)?),
+                    None => { return Err(unexpected(interp, parser)); }
+                }
+            }
+            _ => units.push(unit)
+        }
+    }
+}
+
+// The code unit a single-char escape stands for.
+fn escaped_unit(unit: u16) -> Option<u16> {
+    match unit {
+        0x22 | 0x5C | 0x2F => Some(unit),
+        0x62 => Some(0x08),
+        0x66 => Some(0x0C),
+        0x6E => Some(0x0A),
+        0x72 => Some(0x0D),
+        0x74 => Some(0x09),
+        _ => None
+    }
+}
+
+// The four hex digits of a `\u` escape.
+fn parse_hex4(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<u16> {
+    let mut unit: u16 = 0;
+    for _ in 0..4 {
+        let digit = parser.peek().and_then(|digit| char::from_u32(digit as u32)).and_then(|ch| ch.to_digit(16));
+        match digit {
+            Some(digit) => { unit = (unit << 4) | (digit as u16); }
+            None => { return Err(unexpected(interp, parser)); }
+        }
+        parser.position += 1;
+    }
+    Ok(unit)
+}
+
+fn parse_number(interp: &mut Interpreter, parser: &mut Parser) -> VmResult<Value> {
+    let start = parser.position;
+    parser.eat(b'-');
+    if ! parser.eat(b'0') && parser.eat_digits() == 0 {
+        return Err(unexpected(interp, parser));
+    }
+    if parser.eat(b'.') && parser.eat_digits() == 0 {
+        return Err(unexpected(interp, parser));
+    }
+    if parser.eat(b'e') || parser.eat(b'E') {
+        if ! parser.eat(b'+') {
+            parser.eat(b'-');
+        }
+        if parser.eat_digits() == 0 {
+            return Err(unexpected(interp, parser));
+        }
+    }
+    let fval = string_to_number(&parser.units[start..parser.position]);
+    Ok(Value::new_number(interp.heap_mut(), fval))
+}
+
+fn parse(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let text = interp.string_value(args.arg(0))?;
+    let units: Vec<u16> = string::code_units(interp.heap(), text).collect();
+    let mut parser = Parser { units: &units, position: 0, depth: 0 };
+    let result = parse_value(interp, &mut parser)?;
+    parser.skip_white_space();
+    if parser.peek().is_some() {
+        return Err(unexpected(interp, &parser));
+    }
+
+    let reviver = args.arg(1);
+    if ! reviver.is_callable(interp.heap()) {
+        return Ok(result);
+    }
+    let prototype = interp.object_prototype();
+    let root = object::new_object(interp.heap_mut(), prototype);
+    let empty_key = string::atomize_str(interp.heap_mut(), "");
+    object::define_own_property(interp.heap_mut(), root, empty_key,
+                                Property::Data { value: result, attrs: PropertyAttributes::DEFAULT });
+    let root = interp.heap_mut().root(root);
+    walk(interp, reviver, root.get(), empty_key, 0)
+}
+
+// Revive the property of `holder` named `key`, after reviving the properties
+// of its value.  `holder` is rooted by the caller; keys are atoms.
+fn walk(interp: &mut Interpreter, reviver: Value, holder: Value, key: Value, depth: usize) -> VmResult<Value> {
+    if depth > MAX_DEPTH {
+        return Err(too_deep(interp));
+    }
+    let value = interp.get_property(holder, key)?;
+    let value = interp.heap_mut().root(value);
+    if object::is_object(interp.heap(), value.get()) {
+        let keys = if array::is_array(interp.heap(), value.get()) {
+            let length = length_of(interp, value.get())?;
+            (0..length).map(|index| index_key(interp, index)).collect()
+        } else {
+            enumerable_own_keys(interp, value.get())
+        };
+        for element_key in keys {
+            let revived = walk(interp, reviver, value.get(), element_key, depth + 1)?;
+            if revived.is_imm_undef() {
+                interp.delete_property(value.get(), element_key, false)?;
+            } else {
+                let desc = PropertyDescriptor {
+                    value: Some(revived),
+                    writable: Some(true),
+                    enumerable: Some(true),
+                    configurable: Some(true),
+                    ..PropertyDescriptor::default()
+                };
+                define_own_property(interp, value.get(), element_key, &desc)?;
+            }
+        }
+    }
+    interp.call(reviver, holder, &[key, value.get()])
+}
+
+/*****************************************************************************
+ **** JSON.stringify *********************************************************
+ *****************************************************************************/
+
+struct Stringifier {
+    replacer: Option<Value>,
+    // The keys to write for every object, from a replacer array.  They're
+    // atoms, so need no rooting.
+    property_list: Option<Vec<Value>>,
+    gap: Vec<u16>,
+    indent: Vec<u16>,
+    // The objects being written, outermost first.
+    stack: RootedVec,
+    output: Vec<u16>
+}
+impl Stringifier {
+    fn write_str(&mut self, text: &str) {
+        self.output.extend(text.encode_utf16());
+    }
+
+    // A line break and the current indentation, when there's a gap.
+    fn write_line_break(&mut self) {
+        if ! self.gap.is_empty() {
+            self.output.push(0x0A);
+            self.output.extend_from_slice(&self.indent);
+        }
+    }
+}
+
+/**
+ * Write a string as a JSON string literal.  Lone surrogates are escaped, so
+ * that the text is always well-formed Unicode.
+ */
+pub fn quote(units: &[u16], output: &mut Vec<u16>) {
+    output.push(0x22);
+    for (i, &unit) in units.iter().enumerate() {
+        let escape = match unit {
+            0x22 => Some("\\\""),
+            0x5C => Some("\\\\"),
+            0x08 => Some("\\b"),
+            0x0C => Some("\\f"),
+            0x0A => Some("\\n"),
+            0x0D => Some("\\r"),
+            0x09 => Some("\\t"),
+            _ => None
+        };
+        if let Some(escape) = escape {
+            output.extend(escape.encode_utf16());
+            continue;
+        }
+        let lone_surrogate = match unit {
+            0xD800..=0xDBFF => ! units.get(i + 1).is_some_and(|&next| (0xDC00..=0xDFFF).contains(&next)),
+            0xDC00..=0xDFFF => i == 0 || ! (0xD800..=0xDBFF).contains(&units[i - 1]),
+            _ => false
+        };
+        if unit < 0x20 || lone_surrogate {
+            output.extend(format!("\\u{:04x}", unit).encode_utf16());
+        } else {
+            output.push(unit);
+        }
+    }
+    output.push(0x22);
+}
+
+/**
+ * The value to write for a property, after calling its `toJSON` method and
+ * the replacer function, or None if it's to be left out: undefined,
+ * functions and other values JSON has no form for.
+ */
+fn property_value(interp: &mut Interpreter, s: &Stringifier, holder: Value, key: Value, value: Value)
+    -> VmResult<Option<Value>>
+{
+    let key = interp.heap_mut().root(key);
+    let mut value = value;
+    if object::is_object(interp.heap(), value) {
+        let rooted = interp.heap_mut().root(value);
+        let to_json_key = string::atomize_str(interp.heap_mut(), "toJSON");
+        let to_json = interp.get_property(value, to_json_key)?;
+        if to_json.is_callable(interp.heap()) {
+            value = interp.call(to_json, rooted.get(), &[key.get()])?;
+        }
+    }
+    if let Some(replacer) = s.replacer {
+        value = interp.call(replacer, holder, &[key.get(), value])?;
+    }
+    // Boolean, Number and String objects are written as what they wrap.
+    if let Some(primitive) = object::primitive_value(interp.heap(), value) {
+        value = primitive;
+    }
+
+    let heap = interp.heap();
+    let serializable = value.is_imm_null() || value.is_imm_bool() || value.is_number(heap) ||
+        string::is_string(heap, value) || (object::is_object(heap, value) && ! value.is_callable(heap));
+    Ok(if serializable { Some(value) } else { None })
+}
+
+fn serialize_value(interp: &mut Interpreter, s: &mut Stringifier, value: Value) -> VmResult<()> {
+    if value.is_imm_null() {
+        s.write_str("null");
+    } else if let Some(bval) = value.get_imm_bool() {
+        s.write_str(if bval { "true" } else { "false" });
+    } else if let Some(fval) = value.to_f64(interp.heap()) {
+        if fval.is_finite() {
+            s.write_str(&number_to_string(fval));
+        } else {
+            s.write_str("null");
+        }
+    } else if string::is_string(interp.heap(), value) {
+        let units: Vec<u16> = string::code_units(interp.heap(), value).collect();
+        quote(&units, &mut s.output);
+    } else {
+        if s.stack.values().borrow().contains(&value) {
+            return Err(interp.error(ErrorKind::TypeError, "Converting circular structure to JSON"));
+        }
+        if s.stack.len() >= MAX_DEPTH {
+            return Err(too_deep(interp));
+        }
+        s.stack.push(value);
+        let stepback = s.indent.len();
+        let gap = s.gap.clone();
+        s.indent.extend_from_slice(&gap);
+        if array::is_array(interp.heap(), value) {
+            serialize_array(interp, s, value)?;
+        } else {
+            serialize_object(interp, s, value)?;
+        }
+        s.indent.truncate(stepback);
+        s.stack.values().borrow_mut().pop();
+    }
+    Ok(())
+}
+
+fn serialize_object(interp: &mut Interpreter, s: &mut Stringifier, object: Value) -> VmResult<()> {
+    let keys = match s.property_list {
+        Some(ref property_list) => property_list.clone(),
+        None => enumerable_own_keys(interp, object)
+    };
+    s.write_str("{");
+    let mut empty = true;
+    for key in keys {
+        let value = interp.get_property(object, key)?;
+        let value = match property_value(interp, s, object, key, value)? {
+            Some(value) => value,
+            None => { continue; }
+        };
+        if ! empty {
+            s.write_str(",");
+        }
+        empty = false;
+        s.write_line_break();
+        let name: Vec<u16> = string::code_units(interp.heap(), key).collect();
+        quote(&name, &mut s.output);
+        s.write_str(if s.gap.is_empty() { ":" } else { ": " });
+        serialize_value(interp, s, value)?;
+    }
+    close(s, empty, "}");
+    Ok(())
+}
+
+fn serialize_array(interp: &mut Interpreter, s: &mut Stringifier, array: Value) -> VmResult<()> {
+    let length = length_of(interp, array)?;
+    s.write_str("[");
+    for index in 0..length {
+        if index > 0 {
+            s.write_str(",");
+        }
+        s.write_line_break();
+        let value = get_index(interp, array, index)?;
+        let key = string::new_string(interp.heap_mut(), &index.to_string());
+        match property_value(interp, s, array, key, value)? {
+            Some(value) => serialize_value(interp, s, value)?,
+            None => s.write_str("null")
+        }
+    }
+    close(s, length == 0, "]");
+    Ok(())
+}
+
+// Close an object or array, on a line of its own if it had members.
+fn close(s: &mut Stringifier, empty: bool, bracket: &str) {
+    if ! empty && ! s.gap.is_empty() {
+        let stepback = s.indent.len() - s.gap.len();
+        s.output.push(0x0A);
+        s.output.extend_from_slice(&s.indent[..stepback]);
+    }
+    s.write_str(bracket);
+}
+
+// The keys a replacer array lists, converted to strings, without repeats.
+fn property_list(interp: &mut Interpreter, replacer: Value) -> VmResult<Vec<Value>> {
+    let length = length_of(interp, replacer)?;
+    let mut keys: Vec<Value> = Vec::new();
+    for index in 0..length {
+        let item = get_index(interp, replacer, index)?;
+        if ! string::is_string(interp.heap(), item) && ! item.is_number(interp.heap()) {
+            continue;
+        }
+        let item = interp.string_value(item)?;
+        let key = string::atomize(interp.heap_mut(), item);
+        if ! keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    Ok(keys)
+}
+
+// The indentation a `space` argument asks for.
+fn gap_of(interp: &mut Interpreter, space: Value) -> VmResult<Vec<u16>> {
+    if space.is_number(interp.heap()) {
+        let count = integer_value(interp, space)?.max(0.0).min(MAX_GAP as f64);
+        return Ok(vec![0x20; count as usize]);
+    }
+    if string::is_string(interp.heap(), space) {
+        return Ok(string::code_units(interp.heap(), space).take(MAX_GAP).collect());
+    }
+    Ok(Vec::new())
+}
+
+fn stringify(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let replacer = args.arg(1);
+    let mut s = Stringifier {
+        replacer: None,
+        property_list: None,
+        gap: gap_of(interp, args.arg(2))?,
+        indent: Vec::new(),
+        stack: interp.heap_mut().root_vec(Vec::new()),
+        output: Vec::new()
+    };
+    if replacer.is_callable(interp.heap()) {
+        s.replacer = Some(replacer);
+    } else if array::is_array(interp.heap(), replacer) {
+        s.property_list = Some(property_list(interp, replacer)?);
+    }
+
+    let prototype = interp.object_prototype();
+    let wrapper = object::new_object(interp.heap_mut(), prototype);
+    let empty_key = string::atomize_str(interp.heap_mut(), "");
+    object::define_own_property(interp.heap_mut(), wrapper, empty_key,
+                                Property::Data { value: args.arg(0), attrs: PropertyAttributes::DEFAULT });
+    let wrapper = interp.heap_mut().root(wrapper);
+    let value = match property_value(interp, &s, wrapper.get(), empty_key, args.arg(0))? {
+        Some(value) => value,
+        None => { return Ok(Value::new_imm_undef()); }
+    };
+    serialize_value(interp, &mut s, value)?;
+    if s.output.len() > (string::MAX_STRING_LENGTH as usize) {
+        return Err(interp.error(ErrorKind::RangeError, "Invalid string length"));
+    }
+    Ok(string::new_string_from_units(interp.heap_mut(), &s.output))
+}
@@ -0,0 +1,248 @@
+/*!
+ * The `Math` object.
+ *
+ * Most functions are the platform's, but the spec differs from IEEE 754 in
+ * places, so `round`, `pow`, `max` and `min` are written out: `round` rounds
+ * halves up rather than away from zero, and keeps the sign of zero results;
+ * `pow` gives NaN for 1 to the power of NaN or an infinity; and `max` and
+ * `min` order -0 below +0 and give NaN if any argument is NaN.
+ *
+ * `Math.random` draws from an xorshift128+ generator kept by the interpreter,
+ * which can be seeded to make runs repeatable.
+ */
+
+use std::f64;
+use std::time::{SystemTime, UNIX_EPOCH};
+use vm::builtins;
+use vm::function::CallArgs;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, JsObject, ObjectClass, Property};
+use vm::shape::PropertyAttributes;
+use vm::string;
+use vm::value::Value;
+
+/** A pseudo-random number generator: xorshift128+. */
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: [u64; 2]
+}
+impl Random {
+    // The state is expanded from the seed by splitmix64, so that similar
+    // seeds give unrelated sequences.
+    pub fn new(seed: u64) -> Random {
+        let mut mix = seed;
+        let mut next = || {
+            mix = mix.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = mix;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let state = [next(), next()];
+        // The state must not be all zeros.
+        Random { state: if state == [0, 0] { [1, 0] } else { state } }
+    }
+
+    // Seeded from the clock, for when no seed is given.
+    pub fn from_time() -> Random {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Random::new(since_epoch.as_secs() ^ (since_epoch.subsec_nanos() as u64).rotate_left(32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut s1 = self.state[0];
+        let s0 = self.state[1];
+        self.state[0] = s0;
+        s1 ^= s1 << 23;
+        s1 ^= s1 >> 17;
+        s1 ^= s0;
+        s1 ^= s0 >> 26;
+        self.state[1] = s1;
+        self.state[0].wrapping_add(s1)
+    }
+
+    // A number in [0, 1), from the top 53 bits.
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64) / ((1u64 << 53) as f64)
+    }
+}
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.object_prototype();
+    let math = object::new_object(interp.heap_mut(), prototype);
+    interp.heap_mut().get_mut::<JsObject>(math).unwrap().set_class(ObjectClass::Math);
+    let global = interp.global_object();
+    builtins::define_value(interp, global, "Math", math);
+
+    let constants = [
+        ("E", f64::consts::E),
+        ("LN10", f64::consts::LN_10),
+        ("LN2", f64::consts::LN_2),
+        ("LOG2E", f64::consts::LOG2_E),
+        ("LOG10E", f64::consts::LOG10_E),
+        ("PI", f64::consts::PI),
+        ("SQRT1_2", f64::consts::FRAC_1_SQRT_2),
+        ("SQRT2", f64::consts::SQRT_2)
+    ];
+    for &(name, fval) in &constants {
+        define_constant(interp, math, name, fval);
+    }
+
+    builtins::define_method(interp, math, "abs", 1, abs);
+    builtins::define_method(interp, math, "acos", 1, acos);
+    builtins::define_method(interp, math, "asin", 1, asin);
+    builtins::define_method(interp, math, "atan", 1, atan);
+    builtins::define_method(interp, math, "atan2", 2, atan2);
+    builtins::define_method(interp, math, "ceil", 1, ceil);
+    builtins::define_method(interp, math, "cos", 1, cos);
+    builtins::define_method(interp, math, "exp", 1, exp);
+    builtins::define_method(interp, math, "floor", 1, floor);
+    builtins::define_method(interp, math, "log", 1, log);
+    builtins::define_method(interp, math, "max", 2, max);
+    builtins::define_method(interp, math, "min", 2, min);
+    builtins::define_method(interp, math, "pow", 2, pow);
+    builtins::define_method(interp, math, "random", 0, random);
+    builtins::define_method(interp, math, "round", 1, round);
+    builtins::define_method(interp, math, "sin", 1, sin);
+    builtins::define_method(interp, math, "sqrt", 1, sqrt);
+    builtins::define_method(interp, math, "tan", 1, tan);
+}
+
+// A number property which can't be changed, as the constants of `Math` and
+// `Number` are.
+pub fn define_constant(interp: &mut Interpreter, object: Value, name: &str, fval: f64) {
+    let value = Value::new_number(interp.heap_mut(), fval);
+    let key = string::atomize_str(interp.heap_mut(), name);
+    object::define_own_property(interp.heap_mut(), object, key,
+                                Property::Data { value, attrs: PropertyAttributes::NONE });
+}
+
+/** Math.round: the nearest integer, with halves rounded up. */
+pub fn round_half_up(fval: f64) -> f64 {
+    if ! fval.is_finite() || fval == 0.0 {
+        return fval;
+    }
+    let floor = fval.floor();
+    let rounded = if fval - floor >= 0.5 { floor + 1.0 } else { floor };
+    // Numbers from -0.5 up to zero round to -0.
+    if rounded == 0.0 && fval < 0.0 { -0.0 } else { rounded }
+}
+
+/** Math.pow, which differs from IEEE 754's when the base is 1 or -1. */
+pub fn power(base: f64, exponent: f64) -> f64 {
+    if exponent.is_nan() {
+        return f64::NAN;
+    }
+    if exponent.is_infinite() && base.abs() == 1.0 {
+        return f64::NAN;
+    }
+    base.powf(exponent)
+}
+
+/** Math.max of some numbers, with -0 below +0, or NaN if any is NaN. */
+pub fn max_of(fvals: &[f64]) -> f64 {
+    fvals.iter().fold(f64::NEG_INFINITY, |max, &fval| {
+        if max.is_nan() || fval.is_nan() {
+            f64::NAN
+        } else if fval > max || (fval == max && max.is_sign_negative() && fval.is_sign_positive()) {
+            fval
+        } else {
+            max
+        }
+    })
+}
+
+/** Math.min of some numbers, with -0 below +0, or NaN if any is NaN. */
+pub fn min_of(fvals: &[f64]) -> f64 {
+    fvals.iter().fold(f64::INFINITY, |min, &fval| {
+        if min.is_nan() || fval.is_nan() {
+            f64::NAN
+        } else if fval < min || (fval == min && fval.is_sign_negative()) {
+            fval
+        } else {
+            min
+        }
+    })
+}
+
+fn number_result(interp: &mut Interpreter, fval: f64) -> VmResult<Value> {
+    Ok(Value::new_number(interp.heap_mut(), fval))
+}
+
+// A function of one number.
+fn unary(interp: &mut Interpreter, args: &CallArgs, op: fn(f64) -> f64) -> VmResult<Value> {
+    let fval = interp.number_value(args.arg(0))?;
+    number_result(interp, op(fval))
+}
+
+fn abs(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::abs)
+}
+fn acos(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::acos)
+}
+fn asin(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::asin)
+}
+fn atan(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::atan)
+}
+fn ceil(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::ceil)
+}
+fn cos(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::cos)
+}
+fn exp(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::exp)
+}
+fn floor(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::floor)
+}
+fn log(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::ln)
+}
+fn round(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, round_half_up)
+}
+fn sin(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::sin)
+}
+fn sqrt(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::sqrt)
+}
+fn tan(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    unary(interp, args, f64::tan)
+}
+
+fn atan2(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let y = interp.number_value(args.arg(0))?;
+    let x = interp.number_value(args.arg(1))?;
+    number_result(interp, y.atan2(x))
+}
+
+fn pow(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let base = interp.number_value(args.arg(0))?;
+    let exponent = interp.number_value(args.arg(1))?;
+    number_result(interp, power(base, exponent))
+}
+
+// All the arguments are converted, even after a NaN.
+fn number_args(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Vec<f64>> {
+    args.args().iter().map(|&arg| interp.number_value(arg)).collect()
+}
+
+fn max(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fvals = number_args(interp, args)?;
+    number_result(interp, max_of(&fvals))
+}
+
+fn min(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fvals = number_args(interp, args)?;
+    number_result(interp, min_of(&fvals))
+}
+
+fn random(interp: &mut Interpreter, _args: &CallArgs) -> VmResult<Value> {
+    let fval = interp.random_mut().next_f64();
+    number_result(interp, fval)
+}
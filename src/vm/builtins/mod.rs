@@ -0,0 +1,122 @@
+/*!
+ * The built-in objects.
+ *
+ * Each submodule installs one part of the standard library on the global
+ * object: its constructor, the constructor's own functions, and the methods
+ * of its prototype.  Built-in functions are native functions.  Like the
+ * spec's, their properties are writable and configurable but not enumerable,
+ * and constructors' `prototype` properties can't be changed at all.
+ *
+ * A native's callee, this value and arguments are on the interpreter's stack,
+ * so are rooted.  Any other values it holds while running script, such as
+ * the arrays it builds, must be rooted by the native itself.
+ */
+
+pub mod array;
+pub mod date;
+pub mod error;
+pub mod function;
+pub mod json;
+pub mod math;
+pub mod number;
+pub mod object;
+pub mod regexp;
+pub mod string;
+
+use vm::function::{CallArgs, NativeFn};
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self as js_object, Property};
+use vm::shape::PropertyAttributes;
+use vm::string as js_string;
+use vm::value::Value;
+use self::error::ErrorKind;
+
+pub fn install(interp: &mut Interpreter) {
+    object::install(interp);
+    function::install(interp);
+    array::install(interp);
+    string::install(interp);
+    number::install(interp);
+    math::install(interp);
+    json::install(interp);
+    regexp::install(interp);
+    date::install(interp);
+    error::install(interp);
+}
+
+// The attributes of built-in methods and other properties.
+pub fn method_attrs() -> PropertyAttributes {
+    PropertyAttributes::WRITABLE.with(PropertyAttributes::CONFIGURABLE)
+}
+
+// Define a property as built-in properties are defined.
+pub fn define_value(interp: &mut Interpreter, object: Value, name: &str, value: Value) {
+    let key = js_string::atomize_str(interp.heap_mut(), name);
+    js_object::define_own_property(interp.heap_mut(), object, key,
+                                   Property::Data { value, attrs: method_attrs() });
+}
+
+pub fn define_method(interp: &mut Interpreter, object: Value, name: &'static str, length: u32,
+                     call: NativeFn)
+{
+    let func = interp.new_native_function(name, length, call);
+    define_value(interp, object, name, func);
+}
+
+/**
+ * Define a global constructor, linked to its prototype object in both
+ * directions.  Returns the constructor.
+ */
+pub fn define_constructor(interp: &mut Interpreter, name: &'static str, length: u32, call: NativeFn,
+                          prototype: Value)
+    -> Value
+{
+    let constructor = interp.new_native_constructor(name, length, call);
+    let prototype_key = js_string::atomize_str(interp.heap_mut(), "prototype");
+    js_object::define_own_property(interp.heap_mut(), constructor, prototype_key,
+                                   Property::Data { value: prototype, attrs: PropertyAttributes::NONE });
+    define_value(interp, prototype, "constructor", constructor);
+    let global = interp.global_object();
+    define_value(interp, global, name, constructor);
+    constructor
+}
+
+// The this value of a method which works on any value but undefined and null.
+pub fn coercible_this(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<Value> {
+    let this = args.this();
+    if this.is_imm_undef_null() {
+        let message = format!("{} called on null or undefined", method);
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    Ok(this)
+}
+
+// The argument of a function which must be an object.
+pub fn object_arg(interp: &mut Interpreter, value: Value, function: &str) -> VmResult<Value> {
+    if ! js_object::is_object(interp.heap(), value) {
+        let message = format!("{} called on non-object", function);
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    Ok(value)
+}
+
+// ToInteger: the number of a value truncated towards zero, with NaN as zero.
+pub fn integer_value(interp: &mut Interpreter, value: Value) -> VmResult<f64> {
+    let fval = interp.number_value(value)?;
+    Ok(if fval.is_nan() { 0.0 } else { fval.trunc() })
+}
+
+/**
+ * A position in a sequence of `length` items, given by an integer argument
+ * which counts back from the end if it's negative, clamped to `0..=length`.
+ * Missing arguments are `default`.
+ */
+pub fn relative_index(interp: &mut Interpreter, value: Value, length: u32, default: u32) -> VmResult<u32> {
+    if value.is_imm_undef() {
+        return Ok(default);
+    }
+    let relative = integer_value(interp, value)?;
+    let length = length as f64;
+    let index = if relative < 0.0 { (length + relative).max(0.0) } else { relative.min(length) };
+    Ok(index as u32)
+}
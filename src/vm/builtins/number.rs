@@ -0,0 +1,143 @@
+/*!
+ * `Number` and `Number.prototype`.
+ *
+ * `Number` converts its argument to a number primitive whether or not it's
+ * called by `new`, and numbers find their methods on `Number.prototype`
+ * directly.  Number objects are only made by boxing a this value or by
+ * `Object`.  The methods require their this value to be a number or a Number
+ * object.
+ *
+ * The formatting itself is done by `number_format`, exactly, so results agree
+ * digit for digit with other engines.
+ */
+
+use std::f64;
+use vm::builtins::{self, integer_value};
+use vm::builtins::error::ErrorKind;
+use vm::builtins::math::define_constant;
+use vm::conversions::number_to_string;
+use vm::function::CallArgs;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object;
+use vm::number_format::{self, MAX_FRACTION_DIGITS};
+use vm::string;
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.number_prototype();
+    let constructor = builtins::define_constructor(interp, "Number", 1, construct_number, prototype);
+    define_constant(interp, constructor, "MAX_VALUE", f64::MAX);
+    define_constant(interp, constructor, "MIN_VALUE", 5e-324);
+    define_constant(interp, constructor, "NaN", f64::NAN);
+    define_constant(interp, constructor, "NEGATIVE_INFINITY", f64::NEG_INFINITY);
+    define_constant(interp, constructor, "POSITIVE_INFINITY", f64::INFINITY);
+
+    builtins::define_method(interp, prototype, "toString", 1, number_to_string_method);
+    builtins::define_method(interp, prototype, "toLocaleString", 0, to_locale_string);
+    builtins::define_method(interp, prototype, "valueOf", 0, value_of);
+    builtins::define_method(interp, prototype, "toFixed", 1, to_fixed);
+    builtins::define_method(interp, prototype, "toExponential", 1, to_exponential);
+    builtins::define_method(interp, prototype, "toPrecision", 1, to_precision);
+}
+
+fn construct_number(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    if args.is_empty() {
+        return Ok(Value::new_imm_i32(0));
+    }
+    let fval = interp.number_value(args.arg(0))?;
+    Ok(Value::new_number(interp.heap_mut(), fval))
+}
+
+// The this value of a method, which must be a number.
+fn this_number(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<f64> {
+    let this = args.this();
+    let number = object::primitive_value(interp.heap(), this).unwrap_or(this);
+    match number.to_f64(interp.heap()) {
+        Some(fval) => Ok(fval),
+        None => {
+            let message = format!("Number.prototype.{} called on non-number {}", method,
+                                  interp.display_string(this));
+            Err(interp.error(ErrorKind::TypeError, &message))
+        }
+    }
+}
+
+// A count of digits, which must be within `min..=MAX_FRACTION_DIGITS`.
+fn check_digits(interp: &mut Interpreter, count: f64, min: u32, method: &str) -> VmResult<u32> {
+    if count < (min as f64) || count > (MAX_FRACTION_DIGITS as f64) {
+        let message = format!("{}() argument must be between {} and {}", method, min, MAX_FRACTION_DIGITS);
+        return Err(interp.error(ErrorKind::RangeError, &message));
+    }
+    Ok(count as u32)
+}
+
+fn string_result(interp: &mut Interpreter, text: &str) -> VmResult<Value> {
+    Ok(string::new_string(interp.heap_mut(), text))
+}
+
+fn number_to_string_method(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fval = this_number(interp, args, "toString")?;
+    let radix = args.arg(0);
+    let radix = if radix.is_imm_undef() { 10.0 } else { integer_value(interp, radix)? };
+    if ! (2.0..=36.0).contains(&radix) {
+        return Err(interp.error(ErrorKind::RangeError, "toString() radix must be between 2 and 36"));
+    }
+    if radix == 10.0 || ! fval.is_finite() {
+        return string_result(interp, &number_to_string(fval));
+    }
+    string_result(interp, &number_format::to_radix_string(fval, radix as u32))
+}
+
+fn to_locale_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fval = this_number(interp, args, "toLocaleString")?;
+    string_result(interp, &number_to_string(fval))
+}
+
+fn value_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fval = this_number(interp, args, "valueOf")?;
+    Ok(Value::new_number(interp.heap_mut(), fval))
+}
+
+// The digits are checked before NaN is, as the spec orders it.
+fn to_fixed(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fval = this_number(interp, args, "toFixed")?;
+    let fraction_digits = integer_value(interp, args.arg(0))?;
+    let fraction_digits = check_digits(interp, fraction_digits, 0, "toFixed")?;
+    if ! fval.is_finite() {
+        return string_result(interp, &number_to_string(fval));
+    }
+    string_result(interp, &number_format::to_fixed(fval, fraction_digits))
+}
+
+// Non-finite numbers are formatted before the digits are checked.
+fn to_exponential(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fval = this_number(interp, args, "toExponential")?;
+    let fraction_digits = args.arg(0);
+    let fraction_digits = if fraction_digits.is_imm_undef() {
+        None
+    } else {
+        Some(integer_value(interp, fraction_digits)?)
+    };
+    if ! fval.is_finite() {
+        return string_result(interp, &number_to_string(fval));
+    }
+    let fraction_digits = match fraction_digits {
+        Some(count) => Some(check_digits(interp, count, 0, "toExponential")?),
+        None => None
+    };
+    string_result(interp, &number_format::to_exponential(fval, fraction_digits))
+}
+
+fn to_precision(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let fval = this_number(interp, args, "toPrecision")?;
+    let precision = args.arg(0);
+    if precision.is_imm_undef() {
+        return string_result(interp, &number_to_string(fval));
+    }
+    let precision = integer_value(interp, precision)?;
+    if ! fval.is_finite() {
+        return string_result(interp, &number_to_string(fval));
+    }
+    let precision = check_digits(interp, precision, 1, "toPrecision")?;
+    string_result(interp, &number_format::to_precision(fval, precision))
+}
@@ -0,0 +1,573 @@
+/*!
+ * `Object`, its functions for inspecting and defining properties, and
+ * `Object.prototype`.
+ *
+ * `Object.defineProperty` and the functions built on it check a definition
+ * against the existing property, as the spec's [[DefineOwnProperty]] does,
+ * and throw a TypeError for any change a non-configurable property doesn't
+ * allow.
+ */
+
+use vm::array;
+use vm::builtins::{self, coercible_this, object_arg};
+use vm::builtins::error::ErrorKind;
+use vm::function::CallArgs;
+use vm::heap::{Heap, RootedVec};
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, JsObject, Property};
+use vm::shape::PropertyAttributes;
+use vm::string;
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.object_prototype();
+    let constructor = builtins::define_constructor(interp, "Object", 1, construct_object, prototype);
+    builtins::define_method(interp, constructor, "getPrototypeOf", 1, get_prototype_of);
+    builtins::define_method(interp, constructor, "getOwnPropertyDescriptor", 2, get_own_property_descriptor);
+    builtins::define_method(interp, constructor, "getOwnPropertyNames", 1, get_own_property_names);
+    builtins::define_method(interp, constructor, "create", 2, create);
+    builtins::define_method(interp, constructor, "defineProperty", 3, define_property);
+    builtins::define_method(interp, constructor, "defineProperties", 2, define_properties);
+    builtins::define_method(interp, constructor, "seal", 1, seal);
+    builtins::define_method(interp, constructor, "freeze", 1, freeze);
+    builtins::define_method(interp, constructor, "preventExtensions", 1, prevent_extensions);
+    builtins::define_method(interp, constructor, "isSealed", 1, is_sealed);
+    builtins::define_method(interp, constructor, "isFrozen", 1, is_frozen);
+    builtins::define_method(interp, constructor, "isExtensible", 1, is_extensible);
+    builtins::define_method(interp, constructor, "keys", 1, keys);
+
+    builtins::define_method(interp, prototype, "toString", 0, object_to_string);
+    builtins::define_method(interp, prototype, "toLocaleString", 0, to_locale_string);
+    builtins::define_method(interp, prototype, "valueOf", 0, value_of);
+    builtins::define_method(interp, prototype, "hasOwnProperty", 1, has_own_property);
+    builtins::define_method(interp, prototype, "isPrototypeOf", 1, is_prototype_of);
+    builtins::define_method(interp, prototype, "propertyIsEnumerable", 1, property_is_enumerable);
+}
+
+/**
+ * A property descriptor, as scripts write them.  Missing fields leave the
+ * property's existing attributes alone, or default to false or undefined
+ * for a new property.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PropertyDescriptor {
+    pub value: Option<Value>,
+    pub writable: Option<bool>,
+    pub get: Option<Value>,
+    pub set: Option<Value>,
+    pub enumerable: Option<bool>,
+    pub configurable: Option<bool>
+}
+impl PropertyDescriptor {
+    pub fn is_accessor(&self) -> bool {
+        self.get.is_some() || self.set.is_some()
+    }
+    pub fn is_data(&self) -> bool {
+        self.value.is_some() || self.writable.is_some()
+    }
+}
+
+/**
+ * ToPropertyDescriptor: read a descriptor from an object.  Its values are
+ * not rooted once it's returned, so it must be used before anything can
+ * collect.
+ */
+pub fn to_property_descriptor(interp: &mut Interpreter, object: Value) -> VmResult<PropertyDescriptor> {
+    if ! object::is_object(interp.heap(), object) {
+        let message = format!("Property description must be an object: {}", interp.display_string(object));
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    let mut desc = PropertyDescriptor::default();
+    if let Some(value) = descriptor_field(interp, object, "enumerable")? {
+        desc.enumerable = Some(value.to_boolean(interp.heap()));
+    }
+    if let Some(value) = descriptor_field(interp, object, "configurable")? {
+        desc.configurable = Some(value.to_boolean(interp.heap()));
+    }
+    // Reading a field can run a getter, so the values already read are
+    // rooted until the descriptor is complete.
+    let fields = interp.heap_mut().root_vec(Vec::new());
+    desc.value = descriptor_field(interp, object, "value")?;
+    fields.push(desc.value.unwrap_or(Value::new_imm_undef()));
+    if let Some(value) = descriptor_field(interp, object, "writable")? {
+        desc.writable = Some(value.to_boolean(interp.heap()));
+    }
+    for &(name, is_getter) in &[("get", true), ("set", false)] {
+        let accessor = descriptor_field(interp, object, name)?;
+        if let Some(function) = accessor {
+            if ! function.is_imm_undef() && ! function.is_callable(interp.heap()) {
+                let message = format!("{}ter must be a function: {}", if is_getter { "Get" } else { "Set" },
+                                      interp.display_string(function));
+                return Err(interp.error(ErrorKind::TypeError, &message));
+            }
+            fields.push(function);
+        }
+        if is_getter { desc.get = accessor } else { desc.set = accessor }
+    }
+    if desc.is_accessor() && desc.is_data() {
+        return Err(interp.error(ErrorKind::TypeError,
+                                "Invalid property descriptor. Cannot both specify accessors and a value or writable attribute"));
+    }
+    Ok(desc)
+}
+
+// A field of a descriptor object, if it has one.
+fn descriptor_field(interp: &mut Interpreter, object: Value, name: &str) -> VmResult<Option<Value>> {
+    let key = string::atomize_str(interp.heap_mut(), name);
+    if ! object::has_property(interp.heap(), object, key) {
+        return Ok(None);
+    }
+    Ok(Some(interp.get_property(object, key)?))
+}
+
+// FromPropertyDescriptor: an object describing a property.
+fn from_property(interp: &mut Interpreter, property: Property) -> Value {
+    let prototype = interp.object_prototype();
+    let desc = object::new_object(interp.heap_mut(), prototype);
+    let attrs = property.attrs();
+    let mut fields = match property {
+        Property::Data { value, .. } => {
+            vec![("value", value), ("writable", Value::new_imm_bool(attrs.is_writable()))]
+        }
+        Property::Accessor { getter, setter, .. } => vec![("get", getter), ("set", setter)]
+    };
+    fields.push(("enumerable", Value::new_imm_bool(attrs.is_enumerable())));
+    fields.push(("configurable", Value::new_imm_bool(attrs.is_configurable())));
+    for (name, value) in fields {
+        let key = string::atomize_str(interp.heap_mut(), name);
+        object::set_own_value(interp.heap_mut(), desc, key, value);
+    }
+    desc
+}
+
+/**
+ * [[DefineOwnProperty]]: define or change an own property as a descriptor
+ * says, if the object and the existing property allow it.  Returns false if
+ * they don't.
+ *
+ * Defining an array's `length` changes its length as assignment does, and an
+ * index past the length extends it, unless the length isn't writable.
+ */
+pub fn define_own_property(interp: &mut Interpreter, object: Value, key: Value, desc: &PropertyDescriptor)
+    -> VmResult<bool>
+{
+    let is_array = array::is_array(interp.heap(), object);
+    if is_array && key == array::length_key() {
+        return define_array_length(interp, object, desc);
+    }
+    if is_array {
+        if let Some(index) = object::array_index(interp.heap(), key) {
+            let elements = interp.heap().get::<JsObject>(object).unwrap().elements().unwrap();
+            if index >= elements.length() && ! elements.is_length_writable() {
+                return Ok(false);
+            }
+        }
+    }
+
+    let current = object::get_own_property(interp.heap_mut(), object, key);
+    let property = match current {
+        Some(current) => match merge_property(interp.heap(), current, desc) {
+            Some(property) => property,
+            None => return Ok(false)
+        },
+        None => {
+            if ! interp.heap().get::<JsObject>(object).unwrap().is_extensible() {
+                return Ok(false);
+            }
+            let mut attrs = PropertyAttributes::NONE;
+            if desc.enumerable == Some(true) {
+                attrs = attrs.with(PropertyAttributes::ENUMERABLE);
+            }
+            if desc.configurable == Some(true) {
+                attrs = attrs.with(PropertyAttributes::CONFIGURABLE);
+            }
+            if desc.is_accessor() {
+                let getter = desc.get.unwrap_or(Value::new_imm_undef());
+                let setter = desc.set.unwrap_or(Value::new_imm_undef());
+                Property::Accessor { getter, setter, attrs }
+            } else {
+                if desc.writable == Some(true) {
+                    attrs = attrs.with(PropertyAttributes::WRITABLE);
+                }
+                Property::Data { value: desc.value.unwrap_or(Value::new_imm_undef()), attrs }
+            }
+        }
+    };
+    object::define_own_property(interp.heap_mut(), object, key, property);
+    Ok(true)
+}
+
+// The property a descriptor changes an existing property to, or None if the
+// existing property doesn't allow the change.
+fn merge_property(heap: &Heap, current: Property, desc: &PropertyDescriptor) -> Option<Property> {
+    let attrs = current.attrs();
+    if ! attrs.is_configurable() {
+        if desc.configurable == Some(true) {
+            return None;
+        }
+        if desc.enumerable.is_some_and(|enumerable| enumerable != attrs.is_enumerable()) {
+            return None;
+        }
+    }
+    let mut attrs = attrs;
+    for &(field, attr) in &[(desc.enumerable, PropertyAttributes::ENUMERABLE),
+                            (desc.configurable, PropertyAttributes::CONFIGURABLE)] {
+        match field {
+            Some(true) => attrs = attrs.with(attr),
+            Some(false) => attrs = attrs.without(attr),
+            None => {}
+        }
+    }
+
+    match current {
+        Property::Data { value, .. } if ! desc.is_accessor() => {
+            if ! current.attrs().is_configurable() && ! current.attrs().is_writable() {
+                if desc.writable == Some(true) {
+                    return None;
+                }
+                if desc.value.is_some_and(|new_value| ! same_value(heap, new_value, value)) {
+                    return None;
+                }
+            }
+            match desc.writable {
+                Some(true) => attrs = attrs.with(PropertyAttributes::WRITABLE),
+                Some(false) => attrs = attrs.without(PropertyAttributes::WRITABLE),
+                None => {}
+            }
+            Some(Property::Data { value: desc.value.unwrap_or(value), attrs })
+        }
+        Property::Accessor { getter, setter, .. } if ! desc.is_data() => {
+            let changed = desc.get.is_some_and(|new_getter| new_getter != getter) ||
+                          desc.set.is_some_and(|new_setter| new_setter != setter);
+            if changed && ! current.attrs().is_configurable() {
+                return None;
+            }
+            Some(Property::Accessor { getter: desc.get.unwrap_or(getter), setter: desc.set.unwrap_or(setter), attrs })
+        }
+        // Changing between data and accessor properties keeps only the
+        // enumerable and configurable attributes.
+        _ => {
+            if ! current.attrs().is_configurable() {
+                return None;
+            }
+            let attrs = attrs.without(PropertyAttributes::WRITABLE);
+            if desc.is_accessor() {
+                let getter = desc.get.unwrap_or(Value::new_imm_undef());
+                let setter = desc.set.unwrap_or(Value::new_imm_undef());
+                Some(Property::Accessor { getter, setter, attrs })
+            } else {
+                let attrs = if desc.writable == Some(true) { attrs.with(PropertyAttributes::WRITABLE) } else { attrs };
+                Some(Property::Data { value: desc.value.unwrap_or(Value::new_imm_undef()), attrs })
+            }
+        }
+    }
+}
+
+// Defining an array's length truncates the array to a new value, before a
+// false writable takes effect.
+fn define_array_length(interp: &mut Interpreter, array: Value, desc: &PropertyDescriptor) -> VmResult<bool> {
+    let (length, writable) = {
+        let elements = interp.heap().get::<JsObject>(array).unwrap().elements().unwrap();
+        (elements.length(), elements.is_length_writable())
+    };
+    let new_length = match desc.value {
+        Some(value) => {
+            let number = interp.number_value(value)?;
+            if ! array::is_valid_length(number) {
+                return Err(interp.error(ErrorKind::RangeError, "Invalid array length"));
+            }
+            number as u32
+        }
+        None => length
+    };
+    if desc.is_accessor() || desc.configurable == Some(true) || desc.enumerable == Some(true) {
+        return Ok(false);
+    }
+    if ! writable && (desc.writable == Some(true) || new_length != length) {
+        return Ok(false);
+    }
+    let truncated = (new_length == length) || object::set_array_length(interp.heap_mut(), array, new_length);
+    if desc.writable == Some(false) {
+        let elements = interp.heap_mut().get_mut::<JsObject>(array).unwrap().elements_mut().unwrap();
+        elements.set_length_writable(false);
+    }
+    Ok(truncated)
+}
+
+// SameValue: strict equality, except that NaN is itself and the zeros differ.
+pub fn same_value(heap: &Heap, a: Value, b: Value) -> bool {
+    match (a.to_f64(heap), b.to_f64(heap)) {
+        (Some(x), Some(y)) => {
+            (x.is_nan() && y.is_nan()) || (x == y && x.is_sign_negative() == y.is_sign_negative())
+        }
+        _ => a.strict_equals(b, heap)
+    }
+}
+
+// Define a property, throwing if it can't be.
+fn define_or_throw(interp: &mut Interpreter, object: Value, key: Value, desc: &PropertyDescriptor)
+    -> VmResult<()>
+{
+    if ! define_own_property(interp, object, key, desc)? {
+        let message = format!("Cannot redefine property: {}", interp.display_string(key));
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    Ok(())
+}
+
+// Convert a value to an object, wrapping primitives.
+fn construct_object(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let value = args.arg(0);
+    if value.is_imm_undef_null() {
+        let prototype = interp.object_prototype();
+        return Ok(object::new_object(interp.heap_mut(), prototype));
+    }
+    interp.to_object(value)
+}
+
+fn get_prototype_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.getPrototypeOf")?;
+    Ok(object::get_prototype(interp.heap(), object))
+}
+
+fn get_own_property_descriptor(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.getOwnPropertyDescriptor")?;
+    let key = interp.property_key(args.arg(1))?;
+    match object::get_own_property(interp.heap_mut(), object, key) {
+        Some(property) => Ok(from_property(interp, property)),
+        None => Ok(Value::new_imm_undef())
+    }
+}
+
+fn get_own_property_names(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.getOwnPropertyNames")?;
+    let keys = object::own_keys(interp.heap_mut(), object);
+    Ok(builtins::array::new_array_from(interp, &keys))
+}
+
+fn create(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let prototype = args.arg(0);
+    if ! prototype.is_imm_null() && ! object::is_object(interp.heap(), prototype) {
+        let message = format!("Object prototype may only be an Object or null: {}",
+                              interp.display_string(prototype));
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    let object = object::new_object(interp.heap_mut(), prototype);
+    let object = interp.heap_mut().root(object);
+    if ! args.arg(1).is_imm_undef() {
+        define_properties_from(interp, object.get(), args.arg(1))?;
+    }
+    Ok(object.get())
+}
+
+fn define_property(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.defineProperty")?;
+    let key = interp.property_key(args.arg(1))?;
+    let desc = to_property_descriptor(interp, args.arg(2))?;
+    define_or_throw(interp, object, key, &desc)?;
+    Ok(object)
+}
+
+fn define_properties(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.defineProperties")?;
+    define_properties_from(interp, object, args.arg(1))?;
+    Ok(object)
+}
+
+// Define the properties described by the enumerable own properties of an
+// object.  All the descriptors are read before any property is defined.
+fn define_properties_from(interp: &mut Interpreter, object: Value, properties: Value) -> VmResult<()> {
+    let properties = object_arg(interp, properties, "Object.defineProperties")?;
+    let keys = enumerable_own_keys(interp, properties);
+    let mut descs = Vec::with_capacity(keys.len());
+    let values: RootedVec = interp.heap_mut().root_vec(Vec::new());
+    for key in keys {
+        let desc_object = interp.get_property(properties, key)?;
+        values.push(desc_object);
+        let desc = to_property_descriptor(interp, desc_object)?;
+        for value in [desc.value, desc.get, desc.set].iter().flatten() {
+            values.push(*value);
+        }
+        descs.push((key, desc));
+    }
+    for (key, desc) in descs {
+        define_or_throw(interp, object, key, &desc)?;
+    }
+    Ok(())
+}
+
+// Set the attributes of all of an object's own properties, and make it
+// inextensible.
+fn restrict(interp: &mut Interpreter, object: Value, freeze: bool) {
+    for key in object::own_keys(interp.heap_mut(), object) {
+        let property = object::get_own_property(interp.heap_mut(), object, key).unwrap();
+        let mut attrs = property.attrs().without(PropertyAttributes::CONFIGURABLE);
+        if freeze {
+            attrs = attrs.without(PropertyAttributes::WRITABLE);
+        }
+        if attrs == property.attrs() {
+            continue;
+        }
+        if key == array::length_key() && array::is_array(interp.heap(), object) {
+            let elements = interp.heap_mut().get_mut::<JsObject>(object).unwrap().elements_mut().unwrap();
+            elements.set_length_writable(attrs.is_writable());
+            continue;
+        }
+        let property = match property {
+            Property::Data { value, .. } => Property::Data { value, attrs },
+            Property::Accessor { getter, setter, .. } => Property::Accessor { getter, setter, attrs }
+        };
+        object::define_own_property(interp.heap_mut(), object, key, property);
+    }
+    interp.heap_mut().get_mut::<JsObject>(object).unwrap().prevent_extensions();
+}
+
+// Whether an object is inextensible and all its own properties have none of
+// some attributes.  Accessors have no writable attribute.
+fn is_restricted(interp: &mut Interpreter, object: Value, frozen: bool) -> bool {
+    if interp.heap().get::<JsObject>(object).unwrap().is_extensible() {
+        return false;
+    }
+    object::own_keys(interp.heap_mut(), object).into_iter().all(|key| {
+        let property = object::get_own_property(interp.heap_mut(), object, key).unwrap();
+        let writable = match property {
+            Property::Data { attrs, .. } => attrs.is_writable(),
+            Property::Accessor { .. } => false
+        };
+        let restricted = ! frozen || ! writable;
+        restricted && ! property.attrs().is_configurable()
+    })
+}
+
+fn seal(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.seal")?;
+    restrict(interp, object, false);
+    Ok(object)
+}
+
+fn freeze(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.freeze")?;
+    restrict(interp, object, true);
+    Ok(object)
+}
+
+fn prevent_extensions(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.preventExtensions")?;
+    interp.heap_mut().get_mut::<JsObject>(object).unwrap().prevent_extensions();
+    Ok(object)
+}
+
+fn is_sealed(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.isSealed")?;
+    Ok(Value::new_imm_bool(is_restricted(interp, object, false)))
+}
+
+fn is_frozen(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.isFrozen")?;
+    Ok(Value::new_imm_bool(is_restricted(interp, object, true)))
+}
+
+fn is_extensible(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.isExtensible")?;
+    let extensible = interp.heap().get::<JsObject>(object).unwrap().is_extensible();
+    Ok(Value::new_imm_bool(extensible))
+}
+
+fn keys(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let object = object_arg(interp, args.arg(0), "Object.keys")?;
+    let keys = enumerable_own_keys(interp, object);
+    Ok(builtins::array::new_array_from(interp, &keys))
+}
+
+// The keys of an object's own enumerable properties, in `own_keys` order.
+pub fn enumerable_own_keys(interp: &mut Interpreter, object: Value) -> Vec<Value> {
+    let keys = object::own_keys(interp.heap_mut(), object);
+    keys.into_iter().filter(|&key| {
+        object::get_own_property(interp.heap_mut(), object, key).unwrap().attrs().is_enumerable()
+    }).collect()
+}
+
+// Object.prototype.toString: the object's class.
+pub fn object_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = args.this();
+    let text = if this.is_imm_undef() {
+        "[object Undefined]".to_string()
+    } else if this.is_imm_null() {
+        "[object Null]".to_string()
+    } else if object::is_object(interp.heap(), this) {
+        format!("[object {}]", object::class_name(interp.heap(), this))
+    } else {
+        // Primitives would be converted to their wrapper objects.
+        let type_name = match this.type_name(interp.heap()) {
+            "boolean" => "Boolean",
+            "number" => "Number",
+            _ => "String"
+        };
+        format!("[object {}]", type_name)
+    };
+    Ok(string::new_string(interp.heap_mut(), &text))
+}
+
+fn to_locale_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = coercible_this(interp, args, "Object.prototype.toLocaleString")?;
+    let to_string_key = string::atomize_str(interp.heap_mut(), "toString");
+    let to_string = interp.get_property(this, to_string_key)?;
+    if ! to_string.is_callable(interp.heap()) {
+        return Err(interp.error(ErrorKind::TypeError, "toString is not a function"));
+    }
+    interp.call(to_string, this, &[])
+}
+
+fn value_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    coercible_this(interp, args, "Object.prototype.valueOf")
+}
+
+fn has_own_property(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let key = interp.property_key(args.arg(0))?;
+    let this = coercible_this(interp, args, "Object.prototype.hasOwnProperty")?;
+    let has = if object::is_object(interp.heap(), this) {
+        object::get_own_property(interp.heap_mut(), this, key).is_some()
+    } else {
+        has_primitive_property(interp, this, key)
+    };
+    Ok(Value::new_imm_bool(has))
+}
+
+// Strings have their length and indexed characters as own properties.
+fn has_primitive_property(interp: &mut Interpreter, primitive: Value, key: Value) -> bool {
+    match string::string_length(interp.heap(), primitive) {
+        Some(length) => {
+            key == array::length_key() ||
+                object::array_index(interp.heap(), key).is_some_and(|index| index < length)
+        }
+        None => false
+    }
+}
+
+fn is_prototype_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let value = args.arg(0);
+    if ! object::is_object(interp.heap(), value) {
+        return Ok(Value::new_imm_bool(false));
+    }
+    let this = coercible_this(interp, args, "Object.prototype.isPrototypeOf")?;
+    let mut holder = object::get_prototype(interp.heap(), value);
+    while ! holder.is_imm_null() {
+        if holder == this {
+            return Ok(Value::new_imm_bool(true));
+        }
+        holder = object::get_prototype(interp.heap(), holder);
+    }
+    Ok(Value::new_imm_bool(false))
+}
+
+fn property_is_enumerable(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let key = interp.property_key(args.arg(0))?;
+    let this = coercible_this(interp, args, "Object.prototype.propertyIsEnumerable")?;
+    let enumerable = if object::is_object(interp.heap(), this) {
+        object::get_own_property(interp.heap_mut(), this, key).is_some_and(|property| property.attrs().is_enumerable())
+    } else {
+        // The characters of strings are enumerable, but not their length.
+        key != array::length_key() && has_primitive_property(interp, this, key)
+    };
+    Ok(Value::new_imm_bool(enumerable))
+}
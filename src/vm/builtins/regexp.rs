@@ -0,0 +1,268 @@
+/*!
+ * `RegExp` and `RegExp.prototype`.
+ *
+ * The matching itself is done by `vm::regexp`.  The functions here deal with
+ * RegExp objects: their `lastIndex` property, and the arrays `exec` makes of
+ * its matches.  `String.prototype`'s regular expression methods use them too.
+ *
+ * Each match may run at most the interpreter's step limit of instructions.
+ * A match which would take longer throws a RangeError rather than hanging.
+ */
+
+use std::rc::Rc;
+use vm::builtins::{self, integer_value};
+use vm::builtins::error::ErrorKind;
+use vm::function::CallArgs;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::{self, Property};
+use vm::regexp::{self, Captures, RegExp, RegExpError};
+use vm::shape::PropertyAttributes;
+use vm::string;
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.regexp_prototype();
+    builtins::define_constructor(interp, "RegExp", 2, construct_regexp, prototype);
+    builtins::define_method(interp, prototype, "exec", 1, exec);
+    builtins::define_method(interp, prototype, "test", 1, test);
+    builtins::define_method(interp, prototype, "toString", 0, regexp_to_string);
+}
+
+/**
+ * A new RegExp object, from pattern and flags strings.  A pattern or flags
+ * which aren't valid are a SyntaxError.
+ */
+pub fn new_regexp(interp: &mut Interpreter, pattern: Value, flags: Value) -> VmResult<Value> {
+    let pattern: Vec<u16> = string::code_units(interp.heap(), pattern).collect();
+    let flags: Vec<u16> = string::code_units(interp.heap(), flags).collect();
+    match RegExp::new(&pattern, &flags) {
+        Ok(compiled) => Ok(new_regexp_from(interp, Rc::new(compiled))),
+        Err(RegExpError::InvalidFlags) => {
+            let message = format!("Invalid flags supplied to RegExp constructor '{}'",
+                                  String::from_utf16_lossy(&flags));
+            Err(interp.error(ErrorKind::SyntaxError, &message))
+        }
+        Err(RegExpError::Syntax(reason)) => {
+            let message = format!("Invalid regular expression: /{}/: {}",
+                                  String::from_utf16_lossy(&pattern), reason);
+            Err(interp.error(ErrorKind::SyntaxError, &message))
+        }
+    }
+}
+
+fn new_regexp_from(interp: &mut Interpreter, compiled: Rc<RegExp>) -> Value {
+    let prototype = interp.regexp_prototype();
+    regexp::new_regexp_object(interp.heap_mut(), prototype, compiled)
+}
+
+/** The compiled expression of a value, if it's a RegExp object. */
+pub fn regexp_of(interp: &Interpreter, value: Value) -> Option<Rc<RegExp>> {
+    regexp::regexp_of(interp.heap(), value)
+}
+
+/**
+ * The RegExp object a String method works with: the argument itself if it's
+ * one, or else a new one with the argument as its pattern.
+ */
+pub fn regexp_arg(interp: &mut Interpreter, value: Value) -> VmResult<Value> {
+    if regexp_of(interp, value).is_some() {
+        return Ok(value);
+    }
+    let pattern = if value.is_imm_undef() {
+        string::new_string(interp.heap_mut(), "")
+    } else {
+        interp.string_value(value)?
+    };
+    let flags = string::new_string(interp.heap_mut(), "");
+    new_regexp(interp, pattern, flags)
+}
+
+/**
+ * The first match of an expression in some code units at or after `start`.
+ * Running out of steps is a RangeError.
+ */
+pub fn search(interp: &mut Interpreter, compiled: &RegExp, units: &[u16], start: usize)
+    -> VmResult<Option<Captures>>
+{
+    let mut steps = interp.regexp_step_limit();
+    match compiled.search(units, start, &mut steps) {
+        Ok(captures) => Ok(captures),
+        Err(_) => Err(interp.error(ErrorKind::RangeError, "Regular expression too complex to match"))
+    }
+}
+
+/** The captures of a match at exactly `start`, for `split`. */
+pub fn match_at(interp: &mut Interpreter, compiled: &RegExp, units: &[u16], start: usize)
+    -> VmResult<Option<Captures>>
+{
+    let mut steps = interp.regexp_step_limit();
+    match compiled.match_at(units, start, &mut steps) {
+        Ok(captures) => Ok(captures),
+        Err(_) => Err(interp.error(ErrorKind::RangeError, "Regular expression too complex to match"))
+    }
+}
+
+pub fn get_last_index(interp: &mut Interpreter, object: Value) -> VmResult<f64> {
+    let key = string::atomize_str(interp.heap_mut(), "lastIndex");
+    let last_index = interp.get_property(object, key)?;
+    integer_value(interp, last_index)
+}
+
+pub fn set_last_index(interp: &mut Interpreter, object: Value, index: usize) -> VmResult<()> {
+    let key = string::atomize_str(interp.heap_mut(), "lastIndex");
+    let index = Value::new_number(interp.heap_mut(), index as f64);
+    interp.set_property(object, key, index, /* strict = */ true)
+}
+
+/**
+ * The spec's RegExpExec, without the result array: the next match of a
+ * RegExp object in a string.  A global expression searches from its
+ * `lastIndex`, and moves it to the end of the match.
+ */
+pub fn exec_captures(interp: &mut Interpreter, object: Value, compiled: &RegExp, units: &[u16])
+    -> VmResult<Option<Captures>>
+{
+    let last_index = get_last_index(interp, object)?;
+    let global = compiled.flags().global;
+    let start = if global { last_index } else { 0.0 };
+    if start < 0.0 || start > (units.len() as f64) {
+        set_last_index(interp, object, 0)?;
+        return Ok(None);
+    }
+    let captures = match search(interp, compiled, units, start as usize)? {
+        Some(captures) => captures,
+        None => {
+            set_last_index(interp, object, 0)?;
+            return Ok(None);
+        }
+    };
+    if global {
+        set_last_index(interp, object, captures[0].unwrap().1)?;
+    }
+    Ok(Some(captures))
+}
+
+/**
+ * The matches `match` and `replace` work on: the next one, for an expression
+ * which isn't global, or else all of them from the start of the string.  An
+ * empty match moves `lastIndex` on, so the search can't stick there.
+ */
+pub fn exec_all(interp: &mut Interpreter, object: Value, compiled: &RegExp, units: &[u16])
+    -> VmResult<Vec<Captures>>
+{
+    if ! compiled.flags().global {
+        return Ok(exec_captures(interp, object, compiled, units)?.into_iter().collect());
+    }
+    set_last_index(interp, object, 0)?;
+    let mut matches: Vec<Captures> = Vec::new();
+    while let Some(captures) = exec_captures(interp, object, compiled, units)? {
+        let (start, end) = captures[0].unwrap();
+        if start == end {
+            set_last_index(interp, object, end + 1)?;
+        }
+        matches.push(captures);
+    }
+    Ok(matches)
+}
+
+/**
+ * The array `exec` and `match` give for a match: the matched text and each
+ * group's, which is undefined if the group took no part, with `index` and
+ * `input` properties.
+ */
+pub fn match_array(interp: &mut Interpreter, input: Value, captures: &Captures) -> Value {
+    let mut values: Vec<Value> = Vec::with_capacity(captures.len());
+    for capture in captures {
+        values.push(match *capture {
+            Some((start, end)) => string::substring(interp.heap_mut(), input, start as u32, end as u32),
+            None => Value::new_imm_undef()
+        });
+    }
+    let array = builtins::array::new_array_from(interp, &values);
+    let index = Value::new_imm_i32(captures[0].unwrap().0 as i32);
+    for &(name, value) in &[("index", index), ("input", input)] {
+        let key = string::atomize_str(interp.heap_mut(), name);
+        let property = Property::Data { value, attrs: PropertyAttributes::DEFAULT };
+        object::define_own_property(interp.heap_mut(), array, key, property);
+    }
+    array
+}
+
+/*****************************************************************************
+ **** Constructor ************************************************************
+ *****************************************************************************/
+
+/**
+ * A RegExp given as the pattern is copied, or returned as it is when called
+ * as a function.  Flags can't be given with it.
+ */
+fn construct_regexp(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let (pattern, flags) = (args.arg(0), args.arg(1));
+    if let Some(compiled) = regexp_of(interp, pattern) {
+        if ! flags.is_imm_undef() {
+            return Err(interp.error(ErrorKind::TypeError,
+                                    "Cannot supply flags when constructing one RegExp from another"));
+        }
+        if ! args.is_construct() {
+            return Ok(pattern);
+        }
+        return Ok(new_regexp_from(interp, compiled));
+    }
+    let pattern = if pattern.is_imm_undef() {
+        string::new_string(interp.heap_mut(), "")
+    } else {
+        interp.string_value(pattern)?
+    };
+    let pattern = interp.heap_mut().root(pattern);
+    let flags = if flags.is_imm_undef() {
+        string::new_string(interp.heap_mut(), "")
+    } else {
+        interp.string_value(flags)?
+    };
+    new_regexp(interp, pattern.get(), flags)
+}
+
+/*****************************************************************************
+ **** Prototype methods ******************************************************
+ *****************************************************************************/
+
+// The compiled expression of the this value, which must be a RegExp.
+fn this_regexp(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<Rc<RegExp>> {
+    match regexp_of(interp, args.this()) {
+        Some(compiled) => Ok(compiled),
+        None => {
+            let message = format!("RegExp.prototype.{} called on incompatible receiver {}", method,
+                                  interp.display_string(args.this()));
+            Err(interp.error(ErrorKind::TypeError, &message))
+        }
+    }
+}
+
+fn exec(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let compiled = this_regexp(interp, args, "exec")?;
+    let input = interp.string_value(args.arg(0))?;
+    let input = interp.heap_mut().root(input);
+    let units: Vec<u16> = string::code_units(interp.heap(), input.get()).collect();
+    match exec_captures(interp, args.this(), &compiled, &units)? {
+        Some(captures) => Ok(match_array(interp, input.get(), &captures)),
+        None => Ok(Value::new_imm_null())
+    }
+}
+
+fn test(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let compiled = this_regexp(interp, args, "test")?;
+    let input = interp.string_value(args.arg(0))?;
+    let units: Vec<u16> = string::code_units(interp.heap(), input).collect();
+    let captures = exec_captures(interp, args.this(), &compiled, &units)?;
+    Ok(Value::new_imm_bool(captures.is_some()))
+}
+
+// The source and flags, as a literal would write them.
+fn regexp_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let compiled = this_regexp(interp, args, "toString")?;
+    let mut units: Vec<u16> = vec![b'/' as u16];
+    units.extend(compiled.escaped_source());
+    units.push(b'/' as u16);
+    units.extend(compiled.flags().to_str().encode_utf16());
+    Ok(string::new_string_from_units(interp.heap_mut(), &units))
+}
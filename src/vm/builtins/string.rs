@@ -0,0 +1,513 @@
+/*!
+ * `String` and `String.prototype`.
+ *
+ * `String` converts its argument to a string primitive whether or not it's
+ * called by `new`, and primitive strings find their methods on
+ * `String.prototype` directly.  String objects are only made by boxing a this
+ * value or by `Object`.  The methods convert their this value to a string, so
+ * work on any value but undefined and null.
+ *
+ * Methods work on code units, as the language does.  Their results are made
+ * by `string::new_string_from_units` or sliced by `string::substring`, so
+ * short Latin-1 results are immediates.
+ */
+
+use vm::builtins::{self, coercible_this, integer_value, relative_index};
+use vm::builtins::error::ErrorKind;
+use vm::builtins::regexp;
+use vm::conversions::is_str_white_space;
+use vm::function::CallArgs;
+use vm::heap::Rooted;
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object;
+use vm::regexp::{Captures, RegExp};
+use vm::string;
+use vm::value::Value;
+
+pub fn install(interp: &mut Interpreter) {
+    let prototype = interp.string_prototype();
+    let constructor = builtins::define_constructor(interp, "String", 1, construct_string, prototype);
+    builtins::define_method(interp, constructor, "fromCharCode", 1, from_char_code);
+
+    builtins::define_method(interp, prototype, "toString", 0, string_to_string);
+    builtins::define_method(interp, prototype, "valueOf", 0, string_to_string);
+    builtins::define_method(interp, prototype, "charAt", 1, char_at);
+    builtins::define_method(interp, prototype, "charCodeAt", 1, char_code_at);
+    builtins::define_method(interp, prototype, "concat", 1, concat);
+    builtins::define_method(interp, prototype, "indexOf", 1, index_of);
+    builtins::define_method(interp, prototype, "lastIndexOf", 1, last_index_of);
+    builtins::define_method(interp, prototype, "slice", 2, slice);
+    builtins::define_method(interp, prototype, "substring", 2, substring);
+    builtins::define_method(interp, prototype, "substr", 2, substr);
+    builtins::define_method(interp, prototype, "split", 2, split);
+    builtins::define_method(interp, prototype, "replace", 2, replace);
+    builtins::define_method(interp, prototype, "match", 1, match_method);
+    builtins::define_method(interp, prototype, "search", 1, search);
+    builtins::define_method(interp, prototype, "toLowerCase", 0, to_lower_case);
+    builtins::define_method(interp, prototype, "toLocaleLowerCase", 0, to_lower_case);
+    builtins::define_method(interp, prototype, "toUpperCase", 0, to_upper_case);
+    builtins::define_method(interp, prototype, "toLocaleUpperCase", 0, to_upper_case);
+    builtins::define_method(interp, prototype, "trim", 0, trim);
+}
+
+// The this value of a method, converted to a string.  It's rooted, as the
+// methods go on to convert their arguments.
+fn this_string(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<Rooted> {
+    let this = coercible_this(interp, args, method)?;
+    let this = interp.string_value(this)?;
+    Ok(interp.heap_mut().root(this))
+}
+
+fn units_of(interp: &Interpreter, string: Value) -> Vec<u16> {
+    string::code_units(interp.heap(), string).collect()
+}
+
+// An argument converted to a string, as code units.
+fn string_arg(interp: &mut Interpreter, value: Value) -> VmResult<Vec<u16>> {
+    let string = interp.string_value(value)?;
+    Ok(units_of(interp, string))
+}
+
+fn length_of(interp: &Interpreter, string: Value) -> u32 {
+    string::string_length(interp.heap(), string).unwrap()
+}
+
+// A string longer than strings can be is a RangeError, as in other engines.
+fn new_string_checked(interp: &mut Interpreter, units: &[u16]) -> VmResult<Value> {
+    if units.len() > (string::MAX_STRING_LENGTH as usize) {
+        return Err(interp.error(ErrorKind::RangeError, "Invalid string length"));
+    }
+    Ok(string::new_string_from_units(interp.heap_mut(), units))
+}
+
+// The first index of `search` in `units` at or after `from`.
+fn find(units: &[u16], search: &[u16], from: usize) -> Option<usize> {
+    if search.len() > units.len() {
+        return None;
+    }
+    (from..=(units.len() - search.len())).find(|&index| units[index..].starts_with(search))
+}
+
+// The last index of `search` in `units` at or before `from`.
+fn rfind(units: &[u16], search: &[u16], from: usize) -> Option<usize> {
+    if search.len() > units.len() {
+        return None;
+    }
+    (0..=from.min(units.len() - search.len())).rev().find(|&index| units[index..].starts_with(search))
+}
+
+/*****************************************************************************
+ **** Constructor ************************************************************
+ *****************************************************************************/
+
+fn construct_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    if args.is_empty() {
+        return Ok(string::new_string(interp.heap_mut(), ""));
+    }
+    interp.string_value(args.arg(0))
+}
+
+// Each argument is converted to a code unit, modulo 2^16.
+fn from_char_code(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let mut units: Vec<u16> = Vec::with_capacity(args.len());
+    for &arg in args.args() {
+        units.push(interp.uint32_value(arg)? as u16);
+    }
+    Ok(string::new_string_from_units(interp.heap_mut(), &units))
+}
+
+/*****************************************************************************
+ **** Prototype methods ******************************************************
+ *****************************************************************************/
+
+// toString and valueOf: the this value, which must be a string or a String
+// object.
+fn string_to_string(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = args.this();
+    let string = object::primitive_value(interp.heap(), this).unwrap_or(this);
+    if ! string::is_string(interp.heap(), string) {
+        let message = format!("String.prototype.toString called on non-string {}", interp.display_string(this));
+        return Err(interp.error(ErrorKind::TypeError, &message));
+    }
+    Ok(string)
+}
+
+// The index argument of charAt and charCodeAt, if it's in the string.  The
+// string is flattened, to index it directly.
+fn char_index(interp: &mut Interpreter, args: &CallArgs, method: &str) -> VmResult<Option<(Value, u32)>> {
+    let this = this_string(interp, args, method)?;
+    let position = integer_value(interp, args.arg(0))?;
+    let this = string::flatten(interp.heap_mut(), this.get());
+    if position < 0.0 || position >= (length_of(interp, this) as f64) {
+        return Ok(None);
+    }
+    Ok(Some((this, position as u32)))
+}
+
+fn char_at(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    match char_index(interp, args, "String.prototype.charAt")? {
+        Some((this, index)) => Ok(string::substring(interp.heap_mut(), this, index, index + 1)),
+        None => Ok(Value::new_imm_str(b""))
+    }
+}
+
+fn char_code_at(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    match char_index(interp, args, "String.prototype.charCodeAt")? {
+        Some((this, index)) => {
+            let unit = string::code_unit_at(interp.heap(), this, index).unwrap();
+            Ok(Value::new_imm_i32(unit as i32))
+        }
+        None => Ok(Value::from_f64(interp.heap_mut(), f64::NAN))
+    }
+}
+
+// Long results are ropes, so repeated concatenation is cheap.
+fn concat(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let result = this_string(interp, args, "String.prototype.concat")?;
+    for &arg in args.args() {
+        let string = interp.string_value(arg)?;
+        let length = (length_of(interp, result.get()) as u64) + (length_of(interp, string) as u64);
+        if length > (string::MAX_STRING_LENGTH as u64) {
+            return Err(interp.error(ErrorKind::RangeError, "Invalid string length"));
+        }
+        let concatenated = string::concat(interp.heap_mut(), result.get(), string);
+        result.set(concatenated);
+    }
+    Ok(result.get())
+}
+
+fn index_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.indexOf")?;
+    let search = string_arg(interp, args.arg(0))?;
+    let position = integer_value(interp, args.arg(1))?;
+    let units = units_of(interp, this.get());
+    let start = position.max(0.0).min(units.len() as f64) as usize;
+    let index = find(&units, &search, start).map_or(-1, |index| index as i32);
+    Ok(Value::new_imm_i32(index))
+}
+
+// A missing or NaN position searches from the end.
+fn last_index_of(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.lastIndexOf")?;
+    let search = string_arg(interp, args.arg(0))?;
+    let position = interp.number_value(args.arg(1))?;
+    let units = units_of(interp, this.get());
+    let start = if position.is_nan() { units.len() } else { position.trunc().max(0.0).min(units.len() as f64) as usize };
+    let index = rfind(&units, &search, start).map_or(-1, |index| index as i32);
+    Ok(Value::new_imm_i32(index))
+}
+
+fn slice(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.slice")?;
+    let length = length_of(interp, this.get());
+    let start = relative_index(interp, args.arg(0), length, 0)?;
+    let end = relative_index(interp, args.arg(1), length, length)?;
+    Ok(string::substring(interp.heap_mut(), this.get(), start, end.max(start)))
+}
+
+// Positions are clamped to the string, and taken in either order.
+fn substring(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.substring")?;
+    let length = length_of(interp, this.get());
+    let clamp = |position: f64| position.max(0.0).min(length as f64) as u32;
+    let start = clamp(integer_value(interp, args.arg(0))?);
+    let end = if args.arg(1).is_imm_undef() { length } else { clamp(integer_value(interp, args.arg(1))?) };
+    Ok(string::substring(interp.heap_mut(), this.get(), start.min(end), start.max(end)))
+}
+
+// A start counting back from the end if it's negative, and a length.
+fn substr(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.substr")?;
+    let length = length_of(interp, this.get());
+    let start = relative_index(interp, args.arg(0), length, 0)?;
+    let count = if args.arg(1).is_imm_undef() { length as f64 } else { integer_value(interp, args.arg(1))? };
+    let end = start + count.max(0.0).min((length - start) as f64) as u32;
+    Ok(string::substring(interp.heap_mut(), this.get(), start, end))
+}
+
+/**
+ * Split at each occurrence of a separator string, into at most `limit`
+ * pieces.  An empty separator splits between code units, and a missing one
+ * gives the whole string.  A RegExp separator splits at its matches.
+ */
+fn split(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.split")?;
+    let limit = if args.arg(1).is_imm_undef() { u32::MAX } else { interp.uint32_value(args.arg(1))? };
+    if let Some(compiled) = regexp::regexp_of(interp, args.arg(0)) {
+        return split_regexp(interp, this.get(), &compiled, limit);
+    }
+    let separator = if args.arg(0).is_imm_undef() { None } else { Some(string_arg(interp, args.arg(0))?) };
+
+    let this = this.get();
+    let length = length_of(interp, this);
+    let mut pieces: Vec<Value> = Vec::new();
+    match separator {
+        _ if limit == 0 => {}
+        None => pieces.push(this),
+        Some(ref separator) if separator.is_empty() => {
+            for index in 0..length.min(limit) {
+                pieces.push(string::substring(interp.heap_mut(), this, index, index + 1));
+            }
+        }
+        Some(ref separator) => {
+            let units = units_of(interp, this);
+            let mut start = 0;
+            while let Some(index) = find(&units, separator, start) {
+                pieces.push(string::substring(interp.heap_mut(), this, start as u32, index as u32));
+                if pieces.len() as u32 == limit {
+                    return Ok(builtins::array::new_array_from(interp, &pieces));
+                }
+                start = index + separator.len();
+            }
+            pieces.push(string::substring(interp.heap_mut(), this, start as u32, length));
+        }
+    }
+    Ok(builtins::array::new_array_from(interp, &pieces))
+}
+
+/**
+ * Split at the matches of a RegExp, as the spec's SplitMatch finds them:
+ * anchored at each position in turn, ignoring empty matches at the end of
+ * the last piece.  The texts of the groups of each match are pieces too.
+ */
+fn split_regexp(interp: &mut Interpreter, this: Value, compiled: &RegExp, limit: u32) -> VmResult<Value> {
+    let units = units_of(interp, this);
+    let mut pieces: Vec<Value> = Vec::new();
+    if limit == 0 {
+        return Ok(builtins::array::new_array_from(interp, &pieces));
+    }
+    if units.is_empty() {
+        if regexp::match_at(interp, compiled, &units, 0)?.is_none() {
+            pieces.push(this);
+        }
+        return Ok(builtins::array::new_array_from(interp, &pieces));
+    }
+
+    let mut piece_start = 0;
+    let mut position = 0;
+    while position < units.len() {
+        let captures = match regexp::match_at(interp, compiled, &units, position)? {
+            Some(captures) => captures,
+            None => {
+                position += 1;
+                continue;
+            }
+        };
+        let end = captures[0].unwrap().1;
+        if end == piece_start {
+            position += 1;
+            continue;
+        }
+        pieces.push(string::substring(interp.heap_mut(), this, piece_start as u32, position as u32));
+        for capture in &captures[1..] {
+            if pieces.len() as u32 == limit {
+                break;
+            }
+            pieces.push(capture_value(interp, this, *capture));
+        }
+        if pieces.len() as u32 == limit {
+            return Ok(builtins::array::new_array_from(interp, &pieces));
+        }
+        piece_start = end;
+        position = end;
+    }
+    pieces.push(string::substring(interp.heap_mut(), this, piece_start as u32, units.len() as u32));
+    Ok(builtins::array::new_array_from(interp, &pieces))
+}
+
+// The text a group captured, or undefined if it took no part in the match.
+fn capture_value(interp: &mut Interpreter, string: Value, capture: Option<(usize, usize)>) -> Value {
+    match capture {
+        Some((start, end)) => string::substring(interp.heap_mut(), string, start as u32, end as u32),
+        None => Value::new_imm_undef()
+    }
+}
+
+/**
+ * Replace the first occurrence of a search string, or the matches of a
+ * RegExp (all of them, if it's global).  Each is replaced either by the
+ * result of calling a function with the match, the groups' texts, its
+ * position and the string, or by a replacement string, in which `$$`, `$&`,
+ * `` $` ``, `$'` and `$n` stand for a dollar sign, the match, the text
+ * before and after it, and the text of group `n`.
+ */
+fn replace(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.replace")?;
+    let search_value = args.arg(0);
+    let compiled = regexp::regexp_of(interp, search_value);
+    let search = match compiled {
+        Some(_) => Vec::new(),
+        None => string_arg(interp, search_value)?
+    };
+    let replace_value = args.arg(1);
+    let replacement = if replace_value.is_callable(interp.heap()) {
+        None
+    } else {
+        Some(string_arg(interp, replace_value)?)
+    };
+
+    let units = units_of(interp, this.get());
+    let matches: Vec<Captures> = match compiled {
+        Some(compiled) => regexp::exec_all(interp, search_value, &compiled, &units)?,
+        None => find(&units, &search, 0).map(|index| vec![Some((index, index + search.len()))]).into_iter().collect()
+    };
+    if matches.is_empty() {
+        return Ok(this.get());
+    }
+
+    let mut result: Vec<u16> = Vec::with_capacity(units.len());
+    let mut last_end = 0;
+    for captures in &matches {
+        let (start, end) = captures[0].unwrap();
+        result.extend_from_slice(&units[last_end..start]);
+        match replacement {
+            Some(ref replacement) => result.extend(expand_replacement(replacement, &units, captures)),
+            None => {
+                let mut call_args: Vec<Value> = Vec::with_capacity(captures.len() + 2);
+                for &capture in captures {
+                    call_args.push(capture_value(interp, this.get(), capture));
+                }
+                call_args.push(Value::new_imm_i32(start as i32));
+                call_args.push(this.get());
+                let value = interp.call(replace_value, Value::new_imm_undef(), &call_args)?;
+                result.extend(string_arg(interp, value)?);
+            }
+        }
+        last_end = end;
+    }
+    result.extend_from_slice(&units[last_end..]);
+    new_string_checked(interp, &result)
+}
+
+/**
+ * A replacement string with its `$` patterns expanded, for a match in
+ * `units` with the given captures, the first being the whole match.  `$n`
+ * and `$nn` name groups which exist, preferring two digits.  Other `$`s are
+ * kept as they are.
+ */
+pub fn expand_replacement(replacement: &[u16], units: &[u16], captures: &[Option<(usize, usize)>]) -> Vec<u16> {
+    let (start, end) = captures[0].unwrap();
+    let group_count = captures.len() - 1;
+    let digit = |index: usize| {
+        replacement.get(index).cloned().filter(|unit| (0x30..=0x39).contains(unit)).map(|unit| (unit - 0x30) as usize)
+    };
+
+    let mut result: Vec<u16> = Vec::with_capacity(replacement.len());
+    let mut index = 0;
+    while index < replacement.len() {
+        if replacement[index] == (b'$' as u16) && index + 1 < replacement.len() {
+            let expansion = match replacement[index + 1] as u32 {
+                0x24 => Some((&replacement[index..(index + 1)], 2)),
+                0x26 => Some((&units[start..end], 2)),
+                0x60 => Some((&units[..start], 2)),
+                0x27 => Some((&units[end..], 2)),
+                _ => {
+                    let group = match (digit(index + 1), digit(index + 2)) {
+                        (Some(tens), Some(ones)) if (1..=group_count).contains(&(tens * 10 + ones)) => {
+                            Some((tens * 10 + ones, 3))
+                        }
+                        (Some(ones), _) if (1..=group_count).contains(&ones) => Some((ones, 2)),
+                        _ => None
+                    };
+                    group.map(|(group, length)| match captures[group] {
+                        Some((start, end)) => (&units[start..end], length),
+                        None => (&units[..0], length)
+                    })
+                }
+            };
+            if let Some((expansion, length)) = expansion {
+                result.extend_from_slice(expansion);
+                index += length;
+                continue;
+            }
+        }
+        result.push(replacement[index]);
+        index += 1;
+    }
+    result
+}
+
+/**
+ * The match of a RegExp, as `exec` gives it, if it isn't global.  A global
+ * one gives an array of the texts of all its matches, or null if there are
+ * none.  Other values are made into RegExps.
+ */
+fn match_method(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.match")?;
+    let object = regexp::regexp_arg(interp, args.arg(0))?;
+    let object = interp.heap_mut().root(object);
+    let compiled = regexp::regexp_of(interp, object.get()).unwrap();
+    let units = units_of(interp, this.get());
+    let matches = regexp::exec_all(interp, object.get(), &compiled, &units)?;
+    if matches.is_empty() {
+        return Ok(Value::new_imm_null());
+    }
+    if ! compiled.flags().global {
+        return Ok(regexp::match_array(interp, this.get(), &matches[0]));
+    }
+    let mut texts: Vec<Value> = Vec::with_capacity(matches.len());
+    for captures in &matches {
+        texts.push(capture_value(interp, this.get(), captures[0]));
+    }
+    Ok(builtins::array::new_array_from(interp, &texts))
+}
+
+// The position of the first match of a RegExp, or -1.  The search starts at
+// the beginning whatever the RegExp's `lastIndex`.
+fn search(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.search")?;
+    let object = regexp::regexp_arg(interp, args.arg(0))?;
+    let compiled = regexp::regexp_of(interp, object).unwrap();
+    let units = units_of(interp, this.get());
+    let index = match regexp::search(interp, &compiled, &units, 0)? {
+        Some(captures) => captures[0].unwrap().0 as i32,
+        None => -1
+    };
+    Ok(Value::new_imm_i32(index))
+}
+
+fn to_lower_case(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.toLowerCase")?;
+    let units = units_of(interp, this.get());
+    let result = map_case(&units, str::to_lowercase);
+    new_string_checked(interp, &result)
+}
+
+fn to_upper_case(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.toUpperCase")?;
+    let units = units_of(interp, this.get());
+    let result = map_case(&units, str::to_uppercase);
+    new_string_checked(interp, &result)
+}
+
+/**
+ * Map the case of the text in some code units, by Unicode's full case
+ * mappings, which may change the length.  Runs of text between unpaired
+ * surrogates are mapped as a whole, so that a final sigma is lowered as one;
+ * the surrogates themselves are kept.
+ */
+fn map_case(units: &[u16], map: fn(&str) -> String) -> Vec<u16> {
+    let mut result: Vec<u16> = Vec::with_capacity(units.len());
+    let mut text = String::new();
+    for decoded in char::decode_utf16(units.iter().cloned()) {
+        match decoded {
+            Ok(ch) => text.push(ch),
+            Err(error) => {
+                result.extend(map(&text).encode_utf16());
+                text.clear();
+                result.push(error.unpaired_surrogate());
+            }
+        }
+    }
+    result.extend(map(&text).encode_utf16());
+    result
+}
+
+// White space and line terminators are trimmed from both ends.
+fn trim(interp: &mut Interpreter, args: &CallArgs) -> VmResult<Value> {
+    let this = this_string(interp, args, "String.prototype.trim")?;
+    let units = units_of(interp, this.get());
+    let start = units.iter().position(|&unit| ! is_str_white_space(unit)).unwrap_or(units.len());
+    let end = units.iter().rposition(|&unit| ! is_str_white_space(unit)).map_or(start, |index| index + 1);
+    Ok(string::substring(interp.heap_mut(), this.get(), start as u32, end as u32))
+}
@@ -0,0 +1,1799 @@
+/*!
+ * The bytecode compiler, which translates a parsed program into `FunctionCode`.
+ *
+ * Each function is compiled in two steps.  Its declarations are collected
+ * first, along with the free variables of the functions nested inside it, to
+ * decide which of its variables are captured.  Captured variables are given
+ * environment slots, and the rest are given registers.  Then its body is
+ * compiled, resolving each name to a register, an environment slot in an
+ * enclosing function, or a global.
+ *
+ * A catch parameter is scoped to its catch block, where it shadows other
+ * bindings of its name.  It's given a register, or an environment slot of its
+ * function if a function nested in the block refers to it, so closures made by
+ * different runs of the block share it.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use parser::ast::{self, AstKind, AstNode, FunctionDef, PropertyDefinitionKind};
+use parser::ast_builder::FullToken;
+use parser::token_kind::TokenKind;
+use parser::tokenizer::{Token, TokenLocation};
+use vm::bytecode::{BytecodeWriter, Constant, FunctionCode, Handler, JumpSite, Op};
+use vm::conversions::number_to_string;
+use vm::regexp::{RegExp, RegExpError};
+
+#[derive(Debug)]
+pub enum CompileError {
+    Unsupported(&'static str),
+    InvalidEscape(TokenLocation),
+    InvalidRegExp(TokenLocation, RegExpError),
+    TooManyConstants,
+    TooManyRegisters,
+    TooManyInlineCaches,
+    TooManyArguments,
+    TooDeeplyNested
+}
+pub type CompileResult<T> = Result<T, CompileError>;
+
+/** Compile a program to the code of its top level. */
+pub fn compile_program(program: &ast::ProgramNode) -> CompileResult<Rc<FunctionCode>> {
+    let mut compiler = Compiler::new();
+    compiler.compile_program(program)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum VarLocation {
+    Register(u16),
+    Env(u16)
+}
+
+/**
+ * A statement enclosing the code being compiled, which break, continue or
+ * return may leave.
+ */
+enum Control {
+    // A loop or labelled statement, and the jumps to its end and (for loops)
+    // to where it continues with the next iteration.
+    Breakable {
+        labels: Vec<String>,
+        is_loop: bool,
+        break_jumps: Vec<JumpSite>,
+        continue_jumps: Vec<JumpSite>
+    },
+    // A try statement with a finally block, and the calls to the block.
+    // Returns leaving the statement keep their value in `value_register`
+    // while the block runs.
+    Finally {
+        gosubs: Vec<JumpSite>,
+        value_register: u16
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    Register(u16),
+    Env(u8, u16),
+    Global(u16)
+}
+
+/** The state of a function being compiled. */
+struct FunctionState {
+    writer: BytecodeWriter,
+    constants: Vec<Constant>,
+    number_constants: HashMap<u64, u16>,
+    string_constants: HashMap<Vec<u16>, u16>,
+
+    // Variables of the program are globals, so it has none of its own.
+    program: bool,
+    vars: HashMap<String, VarLocation>,
+    env_size: u16,
+
+    // The environment slots of captured catch parameters, by try statement,
+    // and the catch parameters in scope, innermost last.
+    catch_slots: HashMap<*const ast::TryStmtNode, u16>,
+    catch_scopes: Vec<(String, VarLocation)>,
+
+    controls: Vec<Control>,
+    handlers: Vec<Handler>,
+
+    next_register: u16,
+    max_registers: u16,
+    inline_cache_count: u16
+}
+impl FunctionState {
+    fn new(program: bool) -> FunctionState {
+        FunctionState {
+            writer: BytecodeWriter::new(),
+            constants: Vec::new(),
+            number_constants: HashMap::new(),
+            string_constants: HashMap::new(),
+            program,
+            vars: HashMap::new(),
+            env_size: 0,
+            catch_slots: HashMap::new(),
+            catch_scopes: Vec::new(),
+            controls: Vec::new(),
+            handlers: Vec::new(),
+            next_register: 0,
+            max_registers: 0,
+            inline_cache_count: 0
+        }
+    }
+
+    fn has_env(&self) -> bool {
+        self.env_size > 0
+    }
+
+    fn alloc_register(&mut self) -> CompileResult<u16> {
+        let register = self.next_register;
+        if register == u16::MAX {
+            return Err(CompileError::TooManyRegisters);
+        }
+        self.next_register += 1;
+        self.max_registers = self.max_registers.max(self.next_register);
+        Ok(register)
+    }
+    // Temporaries are freed in the reverse order of allocation.
+    fn free_register(&mut self, register: u16) {
+        assert!(register + 1 == self.next_register);
+        self.next_register = register;
+    }
+
+    fn alloc_inline_cache(&mut self) -> CompileResult<u16> {
+        let cache = self.inline_cache_count;
+        if cache == u16::MAX {
+            return Err(CompileError::TooManyInlineCaches);
+        }
+        self.inline_cache_count += 1;
+        Ok(cache)
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> CompileResult<u16> {
+        if self.constants.len() > (u16::MAX as usize) {
+            return Err(CompileError::TooManyConstants);
+        }
+        self.constants.push(constant);
+        Ok((self.constants.len() - 1) as u16)
+    }
+}
+
+/**
+ * The names declared by a function, the functions nested directly within it,
+ * and its try statements with catch blocks.
+ */
+struct Declarations<'a> {
+    vars: Vec<String>,
+    functions: Vec<&'a FunctionDef>,
+    nested: Vec<&'a FunctionDef>,
+    catches: Vec<&'a ast::TryStmtNode>
+}
+
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    free_variables: HashMap<*const FunctionDef, Rc<HashSet<String>>>
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler { functions: Vec::new(), free_variables: HashMap::new() }
+    }
+
+    pub fn compile_program(&mut self, program: &ast::ProgramNode) -> CompileResult<Rc<FunctionCode>> {
+        let decls = collect_declarations(program.source_elements());
+        let mut state = FunctionState::new(/* program = */ true);
+        let env_size = self.assign_catch_slots(&mut state, &decls, 1);
+        if env_size > 1 {
+            state.env_size = env_size;
+        }
+        self.functions.push(state);
+
+        if self.state().has_env() {
+            let env_size = self.state().env_size;
+            self.writer().emit_u16(Op::CreateEnv, env_size);
+        }
+        // Hoist declarations: vars are defined before any code runs, and
+        // functions are bound before any statement runs.  Functions are
+        // declared first too, so the assignment binding them is allowed in
+        // strict code.
+        for name in &decls.vars {
+            let name_idx = self.string_constant(name)?;
+            self.writer().emit_u16(Op::DeclareGlobal, name_idx);
+        }
+        for func in &decls.functions {
+            let name_idx = self.string_constant(func_name(func))?;
+            self.writer().emit_u16(Op::DeclareGlobal, name_idx);
+            self.compile_closure(func, /* is_expr = */ false)?;
+            self.writer().emit_u16(Op::SetGlobal, name_idx);
+            self.writer().emit(Op::Pop);
+        }
+
+        for stmt in program.source_elements() {
+            self.compile_statement(stmt.as_ref())?;
+        }
+        self.writer().emit(Op::LoadCompletion);
+        self.writer().emit(Op::Return);
+
+        let state = self.functions.pop().unwrap();
+        let code = FunctionCode::new_program(state.max_registers, state.env_size, program.is_strict(),
+                                             state.writer.finish(), state.constants);
+        Ok(Rc::new(code.with_handlers(state.handlers)))
+    }
+
+    fn compile_function(&mut self, func: &FunctionDef, is_expr: bool)
+        -> CompileResult<Rc<FunctionCode>>
+    {
+        let decls = collect_declarations(func.body());
+        let params: Vec<String> = func.params().iter().map(|p| token_name(p).to_string()).collect();
+        if params.len() > (u8::MAX as usize) {
+            return Err(CompileError::TooManyArguments);
+        }
+
+        // Functions referring to `arguments` get an arguments object, unless a
+        // parameter or function declaration takes the name.
+        let arguments_object = ! params.iter().any(|p| p == "arguments") &&
+            ! decls.functions.iter().any(|f| func_name(f) == "arguments") &&
+            refers_to_arguments(func);
+
+        // Only named function expressions bind their own name, and only when
+        // nothing else in the function is declared with it.
+        let mut declared: Vec<String> = params.clone();
+        if arguments_object {
+            declared.push("arguments".to_string());
+        }
+        declared.extend(decls.vars.iter().cloned());
+        declared.extend(decls.functions.iter().map(|f| func_name(f).to_string()));
+        let self_name = match func.name() {
+            Some(name) if is_expr && ! declared.iter().any(|d| d == token_name(name)) => {
+                declared.push(token_name(name).to_string());
+                Some(token_name(name).to_string())
+            }
+            _ => None
+        };
+
+        // The arguments object of a non-strict function aliases its
+        // parameters, so they're kept in its environment.
+        let mapped_arguments = arguments_object && ! func.is_strict();
+        let mut captured = HashSet::new();
+        if mapped_arguments {
+            captured.extend(params.iter().cloned());
+        }
+        for nested in &decls.nested {
+            for name in self.free_variables_of(nested).iter() {
+                if declared.contains(name) {
+                    captured.insert(name.clone());
+                }
+            }
+        }
+
+        // Parameters always arrive in the first registers, followed by any
+        // arguments object.  Captured parameters are then copied into the
+        // environment.
+        let mut state = FunctionState::new(/* program = */ false);
+        state.next_register = params.len() as u16;
+        state.max_registers = state.next_register;
+        let arguments_register = if arguments_object { Some(state.alloc_register()?) } else { None };
+        let mut next_env_slot: u16 = 1;
+        let mut captured_params = Vec::new();
+        for name in &declared {
+            if state.vars.contains_key(name) {
+                continue;
+            }
+            let param_register = match params.iter().rposition(|p| p == name) {
+                Some(idx) => Some(idx as u16),
+                None if name == "arguments" => arguments_register,
+                None => None
+            };
+            let location = if captured.contains(name) {
+                next_env_slot += 1;
+                VarLocation::Env(next_env_slot - 1)
+            } else if let Some(register) = param_register {
+                VarLocation::Register(register)
+            } else {
+                VarLocation::Register(state.alloc_register()?)
+            };
+            if let (VarLocation::Env(slot), Some(register)) = (location, param_register) {
+                captured_params.push((register, slot));
+            }
+            state.vars.insert(name.clone(), location);
+        }
+        next_env_slot = self.assign_catch_slots(&mut state, &decls, next_env_slot);
+        if next_env_slot > 1 {
+            state.env_size = next_env_slot;
+        }
+        self.functions.push(state);
+
+        // Prologue.
+        if self.state().has_env() {
+            let env_size = self.state().env_size;
+            self.writer().emit_u16(Op::CreateEnv, env_size);
+        }
+        for &(register, slot) in &captured_params {
+            self.writer().emit_u16(Op::GetLocal, register);
+            self.writer().emit_u8_u16(Op::SetEnv, 0, slot);
+            self.writer().emit(Op::Pop);
+        }
+        if let (true, Some(register)) = (mapped_arguments, arguments_register) {
+            // A repeated parameter name is bound to its last argument.
+            self.writer().emit_u16(Op::GetLocal, register);
+            for (idx, name) in params.iter().enumerate() {
+                if let (Some(VarLocation::Env(slot)), true) =
+                    (self.state().vars.get(name).cloned(), params.iter().rposition(|p| p == name) == Some(idx))
+                {
+                    self.writer().emit_u8_u16(Op::MapArgument, idx as u8, slot);
+                }
+            }
+            self.writer().emit(Op::Pop);
+        }
+        if let Some(ref name) = self_name {
+            self.writer().emit(Op::Callee);
+            self.emit_set_name(name)?;
+            self.writer().emit(Op::Pop);
+        }
+        for nested in &decls.functions {
+            self.compile_closure(nested, /* is_expr = */ false)?;
+            self.emit_set_name(func_name(nested))?;
+            self.writer().emit(Op::Pop);
+        }
+
+        for stmt in func.body() {
+            self.compile_statement(stmt.as_ref())?;
+        }
+        self.writer().emit(Op::Undefined);
+        self.writer().emit(Op::Return);
+
+        let state = self.functions.pop().unwrap();
+        let name = func.name().map(|name| token_name(name).to_string());
+        let code = FunctionCode::new(name, params.len() as u16, state.max_registers, state.env_size,
+                                     func.is_strict(), state.writer.finish(), state.constants)
+                       .with_handlers(state.handlers);
+        Ok(Rc::new(if arguments_object { code.with_arguments_object() } else { code }))
+    }
+
+    // Give the captured catch parameters of a function environment slots from
+    // `next_env_slot`, returning the slot after them.
+    fn assign_catch_slots(&mut self, state: &mut FunctionState, decls: &Declarations, next_env_slot: u16)
+        -> u16
+    {
+        let mut next_env_slot = next_env_slot;
+        for &try_stmt in &decls.catches {
+            let name = token_name(try_stmt.catch_param().unwrap());
+            let block = try_stmt.catch_block().unwrap().downcast_ref::<ast::BlockStmtNode>().unwrap();
+            let nested = collect_declarations(block.statements()).nested;
+            if nested.iter().any(|func| self.free_variables_of(func).contains(name)) {
+                state.catch_slots.insert(try_stmt as *const ast::TryStmtNode, next_env_slot);
+                next_env_slot += 1;
+            }
+        }
+        next_env_slot
+    }
+
+    // Compile a nested function, and emit code creating a closure of it.
+    fn compile_closure(&mut self, func: &FunctionDef, is_expr: bool) -> CompileResult<()> {
+        let code = self.compile_function(func, is_expr)?;
+        let idx = self.state().add_constant(Constant::Function(code))?;
+        self.writer().emit_u16(Op::Closure, idx);
+        Ok(())
+    }
+
+    /**
+     * The names referenced by a function or the functions nested within it,
+     * which it does not declare itself.
+     */
+    fn free_variables_of(&mut self, func: &FunctionDef) -> Rc<HashSet<String>> {
+        let key = func as *const FunctionDef;
+        if let Some(free) = self.free_variables.get(&key) {
+            return free.clone();
+        }
+
+        let decls = collect_declarations(func.body());
+        let mut references = HashSet::new();
+        for stmt in func.body() {
+            collect_references(stmt.as_ref(), &mut references);
+        }
+        for nested in &decls.nested {
+            references.extend(self.free_variables_of(nested).iter().cloned());
+        }
+
+        // Each function has its own arguments.
+        references.remove("arguments");
+        for param in func.params() {
+            references.remove(token_name(param));
+        }
+        for name in &decls.vars {
+            references.remove(name);
+        }
+        for nested in &decls.functions {
+            references.remove(func_name(nested));
+        }
+        if let Some(name) = func.name() {
+            references.remove(token_name(name));
+        }
+
+        let free = Rc::new(references);
+        self.free_variables.insert(key, free.clone());
+        free
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+    fn writer(&mut self) -> &mut BytecodeWriter {
+        &mut self.state().writer
+    }
+
+    fn resolve(&mut self, name: &str) -> CompileResult<Binding> {
+        let current = self.functions.len() - 1;
+        let mut hops: usize = 0;
+        for (depth, state) in self.functions.iter().enumerate().rev() {
+            let catch_location = state.catch_scopes.iter().rev()
+                                      .find(|(param, _)| param == name)
+                                      .map(|&(_, location)| location);
+            match catch_location.or_else(|| state.vars.get(name).cloned()) {
+                Some(VarLocation::Register(register)) => {
+                    assert!(depth == current);
+                    return Ok(Binding::Register(register));
+                }
+                Some(VarLocation::Env(slot)) => {
+                    if hops > (u8::MAX as usize) {
+                        return Err(CompileError::TooDeeplyNested);
+                    }
+                    return Ok(Binding::Env(hops as u8, slot));
+                }
+                None => {}
+            }
+            if state.program {
+                break;
+            }
+            if state.has_env() {
+                hops += 1;
+            }
+        }
+        Ok(Binding::Global(self.string_constant(name)?))
+    }
+
+    fn emit_get_name(&mut self, name: &str) -> CompileResult<()> {
+        match self.resolve(name)? {
+            Binding::Register(register) => self.writer().emit_u16(Op::GetLocal, register),
+            Binding::Env(hops, slot) => self.writer().emit_u8_u16(Op::GetEnv, hops, slot),
+            Binding::Global(name_idx) => self.writer().emit_u16(Op::GetGlobal, name_idx)
+        }
+        Ok(())
+    }
+    fn emit_set_name(&mut self, name: &str) -> CompileResult<()> {
+        match self.resolve(name)? {
+            Binding::Register(register) => self.writer().emit_u16(Op::SetLocal, register),
+            Binding::Env(hops, slot) => self.writer().emit_u8_u16(Op::SetEnv, hops, slot),
+            Binding::Global(name_idx) => self.writer().emit_u16(Op::SetGlobal, name_idx)
+        }
+        Ok(())
+    }
+
+    // Property gets and sets each have their own inline cache.
+    fn emit_get_prop(&mut self, name_idx: u16) -> CompileResult<()> {
+        let cache = self.state().alloc_inline_cache()?;
+        self.writer().emit_u16_u16(Op::GetProp, name_idx, cache);
+        Ok(())
+    }
+    fn emit_set_prop(&mut self, name_idx: u16) -> CompileResult<()> {
+        let cache = self.state().alloc_inline_cache()?;
+        self.writer().emit_u16_u16(Op::SetProp, name_idx, cache);
+        Ok(())
+    }
+    fn emit_get_elem(&mut self) -> CompileResult<()> {
+        let cache = self.state().alloc_inline_cache()?;
+        self.writer().emit_u16(Op::GetElem, cache);
+        Ok(())
+    }
+    fn emit_set_elem(&mut self) -> CompileResult<()> {
+        let cache = self.state().alloc_inline_cache()?;
+        self.writer().emit_u16(Op::SetElem, cache);
+        Ok(())
+    }
+
+    fn string_constant(&mut self, text: &str) -> CompileResult<u16> {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        self.string_constant_units(units)
+    }
+    fn string_constant_units(&mut self, units: Vec<u16>) -> CompileResult<u16> {
+        if let Some(&idx) = self.state().string_constants.get(&units) {
+            return Ok(idx);
+        }
+        let idx = self.state().add_constant(Constant::String(units.clone().into_boxed_slice()))?;
+        self.state().string_constants.insert(units, idx);
+        Ok(idx)
+    }
+
+    fn emit_number(&mut self, fval: f64) -> CompileResult<()> {
+        let is_int = (fval.trunc() == fval) && ! (fval == 0.0 && fval.is_sign_negative());
+        if is_int && (fval >= (i8::MIN as f64)) && (fval <= (i8::MAX as f64)) {
+            self.writer().emit_i8(Op::Int8, fval as i8);
+            return Ok(());
+        }
+        if is_int && (fval >= (i32::MIN as f64)) && (fval <= (i32::MAX as f64)) {
+            self.writer().emit_i32(Op::Int32, fval as i32);
+            return Ok(());
+        }
+
+        let bits = fval.to_bits();
+        let idx = match self.state().number_constants.get(&bits) {
+            Some(&idx) => idx,
+            None => {
+                let idx = self.state().add_constant(Constant::Number(fval))?;
+                self.state().number_constants.insert(bits, idx);
+                idx
+            }
+        };
+        self.writer().emit_u16(Op::Const, idx);
+        Ok(())
+    }
+
+    /*************************************************************************
+     **** Statements *********************************************************
+     *************************************************************************/
+
+    fn compile_statement(&mut self, node: &dyn AstNode) -> CompileResult<()> {
+        match node.kind() {
+            AstKind::BlockStmt => {
+                let block = node.downcast_ref::<ast::BlockStmtNode>().unwrap();
+                for stmt in block.statements() {
+                    self.compile_statement(stmt.as_ref())?;
+                }
+            }
+            AstKind::VarStmt => {
+                let var_stmt = node.downcast_ref::<ast::VarStmtNode>().unwrap();
+                for decl in var_stmt.var_decls() {
+                    if let Some(init_expr) = decl.init_expr() {
+                        self.compile_expression(init_expr)?;
+                        self.emit_set_name(token_name(decl.name()))?;
+                        self.writer().emit(Op::Pop);
+                    }
+                }
+            }
+            AstKind::EmptyStmt | AstKind::FunctionDecl => {
+                // Function declarations are bound when their scope is entered.
+            }
+            AstKind::IfStmt => {
+                let if_stmt = node.downcast_ref::<ast::IfStmtNode>().unwrap();
+                self.compile_expression(if_stmt.cond_expr())?;
+                let else_jump = self.writer().emit_jump(Op::JumpIfFalse);
+                self.compile_statement(if_stmt.if_true_stmt())?;
+                match if_stmt.if_false_stmt() {
+                    Some(if_false_stmt) => {
+                        let end_jump = self.writer().emit_jump(Op::Jump);
+                        self.writer().patch_jump(else_jump);
+                        self.compile_statement(if_false_stmt)?;
+                        self.writer().patch_jump(end_jump);
+                    }
+                    None => {
+                        self.writer().patch_jump(else_jump);
+                    }
+                }
+            }
+            AstKind::ExprStmt => {
+                let expr_stmt = node.downcast_ref::<ast::ExprStmtNode>().unwrap();
+                self.compile_expression(expr_stmt.expression())?;
+                if self.state().program {
+                    self.writer().emit(Op::StoreCompletion);
+                } else {
+                    self.writer().emit(Op::Pop);
+                }
+            }
+            AstKind::ReturnStmt => {
+                let return_stmt = node.downcast_ref::<ast::ReturnStmtNode>().unwrap();
+                match return_stmt.expression() {
+                    Some(expr) => self.compile_expression(expr)?,
+                    None => self.writer().emit(Op::Undefined)
+                }
+                self.compile_return()?;
+            }
+            AstKind::WithStmt => {
+                return Err(CompileError::Unsupported("with statement"));
+            }
+            AstKind::ThrowStmt => {
+                let throw_stmt = node.downcast_ref::<ast::ThrowStmtNode>().unwrap();
+                self.compile_expression(throw_stmt.expression())?;
+                self.writer().emit(Op::Throw);
+            }
+            AstKind::TryStmt => {
+                let try_stmt = node.downcast_ref::<ast::TryStmtNode>().unwrap();
+                self.compile_try(try_stmt)?;
+            }
+            AstKind::WhileStmt | AstKind::DoWhileStmt | AstKind::ForStmt | AstKind::ForInStmt => {
+                self.compile_loop(node, Vec::new())?;
+            }
+            AstKind::LabelledStmt => {
+                // Consecutive labels all label the statement they lead to.
+                let mut labels = Vec::new();
+                let mut body = node;
+                while let Some(labelled) = body.downcast_ref::<ast::LabelledStmtNode>() {
+                    labels.push(token_name(labelled.label()).to_string());
+                    body = labelled.body_stmt();
+                }
+                if is_loop(body) {
+                    self.compile_loop(body, labels)?;
+                } else {
+                    self.push_breakable(labels, /* is_loop = */ false);
+                    self.compile_statement(body)?;
+                    let (break_jumps, _) = self.pop_breakable();
+                    self.patch_jumps(break_jumps);
+                }
+            }
+            AstKind::BreakStmt => {
+                let break_stmt = node.downcast_ref::<ast::BreakStmtNode>().unwrap();
+                self.compile_break_or_continue(break_stmt.label(), /* is_break = */ true);
+            }
+            AstKind::ContinueStmt => {
+                let continue_stmt = node.downcast_ref::<ast::ContinueStmtNode>().unwrap();
+                self.compile_break_or_continue(continue_stmt.label(), /* is_break = */ false);
+            }
+            _ => {
+                panic!("Not a statement: {:?}", node.kind());
+            }
+        }
+        Ok(())
+    }
+
+    // Return the value on the stack, first running the finally blocks of any
+    // try statements being left.
+    fn compile_return(&mut self) -> CompileResult<()> {
+        let finallys = self.finally_controls(0);
+        let value_register = match finallys.last() {
+            Some(&outermost) => match self.state().controls[outermost] {
+                Control::Finally { value_register, .. } => value_register,
+                _ => unreachable!()
+            },
+            None => {
+                self.writer().emit(Op::Return);
+                return Ok(());
+            }
+        };
+        self.writer().emit_u16(Op::SetLocal, value_register);
+        self.writer().emit(Op::Pop);
+        self.emit_gosubs(&finallys);
+        self.writer().emit_u16(Op::GetLocal, value_register);
+        self.writer().emit(Op::Return);
+        Ok(())
+    }
+
+    /**
+     * Jump to the end of the statement a break leaves, or to where the loop a
+     * continue continues, running the finally blocks of any try statements
+     * left on the way.  The parser has checked that the target exists.
+     */
+    fn compile_break_or_continue(&mut self, label: Option<&FullToken>, is_break: bool) {
+        let label = label.map(token_name);
+        let target = self.state().controls.iter().rposition(|control| match *control {
+            Control::Breakable { ref labels, is_loop, .. } => {
+                (is_break || is_loop) && match label {
+                    Some(label) => labels.iter().any(|l| l == label),
+                    None => is_loop
+                }
+            }
+            Control::Finally { .. } => false
+        }).expect("break or continue target");
+
+        let finallys = self.finally_controls(target + 1);
+        self.emit_gosubs(&finallys);
+        let site = self.writer().emit_jump(Op::Jump);
+        match self.state().controls[target] {
+            Control::Breakable { ref mut break_jumps, ref mut continue_jumps, .. } => {
+                if is_break { break_jumps.push(site) } else { continue_jumps.push(site) }
+            }
+            Control::Finally { .. } => unreachable!()
+        }
+    }
+
+    // The indices of the finally controls from `start`, innermost first.
+    fn finally_controls(&mut self, start: usize) -> Vec<usize> {
+        let controls = &self.state().controls;
+        (start..controls.len()).rev()
+            .filter(|&idx| matches!(controls[idx], Control::Finally { .. }))
+            .collect()
+    }
+
+    // Call the finally blocks of controls, in order.
+    fn emit_gosubs(&mut self, finallys: &[usize]) {
+        for &idx in finallys {
+            let site = self.writer().emit_jump(Op::Gosub);
+            match self.state().controls[idx] {
+                Control::Finally { ref mut gosubs, .. } => gosubs.push(site),
+                Control::Breakable { .. } => unreachable!()
+            }
+        }
+    }
+
+    fn push_breakable(&mut self, labels: Vec<String>, is_loop: bool) {
+        self.state().controls.push(Control::Breakable {
+            labels,
+            is_loop,
+            break_jumps: Vec::new(),
+            continue_jumps: Vec::new()
+        });
+    }
+    // Pop a loop or labelled statement, returning its break and continue jumps.
+    fn pop_breakable(&mut self) -> (Vec<JumpSite>, Vec<JumpSite>) {
+        match self.state().controls.pop() {
+            Some(Control::Breakable { break_jumps, continue_jumps, .. }) => (break_jumps, continue_jumps),
+            _ => panic!("Not a breakable control")
+        }
+    }
+    fn patch_jumps(&mut self, sites: Vec<JumpSite>) {
+        for site in sites {
+            self.writer().patch_jump(site);
+        }
+    }
+
+    /**
+     * Compile a loop, with the labels of any labelled statements directly
+     * enclosing it.  Conditions are tested before each iteration, except the
+     * first of a do-while loop.
+     */
+    fn compile_loop(&mut self, node: &dyn AstNode, labels: Vec<String>) -> CompileResult<()> {
+        match node.kind() {
+            AstKind::WhileStmt => {
+                let while_stmt = node.downcast_ref::<ast::WhileStmtNode>().unwrap();
+                let start = self.writer().position();
+                self.compile_expression(while_stmt.cond_expr())?;
+                let exit_jump = self.writer().emit_jump(Op::JumpIfFalse);
+                self.push_breakable(labels, /* is_loop = */ true);
+                self.compile_statement(while_stmt.body_stmt())?;
+                let (break_jumps, continue_jumps) = self.pop_breakable();
+                self.patch_jumps(continue_jumps);
+                self.writer().emit_jump_back(Op::Jump, start);
+                self.writer().patch_jump(exit_jump);
+                self.patch_jumps(break_jumps);
+            }
+            AstKind::DoWhileStmt => {
+                let do_while_stmt = node.downcast_ref::<ast::DoWhileStmtNode>().unwrap();
+                let start = self.writer().position();
+                self.push_breakable(labels, /* is_loop = */ true);
+                self.compile_statement(do_while_stmt.body_stmt())?;
+                let (break_jumps, continue_jumps) = self.pop_breakable();
+                self.patch_jumps(continue_jumps);
+                self.compile_expression(do_while_stmt.cond_expr())?;
+                self.writer().emit_jump_back(Op::JumpIfTrue, start);
+                self.patch_jumps(break_jumps);
+            }
+            AstKind::ForStmt => {
+                let for_stmt = node.downcast_ref::<ast::ForStmtNode>().unwrap();
+                match for_stmt.init() {
+                    Some(init) if init.kind() == AstKind::VarStmt => self.compile_statement(init)?,
+                    Some(init_expr) => {
+                        self.compile_expression(init_expr)?;
+                        self.writer().emit(Op::Pop);
+                    }
+                    None => {}
+                }
+                let start = self.writer().position();
+                let exit_jump = match for_stmt.cond_expr() {
+                    Some(cond_expr) => {
+                        self.compile_expression(cond_expr)?;
+                        Some(self.writer().emit_jump(Op::JumpIfFalse))
+                    }
+                    None => None
+                };
+                self.push_breakable(labels, /* is_loop = */ true);
+                self.compile_statement(for_stmt.body_stmt())?;
+                let (break_jumps, continue_jumps) = self.pop_breakable();
+                self.patch_jumps(continue_jumps);
+                if let Some(update_expr) = for_stmt.update_expr() {
+                    self.compile_expression(update_expr)?;
+                    self.writer().emit(Op::Pop);
+                }
+                self.writer().emit_jump_back(Op::Jump, start);
+                if let Some(exit_jump) = exit_jump {
+                    self.writer().patch_jump(exit_jump);
+                }
+                self.patch_jumps(break_jumps);
+            }
+            AstKind::ForInStmt => {
+                let for_in_stmt = node.downcast_ref::<ast::ForInStmtNode>().unwrap();
+                self.compile_for_in(for_in_stmt, labels)?;
+            }
+            _ => panic!("Not a loop: {:?}", node.kind())
+        }
+        Ok(())
+    }
+
+    /**
+     * Compile a for-in loop.  The loop's state is kept in a register, and each
+     * key in another while it is stored to the target:
+     *
+     *   object; ForInKeys; save the state
+     *   next:  load the state; ForInNext; JumpIfFalse end
+     *          save the key; store it to the target; body; Jump next
+     *   end:
+     *
+     * A var target's initializer is evaluated first, before the object.
+     */
+    fn compile_for_in(&mut self, for_in_stmt: &ast::ForInStmtNode, labels: Vec<String>)
+        -> CompileResult<()>
+    {
+        let target = for_in_stmt.target();
+        if target.kind() == AstKind::VarStmt {
+            self.compile_statement(target)?;
+        }
+        let state_register = self.state().alloc_register()?;
+        let key_register = self.state().alloc_register()?;
+        self.compile_expression(for_in_stmt.object_expr())?;
+        self.writer().emit(Op::ForInKeys);
+        self.writer().emit_u16(Op::SetLocal, state_register);
+        self.writer().emit(Op::Pop);
+
+        let start = self.writer().position();
+        self.writer().emit_u16(Op::GetLocal, state_register);
+        self.writer().emit(Op::ForInNext);
+        let exit_jump = self.writer().emit_jump(Op::JumpIfFalse);
+        self.writer().emit_u16(Op::SetLocal, key_register);
+        self.writer().emit(Op::Pop);
+        match target.kind() {
+            AstKind::VarStmt => {
+                let var_stmt = target.downcast_ref::<ast::VarStmtNode>().unwrap();
+                self.writer().emit_u16(Op::GetLocal, key_register);
+                self.emit_set_name(token_name(var_stmt.var_decls()[0].name()))?;
+            }
+            AstKind::PropertyExpr => {
+                let prop_expr = target.downcast_ref::<ast::PropertyExprNode>().unwrap();
+                self.compile_expression(prop_expr.target_expr())?;
+                let name_idx = self.string_constant(token_name(prop_expr.property_name()))?;
+                self.writer().emit_u16(Op::GetLocal, key_register);
+                self.emit_set_prop(name_idx)?;
+            }
+            AstKind::ElementExpr => {
+                let elem_expr = target.downcast_ref::<ast::ElementExprNode>().unwrap();
+                self.compile_expression(elem_expr.target_expr())?;
+                self.compile_expression(elem_expr.element_expr())?;
+                self.writer().emit_u16(Op::GetLocal, key_register);
+                self.emit_set_elem()?;
+            }
+            _ => {
+                self.writer().emit_u16(Op::GetLocal, key_register);
+                self.emit_set_name(token_name(target.identifier().expect("for-in target")))?;
+            }
+        }
+        self.writer().emit(Op::Pop);
+
+        self.push_breakable(labels, /* is_loop = */ true);
+        self.compile_statement(for_in_stmt.body_stmt())?;
+        let (break_jumps, continue_jumps) = self.pop_breakable();
+        self.patch_jumps(continue_jumps);
+        self.writer().emit_jump_back(Op::Jump, start);
+        self.writer().patch_jump(exit_jump);
+        self.patch_jumps(break_jumps);
+        self.state().free_register(key_register);
+        self.state().free_register(state_register);
+        Ok(())
+    }
+
+    /**
+     * Compile a try statement.  Its try block is followed by the catch and
+     * finally handlers, then the finally block itself:
+     *
+     *   try block; [Gosub finally]; Jump end
+     *   catch:   bind the exception; catch block; [Gosub finally]; Jump end
+     *   finally handler: save the exception; Gosub finally; rethrow it
+     *   finally: save the return address; finally block; Ret
+     *   end:
+     *
+     * The catch handler covers the try block, and the finally handler covers
+     * both the try and catch blocks, up to itself.  In a program, a finally
+     * block left normally keeps the completion value it was entered with.
+     */
+    fn compile_try(&mut self, try_stmt: &ast::TryStmtNode) -> CompileResult<()> {
+        // The finally block's registers hold the value of a return or the
+        // exception passing through it, and the address to return to.
+        let finally_registers = if try_stmt.finally_block().is_some() {
+            let value_register = self.state().alloc_register()?;
+            let address_register = self.state().alloc_register()?;
+            self.state().controls.push(Control::Finally { gosubs: Vec::new(), value_register });
+            Some((value_register, address_register))
+        } else {
+            None
+        };
+
+        let mut end_jumps = Vec::new();
+        let start = self.writer().position();
+        self.compile_statement(try_stmt.try_block())?;
+        let try_end = self.writer().position();
+        self.emit_leave_try(&mut end_jumps);
+
+        if let Some(param) = try_stmt.catch_param() {
+            let target = self.writer().position();
+            self.add_handler(start, try_end, target);
+            self.compile_catch(try_stmt, token_name(param))?;
+            self.emit_leave_try(&mut end_jumps);
+        }
+
+        if let Some((value_register, address_register)) = finally_registers {
+            let mut gosubs = match self.state().controls.pop() {
+                Some(Control::Finally { gosubs, .. }) => gosubs,
+                _ => panic!("Not a finally control")
+            };
+            let target = self.writer().position();
+            self.add_handler(start, target, target);
+            self.writer().emit_u16(Op::SetLocal, value_register);
+            self.writer().emit(Op::Pop);
+            gosubs.push(self.writer().emit_jump(Op::Gosub));
+            self.writer().emit_u16(Op::GetLocal, value_register);
+            self.writer().emit(Op::Throw);
+
+            self.patch_jumps(gosubs);
+            self.writer().emit_u16(Op::SetLocal, address_register);
+            self.writer().emit(Op::Pop);
+            let completion_register = if self.state().program {
+                let register = self.state().alloc_register()?;
+                self.writer().emit(Op::LoadCompletion);
+                self.writer().emit_u16(Op::SetLocal, register);
+                self.writer().emit(Op::Pop);
+                Some(register)
+            } else {
+                None
+            };
+            self.compile_statement(try_stmt.finally_block().unwrap())?;
+            if let Some(register) = completion_register {
+                self.writer().emit_u16(Op::GetLocal, register);
+                self.writer().emit(Op::StoreCompletion);
+                self.state().free_register(register);
+            }
+            self.writer().emit_u16(Op::Ret, address_register);
+            self.state().free_register(address_register);
+            self.state().free_register(value_register);
+        }
+        self.patch_jumps(end_jumps);
+        Ok(())
+    }
+
+    // Leave the try or catch block of a try statement normally, calling its
+    // finally block if it has one.
+    fn emit_leave_try(&mut self, end_jumps: &mut Vec<JumpSite>) {
+        if let Some(&Control::Finally { .. }) = self.state().controls.last() {
+            let last = self.state().controls.len() - 1;
+            self.emit_gosubs(&[last]);
+        }
+        end_jumps.push(self.writer().emit_jump(Op::Jump));
+    }
+
+    fn add_handler(&mut self, start: usize, end: usize, target: usize) {
+        self.state().handlers.push(Handler { start: start as u32, end: end as u32, target: target as u32 });
+    }
+
+    // Compile a catch block, entered with the exception on the stack.
+    fn compile_catch(&mut self, try_stmt: &ast::TryStmtNode, param: &str) -> CompileResult<()> {
+        let key = try_stmt as *const ast::TryStmtNode;
+        let location = match self.state().catch_slots.get(&key) {
+            Some(&slot) => VarLocation::Env(slot),
+            None => VarLocation::Register(self.state().alloc_register()?)
+        };
+        self.state().catch_scopes.push((param.to_string(), location));
+        self.emit_set_name(param)?;
+        self.writer().emit(Op::Pop);
+        self.compile_statement(try_stmt.catch_block().unwrap())?;
+        self.state().catch_scopes.pop();
+        if let VarLocation::Register(register) = location {
+            self.state().free_register(register);
+        }
+        Ok(())
+    }
+
+    /*************************************************************************
+     **** Expressions ********************************************************
+     *************************************************************************/
+
+    fn compile_expression(&mut self, node: &dyn AstNode) -> CompileResult<()> {
+        match node.kind() {
+            AstKind::AtomicExpr => {
+                let atomic = node.downcast_ref::<ast::AtomicExprNode>().unwrap();
+                self.compile_atomic(atomic.name())?;
+            }
+            AstKind::FunctionExpr => {
+                let func_expr = node.downcast_ref::<ast::FunctionExprNode>().unwrap();
+                self.compile_closure(func_expr.func(), /* is_expr = */ true)?;
+            }
+            AstKind::ObjectLiteralExpr => {
+                let object_expr = node.downcast_ref::<ast::ObjectLiteralExprNode>().unwrap();
+                self.compile_object_literal(object_expr)?;
+            }
+            AstKind::ArrayLiteralExpr => {
+                let array_expr = node.downcast_ref::<ast::ArrayLiteralExprNode>().unwrap();
+                self.compile_array_literal(array_expr)?;
+            }
+            AstKind::PropertyExpr => {
+                let prop_expr = node.downcast_ref::<ast::PropertyExprNode>().unwrap();
+                self.compile_expression(prop_expr.target_expr())?;
+                let name_idx = self.string_constant(token_name(prop_expr.property_name()))?;
+                self.emit_get_prop(name_idx)?;
+            }
+            AstKind::ElementExpr => {
+                let elem_expr = node.downcast_ref::<ast::ElementExprNode>().unwrap();
+                self.compile_expression(elem_expr.target_expr())?;
+                self.compile_expression(elem_expr.element_expr())?;
+                self.emit_get_elem()?;
+            }
+            AstKind::CallExpr => {
+                let call_expr = node.downcast_ref::<ast::CallExprNode>().unwrap();
+                self.compile_call(call_expr)?;
+            }
+            AstKind::ConstructExpr => {
+                let construct_expr = node.downcast_ref::<ast::ConstructExprNode>().unwrap();
+                self.compile_expression(construct_expr.callee_expr())?;
+                let argc = self.compile_arguments(construct_expr.arguments())?;
+                self.writer().emit_u8(Op::New, argc);
+            }
+            AstKind::PostfixOpExpr => {
+                let postfix_expr = node.downcast_ref::<ast::PostfixOpExprNode>().unwrap();
+                let increment = postfix_expr.postfix_op().kind().is_plus_plus();
+                self.compile_update(postfix_expr.sub_expr(), increment, /* postfix = */ true)?;
+            }
+            AstKind::UnaryOpExpr => {
+                let unary_expr = node.downcast_ref::<ast::UnaryOpExprNode>().unwrap();
+                self.compile_unary(unary_expr)?;
+            }
+            AstKind::BinaryOpExpr => {
+                let binary_expr = node.downcast_ref::<ast::BinaryOpExprNode>().unwrap();
+                self.compile_binary(binary_expr)?;
+            }
+            AstKind::CondExpr => {
+                let cond_expr = node.downcast_ref::<ast::CondExprNode>().unwrap();
+                self.compile_expression(cond_expr.cond_expr())?;
+                let else_jump = self.writer().emit_jump(Op::JumpIfFalse);
+                self.compile_expression(cond_expr.if_expr())?;
+                let end_jump = self.writer().emit_jump(Op::Jump);
+                self.writer().patch_jump(else_jump);
+                self.compile_expression(cond_expr.else_expr())?;
+                self.writer().patch_jump(end_jump);
+            }
+            AstKind::AssignExpr => {
+                let assign_expr = node.downcast_ref::<ast::AssignExprNode>().unwrap();
+                self.compile_assignment(assign_expr)?;
+            }
+            AstKind::CommaExpr => {
+                let comma_expr = node.downcast_ref::<ast::CommaExprNode>().unwrap();
+                self.compile_expression(comma_expr.left_expr())?;
+                self.writer().emit(Op::Pop);
+                self.compile_expression(comma_expr.right_expr())?;
+            }
+            _ => {
+                panic!("Not an expression: {:?}", node.kind());
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_atomic(&mut self, token: &FullToken) -> CompileResult<()> {
+        let kind = token.kind();
+        if kind.is_identifier() {
+            return self.emit_get_name(token_name(token));
+        }
+        if kind.is_this_keyword() {
+            self.writer().emit(Op::This);
+        } else if kind.is_null_keyword() {
+            self.writer().emit(Op::Null);
+        } else if kind.is_true_keyword() {
+            self.writer().emit(Op::True);
+        } else if kind.is_false_keyword() {
+            self.writer().emit(Op::False);
+        } else if kind.is_string_literal() {
+            let units = decode_string_literal(token)?;
+            let idx = self.string_constant_units(units)?;
+            self.writer().emit_u16(Op::Const, idx);
+        } else if kind.is_regexp_literal() {
+            return self.compile_regexp_literal(token);
+        } else {
+            self.emit_number(numeric_literal_value(token))?;
+        }
+        Ok(())
+    }
+
+    // The pattern is compiled here to report errors early, and again when
+    // the literal is evaluated.
+    fn compile_regexp_literal(&mut self, token: &FullToken) -> CompileResult<()> {
+        let text = token.text().expect("literal text");
+        let body_end = text.rfind('/').expect("regexp literal");
+        let pattern: Vec<u16> = text[1..body_end].encode_utf16().collect();
+        let flags: Vec<u16> = text[(body_end + 1)..].encode_utf16().collect();
+        if let Err(error) = RegExp::new(&pattern, &flags) {
+            return Err(CompileError::InvalidRegExp(token.location().clone(), error));
+        }
+        let pattern_idx = self.string_constant_units(pattern)?;
+        let flags_idx = self.string_constant_units(flags)?;
+        self.writer().emit_u16_u16(Op::NewRegExp, pattern_idx, flags_idx);
+        Ok(())
+    }
+
+    fn compile_object_literal(&mut self, object_expr: &ast::ObjectLiteralExprNode) -> CompileResult<()> {
+        self.writer().emit(Op::NewObject);
+        for property in object_expr.properties() {
+            let name_idx = self.property_name_constant(property.name())?;
+            match *property.kind() {
+                PropertyDefinitionKind::Init(ref value_expr) => {
+                    self.compile_expression(value_expr.as_ref())?;
+                    self.writer().emit_u16(Op::InitProp, name_idx);
+                }
+                PropertyDefinitionKind::Getter(ref func) => {
+                    self.compile_closure(func, /* is_expr = */ true)?;
+                    self.writer().emit_u16(Op::InitGetter, name_idx);
+                }
+                PropertyDefinitionKind::Setter(ref func) => {
+                    self.compile_closure(func, /* is_expr = */ true)?;
+                    self.writer().emit_u16(Op::InitSetter, name_idx);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_array_literal(&mut self, array_expr: &ast::ArrayLiteralExprNode) -> CompileResult<()> {
+        self.writer().emit(Op::NewArray);
+        for element in array_expr.elements() {
+            match *element {
+                Some(ref element_expr) => {
+                    self.compile_expression(element_expr.as_ref())?;
+                    self.writer().emit(Op::ArrayPush);
+                }
+                None => self.writer().emit(Op::ArrayPushHole)
+            }
+        }
+        Ok(())
+    }
+
+    // Property names in literals may be identifier names, strings or numbers.
+    fn property_name_constant(&mut self, token: &FullToken) -> CompileResult<u16> {
+        let kind = token.kind();
+        if kind.is_string_literal() {
+            let units = decode_string_literal(token)?;
+            self.string_constant_units(units)
+        } else if kind.is_numeric_literal() {
+            self.string_constant(&number_to_string(numeric_literal_value(token)))
+        } else {
+            self.string_constant(token_name(token))
+        }
+    }
+
+    fn compile_arguments(&mut self, arguments: &[Box<dyn AstNode>]) -> CompileResult<u8> {
+        if arguments.len() > (u8::MAX as usize) {
+            return Err(CompileError::TooManyArguments);
+        }
+        for arg in arguments {
+            self.compile_expression(arg.as_ref())?;
+        }
+        Ok(arguments.len() as u8)
+    }
+
+    fn compile_call(&mut self, call_expr: &ast::CallExprNode) -> CompileResult<()> {
+        // Method calls pass the object they were looked up on as this.
+        let callee = call_expr.sub_expr();
+        match callee.kind() {
+            AstKind::PropertyExpr => {
+                let prop_expr = callee.downcast_ref::<ast::PropertyExprNode>().unwrap();
+                self.compile_expression(prop_expr.target_expr())?;
+                self.writer().emit(Op::Dup);
+                let name_idx = self.string_constant(token_name(prop_expr.property_name()))?;
+                self.emit_get_prop(name_idx)?;
+                self.writer().emit(Op::Swap);
+            }
+            AstKind::ElementExpr => {
+                let elem_expr = callee.downcast_ref::<ast::ElementExprNode>().unwrap();
+                self.compile_expression(elem_expr.target_expr())?;
+                self.writer().emit(Op::Dup);
+                self.compile_expression(elem_expr.element_expr())?;
+                self.emit_get_elem()?;
+                self.writer().emit(Op::Swap);
+            }
+            _ => {
+                self.compile_expression(callee)?;
+                self.writer().emit(Op::Undefined);
+            }
+        }
+        let argc = self.compile_arguments(call_expr.arguments())?;
+        self.writer().emit_u8(Op::Call, argc);
+        Ok(())
+    }
+
+    fn compile_unary(&mut self, unary_expr: &ast::UnaryOpExprNode) -> CompileResult<()> {
+        let kind = unary_expr.unary_op().kind();
+        let sub_expr = unary_expr.sub_expr();
+        if kind.is_plus_plus() || kind.is_minus_minus() {
+            return self.compile_update(sub_expr, kind.is_plus_plus(), /* postfix = */ false);
+        }
+        if kind.is_delete_keyword() {
+            return self.compile_delete(sub_expr);
+        }
+        if kind.is_typeof_keyword() {
+            // Unlike other references, typeof an undeclared global isn't an error.
+            if let Some(name) = sub_expr.identifier() {
+                if let Binding::Global(name_idx) = self.resolve(token_name(name))? {
+                    self.writer().emit_u16(Op::TypeOfGlobal, name_idx);
+                    return Ok(());
+                }
+            }
+            self.compile_expression(sub_expr)?;
+            self.writer().emit(Op::TypeOf);
+            return Ok(());
+        }
+        if kind.is_void_keyword() {
+            self.compile_expression(sub_expr)?;
+            self.writer().emit(Op::Pop);
+            self.writer().emit(Op::Undefined);
+            return Ok(());
+        }
+
+        // Fold negated numeric literals.
+        if kind.is_minus() {
+            if let Some(atomic) = sub_expr.downcast_ref::<ast::AtomicExprNode>() {
+                if atomic.name().kind().is_numeric_literal() {
+                    return self.emit_number(-numeric_literal_value(atomic.name()));
+                }
+            }
+        }
+
+        self.compile_expression(sub_expr)?;
+        let op = if kind.is_plus() {
+            Op::Pos
+        } else if kind.is_minus() {
+            Op::Neg
+        } else if kind.is_bang() {
+            Op::Not
+        } else {
+            assert!(kind.is_tilde());
+            Op::BitNot
+        };
+        self.writer().emit(op);
+        Ok(())
+    }
+
+    fn compile_delete(&mut self, target: &dyn AstNode) -> CompileResult<()> {
+        match target.kind() {
+            AstKind::PropertyExpr => {
+                let prop_expr = target.downcast_ref::<ast::PropertyExprNode>().unwrap();
+                self.compile_expression(prop_expr.target_expr())?;
+                let name_idx = self.string_constant(token_name(prop_expr.property_name()))?;
+                self.writer().emit_u16(Op::DeleteProp, name_idx);
+            }
+            AstKind::ElementExpr => {
+                let elem_expr = target.downcast_ref::<ast::ElementExprNode>().unwrap();
+                self.compile_expression(elem_expr.target_expr())?;
+                self.compile_expression(elem_expr.element_expr())?;
+                self.writer().emit(Op::DeleteElem);
+            }
+            _ => {
+                match target.identifier() {
+                    // Declared variables can't be deleted.
+                    Some(name) => match self.resolve(token_name(name))? {
+                        Binding::Global(name_idx) => {
+                            self.writer().emit_u16(Op::DeleteGlobal, name_idx);
+                        }
+                        _ => self.writer().emit(Op::False)
+                    },
+                    None => {
+                        self.compile_expression(target)?;
+                        self.writer().emit(Op::Pop);
+                        self.writer().emit(Op::True);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_binary(&mut self, binary_expr: &ast::BinaryOpExprNode) -> CompileResult<()> {
+        let kind = binary_expr.binary_op().kind();
+        self.compile_expression(binary_expr.left_expr())?;
+
+        if kind.is_logical_and() || kind.is_logical_or() {
+            let op = if kind.is_logical_and() { Op::JumpIfFalseKeep } else { Op::JumpIfTrueKeep };
+            let end_jump = self.writer().emit_jump(op);
+            self.compile_expression(binary_expr.right_expr())?;
+            self.writer().patch_jump(end_jump);
+            return Ok(());
+        }
+
+        self.compile_expression(binary_expr.right_expr())?;
+        let op = binary_op_for(kind).expect("binary operator");
+        self.writer().emit(op);
+        Ok(())
+    }
+
+    fn compile_assignment(&mut self, assign_expr: &ast::AssignExprNode) -> CompileResult<()> {
+        let kind = assign_expr.assignment_op().kind();
+        let op = if kind.is_assign() { None } else { Some(compound_op_for(kind)) };
+        let target = assign_expr.left_expr();
+        let value = assign_expr.right_expr();
+
+        match target.kind() {
+            AstKind::PropertyExpr => {
+                let prop_expr = target.downcast_ref::<ast::PropertyExprNode>().unwrap();
+                self.compile_expression(prop_expr.target_expr())?;
+                let name_idx = self.string_constant(token_name(prop_expr.property_name()))?;
+                if let Some(op) = op {
+                    self.writer().emit(Op::Dup);
+                    self.emit_get_prop(name_idx)?;
+                    self.compile_expression(value)?;
+                    self.writer().emit(op);
+                } else {
+                    self.compile_expression(value)?;
+                }
+                self.emit_set_prop(name_idx)?;
+            }
+            AstKind::ElementExpr => {
+                let elem_expr = target.downcast_ref::<ast::ElementExprNode>().unwrap();
+                self.compile_expression(elem_expr.target_expr())?;
+                self.compile_expression(elem_expr.element_expr())?;
+                if let Some(op) = op {
+                    self.writer().emit(Op::Dup2);
+                    self.emit_get_elem()?;
+                    self.compile_expression(value)?;
+                    self.writer().emit(op);
+                } else {
+                    self.compile_expression(value)?;
+                }
+                self.emit_set_elem()?;
+            }
+            _ => {
+                let name = token_name(target.identifier().expect("assignment target"));
+                if let Some(op) = op {
+                    self.emit_get_name(name)?;
+                    self.compile_expression(value)?;
+                    self.writer().emit(op);
+                } else {
+                    self.compile_expression(value)?;
+                }
+                self.emit_set_name(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Compile an increment or decrement.  The target's value is converted to a
+     * number, and postfix forms keep it in a temporary register as their result.
+     */
+    fn compile_update(&mut self, target: &dyn AstNode, increment: bool, postfix: bool)
+        -> CompileResult<()>
+    {
+        enum Target {
+            Name(String),
+            Prop(u16),
+            Elem
+        }
+        let update_target = match target.kind() {
+            AstKind::PropertyExpr => {
+                let prop_expr = target.downcast_ref::<ast::PropertyExprNode>().unwrap();
+                self.compile_expression(prop_expr.target_expr())?;
+                let name_idx = self.string_constant(token_name(prop_expr.property_name()))?;
+                self.writer().emit(Op::Dup);
+                self.emit_get_prop(name_idx)?;
+                Target::Prop(name_idx)
+            }
+            AstKind::ElementExpr => {
+                let elem_expr = target.downcast_ref::<ast::ElementExprNode>().unwrap();
+                self.compile_expression(elem_expr.target_expr())?;
+                self.compile_expression(elem_expr.element_expr())?;
+                self.writer().emit(Op::Dup2);
+                self.emit_get_elem()?;
+                Target::Elem
+            }
+            _ => {
+                let name = token_name(target.identifier().expect("update target")).to_string();
+                self.emit_get_name(&name)?;
+                Target::Name(name)
+            }
+        };
+        self.writer().emit(Op::Pos);
+
+        let temp = if postfix {
+            let temp = self.state().alloc_register()?;
+            self.writer().emit_u16(Op::SetLocal, temp);
+            Some(temp)
+        } else {
+            None
+        };
+        self.writer().emit_i8(Op::Int8, 1);
+        self.writer().emit(if increment { Op::Add } else { Op::Sub });
+
+        match update_target {
+            Target::Name(ref name) => self.emit_set_name(name)?,
+            Target::Prop(name_idx) => self.emit_set_prop(name_idx)?,
+            Target::Elem => self.emit_set_elem()?
+        }
+
+        if let Some(temp) = temp {
+            self.writer().emit(Op::Pop);
+            self.writer().emit_u16(Op::GetLocal, temp);
+            self.state().free_register(temp);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+/*****************************************************************************
+ **** Analysis ***************************************************************
+ *****************************************************************************/
+
+// The child nodes of a node, not including the bodies of nested functions.
+fn node_children(node: &dyn AstNode) -> Vec<&dyn AstNode> {
+    match node.kind() {
+        AstKind::Program => {
+            let program = node.downcast_ref::<ast::ProgramNode>().unwrap();
+            program.source_elements().iter().map(|s| s.as_ref()).collect()
+        }
+        AstKind::BlockStmt => {
+            let block = node.downcast_ref::<ast::BlockStmtNode>().unwrap();
+            block.statements().iter().map(|s| s.as_ref()).collect()
+        }
+        AstKind::VarStmt => {
+            let var_stmt = node.downcast_ref::<ast::VarStmtNode>().unwrap();
+            var_stmt.var_decls().iter().filter_map(|decl| decl.init_expr()).collect()
+        }
+        AstKind::IfStmt => {
+            let if_stmt = node.downcast_ref::<ast::IfStmtNode>().unwrap();
+            let mut children = vec![if_stmt.cond_expr(), if_stmt.if_true_stmt()];
+            children.extend(if_stmt.if_false_stmt());
+            children
+        }
+        AstKind::ExprStmt => {
+            vec![node.downcast_ref::<ast::ExprStmtNode>().unwrap().expression()]
+        }
+        AstKind::WithStmt => {
+            let with_stmt = node.downcast_ref::<ast::WithStmtNode>().unwrap();
+            vec![with_stmt.object_expr(), with_stmt.body_stmt()]
+        }
+        AstKind::ReturnStmt => {
+            node.downcast_ref::<ast::ReturnStmtNode>().unwrap().expression().into_iter().collect()
+        }
+        AstKind::ThrowStmt => {
+            vec![node.downcast_ref::<ast::ThrowStmtNode>().unwrap().expression()]
+        }
+        AstKind::TryStmt => {
+            let try_stmt = node.downcast_ref::<ast::TryStmtNode>().unwrap();
+            let mut children = vec![try_stmt.try_block()];
+            children.extend(try_stmt.catch_block());
+            children.extend(try_stmt.finally_block());
+            children
+        }
+        AstKind::WhileStmt => {
+            let while_stmt = node.downcast_ref::<ast::WhileStmtNode>().unwrap();
+            vec![while_stmt.cond_expr(), while_stmt.body_stmt()]
+        }
+        AstKind::DoWhileStmt => {
+            let do_while_stmt = node.downcast_ref::<ast::DoWhileStmtNode>().unwrap();
+            vec![do_while_stmt.body_stmt(), do_while_stmt.cond_expr()]
+        }
+        AstKind::ForStmt => {
+            let for_stmt = node.downcast_ref::<ast::ForStmtNode>().unwrap();
+            let mut children: Vec<&dyn AstNode> = for_stmt.init().into_iter().collect();
+            children.extend(for_stmt.cond_expr());
+            children.extend(for_stmt.update_expr());
+            children.push(for_stmt.body_stmt());
+            children
+        }
+        AstKind::ForInStmt => {
+            let for_in_stmt = node.downcast_ref::<ast::ForInStmtNode>().unwrap();
+            vec![for_in_stmt.target(), for_in_stmt.object_expr(), for_in_stmt.body_stmt()]
+        }
+        AstKind::LabelledStmt => {
+            vec![node.downcast_ref::<ast::LabelledStmtNode>().unwrap().body_stmt()]
+        }
+        AstKind::CallExpr => {
+            let call_expr = node.downcast_ref::<ast::CallExprNode>().unwrap();
+            let mut children = vec![call_expr.sub_expr()];
+            children.extend(call_expr.arguments().iter().map(|a| a.as_ref()));
+            children
+        }
+        AstKind::ElementExpr => {
+            let elem_expr = node.downcast_ref::<ast::ElementExprNode>().unwrap();
+            vec![elem_expr.target_expr(), elem_expr.element_expr()]
+        }
+        AstKind::PropertyExpr => {
+            vec![node.downcast_ref::<ast::PropertyExprNode>().unwrap().target_expr()]
+        }
+        AstKind::ConstructExpr => {
+            let construct_expr = node.downcast_ref::<ast::ConstructExprNode>().unwrap();
+            let mut children = vec![construct_expr.callee_expr()];
+            children.extend(construct_expr.arguments().iter().map(|a| a.as_ref()));
+            children
+        }
+        AstKind::PostfixOpExpr => {
+            vec![node.downcast_ref::<ast::PostfixOpExprNode>().unwrap().sub_expr()]
+        }
+        AstKind::UnaryOpExpr => {
+            vec![node.downcast_ref::<ast::UnaryOpExprNode>().unwrap().sub_expr()]
+        }
+        AstKind::BinaryOpExpr => {
+            let binary_expr = node.downcast_ref::<ast::BinaryOpExprNode>().unwrap();
+            vec![binary_expr.left_expr(), binary_expr.right_expr()]
+        }
+        AstKind::CondExpr => {
+            let cond_expr = node.downcast_ref::<ast::CondExprNode>().unwrap();
+            vec![cond_expr.cond_expr(), cond_expr.if_expr(), cond_expr.else_expr()]
+        }
+        AstKind::AssignExpr => {
+            let assign_expr = node.downcast_ref::<ast::AssignExprNode>().unwrap();
+            vec![assign_expr.left_expr(), assign_expr.right_expr()]
+        }
+        AstKind::CommaExpr => {
+            let comma_expr = node.downcast_ref::<ast::CommaExprNode>().unwrap();
+            vec![comma_expr.left_expr(), comma_expr.right_expr()]
+        }
+        AstKind::ObjectLiteralExpr => {
+            let object_expr = node.downcast_ref::<ast::ObjectLiteralExprNode>().unwrap();
+            object_expr.properties().iter().filter_map(|property| {
+                match *property.kind() {
+                    PropertyDefinitionKind::Init(ref value_expr) => Some(value_expr.as_ref()),
+                    _ => None
+                }
+            }).collect()
+        }
+        AstKind::ArrayLiteralExpr => {
+            let array_expr = node.downcast_ref::<ast::ArrayLiteralExprNode>().unwrap();
+            array_expr.elements().iter().filter_map(|element| element.as_ref().map(|expr| expr.as_ref())).collect()
+        }
+        AstKind::EmptyStmt | AstKind::BreakStmt | AstKind::ContinueStmt | AstKind::FunctionDecl |
+        AstKind::FunctionExpr | AstKind::AtomicExpr => Vec::new()
+    }
+}
+
+fn is_loop(node: &dyn AstNode) -> bool {
+    matches!(node.kind(), AstKind::WhileStmt | AstKind::DoWhileStmt | AstKind::ForStmt |
+                          AstKind::ForInStmt)
+}
+
+fn nested_function(node: &dyn AstNode) -> Option<&FunctionDef> {
+    match node.kind() {
+        AstKind::FunctionDecl => Some(node.downcast_ref::<ast::FunctionDeclNode>().unwrap().func()),
+        AstKind::FunctionExpr => Some(node.downcast_ref::<ast::FunctionExprNode>().unwrap().func()),
+        _ => None
+    }
+}
+
+// Collect the declarations in a function body, without entering nested functions.
+fn collect_declarations(body: &[Box<dyn AstNode>]) -> Declarations<'_> {
+    fn visit<'a>(node: &'a dyn AstNode, decls: &mut Declarations<'a>) {
+        if let Some(var_stmt) = node.downcast_ref::<ast::VarStmtNode>() {
+            for decl in var_stmt.var_decls() {
+                let name = token_name(decl.name());
+                if ! decls.vars.iter().any(|var| var == name) {
+                    decls.vars.push(name.to_string());
+                }
+            }
+        }
+        if let Some(func) = nested_function(node) {
+            if node.kind() == AstKind::FunctionDecl {
+                decls.functions.push(func);
+            }
+            decls.nested.push(func);
+        }
+        if let Some(try_stmt) = node.downcast_ref::<ast::TryStmtNode>() {
+            if try_stmt.catch_param().is_some() {
+                decls.catches.push(try_stmt);
+            }
+        }
+        if let Some(object_expr) = node.downcast_ref::<ast::ObjectLiteralExprNode>() {
+            for property in object_expr.properties() {
+                match *property.kind() {
+                    PropertyDefinitionKind::Getter(ref func) |
+                    PropertyDefinitionKind::Setter(ref func) => decls.nested.push(func),
+                    PropertyDefinitionKind::Init(_) => {}
+                }
+            }
+        }
+        for child in node_children(node) {
+            visit(child, decls);
+        }
+    }
+
+    let mut decls = Declarations {
+        vars: Vec::new(),
+        functions: Vec::new(),
+        nested: Vec::new(),
+        catches: Vec::new()
+    };
+    for stmt in body {
+        visit(stmt.as_ref(), &mut decls);
+    }
+    decls
+}
+
+// Whether the body of a function, outside its nested functions, refers to
+// `arguments`.
+fn refers_to_arguments(func: &FunctionDef) -> bool {
+    let mut references = HashSet::new();
+    for stmt in func.body() {
+        collect_references(stmt.as_ref(), &mut references);
+    }
+    references.contains("arguments")
+}
+
+// Collect the names referenced in a node, without entering nested functions.
+
+fn collect_references(node: &dyn AstNode, references: &mut HashSet<String>) {
+    if let Some(name) = node.identifier() {
+        references.insert(token_name(name).to_string());
+    }
+    if let Some(var_stmt) = node.downcast_ref::<ast::VarStmtNode>() {
+        for decl in var_stmt.var_decls() {
+            references.insert(token_name(decl.name()).to_string());
+        }
+    }
+    for child in node_children(node) {
+        collect_references(child, references);
+    }
+}
+
+/*****************************************************************************
+ **** Tokens *****************************************************************
+ *****************************************************************************/
+
+fn token_name(token: &FullToken) -> &str {
+    token.text().expect("identifier text")
+}
+
+fn func_name(func: &FunctionDef) -> &str {
+    token_name(func.name().expect("function declaration name"))
+}
+
+fn binary_op_for(kind: TokenKind) -> Option<Op> {
+    let op = if kind.is_plus() {
+        Op::Add
+    } else if kind.is_minus() {
+        Op::Sub
+    } else if kind.is_star() {
+        Op::Mul
+    } else if kind.is_slash() {
+        Op::Div
+    } else if kind.is_percent() {
+        Op::Mod
+    } else if kind.is_shift_left() {
+        Op::Shl
+    } else if kind.is_shift_right() {
+        Op::Sar
+    } else if kind.is_arithmetic_shift_right() {
+        // The tokenizer names ">>>" the arithmetic shift.
+        Op::Shr
+    } else if kind.is_bit_and() {
+        Op::BitAnd
+    } else if kind.is_bit_or() {
+        Op::BitOr
+    } else if kind.is_bit_xor() {
+        Op::BitXor
+    } else if kind.is_less() {
+        Op::Lt
+    } else if kind.is_less_equal() {
+        Op::Le
+    } else if kind.is_greater() {
+        Op::Gt
+    } else if kind.is_greater_equal() {
+        Op::Ge
+    } else if kind.is_equal() {
+        Op::Eq
+    } else if kind.is_not_equal() {
+        Op::Ne
+    } else if kind.is_strict_equal() {
+        Op::StrictEq
+    } else if kind.is_strict_not_equal() {
+        Op::StrictNe
+    } else if kind.is_instanceof_keyword() {
+        Op::InstanceOf
+    } else if kind.is_in_keyword() {
+        Op::In
+    } else {
+        return None;
+    };
+    Some(op)
+}
+
+fn compound_op_for(kind: TokenKind) -> Op {
+    if kind.is_plus_assign() {
+        Op::Add
+    } else if kind.is_minus_assign() {
+        Op::Sub
+    } else if kind.is_star_assign() {
+        Op::Mul
+    } else if kind.is_slash_assign() {
+        Op::Div
+    } else if kind.is_percent_assign() {
+        Op::Mod
+    } else if kind.is_shift_left_assign() {
+        Op::Shl
+    } else if kind.is_shift_right_assign() {
+        Op::Sar
+    } else if kind.is_arithmetic_shift_right_assign() {
+        Op::Shr
+    } else if kind.is_bit_and_assign() {
+        Op::BitAnd
+    } else if kind.is_bit_or_assign() {
+        Op::BitOr
+    } else {
+        assert!(kind.is_bit_xor_assign());
+        Op::BitXor
+    }
+}
+
+fn numeric_literal_value(token: &FullToken) -> f64 {
+    let kind = token.kind();
+    let text = token.text().expect("literal text");
+    if kind.is_hex_integer_literal() {
+        return text[2..].chars().fold(0.0, |acc, ch| acc * 16.0 + (ch.to_digit(16).unwrap() as f64));
+    }
+    if kind.is_oct_integer_literal() {
+        return text[1..].chars().fold(0.0, |acc, ch| acc * 8.0 + (ch.to_digit(8).unwrap() as f64));
+    }
+    assert!(kind.is_integer_literal() || kind.is_float_literal());
+    text.parse::<f64>().expect("numeric literal")
+}
+
+// Decode a string literal's escape sequences into code units.
+fn decode_string_literal(token: &FullToken) -> CompileResult<Vec<u16>> {
+    let text = token.text().expect("literal text");
+    let body = &text[1..(text.len() - 1)];
+    let invalid = || CompileError::InvalidEscape(token.location().clone());
+
+    let mut units = Vec::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            let mut buf = [0u16; 2];
+            units.extend_from_slice(ch.encode_utf16(&mut buf));
+            continue;
+        }
+
+        let escaped = chars.next().ok_or_else(invalid)?;
+        let unit: u16 = match escaped {
+            'n' => 0x0A,
+            't' => 0x09,
+            'r' => 0x0D,
+            'b' => 0x08,
+            'f' => 0x0C,
+            'v' => 0x0B,
+            'x' | 'u' => {
+                let digits = if escaped == 'x' { 2 } else { 4 };
+                let mut value: u32 = 0;
+                for _ in 0..digits {
+                    let digit = chars.next().and_then(|ch| ch.to_digit(16)).ok_or_else(invalid)?;
+                    value = (value << 4) | digit;
+                }
+                value as u16
+            }
+            '0'..='7' => {
+                // Legacy octal escapes take up to three digits, up to \377.
+                let mut value = escaped.to_digit(8).unwrap();
+                let max_digits = if value <= 3 { 3 } else { 2 };
+                for _ in 1..max_digits {
+                    match chars.peek().and_then(|ch| ch.to_digit(8)) {
+                        Some(digit) => {
+                            value = (value << 3) | digit;
+                            chars.next();
+                        }
+                        None => break
+                    }
+                }
+                value as u16
+            }
+            '\r' => {
+                // Line continuations produce nothing.
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '\n' | '\u{2028}' | '\u{2029}' => {
+                continue;
+            }
+            other => {
+                let mut buf = [0u16; 2];
+                units.extend_from_slice(other.encode_utf16(&mut buf));
+                continue;
+            }
+        };
+        units.push(unit);
+    }
+    Ok(units)
+}
@@ -0,0 +1,523 @@
+/*!
+ * The bytecode format.
+ *
+ * Bytecode is a compact, stack-based instruction stream.  Each instruction is a
+ * one-byte opcode followed by its operands, encoded little-endian.  Jump
+ * offsets are relative to the start of the following instruction.
+ *
+ * Every function (and the program itself) compiles to a `FunctionCode`, which
+ * holds its instructions and a constant pool.  Constants don't refer to the
+ * heap, so compiled code can be shared and run on any heap.
+ *
+ * Property accesses carry the index of an inline cache, which the interpreter
+ * keeps for each site.  Caches are numbered from zero within each function.
+ *
+ * Each frame has registers for its parameters, non-captured variables and
+ * compiler temporaries.  Variables captured by inner functions live instead in
+ * a heap environment, whose slot 0 links to the enclosing environment.
+ *
+ * A function's handler table lists the ranges of its code covered by try
+ * statements, and where to continue when an exception is thrown within each.
+ * Statements begin with an empty operand stack, so a handler starts with
+ * only the thrown value on it.  Finally blocks are subroutines within their
+ * function: each way of leaving a try statement calls its finally block with
+ * Gosub, which returns with Ret to just after the call.
+ */
+
+pub mod compiler;
+
+use std::fmt;
+use std::rc::Rc;
+
+/** The shape of the operands following an opcode. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operands {
+    None,
+    U8,
+    I8,
+    U16,
+    I32,
+    U8U16,
+    U16U16
+}
+impl Operands {
+    pub fn size(self) -> usize {
+        match self {
+            Operands::None => 0,
+            Operands::U8 | Operands::I8 => 1,
+            Operands::U16 => 2,
+            Operands::U8U16 => 3,
+            Operands::I32 | Operands::U16U16 => 4
+        }
+    }
+}
+
+macro_rules! define_ops {
+    ($($op:ident: $operands:ident),*) => {
+        #[repr(u8)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Op {
+            $($op),*
+        }
+
+        const ALL_OPS: &[Op] = &[$(Op::$op),*];
+
+        impl Op {
+            pub fn operands(self) -> Operands {
+                match self {
+                    $(Op::$op => Operands::$operands),*
+                }
+            }
+        }
+    }
+}
+
+define_ops! {
+    // Push constants: Int8 and Int32 push immediate integers, Const pushes an
+    // entry from the constant pool.
+    Undefined: None,
+    Null: None,
+    True: None,
+    False: None,
+    Int8: I8,
+    Int32: I32,
+    Const: U16,
+    This: None,
+    Callee: None,
+
+    // Stack manipulation.
+    Pop: None,
+    Dup: None,
+    Dup2: None,
+    Swap: None,
+
+    // Variables.  Stores leave the stored value on the stack.  Env accesses
+    // take the number of environments to skip, and the slot.  Global accesses
+    // take the constant index of the name.
+    GetLocal: U16,
+    SetLocal: U16,
+    GetEnv: U8U16,
+    SetEnv: U8U16,
+    GetGlobal: U16,
+    SetGlobal: U16,
+    DeclareGlobal: U16,
+    DeleteGlobal: U16,
+    TypeOfGlobal: U16,
+    CreateEnv: U16,
+    // Maps an index of the arguments object on the stack, when it has that
+    // argument, to a slot of the current environment.
+    MapArgument: U8U16,
+
+    // Properties.  Named accesses take the constant index of the name.  Gets
+    // and sets take the index of their inline cache, after any name.
+    GetProp: U16U16,
+    SetProp: U16U16,
+    DeleteProp: U16,
+    GetElem: U16,
+    SetElem: U16,
+    DeleteElem: None,
+
+    // Object literals.  NewObject pushes an empty object, and the Init ops
+    // define a property on the object below the value (or accessor function)
+    // on top of the stack, popping only the value.
+    NewObject: None,
+    InitProp: U16,
+    InitGetter: U16,
+    InitSetter: U16,
+
+    // Array literals.  NewArray pushes an empty array, and ArrayPush pops a
+    // value and appends it to the array below it.  ArrayPushHole appends a
+    // hole.
+    NewArray: None,
+    ArrayPush: None,
+    ArrayPushHole: None,
+
+    // Regular expression literals.  NewRegExp pushes a new RegExp object, from
+    // the constant indices of its pattern and flags.  Each evaluation of a
+    // literal makes a new object.
+    NewRegExp: U16U16,
+
+    // Operators.
+    Add: None,
+    Sub: None,
+    Mul: None,
+    Div: None,
+    Mod: None,
+    Shl: None,
+    Sar: None,
+    Shr: None,
+    BitAnd: None,
+    BitOr: None,
+    BitXor: None,
+    Lt: None,
+    Gt: None,
+    Le: None,
+    Ge: None,
+    Eq: None,
+    Ne: None,
+    StrictEq: None,
+    StrictNe: None,
+    InstanceOf: None,
+    In: None,
+    Pos: None,
+    Neg: None,
+    Not: None,
+    BitNot: None,
+    TypeOf: None,
+
+    // Control flow.  The "Keep" conditional jumps leave the tested value on the
+    // stack when they jump, and pop it otherwise.
+    Jump: I32,
+    JumpIfTrue: I32,
+    JumpIfFalse: I32,
+    JumpIfTrueKeep: I32,
+    JumpIfFalseKeep: I32,
+
+    // For-in loops.  ForInKeys pops a value and pushes the state of a loop over
+    // its enumerable keys.  ForInNext pops a loop state and pushes the next key
+    // and true, or only false when no keys remain.  Keys whose properties were
+    // deleted after the loop began are skipped.
+    ForInKeys: None,
+    ForInNext: None,
+
+    // Exceptions.  Throw throws the value on top of the stack.  Gosub pushes
+    // the pc of the next instruction and jumps to a finally block, which
+    // saves it in a register, and returns to it with Ret.
+    Throw: None,
+    Gosub: I32,
+    Ret: U16,
+
+    // Functions.  Calls expect the callee, the this value and the arguments on
+    // the stack; constructs expect the callee and the arguments.
+    Closure: U16,
+    Call: U8,
+    New: U8,
+    Return: None,
+
+    // The completion value of the program.
+    StoreCompletion: None,
+    LoadCompletion: None
+}
+
+impl Op {
+    pub fn from_u8(byte: u8) -> Option<Op> {
+        ALL_OPS.get(byte as usize).cloned()
+    }
+
+    // The size of the instruction, including its operands.
+    pub fn length(self) -> usize {
+        1 + self.operands().size()
+    }
+
+    // The offset of the inline cache index among the operands, for ops with one.
+    pub fn inline_cache_operand(self) -> Option<usize> {
+        match self {
+            Op::GetProp | Op::SetProp => Some(2),
+            Op::GetElem | Op::SetElem => Some(0),
+            _ => None
+        }
+    }
+
+    pub fn is_jump(self) -> bool {
+        matches!(self, Op::Jump | Op::JumpIfTrue | Op::JumpIfFalse |
+                       Op::JumpIfTrueKeep | Op::JumpIfFalseKeep | Op::Gosub)
+    }
+}
+
+pub fn read_u8(code: &[u8], pc: usize) -> u8 {
+    code[pc]
+}
+pub fn read_i8(code: &[u8], pc: usize) -> i8 {
+    code[pc] as i8
+}
+pub fn read_u16(code: &[u8], pc: usize) -> u16 {
+    (code[pc] as u16) | ((code[pc + 1] as u16) << 8)
+}
+pub fn read_i32(code: &[u8], pc: usize) -> i32 {
+    (code[pc] as u32 | ((code[pc + 1] as u32) << 8) |
+        ((code[pc + 2] as u32) << 16) | ((code[pc + 3] as u32) << 24)) as i32
+}
+
+/** An entry in a function's constant pool. */
+#[derive(Debug)]
+pub enum Constant {
+    Number(f64),
+    String(Box<[u16]>),
+    Function(Rc<FunctionCode>)
+}
+
+/**
+ * An entry in a handler table: exceptions thrown by the instructions starting
+ * in `start..end` continue at `target`.  Inner try statements' entries come
+ * before those of the statements enclosing them.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handler {
+    pub start: u32,
+    pub end: u32,
+    pub target: u32
+}
+
+/** A compiled function, or program. */
+#[derive(Debug)]
+pub struct FunctionCode {
+    name: Option<String>,
+    param_count: u16,
+    register_count: u16,
+    env_size: u16,
+    strict: bool,
+    program: bool,
+    arguments_object: bool,
+    inline_cache_count: u16,
+    code: Vec<u8>,
+    constants: Vec<Constant>,
+    handlers: Vec<Handler>
+}
+impl FunctionCode {
+    pub fn new(name: Option<String>, param_count: u16, register_count: u16, env_size: u16,
+               strict: bool, code: Vec<u8>, constants: Vec<Constant>)
+        -> FunctionCode
+    {
+        assert!(param_count <= register_count);
+        let inline_cache_count = count_inline_caches(&code);
+        FunctionCode { name, param_count, register_count, env_size, strict, program: false,
+                       arguments_object: false, inline_cache_count, code, constants,
+                       handlers: Vec::new() }
+    }
+    // The function gets an arguments object, in the register after its
+    // parameters.
+    pub fn with_arguments_object(mut self) -> FunctionCode {
+        assert!(self.param_count < self.register_count);
+        self.arguments_object = true;
+        self
+    }
+    pub fn with_handlers(mut self, handlers: Vec<Handler>) -> FunctionCode {
+        assert!(handlers.iter().all(|h| h.start <= h.end && (h.end as usize) <= self.code.len() &&
+                                        (h.target as usize) < self.code.len()));
+        self.handlers = handlers;
+        self
+    }
+    pub fn new_program(register_count: u16, env_size: u16, strict: bool, code: Vec<u8>,
+                       constants: Vec<Constant>)
+        -> FunctionCode
+    {
+        let inline_cache_count = count_inline_caches(&code);
+        FunctionCode { name: None, param_count: 0, register_count, env_size, strict,
+                       program: true, arguments_object: false, inline_cache_count, code, constants,
+                       handlers: Vec::new() }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    pub fn param_count(&self) -> u16 {
+        self.param_count
+    }
+    // Registers hold the parameters, followed by locals and temporaries.
+    pub fn register_count(&self) -> u16 {
+        self.register_count
+    }
+    // The number of slots in the function's environment, including the parent
+    // link, or zero if it doesn't create one.
+    pub fn env_size(&self) -> u16 {
+        self.env_size
+    }
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+    pub fn is_program(&self) -> bool {
+        self.program
+    }
+    pub fn has_arguments_object(&self) -> bool {
+        self.arguments_object
+    }
+    pub fn inline_cache_count(&self) -> u16 {
+        self.inline_cache_count
+    }
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+    pub fn constant(&self, idx: u16) -> &Constant {
+        &self.constants[idx as usize]
+    }
+    pub fn handlers(&self) -> &[Handler] {
+        &self.handlers
+    }
+    // Where to continue when the instruction at `pc` throws, if it's within
+    // a try statement.
+    pub fn handler_for(&self, pc: usize) -> Option<usize> {
+        self.handlers.iter()
+            .find(|h| (h.start as usize) <= pc && pc < (h.end as usize))
+            .map(|h| h.target as usize)
+    }
+
+    pub fn write_disassembly(&self, w: &mut dyn fmt::Write) -> Result<(), fmt::Error> {
+        writeln!(w, "Function {} (params={}, registers={}, env={}, caches={}{}{})",
+                 self.name().unwrap_or("<anonymous>"), self.param_count, self.register_count,
+                 self.env_size, self.inline_cache_count,
+                 if self.arguments_object { ", arguments" } else { "" },
+                 if self.strict { ", strict" } else { "" })?;
+
+        let mut pc = 0;
+        while pc < self.code.len() {
+            let op = Op::from_u8(self.code[pc]).expect("valid opcode");
+            write!(w, "  {:04} {:?}", pc, op)?;
+            let operand_pc = pc + 1;
+            pc += op.length();
+            match op.operands() {
+                Operands::None => {}
+                Operands::U8 => write!(w, " {}", read_u8(&self.code, operand_pc))?,
+                Operands::I8 => write!(w, " {}", read_i8(&self.code, operand_pc))?,
+                Operands::U16 => write!(w, " {}", read_u16(&self.code, operand_pc))?,
+                Operands::U8U16 => write!(w, " {} {}", read_u8(&self.code, operand_pc),
+                                          read_u16(&self.code, operand_pc + 1))?,
+                Operands::U16U16 => write!(w, " {} {}", read_u16(&self.code, operand_pc),
+                                           read_u16(&self.code, operand_pc + 2))?,
+                Operands::I32 => {
+                    let operand = read_i32(&self.code, operand_pc);
+                    if op.is_jump() {
+                        write!(w, " -> {}", (pc as isize) + (operand as isize))?;
+                    } else {
+                        write!(w, " {}", operand)?;
+                    }
+                }
+            }
+            writeln!(w)?;
+        }
+
+        for handler in &self.handlers {
+            writeln!(w, "  handler {}..{} -> {}", handler.start, handler.end, handler.target)?;
+        }
+        for (idx, constant) in self.constants.iter().enumerate() {
+            match *constant {
+                Constant::Number(fval) => writeln!(w, "  const {}: {}", idx, fval)?,
+                Constant::String(ref units) => {
+                    writeln!(w, "  const {}: {:?}", idx, String::from_utf16_lossy(units))?
+                }
+                Constant::Function(ref func) => {
+                    writeln!(w, "  const {}: function {}", idx, func.name().unwrap_or("<anonymous>"))?
+                }
+            }
+        }
+        for constant in &self.constants {
+            if let Constant::Function(ref func) = *constant {
+                func.write_disassembly(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn disassembly_string(&self) -> String {
+        let mut str = String::new();
+        self.write_disassembly(&mut str).unwrap();
+        str
+    }
+}
+
+// The number of inline caches used by code, one more than the highest index.
+fn count_inline_caches(code: &[u8]) -> u16 {
+    let mut count = 0;
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = Op::from_u8(code[pc]).expect("valid opcode");
+        if let Some(offset) = op.inline_cache_operand() {
+            count = count.max(read_u16(code, pc + 1 + offset) + 1);
+        }
+        pc += op.length();
+    }
+    count
+}
+
+/** A position in emitted code holding a jump offset still to be patched. */
+#[derive(Debug, Clone, Copy)]
+pub struct JumpSite(usize);
+
+/** Emits instructions into a code buffer. */
+pub struct BytecodeWriter {
+    code: Vec<u8>
+}
+impl BytecodeWriter {
+    pub fn new() -> BytecodeWriter {
+        BytecodeWriter { code: Vec::new() }
+    }
+
+    pub fn position(&self) -> usize {
+        self.code.len()
+    }
+    pub fn finish(self) -> Vec<u8> {
+        self.code
+    }
+
+    pub fn emit(&mut self, op: Op) {
+        assert!(op.operands() == Operands::None);
+        self.code.push(op as u8);
+    }
+    pub fn emit_u8(&mut self, op: Op, operand: u8) {
+        assert!(op.operands() == Operands::U8);
+        self.code.push(op as u8);
+        self.code.push(operand);
+    }
+    pub fn emit_i8(&mut self, op: Op, operand: i8) {
+        assert!(op.operands() == Operands::I8);
+        self.code.push(op as u8);
+        self.code.push(operand as u8);
+    }
+    pub fn emit_u16(&mut self, op: Op, operand: u16) {
+        assert!(op.operands() == Operands::U16);
+        self.code.push(op as u8);
+        self.push_u16(operand);
+    }
+    pub fn emit_i32(&mut self, op: Op, operand: i32) {
+        assert!(op.operands() == Operands::I32);
+        self.code.push(op as u8);
+        self.push_i32(operand);
+    }
+    pub fn emit_u8_u16(&mut self, op: Op, operand0: u8, operand1: u16) {
+        assert!(op.operands() == Operands::U8U16);
+        self.code.push(op as u8);
+        self.code.push(operand0);
+        self.push_u16(operand1);
+    }
+    pub fn emit_u16_u16(&mut self, op: Op, operand0: u16, operand1: u16) {
+        assert!(op.operands() == Operands::U16U16);
+        self.code.push(op as u8);
+        self.push_u16(operand0);
+        self.push_u16(operand1);
+    }
+
+    // Emit a forward jump, to be patched once its target is known.
+    pub fn emit_jump(&mut self, op: Op) -> JumpSite {
+        assert!(op.is_jump());
+        self.code.push(op as u8);
+        let site = JumpSite(self.code.len());
+        self.push_i32(0);
+        site
+    }
+    // Point a forward jump at the current position.
+    pub fn patch_jump(&mut self, site: JumpSite) {
+        let offset = self.code.len() - (site.0 + 4);
+        let bytes = (offset as i32).to_le_bytes();
+        self.code[site.0..(site.0 + 4)].copy_from_slice(&bytes);
+    }
+    // Emit a jump to an earlier position.
+    pub fn emit_jump_back(&mut self, op: Op, target: usize) {
+        assert!(op.is_jump());
+        let offset = (target as isize) - ((self.code.len() + op.length()) as isize);
+        self.emit_i32(op, offset as i32);
+    }
+
+    fn push_u16(&mut self, operand: u16) {
+        self.code.extend_from_slice(&operand.to_le_bytes());
+    }
+    fn push_i32(&mut self, operand: i32) {
+        self.code.extend_from_slice(&operand.to_le_bytes());
+    }
+}
+impl Default for BytecodeWriter {
+    fn default() -> BytecodeWriter {
+        BytecodeWriter::new()
+    }
+}
@@ -0,0 +1,299 @@
+/*!
+ * The type conversions of the language: ToBoolean, ToNumber, ToString,
+ * ToInt32, ToUint32, ToPrimitive and ToPropertyKey.
+ *
+ * Conversions of objects to primitives may run script, so they are left to
+ * the interpreter.  The methods here convert primitives, and give objects
+ * the results of the default conversions, without running script.
+ */
+
+use std::f64;
+use vm::function::Function;
+use vm::heap::Heap;
+use vm::object::{self, JsObject};
+use vm::string;
+use vm::value::{UnpackedValue, Value};
+
+/** The type of a value, as the spec defines it. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Undefined,
+    Null,
+    Boolean,
+    Number,
+    String,
+    Object
+}
+
+/** The type a conversion to a primitive prefers. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreferredType {
+    Default,
+    Number,
+    String
+}
+
+impl Value {
+    pub fn value_type(&self, heap: &Heap) -> ValueType {
+        match self.unpack() {
+            UnpackedValue::Undefined => ValueType::Undefined,
+            UnpackedValue::Null => ValueType::Null,
+            UnpackedValue::Bool(_) => ValueType::Boolean,
+            UnpackedValue::Int32(_) | UnpackedValue::Float64(_) => ValueType::Number,
+            UnpackedValue::ImmStr(_) => ValueType::String,
+            UnpackedValue::Ptr(_) => {
+                if self.is_number(heap) {
+                    ValueType::Number
+                } else if self.is_string(heap) {
+                    ValueType::String
+                } else {
+                    ValueType::Object
+                }
+            }
+        }
+    }
+    pub fn is_primitive(&self, heap: &Heap) -> bool {
+        self.value_type(heap) != ValueType::Object
+    }
+    pub fn is_callable(&self, heap: &Heap) -> bool {
+        heap.get::<JsObject>(*self).is_some_and(|obj| obj.function().is_some())
+    }
+
+    // The result of the typeof operator.
+    pub fn type_name(&self, heap: &Heap) -> &'static str {
+        match self.value_type(heap) {
+            ValueType::Undefined => "undefined",
+            ValueType::Null => "object",
+            ValueType::Boolean => "boolean",
+            ValueType::Number => "number",
+            ValueType::String => "string",
+            ValueType::Object => if self.is_callable(heap) { "function" } else { "object" }
+        }
+    }
+
+    pub fn to_boolean(&self, heap: &Heap) -> bool {
+        if let Some(bval) = self.get_imm_bool() {
+            return bval;
+        }
+        if let Some(fval) = self.to_f64(heap) {
+            return fval != 0.0 && ! fval.is_nan();
+        }
+        if let Some(length) = string::string_length(heap, *self) {
+            return length > 0;
+        }
+        ! self.is_imm_undef_null()
+    }
+
+    // Functions convert to their source text, which isn't kept, so convert to
+    // a placeholder of the same form.
+    pub fn to_primitive(&self, heap: &mut Heap, _hint: PreferredType) -> Value {
+        let function = heap.get::<JsObject>(*self).and_then(|obj| obj.function()).map(|function| {
+            let body = match *function {
+                Function::Script(_) => "[bytecode]",
+                Function::Native(_) | Function::Host(_) | Function::Bound(_) => "[native code]"
+            };
+            format!("function {}() {{ {} }}", function.name().unwrap_or(""), body)
+        });
+        if let Some(text) = function {
+            return string::new_string(heap, &text);
+        }
+        if object::is_object(heap, *self) {
+            return string::new_string(heap, "[object Object]");
+        }
+        *self
+    }
+
+    // Objects must have been converted to primitives first.
+    pub fn to_number(&self, heap: &Heap) -> f64 {
+        if let Some(fval) = self.to_f64(heap) {
+            return fval;
+        }
+        match self.value_type(heap) {
+            ValueType::Undefined => f64::NAN,
+            ValueType::Null => 0.0,
+            ValueType::Boolean => if self.is_imm_true() { 1.0 } else { 0.0 },
+            ValueType::String => {
+                let units: Vec<u16> = string::code_units(heap, *self).collect();
+                string_to_number(&units)
+            }
+            ValueType::Number | ValueType::Object => f64::NAN
+        }
+    }
+
+    pub fn to_int32(&self, heap: &Heap) -> i32 {
+        match self.get_imm_i32() {
+            Some(ival) => ival,
+            None => f64_to_uint32(self.to_number(heap)) as i32
+        }
+    }
+    pub fn to_uint32(&self, heap: &Heap) -> u32 {
+        match self.get_imm_i32() {
+            Some(ival) => ival as u32,
+            None => f64_to_uint32(self.to_number(heap))
+        }
+    }
+
+    // Objects must have been converted to primitives first.
+    pub fn to_string(&self, heap: &mut Heap) -> Value {
+        let text = match self.value_type(heap) {
+            ValueType::String => return *self,
+            ValueType::Undefined => "undefined".to_string(),
+            ValueType::Null => "null".to_string(),
+            ValueType::Boolean => if self.is_imm_true() { "true" } else { "false" }.to_string(),
+            ValueType::Number => number_to_string(self.to_f64(heap).unwrap()),
+            ValueType::Object => {
+                let primitive = self.to_primitive(heap, PreferredType::String);
+                return primitive.to_string(heap);
+            }
+        };
+        string::new_string(heap, &text)
+    }
+
+    // Property keys are the atoms of the keys' strings.
+    pub fn to_property_key(&self, heap: &mut Heap) -> Value {
+        let key = self.to_string(heap);
+        string::atomize(heap, key)
+    }
+}
+
+// The modulo-2^32 integer of a number, as ToUint32 computes it.
+pub fn f64_to_uint32(fval: f64) -> u32 {
+    if ! fval.is_finite() {
+        return 0;
+    }
+    let modulo = fval.trunc() % 4294967296.0;
+    let modulo = if modulo < 0.0 { modulo + 4294967296.0 } else { modulo };
+    modulo as u32
+}
+
+/**
+ * Convert a number to a string, using the fewest digits which uniquely
+ * identify it.
+ */
+pub fn number_to_string(fval: f64) -> String {
+    if fval.is_nan() {
+        return "NaN".to_string();
+    }
+    if fval == 0.0 {
+        return "0".to_string();
+    }
+    if fval < 0.0 {
+        return format!("-{}", number_to_string(-fval));
+    }
+    if fval.is_infinite() {
+        return "Infinity".to_string();
+    }
+
+    let (digits, n) = shortest_digits(fval);
+    let k = digits.len() as i32;
+
+    if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..(n as usize)], &digits[(n as usize)..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat((-n) as usize), digits)
+    } else {
+        let sign = if n - 1 < 0 { '-' } else { '+' };
+        if k == 1 {
+            format!("{}e{}{}", digits, sign, (n - 1).abs())
+        } else {
+            format!("{}.{}e{}{}", &digits[..1], &digits[1..], sign, (n - 1).abs())
+        }
+    }
+}
+
+/**
+ * The fewest decimal digits which uniquely identify a positive finite number,
+ * and the exponent `n` for which the number is `0.digits * 10^n`.
+ */
+pub fn shortest_digits(fval: f64) -> (String, i32) {
+    debug_assert!(fval > 0.0 && fval.is_finite());
+    // Rust's exponent formatting gives the shortest round-tripping digits.
+    let formatted = format!("{:e}", fval);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    let digits: String = mantissa.chars().filter(|&ch| ch != '.').collect();
+    (digits, exponent[1..].parse::<i32>().unwrap() + 1)
+}
+
+// White space and line terminators, which are trimmed from numeric strings,
+// and by `String.prototype.trim`.
+pub fn is_str_white_space(unit: u16) -> bool {
+    match unit {
+        0x09 | 0x0A | 0x0B | 0x0C | 0x0D | 0x20 | 0xA0 | 0x1680 | 0x2028 | 0x2029 |
+        0x202F | 0x205F | 0x3000 | 0xFEFF => true,
+        _ => (0x2000..=0x200A).contains(&unit)
+    }
+}
+
+/**
+ * Convert a string to a number, following the grammar of StringNumericLiteral.
+ * Strings which don't match it convert to NaN.
+ */
+pub fn string_to_number(units: &[u16]) -> f64 {
+    let start = units.iter().position(|&u| ! is_str_white_space(u)).unwrap_or(units.len());
+    let end = units.iter().rposition(|&u| ! is_str_white_space(u)).map_or(start, |i| i + 1);
+    let trimmed = &units[start..end];
+    if trimmed.is_empty() {
+        return 0.0;
+    }
+    if trimmed.iter().any(|&u| u > 0x7F) {
+        return f64::NAN;
+    }
+    let text: String = trimmed.iter().map(|&u| u as u8 as char).collect();
+
+    // Hex literals are unsigned.
+    if text.starts_with("0x") || text.starts_with("0X") {
+        let hex_digits = &text[2..];
+        if hex_digits.is_empty() || ! hex_digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return f64::NAN;
+        }
+        return hex_digits.chars().fold(0.0, |acc, ch| acc * 16.0 + (ch.to_digit(16).unwrap() as f64));
+    }
+
+    let (negative, unsigned) = match text.as_bytes()[0] {
+        b'+' => (false, &text[1..]),
+        b'-' => (true, &text[1..]),
+        _ => (false, &text[..])
+    };
+    let magnitude = if unsigned == "Infinity" {
+        f64::INFINITY
+    } else if is_str_unsigned_decimal_literal(unsigned) {
+        unsigned.parse::<f64>().unwrap()
+    } else {
+        return f64::NAN;
+    };
+    if negative { -magnitude } else { magnitude }
+}
+
+// Digits with an optional fraction, with digits on at least one side of the
+// point, and an optional exponent.
+fn is_str_unsigned_decimal_literal(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let count_digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+    let mut pos = 0;
+    let int_digits = count_digits(pos);
+    pos += int_digits;
+    let mut frac_digits = 0;
+    if pos < bytes.len() && bytes[pos] == b'.' {
+        pos += 1;
+        frac_digits = count_digits(pos);
+        pos += frac_digits;
+    }
+    if int_digits == 0 && frac_digits == 0 {
+        return false;
+    }
+    if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+        pos += 1;
+        if pos < bytes.len() && (bytes[pos] == b'+' || bytes[pos] == b'-') {
+            pos += 1;
+        }
+        let exp_digits = count_digits(pos);
+        if exp_digits == 0 {
+            return false;
+        }
+        pos += exp_digits;
+    }
+    pos == bytes.len()
+}
@@ -0,0 +1,208 @@
+/*!
+ * Function values.
+ *
+ * Functions are objects with a `Function` attached, which says how to call
+ * them.  A script function pairs compiled code with the environment it
+ * closes over.  The environment is a slots cell whose slot 0 links to its
+ * parent, or is undefined for functions created at the top level.  A native
+ * function is implemented in Rust, and a host function by a Rust closure an
+ * embedder gives.  A bound function, made by
+ * `Function.prototype.bind`, calls its target with a fixed this value and
+ * leading arguments.
+ *
+ * All kinds are called the same way: the callee, the this value and the
+ * arguments are pushed on the interpreter's stack.  A script function then
+ * gets a frame, while native and host functions are passed the values in a `CallArgs`
+ * and returns its result directly.
+ */
+
+use std::rc::Rc;
+use vm::bytecode::FunctionCode;
+use vm::heap::{Heap, Tracer};
+use vm::interpreter::{Interpreter, VmResult};
+use vm::object::JsObject;
+use vm::value::Value;
+
+/**
+ * The Rust function behind a native function.  Its values stay on the
+ * interpreter's stack during the call, so are rooted.
+ */
+pub type NativeFn = fn(&mut Interpreter, &CallArgs) -> VmResult<Value>;
+
+/** The Rust closure behind a host function, called as a `NativeFn` is. */
+pub type HostFn = Rc<dyn Fn(&mut Interpreter, &CallArgs) -> VmResult<Value>>;
+
+/** A function defined in script. */
+#[derive(Clone)]
+pub struct ScriptFunction {
+    code: Rc<FunctionCode>,
+    env: Value
+}
+impl ScriptFunction {
+    pub fn new(code: Rc<FunctionCode>, env: Value) -> ScriptFunction {
+        ScriptFunction { code, env }
+    }
+    pub fn code(&self) -> &Rc<FunctionCode> {
+        &self.code
+    }
+    pub fn env(&self) -> Value {
+        self.env
+    }
+}
+
+/**
+ * A function implemented in Rust.  Native constructors are called with an
+ * undefined this value by `new`, and must create the object themselves.
+ */
+#[derive(Clone, Copy)]
+pub struct NativeFunction {
+    name: &'static str,
+    call: NativeFn,
+    constructor: bool
+}
+impl NativeFunction {
+    pub fn new(name: &'static str, call: NativeFn, constructor: bool) -> NativeFunction {
+        NativeFunction { name, call, constructor }
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn call(&self) -> NativeFn {
+        self.call
+    }
+    pub fn is_constructor(&self) -> bool {
+        self.constructor
+    }
+}
+
+/**
+ * A function implemented by a closure, which may hold state of its own.
+ * Host functions can't be called by `new`.
+ */
+#[derive(Clone)]
+pub struct HostFunction {
+    name: String,
+    call: HostFn
+}
+impl HostFunction {
+    pub fn new(name: &str, call: HostFn) -> HostFunction {
+        HostFunction { name: name.to_string(), call }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn call(&self) -> &HostFn {
+        &self.call
+    }
+}
+
+/**
+ * A function made by `bind`.  Calling it by `new` ignores the bound this
+ * value, and constructs the target.
+ */
+#[derive(Clone)]
+pub struct BoundFunction {
+    target: Value,
+    this: Value,
+    args: Vec<Value>,
+    constructor: bool
+}
+impl BoundFunction {
+    pub fn new(target: Value, this: Value, args: Vec<Value>, constructor: bool) -> BoundFunction {
+        BoundFunction { target, this, args, constructor }
+    }
+    pub fn target(&self) -> Value {
+        self.target
+    }
+    pub fn this(&self) -> Value {
+        self.this
+    }
+    pub fn args(&self) -> &[Value] {
+        &self.args
+    }
+}
+
+/** How to call a function object. */
+#[derive(Clone)]
+pub enum Function {
+    Script(ScriptFunction),
+    Native(NativeFunction),
+    Host(HostFunction),
+    Bound(BoundFunction)
+}
+impl Function {
+    pub fn is_constructor(&self) -> bool {
+        match *self {
+            Function::Script(_) => true,
+            Function::Native(ref native) => native.is_constructor(),
+            Function::Host(_) => false,
+            Function::Bound(ref bound) => bound.constructor
+        }
+    }
+
+    // Bound functions are named by their `name` property, which isn't kept
+    // here.
+    pub fn name(&self) -> Option<&str> {
+        match *self {
+            Function::Script(ref func) => func.code().name(),
+            Function::Native(ref native) => Some(native.name()),
+            Function::Host(ref host) => Some(host.name()),
+            Function::Bound(_) => None
+        }
+    }
+
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match *self {
+            Function::Script(ref func) => tracer.trace_value(func.env),
+            Function::Native(_) | Function::Host(_) => {}
+            Function::Bound(ref bound) => {
+                tracer.trace_value(bound.target);
+                tracer.trace_value(bound.this);
+                tracer.trace_values(&bound.args);
+            }
+        }
+    }
+}
+
+pub fn new_function(heap: &mut Heap, prototype: Value, function: Function) -> Value {
+    let shape = heap.root_shape().clone();
+    heap.alloc(JsObject::new_function(shape, prototype, function))
+}
+
+/** The values a native function is called with. */
+pub struct CallArgs {
+    callee: Value,
+    this: Value,
+    args: Vec<Value>,
+    construct: bool
+}
+impl CallArgs {
+    pub fn new(callee: Value, this: Value, args: Vec<Value>, construct: bool) -> CallArgs {
+        CallArgs { callee, this, args, construct }
+    }
+
+    pub fn callee(&self) -> Value {
+        self.callee
+    }
+    pub fn this(&self) -> Value {
+        self.this
+    }
+    // Whether the function was called by `new`.
+    pub fn is_construct(&self) -> bool {
+        self.construct
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+    // An argument, or undefined if it wasn't passed.
+    pub fn arg(&self, idx: usize) -> Value {
+        self.args.get(idx).cloned().unwrap_or(Value::new_imm_undef())
+    }
+    pub fn args(&self) -> &[Value] {
+        &self.args
+    }
+}
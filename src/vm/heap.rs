@@ -0,0 +1,436 @@
+/*!
+ * The managed heap.
+ *
+ * Every heap cell starts with a `HeapValue` header recording the kind of the
+ * cell and its mark bit.  Cells are 16-byte aligned, so that pointer values
+ * keep the low bits free for the value tag, and `Value`s point directly at the
+ * header.
+ *
+ * Memory is reclaimed by a precise mark-sweep collector.  Collection only
+ * happens at safepoints, when `collect` or `maybe_collect` is called, and never
+ * during allocation.  Rust code holding values across a safepoint must keep
+ * them in a root (see `Rooted`, `RootedVec` and `Heap::register_root`).
+ * Values which are neither rooted nor reachable from a root are dangling
+ * after a collection.  The heap checks pointer values against its cells
+ * before following them, so a dangling value, or one from another heap, is
+ * never dereferenced: it reads as pointing at no cell.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::mem;
+use std::rc::{Rc, Weak};
+use vm::object::{AccessorPair, JsObject};
+use vm::shape::Shape;
+use vm::string::{AtomTable, HeapString};
+use vm::value::Value;
+
+/** The kind of a heap cell. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Slots,
+    Double,
+    String,
+    Object,
+    Accessor
+}
+
+/** The header at the start of every heap cell. */
+pub struct HeapValue {
+    kind: CellKind,
+    marked: Cell<bool>
+}
+impl HeapValue {
+    pub fn kind(&self) -> CellKind {
+        self.kind
+    }
+}
+
+/**
+ * A type which is stored in heap cells.  Each cell type has its own kind,
+ * and must trace all of the values it holds.
+ */
+pub trait CellType: Sized {
+    const KIND: CellKind;
+
+    fn trace(&self, tracer: &mut Tracer);
+
+    // The number of bytes owned by the cell outside of the cell itself.  This
+    // may change after allocation, and is recounted for live cells when the
+    // heap is collected.
+    fn extra_size(&self) -> usize {
+        0
+    }
+}
+
+#[repr(C, align(16))]
+struct HeapCell<T: CellType> {
+    header: HeapValue,
+    body: T
+}
+
+/** Per-kind operations on cells, used by the collector. */
+struct CellOps {
+    trace: unsafe fn(*const HeapValue, &mut Tracer),
+    size: unsafe fn(*const HeapValue) -> usize,
+    free: unsafe fn(*mut HeapValue)
+}
+impl CellOps {
+    fn of<T: CellType>() -> CellOps {
+        CellOps { trace: trace_cell::<T>, size: cell_size::<T>, free: free_cell::<T> }
+    }
+
+    fn for_kind(kind: CellKind) -> CellOps {
+        match kind {
+            CellKind::Slots => CellOps::of::<SlotsCell>(),
+            CellKind::Double => CellOps::of::<HeapDouble>(),
+            CellKind::String => CellOps::of::<HeapString>(),
+            CellKind::Object => CellOps::of::<JsObject>(),
+            CellKind::Accessor => CellOps::of::<AccessorPair>()
+        }
+    }
+}
+
+unsafe fn trace_cell<T: CellType>(ptr: *const HeapValue, tracer: &mut Tracer) {
+    unsafe { (*(ptr as *const HeapCell<T>)).body.trace(tracer) }
+}
+
+unsafe fn cell_size<T: CellType>(ptr: *const HeapValue) -> usize {
+    mem::size_of::<HeapCell<T>>() + unsafe { (*(ptr as *const HeapCell<T>)).body.extra_size() }
+}
+
+unsafe fn free_cell<T: CellType>(ptr: *mut HeapValue) {
+    drop(unsafe { Box::from_raw(ptr as *mut HeapCell<T>) });
+}
+
+// Hashes cell pointers, which are distinct and 16-byte aligned, so need no
+// more mixing than a multiplication.
+#[derive(Default)]
+struct CellHasher(u64);
+impl Hasher for CellHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 << 8 | byte as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+    fn write_usize(&mut self, word: usize) {
+        self.0 = ((word >> 4) as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+type CellSet = HashSet<*mut HeapValue, BuildHasherDefault<CellHasher>>;
+
+/**
+ * Marks the cells reachable from traced values.  Values which don't point at
+ * a cell of the heap being collected are ignored.
+ */
+pub struct Tracer<'a> {
+    cells: &'a CellSet,
+    mark_stack: Vec<*const HeapValue>
+}
+impl<'a> Tracer<'a> {
+    fn new(cells: &'a CellSet) -> Tracer<'a> {
+        Tracer { cells, mark_stack: Vec::new() }
+    }
+
+    pub fn trace_value(&mut self, value: Value) {
+        if let Some(ptr) = value.get_mut_ptr() {
+            if ! self.cells.contains(&ptr) {
+                return;
+            }
+            let header = unsafe { &*ptr };
+            if ! header.marked.get() {
+                header.marked.set(true);
+                self.mark_stack.push(ptr);
+            }
+        }
+    }
+
+    pub fn trace_values(&mut self, values: &[Value]) {
+        for value in values {
+            self.trace_value(*value);
+        }
+    }
+
+    // Trace the contents of marked cells until no unscanned cells remain.
+    fn drain(&mut self) {
+        while let Some(ptr) = self.mark_stack.pop() {
+            let ops = CellOps::for_kind(unsafe { (*ptr).kind });
+            unsafe { (ops.trace)(ptr, self) };
+        }
+    }
+}
+
+/** A structure holding values, which can be registered as a root. */
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+impl Trace for Cell<Value> {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.trace_value(self.get());
+    }
+}
+impl Trace for RefCell<Vec<Value>> {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.trace_values(&self.borrow());
+    }
+}
+
+/** A rooted value, which stays alive until the handle (and its clones) are dropped. */
+#[derive(Clone)]
+pub struct Rooted(Rc<Cell<Value>>);
+impl Rooted {
+    pub fn get(&self) -> Value {
+        self.0.get()
+    }
+    pub fn set(&self, value: Value) {
+        self.0.set(value)
+    }
+}
+
+/** A rooted, growable vector of values. */
+#[derive(Clone)]
+pub struct RootedVec(Rc<RefCell<Vec<Value>>>);
+impl RootedVec {
+    pub fn values(&self) -> &RefCell<Vec<Value>> {
+        &self.0
+    }
+    pub fn get(&self, idx: usize) -> Value {
+        self.0.borrow()[idx]
+    }
+    pub fn push(&self, value: Value) {
+        self.0.borrow_mut().push(value)
+    }
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+}
+
+/** A fixed-length vector of values, stored in a heap cell. */
+pub struct SlotsCell {
+    slots: Box<[Value]>
+}
+impl SlotsCell {
+    pub fn new(length: usize, init: Value) -> SlotsCell {
+        SlotsCell { slots: vec![init; length].into_boxed_slice() }
+    }
+    pub fn slots(&self) -> &[Value] {
+        &self.slots
+    }
+    pub fn slots_mut(&mut self) -> &mut [Value] {
+        &mut self.slots
+    }
+}
+impl CellType for SlotsCell {
+    const KIND: CellKind = CellKind::Slots;
+
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.trace_values(&self.slots);
+    }
+
+    fn extra_size(&self) -> usize {
+        self.slots.len() * mem::size_of::<Value>()
+    }
+}
+
+/** A double which can't be represented as an immediate value. */
+pub struct HeapDouble(f64);
+impl HeapDouble {
+    pub fn new(fval: f64) -> HeapDouble {
+        HeapDouble(fval)
+    }
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+impl CellType for HeapDouble {
+    const KIND: CellKind = CellKind::Double;
+
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
+const MIN_COLLECT_THRESHOLD: usize = 1024 * 1024;
+
+// The fewest registered roots at which dead ones are pruned between
+// collections.
+const MIN_ROOTS_LIMIT: usize = 256;
+
+/**
+ * The heap, which owns all cells, the registry of roots, the atom table and
+ * the root of the shape tree.
+ */
+pub struct Heap {
+    cells: CellSet,
+    roots: Vec<Weak<dyn Trace>>,
+    roots_limit: usize,
+    atoms: AtomTable,
+    root_shape: Rc<Shape>,
+    bytes_allocated: usize,
+    collect_threshold: usize
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            cells: CellSet::default(),
+            roots: Vec::new(),
+            roots_limit: MIN_ROOTS_LIMIT,
+            atoms: AtomTable::new(),
+            root_shape: Shape::new_root(),
+            bytes_allocated: 0,
+            collect_threshold: MIN_COLLECT_THRESHOLD
+        }
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn alloc<T: CellType>(&mut self, body: T) -> Value {
+        self.bytes_allocated += mem::size_of::<HeapCell<T>>() + body.extra_size();
+        let header = HeapValue { kind: T::KIND, marked: Cell::new(false) };
+        let ptr = Box::into_raw(Box::new(HeapCell { header, body })) as *mut HeapValue;
+        self.cells.insert(ptr);
+        Value::new_ptr(ptr)
+    }
+
+    // The cell a value points to, if it's a live cell of this heap.
+    fn cell_of(&self, value: Value) -> Option<*mut HeapValue> {
+        value.get_mut_ptr().filter(|ptr| self.cells.contains(ptr))
+    }
+
+    /**
+     * Access the cell a value points to.  A value which isn't a pointer to a
+     * live cell of this heap, such as one left unrooted across a collection or
+     * taken from another heap, points at no cell, and gives `None`.
+     */
+    pub fn kind_of(&self, value: Value) -> Option<CellKind> {
+        self.cell_of(value).map(|ptr| unsafe { (*ptr).kind })
+    }
+    pub fn get<T: CellType>(&self, value: Value) -> Option<&T> {
+        match self.cell_of(value) {
+            Some(ptr) if unsafe { (*ptr).kind } == T::KIND => {
+                Some(unsafe { &(*(ptr as *const HeapCell<T>)).body })
+            }
+            _ => None
+        }
+    }
+    pub fn get_mut<T: CellType>(&mut self, value: Value) -> Option<&mut T> {
+        match self.cell_of(value) {
+            Some(ptr) if unsafe { (*ptr).kind } == T::KIND => {
+                Some(unsafe { &mut (*(ptr as *mut HeapCell<T>)).body })
+            }
+            _ => None
+        }
+    }
+
+    pub fn atoms(&self) -> &AtomTable {
+        &self.atoms
+    }
+    pub fn atoms_mut(&mut self) -> &mut AtomTable {
+        &mut self.atoms
+    }
+
+    // The empty shape, which new objects start with.
+    pub fn root_shape(&self) -> &Rc<Shape> {
+        &self.root_shape
+    }
+
+    pub fn root(&mut self, value: Value) -> Rooted {
+        let root = Rc::new(Cell::new(value));
+        self.register_root(&root);
+        Rooted(root)
+    }
+    pub fn root_vec(&mut self, values: Vec<Value>) -> RootedVec {
+        let root = Rc::new(RefCell::new(values));
+        self.register_root(&root);
+        RootedVec(root)
+    }
+
+    // Register a structure whose values are traced as roots, for as long as
+    // it is alive.  Dead roots are forgotten by collections, and also whenever
+    // the registry doubles in size, as code which roots values without
+    // allocating would otherwise never let it shrink.
+    pub fn register_root<T: Trace + 'static>(&mut self, root: &Rc<T>) {
+        if self.roots.len() >= self.roots_limit {
+            self.roots.retain(|weak| weak.upgrade().is_some());
+            self.roots_limit = (self.roots.len() * 2).max(MIN_ROOTS_LIMIT);
+        }
+        let weak: Weak<T> = Rc::downgrade(root);
+        self.roots.push(weak);
+    }
+
+    pub fn root_count(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.bytes_allocated >= self.collect_threshold
+    }
+    pub fn maybe_collect(&mut self) {
+        if self.should_collect() {
+            self.collect();
+        }
+    }
+
+    pub fn collect(&mut self) {
+        // Mark everything reachable from atoms and live roots, forgetting dead roots.
+        let mut tracer = Tracer::new(&self.cells);
+        self.atoms.trace(&mut tracer);
+        tracer.drain();
+        self.roots.retain(|weak| {
+            match weak.upgrade() {
+                Some(root) => {
+                    root.trace(&mut tracer);
+                    tracer.drain();
+                    true
+                }
+                None => false
+            }
+        });
+
+        // Sweep unmarked cells, and clear the marks of survivors.
+        let mut live_bytes = 0;
+        self.cells.retain(|&ptr| {
+            let header = unsafe { &*ptr };
+            let ops = CellOps::for_kind(header.kind);
+            if header.marked.get() {
+                header.marked.set(false);
+                live_bytes += unsafe { (ops.size)(ptr) };
+                true
+            } else {
+                unsafe { (ops.free)(ptr) };
+                false
+            }
+        });
+
+        self.roots_limit = (self.roots.len() * 2).max(MIN_ROOTS_LIMIT);
+        self.bytes_allocated = live_bytes;
+        self.collect_threshold = (self.bytes_allocated * 2).max(MIN_COLLECT_THRESHOLD);
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap::new()
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        for &ptr in &self.cells {
+            let ops = CellOps::for_kind(unsafe { (*ptr).kind });
+            unsafe { (ops.free)(ptr) };
+        }
+    }
+}
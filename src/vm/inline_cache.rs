@@ -0,0 +1,119 @@
+/*!
+ * Inline caches for property accesses.
+ *
+ * Each property get or set site in a function has a cache, recording where
+ * the property was found on the shapes seen at that site.  A site starts out
+ * uninitialized, becomes monomorphic once it caches one shape, and
+ * polymorphic as it caches more, up to `MAX_POLYMORPHIC_ENTRIES`.  A site
+ * seeing more shapes than that becomes megamorphic, and stops caching.
+ *
+ * Only own data properties are cached, so a hit reads or writes a slot of
+ * the object directly.  A set which added a property also caches the shape
+ * it transitioned to, so later sets can add the property without a lookup.
+ *
+ * Keys are atoms, so caches hold no heap values and need no tracing.
+ */
+
+use std::mem;
+use std::rc::Rc;
+use vm::shape::Shape;
+use vm::value::Value;
+
+pub const MAX_POLYMORPHIC_ENTRIES: usize = 4;
+
+/** A cached property location, for objects of one shape. */
+#[derive(Clone)]
+pub struct CacheEntry {
+    shape: Rc<Shape>,
+    key: Value,
+    slot: u32,
+    // For sets adding a property, the shape the object changes to.
+    transition: Option<Rc<Shape>>
+}
+impl CacheEntry {
+    pub fn new(shape: Rc<Shape>, key: Value, slot: u32) -> CacheEntry {
+        CacheEntry { shape, key, slot, transition: None }
+    }
+    pub fn new_transition(shape: Rc<Shape>, key: Value, slot: u32, transition: Rc<Shape>)
+        -> CacheEntry
+    {
+        CacheEntry { shape, key, slot, transition: Some(transition) }
+    }
+
+    pub fn slot(&self) -> u32 {
+        self.slot
+    }
+    pub fn transition(&self) -> Option<&Rc<Shape>> {
+        self.transition.as_ref()
+    }
+
+    fn matches(&self, shape: &Rc<Shape>, key: Value) -> bool {
+        Rc::ptr_eq(&self.shape, shape) && (self.key == key)
+    }
+}
+
+/** The state of an inline cache. */
+#[derive(Clone)]
+pub enum InlineCache {
+    Uninitialized,
+    Monomorphic(CacheEntry),
+    Polymorphic(Vec<CacheEntry>),
+    Megamorphic
+}
+impl InlineCache {
+    pub fn is_megamorphic(&self) -> bool {
+        matches!(*self, InlineCache::Megamorphic)
+    }
+
+    pub fn lookup(&self, shape: &Rc<Shape>, key: Value) -> Option<&CacheEntry> {
+        match *self {
+            InlineCache::Monomorphic(ref entry) if entry.matches(shape, key) => Some(entry),
+            InlineCache::Polymorphic(ref entries) => entries.iter().find(|e| e.matches(shape, key)),
+            _ => None
+        }
+    }
+
+    pub fn add(&mut self, entry: CacheEntry) {
+        let state = mem::replace(self, InlineCache::Uninitialized);
+        *self = match state {
+            InlineCache::Uninitialized => InlineCache::Monomorphic(entry),
+            InlineCache::Monomorphic(first) => InlineCache::Polymorphic(vec![first, entry]),
+            InlineCache::Polymorphic(mut entries) => {
+                if entries.len() < MAX_POLYMORPHIC_ENTRIES {
+                    entries.push(entry);
+                    InlineCache::Polymorphic(entries)
+                } else {
+                    InlineCache::Megamorphic
+                }
+            }
+            InlineCache::Megamorphic => InlineCache::Megamorphic
+        };
+    }
+}
+
+/**
+ * Counts of property accesses on objects at cached sites.  Hits are accesses
+ * found in their site's cache, and misses are the other accesses, which may
+ * then be added to it.  Accesses at megamorphic sites are counted separately.
+ * Accesses on primitives aren't cached, so aren't counted, and nor are
+ * accesses to arrays' elements and lengths.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InlineCacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+    pub megamorphic: u64
+}
+impl InlineCacheCounters {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses + self.megamorphic
+    }
+
+    // The fraction of accesses which hit, or zero if there were none.
+    pub fn hit_rate(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            accesses => (self.hits as f64) / (accesses as f64)
+        }
+    }
+}
@@ -0,0 +1,1740 @@
+/*!
+ * The bytecode interpreter.
+ *
+ * All frames share a single rooted value stack.  Each frame's callee and this
+ * value sit just below its registers, and its current environment just above
+ * them, followed by its operand stack.
+ *
+ * Calls between script functions don't recurse on the Rust stack, but calls
+ * made by the interpreter itself, to accessors and conversion methods, and
+ * calls from native functions, run a nested `execute`.  A frame entered by
+ * `new` returns its this value unless its function returns an object.
+ *
+ * Non-strict functions called with an undefined or null this value get the
+ * global object instead, and primitive this values are boxed.  A function's
+ * arguments object is made as its frame is pushed, before extra arguments are
+ * dropped.  A non-strict function's parameters are kept in its environment,
+ * and its prologue maps the arguments object's indices to them.
+ *
+ * A thrown value unwinds frames to the innermost handler covering where each
+ * stopped, as given by its code's handler table.  The handler is entered with
+ * the frame's operand stack holding just the value.  If no frame entered by
+ * the current `execute` handles it, it's returned as an `Exception`, with the
+ * frames it unwound as its stack trace.  The errors the interpreter itself
+ * throws are Error objects, whose `stack` is captured as they're made.
+ *
+ * Collection happens at function entry and on backward jumps, when every live
+ * value is on the stack or in a root.  As calls from the interpreter may
+ * collect, operands stay on the stack until an instruction is done with them.
+ *
+ * Global variables are properties of the global object, which is the `this`
+ * value of the program.
+ *
+ * Element accesses on arrays with non-negative int32 keys try the array's
+ * elements directly, without converting the key to a string.
+ */
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64;
+use std::fmt;
+use std::rc::Rc;
+
+use parser::ast::ProgramNode;
+use vm::array;
+use vm::builtins;
+use vm::builtins::error::{self, ErrorKind};
+use vm::builtins::math::Random;
+use vm::bytecode::{self, Constant, FunctionCode, Op};
+use vm::bytecode::compiler::{self, CompileError};
+use vm::conversions::{f64_to_uint32, PreferredType, ValueType};
+use vm::function::{self, CallArgs, Function, HostFn, HostFunction, NativeFn, NativeFunction, ScriptFunction};
+use vm::heap::{Heap, Rooted, RootedVec, SlotsCell};
+use vm::inline_cache::{CacheEntry, InlineCache, InlineCacheCounters};
+use vm::object::{self, JsObject, Property};
+use vm::regexp::{self, RegExp};
+use vm::time::{Clock, TimeZone};
+use vm::shape::PropertyAttributes;
+use vm::string;
+use vm::value::Value;
+
+const MAX_FRAMES: usize = 10000;
+// Calls made from Rust recurse on the Rust stack, so are limited separately.
+const MAX_NESTED_CALLS: usize = 500;
+
+// The slot of a for-in loop's state holding its first key.
+const FOR_IN_FIRST_KEY: usize = 2;
+
+/**
+ * A thrown value, and the stack when it was thrown.  The value is unrooted,
+ * so must be rooted to be kept across a collection.
+ */
+#[derive(Debug, Clone)]
+pub struct Exception {
+    value: Value,
+    stack_trace: Vec<StackFrame>
+}
+impl Exception {
+    pub fn new(value: Value) -> Exception {
+        Exception { value, stack_trace: Vec::new() }
+    }
+    pub fn value(&self) -> Value {
+        self.value
+    }
+    // The script frames the value was thrown out of, innermost first.
+    pub fn stack_trace(&self) -> &[StackFrame] {
+        &self.stack_trace
+    }
+}
+
+/** A script frame in a stack trace. */
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    // The name of the frame's function, or None for the program.
+    function: Option<String>,
+    pc: usize
+}
+impl StackFrame {
+    pub fn function(&self) -> Option<&str> {
+        self.function.as_deref()
+    }
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+}
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref name) => write!(f, "at {} (pc {})", name, self.pc),
+            None => write!(f, "at <program> (pc {})", self.pc)
+        }
+    }
+}
+
+/** Why running a program failed. */
+#[derive(Debug)]
+pub enum RunError {
+    Compile(CompileError),
+    // The program threw an exception it didn't catch.
+    Throw(Exception)
+}
+impl From<CompileError> for RunError {
+    fn from(error: CompileError) -> RunError {
+        RunError::Compile(error)
+    }
+}
+impl From<Exception> for RunError {
+    fn from(exception: Exception) -> RunError {
+        RunError::Throw(exception)
+    }
+}
+
+pub type VmResult<T> = Result<T, Exception>;
+
+/** The state kept for code which has been run on this heap. */
+struct LoadedCode {
+    code: Rc<FunctionCode>,
+    // The constants, materialized in the heap.
+    constants: RootedVec,
+    inline_caches: RefCell<Vec<InlineCache>>
+}
+
+struct Frame {
+    code: Rc<LoadedCode>,
+    // The stack index of register 0.
+    base: usize,
+    // The pc after the instruction the frame is running, or is to resume at.
+    pc: usize,
+    // Whether the frame was entered by `new`.
+    construct: bool
+}
+
+pub struct Interpreter {
+    heap: Heap,
+    stack: RootedVec,
+    frames: Vec<Frame>,
+    nested_calls: usize,
+    global_object: Rooted,
+    object_prototype: Rooted,
+    function_prototype: Rooted,
+    array_prototype: Rooted,
+    string_prototype: Rooted,
+    number_prototype: Rooted,
+    regexp_prototype: Rooted,
+    date_prototype: Rooted,
+    // The prototypes of the kinds of error, indexed by kind.
+    error_prototypes: RootedVec,
+    loaded_code: HashMap<*const FunctionCode, Rc<LoadedCode>>,
+    inline_cache_counters: InlineCacheCounters,
+    completion: Rooted,
+    random: Random,
+    regexp_step_limit: u64,
+    clock: Clock,
+    time_zone: TimeZone
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut heap = Heap::new();
+        let stack = heap.root_vec(Vec::new());
+        let object_prototype = object::new_object(&mut heap, Value::new_imm_null());
+        let global_object = object::new_object(&mut heap, object_prototype);
+        let function_prototype = Function::Native(NativeFunction::new("", return_undefined, false));
+        let function_prototype = function::new_function(&mut heap, object_prototype, function_prototype);
+        let array_prototype = array::new_array(&mut heap, object_prototype);
+        let object_prototype = heap.root(object_prototype);
+        let global_object = heap.root(global_object);
+        let function_prototype = heap.root(function_prototype);
+        let array_prototype = heap.root(array_prototype);
+        let string_prototype = object::new_object(&mut heap, object_prototype.get());
+        let string_prototype = heap.root(string_prototype);
+        let number_prototype = object::new_object(&mut heap, object_prototype.get());
+        let number_prototype = heap.root(number_prototype);
+        // RegExp.prototype is itself a RegExp, which matches the empty string.
+        let empty_regexp = Rc::new(RegExp::new(&[], &[]).unwrap());
+        let regexp_prototype = regexp::new_regexp_object(&mut heap, object_prototype.get(), empty_regexp);
+        let regexp_prototype = heap.root(regexp_prototype);
+        // And Date.prototype is a Date, with an invalid time.
+        let date_prototype = object::new_date(&mut heap, object_prototype.get(), f64::NAN);
+        let date_prototype = heap.root(date_prototype);
+        let error_prototype = object::new_object(&mut heap, object_prototype.get());
+        let error_prototypes = heap.root_vec(vec![error_prototype]);
+        for _ in 1..ErrorKind::ALL.len() {
+            error_prototypes.push(object::new_object(&mut heap, error_prototype));
+        }
+        let completion = heap.root(Value::new_imm_undef());
+
+        let mut interp = Interpreter {
+            heap,
+            stack,
+            frames: Vec::new(),
+            nested_calls: 0,
+            global_object,
+            object_prototype,
+            function_prototype,
+            array_prototype,
+            string_prototype,
+            number_prototype,
+            regexp_prototype,
+            date_prototype,
+            error_prototypes,
+            loaded_code: HashMap::new(),
+            inline_cache_counters: InlineCacheCounters::default(),
+            completion,
+            random: Random::from_time(),
+            regexp_step_limit: regexp::DEFAULT_STEP_LIMIT,
+            clock: Clock::system(),
+            time_zone: TimeZone::system()
+        };
+        let nan = Value::from_f64(&mut interp.heap, f64::NAN);
+        let infinity = Value::from_f64(&mut interp.heap, f64::INFINITY);
+        interp.define_global("undefined", Value::new_imm_undef());
+        interp.define_global("NaN", nan);
+        interp.define_global("Infinity", infinity);
+        builtins::install(&mut interp);
+        interp
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    pub fn global_object(&self) -> Value {
+        self.global_object.get()
+    }
+    pub fn object_prototype(&self) -> Value {
+        self.object_prototype.get()
+    }
+    pub fn function_prototype(&self) -> Value {
+        self.function_prototype.get()
+    }
+    pub fn array_prototype(&self) -> Value {
+        self.array_prototype.get()
+    }
+    pub fn string_prototype(&self) -> Value {
+        self.string_prototype.get()
+    }
+    pub fn number_prototype(&self) -> Value {
+        self.number_prototype.get()
+    }
+    pub fn regexp_prototype(&self) -> Value {
+        self.regexp_prototype.get()
+    }
+    pub fn date_prototype(&self) -> Value {
+        self.date_prototype.get()
+    }
+    pub fn error_prototype(&self, kind: ErrorKind) -> Value {
+        self.error_prototypes.get(kind as usize)
+    }
+
+    // A native function, which can't be called by `new`.
+    pub fn new_native_function(&mut self, name: &'static str, length: u32, call: NativeFn) -> Value {
+        self.new_native(NativeFunction::new(name, call, false), length)
+    }
+    // A native function which can also be called by `new`.
+    pub fn new_native_constructor(&mut self, name: &'static str, length: u32, call: NativeFn) -> Value {
+        self.new_native(NativeFunction::new(name, call, true), length)
+    }
+
+    // A function implemented by a closure, for embedders.
+    pub fn new_host_function(&mut self, name: &str, length: u32, call: HostFn) -> Value {
+        self.new_function_object(Function::Host(HostFunction::new(name, call)), length)
+    }
+
+    fn new_native(&mut self, native: NativeFunction, length: u32) -> Value {
+        self.new_function_object(Function::Native(native), length)
+    }
+
+    fn new_function_object(&mut self, function: Function, length: u32) -> Value {
+        let func = function::new_function(&mut self.heap, self.function_prototype.get(), function);
+        self.define_function_length(func, length);
+        func
+    }
+
+    // The generator behind `Math.random`.
+    pub fn random_mut(&mut self) -> &mut Random {
+        &mut self.random
+    }
+    // Seed `Math.random`, so that a run can be repeated.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+
+    // The instructions a single regular expression match may run.
+    pub fn regexp_step_limit(&self) -> u64 {
+        self.regexp_step_limit
+    }
+    pub fn set_regexp_step_limit(&mut self, limit: u64) {
+        self.regexp_step_limit = limit;
+    }
+
+    // The time value `Date.now` gives.
+    pub fn current_time(&self) -> f64 {
+        self.clock.now()
+    }
+    // Replace the clock, so that scripts see a time of the embedder's.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    // The zone of dates' local times.
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.time_zone
+    }
+    // Set the zone of local times, such as to `TimeZone::Utc` so that dates
+    // print the same on every machine.
+    pub fn set_time_zone(&mut self, time_zone: TimeZone) {
+        self.time_zone = time_zone;
+    }
+
+    // Define a global which can't be deleted, and isn't enumerated.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        let name = string::atomize_str(&mut self.heap, name);
+        let property = Property::Data { value, attrs: PropertyAttributes::WRITABLE };
+        object::define_own_property(&mut self.heap, self.global_object.get(), name, property);
+    }
+    // The value of a global data property.
+    pub fn global(&mut self, name: &str) -> Option<Value> {
+        let name = string::atomize_str(&mut self.heap, name);
+        match object::get_own_property(&mut self.heap, self.global_object.get(), name) {
+            Some(Property::Data { value, .. }) => Some(value),
+            _ => None
+        }
+    }
+
+    // Run a program, returning its completion value.
+    pub fn run_program(&mut self, program: &ProgramNode) -> Result<Value, RunError> {
+        let code = compiler::compile_program(program)?;
+        Ok(self.run_code(code)?)
+    }
+
+    pub fn run_code(&mut self, code: Rc<FunctionCode>) -> VmResult<Value> {
+        assert!(code.is_program());
+        self.completion.set(Value::new_imm_undef());
+
+        let this = self.global_object.get();
+        self.run_function(code, Value::new_imm_undef(), Value::new_imm_undef(), this, &[])
+    }
+
+    pub fn inline_cache_counters(&self) -> InlineCacheCounters {
+        self.inline_cache_counters
+    }
+    pub fn reset_inline_cache_counters(&mut self) {
+        self.inline_cache_counters = InlineCacheCounters::default();
+    }
+
+    // Convert a value to a Rust string, as the language would convert it.
+    pub fn display_string(&mut self, value: Value) -> String {
+        let string = value.to_string(&mut self.heap);
+        string::to_rust_string(&self.heap, string).unwrap()
+    }
+
+    /*************************************************************************
+     **** Stack **************************************************************
+     *************************************************************************/
+
+    fn push(&self, value: Value) {
+        self.stack.push(value);
+    }
+    fn pop(&self) -> Value {
+        self.stack.values().borrow_mut().pop().expect("non-empty stack")
+    }
+    fn peek(&self, depth: usize) -> Value {
+        let stack = self.stack.values().borrow();
+        stack[stack.len() - 1 - depth]
+    }
+    fn stack_get(&self, idx: usize) -> Value {
+        self.stack.get(idx)
+    }
+    fn stack_set(&self, idx: usize, value: Value) {
+        self.stack.values().borrow_mut()[idx] = value;
+    }
+
+    /*************************************************************************
+     **** Frames *************************************************************
+     *************************************************************************/
+
+    // Push a frame for code whose arguments start at `base`.  Missing
+    // arguments are undefined, and extra arguments are dropped.
+    fn push_frame(&mut self, code: Rc<FunctionCode>, base: usize, argc: usize, env: Value,
+                  construct: bool)
+        -> VmResult<()>
+    {
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(self.error(ErrorKind::RangeError, "Maximum call stack size exceeded"));
+        }
+        if ! code.is_strict() {
+            let this = self.stack_get(base - 1);
+            if this.is_imm_undef_null() {
+                self.stack_set(base - 1, self.global_object.get());
+            } else if ! object::is_object(&self.heap, this) {
+                let this = self.to_object(this)?;
+                self.stack_set(base - 1, this);
+            }
+        }
+        let arguments = if code.has_arguments_object() {
+            let args = self.stack.values().borrow()[base..(base + argc)].to_vec();
+            Some(self.new_arguments_object(self.stack_get(base - 2), &args, code.is_strict()))
+        } else {
+            None
+        };
+        {
+            let mut stack = self.stack.values().borrow_mut();
+            stack.truncate(base + argc.min(code.param_count() as usize));
+            stack.resize(base + code.register_count() as usize, Value::new_imm_undef());
+            if let Some(arguments) = arguments {
+                stack[base + code.param_count() as usize] = arguments;
+            }
+            stack.push(env);
+        }
+        let code = self.load_code(&code);
+        self.frames.push(Frame { code, base, pc: 0, construct });
+        self.heap.maybe_collect();
+        Ok(())
+    }
+
+    /**
+     * Call a function, running it to completion.  The arguments need not be
+     * rooted, as they are pushed before anything can collect.
+     */
+    pub fn call(&mut self, callee: Value, this: Value, args: &[Value]) -> VmResult<Value> {
+        let callee_index = self.stack.len();
+        self.push(callee);
+        self.push(this);
+        for &arg in args {
+            self.push(arg);
+        }
+        self.run_call(callee_index, args.len(), /* construct = */ false)
+    }
+
+    // Call a function as `new` does.
+    pub fn construct(&mut self, callee: Value, args: &[Value]) -> VmResult<Value> {
+        let callee_index = self.stack.len();
+        self.push(callee);
+        for &arg in args {
+            self.push(arg);
+        }
+        match self.construct_this(callee) {
+            Ok(this) => self.stack.values().borrow_mut().insert(callee_index + 1, this),
+            Err(exception) => {
+                self.stack.values().borrow_mut().truncate(callee_index);
+                return Err(exception);
+            }
+        }
+        self.run_call(callee_index, args.len(), /* construct = */ true)
+    }
+
+    // Run a call whose callee, this value and arguments are on the stack.
+    fn run_call(&mut self, callee_index: usize, argc: usize, construct: bool) -> VmResult<Value> {
+        if self.nested_calls >= MAX_NESTED_CALLS {
+            self.stack.values().borrow_mut().truncate(callee_index);
+            return Err(self.error(ErrorKind::RangeError, "Maximum call stack size exceeded"));
+        }
+        self.nested_calls += 1;
+        let entry_depth = self.frames.len();
+        let result = match self.enter_call(callee_index, argc, construct) {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => self.execute(entry_depth),
+            Err(exception) => {
+                self.stack.values().borrow_mut().truncate(callee_index);
+                Err(exception)
+            }
+        };
+        self.nested_calls -= 1;
+        result
+    }
+
+    /**
+     * Start a call whose callee, this value and arguments are on the stack
+     * from `callee_index`.  A script function gets a frame, and None is
+     * returned.  A native or host function runs to completion, and its
+     * result is returned, with the call's values popped.  A bound function's
+     * call becomes a call of its target.
+     */
+    fn enter_call(&mut self, callee_index: usize, argc: usize, construct: bool)
+        -> VmResult<Option<Value>>
+    {
+        let callee = self.stack_get(callee_index);
+        let function = self.heap.get::<JsObject>(callee).and_then(|obj| obj.function()).cloned();
+        match function {
+            Some(Function::Script(func)) => {
+                self.push_frame(func.code().clone(), callee_index + 2, argc, func.env(), construct)?;
+                Ok(None)
+            }
+            Some(Function::Native(native)) => {
+                let this = self.stack_get(callee_index + 1);
+                let args = self.stack.values().borrow()[(callee_index + 2)..].to_vec();
+                let call_args = CallArgs::new(callee, this, args, construct);
+                let result = (native.call())(self, &call_args)?;
+                self.stack.values().borrow_mut().truncate(callee_index);
+                Ok(Some(result))
+            }
+            Some(Function::Host(host)) => {
+                let this = self.stack_get(callee_index + 1);
+                let args = self.stack.values().borrow()[(callee_index + 2)..].to_vec();
+                let call_args = CallArgs::new(callee, this, args, construct);
+                let result = (host.call())(self, &call_args)?;
+                self.stack.values().borrow_mut().truncate(callee_index);
+                Ok(Some(result))
+            }
+            Some(Function::Bound(bound)) => {
+                {
+                    let mut stack = self.stack.values().borrow_mut();
+                    stack[callee_index] = bound.target();
+                    if ! construct {
+                        stack[callee_index + 1] = bound.this();
+                    }
+                    let args_index = callee_index + 2;
+                    stack.splice(args_index..args_index, bound.args().iter().cloned());
+                }
+                self.enter_call(callee_index, argc + bound.args().len(), construct)
+            }
+            None => {
+                let message = format!("{} is not a function", self.display_string(callee));
+                Err(self.error(ErrorKind::TypeError, &message))
+            }
+        }
+    }
+
+    /**
+     * The this value for calling a function by `new`.  Script functions get a
+     * new object inheriting from their `prototype`, or from the object
+     * prototype if that isn't an object.  Native constructors get undefined,
+     * and bound functions the this value of their target.
+     */
+    fn construct_this(&mut self, callee: Value) -> VmResult<Value> {
+        let function = self.heap.get::<JsObject>(callee).and_then(|obj| obj.function()).cloned();
+        match function {
+            Some(Function::Script(_)) => {
+                let prototype_key = string::atomize_str(&mut self.heap, "prototype");
+                let mut prototype = self.get_property(callee, prototype_key)?;
+                if ! object::is_object(&self.heap, prototype) {
+                    prototype = self.object_prototype.get();
+                }
+                Ok(object::new_object(&mut self.heap, prototype))
+            }
+            Some(ref function) if function.is_constructor() => match *function {
+                Function::Bound(ref bound) => self.construct_this(bound.target()),
+                _ => Ok(Value::new_imm_undef())
+            },
+            _ => {
+                let message = format!("{} is not a constructor", self.display_string(callee));
+                Err(self.error(ErrorKind::TypeError, &message))
+            }
+        }
+    }
+
+    fn run_function(&mut self, code: Rc<FunctionCode>, env: Value, callee: Value, this: Value,
+                    args: &[Value])
+        -> VmResult<Value>
+    {
+        let entry_depth = self.frames.len();
+        let base = self.stack.len() + 2;
+        self.push(callee);
+        self.push(this);
+        for &arg in args {
+            self.push(arg);
+        }
+        if let Err(exception) = self.push_frame(code, base, args.len(), env, /* construct = */ false) {
+            self.stack.values().borrow_mut().truncate(base - 2);
+            return Err(exception);
+        }
+        self.execute(entry_depth)
+    }
+
+    // A closure of script code, with a fresh prototype object for `new`.
+    fn new_script_function(&mut self, code: Rc<FunctionCode>, env: Value) -> Value {
+        let length = code.param_count() as u32;
+        let func = function::new_function(&mut self.heap, self.function_prototype.get(),
+                                          Function::Script(ScriptFunction::new(code, env)));
+        let prototype = object::new_object(&mut self.heap, self.object_prototype.get());
+        let constructor_key = string::atomize_str(&mut self.heap, "constructor");
+        let attrs = PropertyAttributes::WRITABLE.with(PropertyAttributes::CONFIGURABLE);
+        object::define_own_property(&mut self.heap, prototype, constructor_key,
+                                    Property::Data { value: func, attrs });
+        let prototype_key = string::atomize_str(&mut self.heap, "prototype");
+        object::define_own_property(&mut self.heap, func, prototype_key,
+                                    Property::Data { value: prototype, attrs: PropertyAttributes::WRITABLE });
+        self.define_function_length(func, length);
+        func
+    }
+
+    pub fn define_function_length(&mut self, func: Value, length: u32) {
+        let property = Property::Data { value: array::length_value(length), attrs: PropertyAttributes::NONE };
+        object::define_own_property(&mut self.heap, func, array::length_key(), property);
+    }
+
+    /**
+     * An arguments object, holding all the arguments of a call.  Non-strict
+     * functions can also find themselves as its `callee`.
+     */
+    fn new_arguments_object(&mut self, callee: Value, args: &[Value], strict: bool) -> Value {
+        let arguments = object::new_object(&mut self.heap, self.object_prototype.get());
+        for (idx, &arg) in args.iter().enumerate() {
+            let key = string::atomize_str(&mut self.heap, &idx.to_string());
+            object::set_own_value(&mut self.heap, arguments, key, arg);
+        }
+        let attrs = PropertyAttributes::WRITABLE.with(PropertyAttributes::CONFIGURABLE);
+        let length = Property::Data { value: array::length_value(args.len() as u32), attrs };
+        object::define_own_property(&mut self.heap, arguments, array::length_key(), length);
+        if ! strict {
+            let callee_key = string::atomize_str(&mut self.heap, "callee");
+            object::define_own_property(&mut self.heap, arguments, callee_key,
+                                        Property::Data { value: callee, attrs });
+        }
+        arguments
+    }
+
+    fn load_code(&mut self, code: &Rc<FunctionCode>) -> Rc<LoadedCode> {
+        let key = &**code as *const FunctionCode;
+        if let Some(loaded) = self.loaded_code.get(&key) {
+            return loaded.clone();
+        }
+
+        let constants = self.heap.root_vec(Vec::with_capacity(code.constants().len()));
+        for constant in code.constants() {
+            let value = match *constant {
+                Constant::Number(fval) => Value::new_number(&mut self.heap, fval),
+                Constant::String(ref units) => string::atomize_units(&mut self.heap, units),
+                // Functions are instantiated by each Closure.
+                Constant::Function(_) => Value::new_imm_undef()
+            };
+            constants.push(value);
+        }
+        let inline_caches = vec![InlineCache::Uninitialized; code.inline_cache_count() as usize];
+        let loaded = Rc::new(LoadedCode {
+            code: code.clone(),
+            constants,
+            inline_caches: RefCell::new(inline_caches)
+        });
+        self.loaded_code.insert(key, loaded.clone());
+        loaded
+    }
+
+    // Run frames until the frame at `entry_depth` returns, or throws an
+    // exception none of the frames handles.
+    fn execute(&mut self, entry_depth: usize) -> VmResult<Value> {
+        loop {
+            match self.run_frame(entry_depth) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(mut exception) => {
+                    if ! self.unwind(entry_depth, &mut exception) {
+                        return Err(exception);
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Pop frames down to `entry_depth` until one has a handler covering where
+     * it stopped, and enter the handler with the thrown value.  The popped
+     * frames are added to the exception's stack trace.  Returns false if none
+     * of the frames handles it.
+     */
+    fn unwind(&mut self, entry_depth: usize, exception: &mut Exception) -> bool {
+        while self.frames.len() > entry_depth {
+            let (target, base, register_count) = {
+                let frame = self.frames.last().unwrap();
+                let code = &frame.code.code;
+                // A frame's pc is past the instruction it stopped at.
+                (code.handler_for(frame.pc - 1), frame.base, code.register_count() as usize)
+            };
+            if let Some(target) = target {
+                self.stack.values().borrow_mut().truncate(base + register_count + 1);
+                self.push(exception.value);
+                self.frames.last_mut().unwrap().pc = target;
+                return true;
+            }
+            let frame = self.frames.pop().unwrap();
+            self.stack.values().borrow_mut().truncate(base - 2);
+            exception.stack_trace.push(stack_frame(&frame));
+        }
+        false
+    }
+
+    // The script frames of the stack, innermost first.
+    pub fn stack_trace(&self) -> Vec<StackFrame> {
+        self.frames.iter().rev().map(stack_frame).collect()
+    }
+
+    // Run the top frame until it calls, returns or throws.  Returns the result
+    // of the frame at `entry_depth`, once it has returned.
+    fn run_frame(&mut self, entry_depth: usize) -> VmResult<Option<Value>> {
+        let frame_index = self.frames.len() - 1;
+        let (loaded, base, mut pc) = {
+            let frame = &self.frames[frame_index];
+            (frame.code.clone(), frame.base, frame.pc)
+        };
+        let code = &loaded.code;
+        let constants = &loaded.constants;
+        let inline_caches = &loaded.inline_caches;
+        let bytes = code.code();
+        let env_index = base + code.register_count() as usize;
+
+        loop {
+            let op = Op::from_u8(bytes[pc]).expect("valid opcode");
+            let operand_pc = pc + 1;
+            pc += op.length();
+            self.frames[frame_index].pc = pc;
+
+            match op {
+                Op::Undefined => self.push(Value::new_imm_undef()),
+                Op::Null => self.push(Value::new_imm_null()),
+                Op::True => self.push(Value::new_imm_bool(true)),
+                Op::False => self.push(Value::new_imm_bool(false)),
+                Op::Int8 => {
+                    self.push(Value::new_imm_i32(bytecode::read_i8(bytes, operand_pc) as i32));
+                }
+                Op::Int32 => {
+                    self.push(Value::new_imm_i32(bytecode::read_i32(bytes, operand_pc)));
+                }
+                Op::Const => {
+                    self.push(constants.get(bytecode::read_u16(bytes, operand_pc) as usize));
+                }
+                Op::This => self.push(self.stack_get(base - 1)),
+                Op::Callee => self.push(self.stack_get(base - 2)),
+
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Dup => self.push(self.peek(0)),
+                Op::Dup2 => {
+                    let (a, b) = (self.peek(1), self.peek(0));
+                    self.push(a);
+                    self.push(b);
+                }
+                Op::Swap => {
+                    let (a, b) = (self.pop(), self.pop());
+                    self.push(a);
+                    self.push(b);
+                }
+
+                Op::GetLocal => {
+                    let register = bytecode::read_u16(bytes, operand_pc) as usize;
+                    self.push(self.stack_get(base + register));
+                }
+                Op::SetLocal => {
+                    let register = bytecode::read_u16(bytes, operand_pc) as usize;
+                    self.stack_set(base + register, self.peek(0));
+                }
+                Op::GetEnv => {
+                    let hops = bytecode::read_u8(bytes, operand_pc);
+                    let slot = bytecode::read_u16(bytes, operand_pc + 1) as usize;
+                    let env = self.env_at(self.stack_get(env_index), hops);
+                    self.push(self.heap.get::<SlotsCell>(env).unwrap().slots()[slot]);
+                }
+                Op::SetEnv => {
+                    let hops = bytecode::read_u8(bytes, operand_pc);
+                    let slot = bytecode::read_u16(bytes, operand_pc + 1) as usize;
+                    let env = self.env_at(self.stack_get(env_index), hops);
+                    let value = self.peek(0);
+                    self.heap.get_mut::<SlotsCell>(env).unwrap().slots_mut()[slot] = value;
+                }
+                Op::GetGlobal => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let value = self.get_global(name)?;
+                    self.push(value);
+                }
+                Op::SetGlobal => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    self.set_global(name, self.peek(0), code.is_strict())?;
+                }
+                Op::DeclareGlobal => {
+                    // Declared globals can't be deleted.
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let global = self.global_object.get();
+                    if object::get_own_property(&mut self.heap, global, name).is_none() {
+                        let attrs = PropertyAttributes::WRITABLE.with(PropertyAttributes::ENUMERABLE);
+                        let property = Property::Data { value: Value::new_imm_undef(), attrs };
+                        object::define_own_property(&mut self.heap, global, name, property);
+                    }
+                }
+                Op::DeleteGlobal => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let global = self.global_object.get();
+                    let deleted = self.delete_property(global, name, code.is_strict())?;
+                    self.push(Value::new_imm_bool(deleted));
+                }
+                Op::TypeOfGlobal => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let global = self.global_object.get();
+                    let type_name = if object::has_property(&self.heap, global, name) {
+                        self.get_property(global, name)?.type_name(&self.heap)
+                    } else {
+                        "undefined"
+                    };
+                    let type_name = string::atomize_str(&mut self.heap, type_name);
+                    self.push(type_name);
+                }
+                Op::CreateEnv => {
+                    let size = bytecode::read_u16(bytes, operand_pc) as usize;
+                    let mut cell = SlotsCell::new(size, Value::new_imm_undef());
+                    cell.slots_mut()[0] = self.stack_get(env_index);
+                    let env = self.heap.alloc(cell);
+                    self.stack_set(env_index, env);
+                }
+                Op::MapArgument => {
+                    let index = bytecode::read_u8(bytes, operand_pc);
+                    let slot = bytecode::read_u16(bytes, operand_pc + 1);
+                    let arguments = self.peek(0);
+                    let key = string::atomize_str(&mut self.heap, &index.to_string());
+                    if object::has_own_property(&self.heap, arguments, key) {
+                        let env = self.stack_get(env_index);
+                        object::map_argument(&mut self.heap, arguments, index as u32, env, slot);
+                    }
+                }
+
+                Op::GetProp => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let cache = bytecode::read_u16(bytes, operand_pc + 2) as usize;
+                    let object = self.peek(0);
+                    let value = self.get_property_cached(inline_caches, cache, object, name)?;
+                    self.pop();
+                    self.push(value);
+                }
+                Op::SetProp => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let cache = bytecode::read_u16(bytes, operand_pc + 2) as usize;
+                    let (object, value) = (self.peek(1), self.peek(0));
+                    self.set_property_cached(inline_caches, cache, object, name, value, code.is_strict())?;
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                Op::DeleteProp => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let object = self.peek(0);
+                    let deleted = self.delete_property(object, name, code.is_strict())?;
+                    self.pop();
+                    self.push(Value::new_imm_bool(deleted));
+                }
+                Op::GetElem => {
+                    let cache = bytecode::read_u16(bytes, operand_pc) as usize;
+                    let (object, key) = (self.peek(1), self.peek(0));
+                    let value = match self.get_element_fast(object, key) {
+                        Some(value) => value,
+                        None => {
+                            let key = self.property_key(key)?;
+                            self.get_property_cached(inline_caches, cache, object, key)?
+                        }
+                    };
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                Op::SetElem => {
+                    let cache = bytecode::read_u16(bytes, operand_pc) as usize;
+                    let (object, key, value) = (self.peek(2), self.peek(1), self.peek(0));
+                    if ! self.set_element_fast(object, key, value) {
+                        let key = self.property_key(key)?;
+                        self.set_property_cached(inline_caches, cache, object, key, value, code.is_strict())?;
+                    }
+                    self.pop();
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                Op::DeleteElem => {
+                    let (object, key) = (self.peek(1), self.peek(0));
+                    let key = self.property_key(key)?;
+                    let deleted = self.delete_property(object, key, code.is_strict())?;
+                    self.pop();
+                    self.pop();
+                    self.push(Value::new_imm_bool(deleted));
+                }
+
+                Op::NewObject => {
+                    let object = object::new_object(&mut self.heap, self.object_prototype.get());
+                    self.push(object);
+                }
+                Op::InitProp => {
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let value = self.pop();
+                    let object = self.peek(0);
+                    let property = Property::Data { value, attrs: PropertyAttributes::DEFAULT };
+                    object::define_own_property(&mut self.heap, object, name, property);
+                }
+                Op::InitGetter | Op::InitSetter => {
+                    // The other half of an existing accessor is kept.
+                    let name = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let func = self.pop();
+                    let object = self.peek(0);
+                    let (mut getter, mut setter) = match object::get_own_property(&mut self.heap, object, name) {
+                        Some(Property::Accessor { getter, setter, .. }) => (getter, setter),
+                        _ => (Value::new_imm_undef(), Value::new_imm_undef())
+                    };
+                    if op == Op::InitGetter {
+                        getter = func;
+                    } else {
+                        setter = func;
+                    }
+                    let attrs = PropertyAttributes::ENUMERABLE.with(PropertyAttributes::CONFIGURABLE);
+                    let property = Property::Accessor { getter, setter, attrs };
+                    object::define_own_property(&mut self.heap, object, name, property);
+                }
+
+                Op::NewArray => {
+                    let array = array::new_array(&mut self.heap, self.array_prototype.get());
+                    self.push(array);
+                }
+                Op::ArrayPush => {
+                    let value = self.pop();
+                    let array = self.peek(0);
+                    self.heap.get_mut::<JsObject>(array).unwrap().elements_mut().unwrap().push(value);
+                }
+                Op::ArrayPushHole => {
+                    let array = self.peek(0);
+                    self.heap.get_mut::<JsObject>(array).unwrap().elements_mut().unwrap().push_hole();
+                }
+
+                Op::NewRegExp => {
+                    let pattern = constants.get(bytecode::read_u16(bytes, operand_pc) as usize);
+                    let flags = constants.get(bytecode::read_u16(bytes, operand_pc + 2) as usize);
+                    let regexp = builtins::regexp::new_regexp(self, pattern, flags)?;
+                    self.push(regexp);
+                }
+
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::Shl | Op::Sar | Op::Shr |
+                Op::BitAnd | Op::BitOr | Op::BitXor | Op::Lt | Op::Gt | Op::Le | Op::Ge |
+                Op::Eq | Op::Ne | Op::StrictEq | Op::StrictNe | Op::InstanceOf | Op::In => {
+                    let (left, right) = (self.peek(1), self.peek(0));
+                    let result = self.binary_op(op, left, right)?;
+                    self.pop();
+                    self.pop();
+                    self.push(result);
+                }
+                Op::Pos => {
+                    let value = self.pop();
+                    let result = if value.is_imm_i32() {
+                        value
+                    } else {
+                        let fval = self.number_value(value)?;
+                        Value::new_number(&mut self.heap, fval)
+                    };
+                    self.push(result);
+                }
+                Op::Neg => {
+                    let value = self.pop();
+                    let result = match value.get_imm_i32() {
+                        Some(ival) if ival != 0 && ival != i32::MIN => Value::new_imm_i32(-ival),
+                        _ => {
+                            let fval = self.number_value(value)?;
+                            Value::new_number(&mut self.heap, -fval)
+                        }
+                    };
+                    self.push(result);
+                }
+                Op::Not => {
+                    let value = self.pop();
+                    self.push(Value::new_imm_bool(! value.to_boolean(&self.heap)));
+                }
+                Op::BitNot => {
+                    let value = self.pop();
+                    let ival = self.int32_value(value)?;
+                    self.push(Value::new_imm_i32(! ival));
+                }
+                Op::TypeOf => {
+                    let value = self.pop();
+                    let type_name = value.type_name(&self.heap);
+                    let type_name = string::atomize_str(&mut self.heap, type_name);
+                    self.push(type_name);
+                }
+
+                Op::Jump | Op::JumpIfTrue | Op::JumpIfFalse |
+                Op::JumpIfTrueKeep | Op::JumpIfFalseKeep => {
+                    let offset = bytecode::read_i32(bytes, operand_pc);
+                    let taken = match op {
+                        Op::Jump => true,
+                        Op::JumpIfTrue | Op::JumpIfFalse => {
+                            let value = self.pop();
+                            value.to_boolean(&self.heap) == (op == Op::JumpIfTrue)
+                        }
+                        _ => {
+                            let value = self.peek(0);
+                            let taken = value.to_boolean(&self.heap) == (op == Op::JumpIfTrueKeep);
+                            if ! taken {
+                                self.pop();
+                            }
+                            taken
+                        }
+                    };
+                    if taken {
+                        pc = ((pc as isize) + (offset as isize)) as usize;
+                        if offset < 0 {
+                            self.heap.maybe_collect();
+                        }
+                    }
+                }
+
+                Op::ForInKeys => {
+                    let value = self.pop();
+                    let state = self.for_in_state(value);
+                    self.push(state);
+                }
+                Op::ForInNext => {
+                    let state = self.pop();
+                    match self.for_in_next(state) {
+                        Some(key) => {
+                            self.push(key);
+                            self.push(Value::new_imm_bool(true));
+                        }
+                        None => self.push(Value::new_imm_bool(false))
+                    }
+                }
+
+                Op::Closure => {
+                    let idx = bytecode::read_u16(bytes, operand_pc);
+                    let func_code = match *code.constant(idx) {
+                        Constant::Function(ref func_code) => func_code.clone(),
+                        _ => panic!("Closure of a non-function constant")
+                    };
+                    let env = self.stack_get(env_index);
+                    let func = self.new_script_function(func_code, env);
+                    self.push(func);
+                }
+                Op::Call | Op::New => {
+                    // New inserts the this value after the callee.
+                    let argc = bytecode::read_u8(bytes, operand_pc) as usize;
+                    let construct = op == Op::New;
+                    let callee_index = if construct {
+                        let callee_index = self.stack.len() - argc - 1;
+                        let this = self.construct_this(self.stack_get(callee_index))?;
+                        self.stack.values().borrow_mut().insert(callee_index + 1, this);
+                        callee_index
+                    } else {
+                        self.stack.len() - argc - 2
+                    };
+                    match self.enter_call(callee_index, argc, construct)? {
+                        Some(value) => self.push(value),
+                        None => return Ok(None)
+                    }
+                }
+                Op::Return => {
+                    let mut value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    if frame.construct && ! object::is_object(&self.heap, value) {
+                        value = self.stack_get(frame.base - 1);
+                    }
+                    self.stack.values().borrow_mut().truncate(frame.base - 2);
+                    if self.frames.len() == entry_depth {
+                        return Ok(Some(value));
+                    }
+                    self.push(value);
+                    return Ok(None);
+                }
+
+                Op::Throw => {
+                    let value = self.pop();
+                    return Err(Exception::new(value));
+                }
+                Op::Gosub => {
+                    let offset = bytecode::read_i32(bytes, operand_pc);
+                    self.push(Value::new_imm_i32(pc as i32));
+                    pc = ((pc as isize) + (offset as isize)) as usize;
+                }
+                Op::Ret => {
+                    let register = bytecode::read_u16(bytes, operand_pc) as usize;
+                    pc = self.stack_get(base + register).get_imm_i32().expect("return address") as usize;
+                }
+
+                Op::StoreCompletion => {
+                    let value = self.pop();
+                    self.completion.set(value);
+                }
+                Op::LoadCompletion => self.push(self.completion.get())
+            }
+        }
+    }
+
+    fn env_at(&self, mut env: Value, hops: u8) -> Value {
+        for _ in 0..hops {
+            env = self.heap.get::<SlotsCell>(env).unwrap().slots()[0];
+        }
+        env
+    }
+
+    /*************************************************************************
+     **** Globals and properties *********************************************
+     *************************************************************************/
+
+    fn get_global(&mut self, name: Value) -> VmResult<Value> {
+        let global = self.global_object.get();
+        if ! object::has_property(&self.heap, global, name) {
+            let message = format!("{} is not defined", self.display_string(name));
+            return Err(self.error(ErrorKind::ReferenceError, &message));
+        }
+        self.get_property(global, name)
+    }
+
+    // Assigning an undeclared global creates it, except in strict code.
+    fn set_global(&mut self, name: Value, value: Value, strict: bool) -> VmResult<()> {
+        let global = self.global_object.get();
+        if strict && ! object::has_property(&self.heap, global, name) {
+            let message = format!("{} is not defined", self.display_string(name));
+            return Err(self.error(ErrorKind::ReferenceError, &message));
+        }
+        self.set_property(global, name, value, strict)
+    }
+
+    fn check_object_coercible(&mut self, object: Value, key: Value) -> VmResult<()> {
+        if object.is_imm_undef_null() {
+            let message = format!("Cannot access property '{}' of {}",
+                                  self.display_string(key), self.display_string(object));
+            return Err(self.error(ErrorKind::TypeError, &message));
+        }
+        Ok(())
+    }
+
+    // Failed assignments and deletions are silent, except in strict code.
+    fn reject(&mut self, strict: bool, message: &str) -> VmResult<()> {
+        if strict {
+            return Err(self.error(ErrorKind::TypeError, message));
+        }
+        Ok(())
+    }
+
+    // Primitives have no properties of their own, except for the length and
+    // indexed characters of strings.  Strings are flattened to index them
+    // directly.
+    fn primitive_own_property(&mut self, primitive: Value, key: Value) -> Option<Value> {
+        let length = string::string_length(&self.heap, primitive)?;
+        if key == string::atomize_str(&mut self.heap, "length") {
+            return Some(Value::new_imm_i32(length as i32));
+        }
+        match object::array_index(&self.heap, key) {
+            Some(index) if index < length => {
+                let primitive = string::flatten(&mut self.heap, primitive);
+                let unit = string::code_unit_at(&self.heap, primitive, index).unwrap();
+                Some(string::new_string_from_units(&mut self.heap, &[unit]))
+            }
+            _ => None
+        }
+    }
+
+    /**
+     * The state of a for-in loop over a value's enumerable keys: the object
+     * the keys are checked against as the loop goes, the index of the next
+     * key, and the keys themselves.  A primitive's keys are those of its
+     * indexed characters, then its prototype's, which are never checked.
+     */
+    fn for_in_state(&mut self, value: Value) -> Value {
+        let (object, keys) = if object::is_object(&self.heap, value) {
+            (value, object::enumerable_keys(&mut self.heap, value))
+        } else {
+            let length = string::string_length(&self.heap, value).unwrap_or(0);
+            let mut keys: Vec<Value> = (0..length).map(|index| {
+                string::atomize_str(&mut self.heap, &index.to_string())
+            }).collect();
+            if let Some(prototype) = self.primitive_prototype(value) {
+                keys.extend(object::enumerable_keys(&mut self.heap, prototype));
+            }
+            (Value::new_imm_null(), keys)
+        };
+        let mut cell = SlotsCell::new(FOR_IN_FIRST_KEY + keys.len(), Value::new_imm_undef());
+        cell.slots_mut()[0] = object;
+        cell.slots_mut()[1] = Value::new_imm_i32(FOR_IN_FIRST_KEY as i32);
+        cell.slots_mut()[FOR_IN_FIRST_KEY..].copy_from_slice(&keys);
+        self.heap.alloc(cell)
+    }
+
+    // Advance a for-in loop to its next key still present on the object.
+    fn for_in_next(&mut self, state: Value) -> Option<Value> {
+        let (next, index) = {
+            let slots = self.heap.get::<SlotsCell>(state).unwrap().slots();
+            let object = slots[0];
+            let mut index = slots[1].get_imm_i32().unwrap() as usize;
+            let mut next = None;
+            while index < slots.len() {
+                let key = slots[index];
+                index += 1;
+                if object.is_imm_null() || object::has_property(&self.heap, object, key) {
+                    next = Some(key);
+                    break;
+                }
+            }
+            (next, index)
+        };
+        self.heap.get_mut::<SlotsCell>(state).unwrap().slots_mut()[1] = Value::new_imm_i32(index as i32);
+        next
+    }
+
+    // The object whose properties a primitive inherits, if it has one.
+    fn primitive_prototype(&self, primitive: Value) -> Option<Value> {
+        if string::is_string(&self.heap, primitive) {
+            return Some(self.string_prototype.get());
+        }
+        if primitive.is_number(&self.heap) {
+            return Some(self.number_prototype.get());
+        }
+        None
+    }
+
+    /**
+     * ES5 ToObject: primitives are wrapped in a Boolean, Number or String
+     * object.  Undefined and null throw a TypeError.
+     */
+    pub fn to_object(&mut self, value: Value) -> VmResult<Value> {
+        if object::is_object(&self.heap, value) {
+            return Ok(value);
+        }
+        if value.is_imm_undef_null() {
+            let message = format!("Cannot convert {} to object", self.display_string(value));
+            return Err(self.error(ErrorKind::TypeError, &message));
+        }
+        let prototype = self.primitive_prototype(value).unwrap_or_else(|| self.object_prototype.get());
+        Ok(object::new_wrapper(&mut self.heap, prototype, value))
+    }
+
+    /**
+     * Get a property of any value but undefined and null.  Primitives'
+     * properties are looked up on their prototype, and accessors found there
+     * are called with the primitive itself as their this value.
+     */
+    pub fn get_property(&mut self, object: Value, key: Value) -> VmResult<Value> {
+        self.check_object_coercible(object, key)?;
+        let mut holder = object;
+        if ! object::is_object(&self.heap, object) {
+            if let Some(value) = self.primitive_own_property(object, key) {
+                return Ok(value);
+            }
+            match self.primitive_prototype(object) {
+                Some(prototype) => holder = prototype,
+                None => return Ok(Value::new_imm_undef())
+            }
+        }
+        match object::lookup_property(&mut self.heap, holder, key) {
+            Some((_, Property::Data { value, .. })) => Ok(value),
+            Some((_, Property::Accessor { getter, .. })) if ! getter.is_imm_undef() => {
+                self.call(getter, object, &[])
+            }
+            _ => Ok(Value::new_imm_undef())
+        }
+    }
+
+    pub fn set_property(&mut self, object: Value, key: Value, value: Value, strict: bool) -> VmResult<()> {
+        self.check_object_coercible(object, key)?;
+        if ! object::is_object(&self.heap, object) {
+            let message = format!("Cannot create property '{}' on {} '{}'", self.display_string(key),
+                                  object.type_name(&self.heap), self.display_string(object));
+            return self.reject(strict, &message);
+        }
+        if (key == array::length_key()) && array::is_array(&self.heap, object) {
+            return self.set_array_length(object, value, strict);
+        }
+
+        match object::lookup_property(&mut self.heap, object, key) {
+            Some((_, Property::Accessor { setter, .. })) => {
+                if setter.is_imm_undef() {
+                    let message = format!("Cannot set property '{}' which has only a getter",
+                                          self.display_string(key));
+                    return self.reject(strict, &message);
+                }
+                self.call(setter, object, &[value])?;
+                Ok(())
+            }
+            Some((_, Property::Data { attrs, .. })) if ! attrs.is_writable() => {
+                let message = format!("Cannot assign to read only property '{}'", self.display_string(key));
+                self.reject(strict, &message)
+            }
+            Some((holder, _)) if holder == object => {
+                object::set_own_value(&mut self.heap, object, key, value);
+                Ok(())
+            }
+            _ => {
+                // Inherited writable properties are shadowed by a new own property.
+                if ! self.heap.get::<JsObject>(object).unwrap().is_extensible() {
+                    let message = format!("Cannot add property '{}', object is not extensible",
+                                          self.display_string(key));
+                    return self.reject(strict, &message);
+                }
+                // Indices past an array's length can't be added once the
+                // length is fixed.
+                if let Some(elements) = self.heap.get::<JsObject>(object).unwrap().elements() {
+                    let index = object::array_index(&self.heap, key);
+                    if ! elements.is_length_writable() && index.is_some_and(|index| index >= elements.length()) {
+                        let message = format!("Cannot add property '{}', length is read-only", self.display_string(key));
+                        return self.reject(strict, &message);
+                    }
+                }
+                object::set_own_value(&mut self.heap, object, key, value);
+                Ok(())
+            }
+        }
+    }
+
+    // Setting an array's length checks that it's valid, and truncates the
+    // array.
+    fn set_array_length(&mut self, array: Value, value: Value, strict: bool) -> VmResult<()> {
+        let length = self.number_value(value)?;
+        if ! array::is_valid_length(length) {
+            return Err(self.error(ErrorKind::RangeError, "Invalid array length"));
+        }
+        let elements = self.heap.get::<JsObject>(array).unwrap().elements().unwrap();
+        if ! elements.is_length_writable() {
+            return self.reject(strict, "Cannot assign to read only property 'length'");
+        }
+        if ! object::set_array_length(&mut self.heap, array, length as u32) {
+            let last = self.heap.get::<JsObject>(array).unwrap().elements().unwrap().length() - 1;
+            let message = format!("Cannot delete property '{}'", last);
+            return self.reject(strict, &message);
+        }
+        Ok(())
+    }
+
+    // Get a present element of an array, for a non-negative int32 key.
+    fn get_element_fast(&self, object: Value, key: Value) -> Option<Value> {
+        let index = key.get_imm_i32().filter(|&index| index >= 0)?;
+        self.heap.get::<JsObject>(object)?.elements()?.get(index as u32)
+    }
+
+    // Set an element of an array, for a non-negative int32 key, if it's
+    // present or can be added without finding an indexed property on the
+    // prototype chain.  Returns false if the set needs the generic path.
+    pub fn set_element_fast(&mut self, object: Value, key: Value, value: Value) -> bool {
+        let index = match key.get_imm_i32() {
+            Some(index) if index >= 0 => index as u32,
+            _ => return false
+        };
+        let can_add = match self.heap.get::<JsObject>(object) {
+            Some(obj) => match obj.elements() {
+                Some(elements) if elements.has(index) => true,
+                Some(elements) => {
+                    obj.is_extensible() &&
+                        ((index < elements.length()) || elements.is_length_writable()) &&
+                        ! array::chain_has_indexed_properties(&self.heap, object)
+                }
+                None => false
+            },
+            None => false
+        };
+        if can_add {
+            self.heap.get_mut::<JsObject>(object).unwrap().elements_mut().unwrap().set(index, value);
+        }
+        can_add
+    }
+
+    fn get_property_cached(&mut self, inline_caches: &RefCell<Vec<InlineCache>>, cache: usize,
+                           object: Value, key: Value)
+        -> VmResult<Value>
+    {
+        // Arrays' elements, String objects' characters and mapped arguments
+        // aren't in their shapes.
+        if object::is_virtual_key(&self.heap, object, key) {
+            return self.get_property(object, key);
+        }
+        let shape = match self.heap.get::<JsObject>(object) {
+            Some(obj) => {
+                if let Some(entry) = inline_caches.borrow()[cache].lookup(obj.shape(), key) {
+                    self.inline_cache_counters.hits += 1;
+                    return Ok(obj.slot(entry.slot()));
+                }
+                obj.shape().clone()
+            }
+            None => return self.get_property(object, key)
+        };
+        if inline_caches.borrow()[cache].is_megamorphic() {
+            self.inline_cache_counters.megamorphic += 1;
+            return self.get_property(object, key);
+        }
+
+        // The cache is updated before the get, which may run script.
+        // Dictionary shapes change in place, so can't be cached.
+        self.inline_cache_counters.misses += 1;
+        if let Some(entry) = shape.lookup(key).filter(|_| ! shape.is_dictionary()) {
+            if ! entry.attrs.is_accessor() {
+                inline_caches.borrow_mut()[cache].add(CacheEntry::new(shape, key, entry.slot));
+            }
+        }
+        self.get_property(object, key)
+    }
+
+    // Sets which write an own data property, or add one without finding the
+    // key on a prototype, don't run script, so can be cached.
+    fn set_property_cached(&mut self, inline_caches: &RefCell<Vec<InlineCache>>, cache: usize,
+                           object: Value, key: Value, value: Value, strict: bool)
+        -> VmResult<()>
+    {
+        // Arrays' elements, String objects' characters and mapped arguments
+        // aren't in their shapes.
+        if object::is_virtual_key(&self.heap, object, key) {
+            return self.set_property(object, key, value, strict);
+        }
+        let (shape, prototype, extensible) = match self.heap.get::<JsObject>(object) {
+            Some(obj) => (obj.shape().clone(), obj.prototype(), obj.is_extensible()),
+            None => return self.set_property(object, key, value, strict)
+        };
+        let can_add = extensible &&
+            (prototype.is_imm_null() || ! object::has_property(&self.heap, prototype, key));
+
+        let hit = inline_caches.borrow()[cache].lookup(&shape, key).cloned();
+        if let Some(entry) = hit {
+            if entry.transition().is_none() || can_add {
+                self.inline_cache_counters.hits += 1;
+                let obj = self.heap.get_mut::<JsObject>(object).unwrap();
+                match entry.transition() {
+                    Some(transition) => obj.append_slot(transition.clone(), value),
+                    None => obj.set_slot(entry.slot(), value)
+                }
+                return Ok(());
+            }
+        }
+        if inline_caches.borrow()[cache].is_megamorphic() {
+            self.inline_cache_counters.megamorphic += 1;
+            return self.set_property(object, key, value, strict);
+        }
+
+        self.inline_cache_counters.misses += 1;
+        if shape.is_dictionary() {
+            return self.set_property(object, key, value, strict);
+        }
+        match shape.lookup(key) {
+            Some(entry) if entry.attrs.is_writable() && ! entry.attrs.is_accessor() => {
+                inline_caches.borrow_mut()[cache].add(CacheEntry::new(shape, key, entry.slot));
+                self.heap.get_mut::<JsObject>(object).unwrap().set_slot(entry.slot, value);
+                Ok(())
+            }
+            None if can_add => {
+                object::set_own_value(&mut self.heap, object, key, value);
+                let transition = self.heap.get::<JsObject>(object).unwrap().shape().clone();
+                if transition.is_dictionary() {
+                    return Ok(());
+                }
+                let slot = transition.count() - 1;
+                let entry = CacheEntry::new_transition(shape, key, slot, transition);
+                inline_caches.borrow_mut()[cache].add(entry);
+                Ok(())
+            }
+            _ => self.set_property(object, key, value, strict)
+        }
+    }
+
+    pub fn delete_property(&mut self, object: Value, key: Value, strict: bool) -> VmResult<bool> {
+        self.check_object_coercible(object, key)?;
+        let deleted = if object::is_object(&self.heap, object) {
+            object::delete_own_property(&mut self.heap, object, key)
+        } else {
+            self.primitive_own_property(object, key).is_none()
+        };
+        if ! deleted {
+            let message = format!("Cannot delete property '{}'", self.display_string(key));
+            self.reject(strict, &message)?;
+        }
+        Ok(deleted)
+    }
+
+    /*************************************************************************
+     **** Operators **********************************************************
+     *************************************************************************/
+
+    fn binary_op(&mut self, op: Op, left: Value, right: Value) -> VmResult<Value> {
+        // Fast paths for int32 operands.
+        if let (Some(a), Some(b)) = (left.get_imm_i32(), right.get_imm_i32()) {
+            let result = match op {
+                Op::Add => a.checked_add(b),
+                Op::Sub => a.checked_sub(b),
+                // Products of zero with a negative number are -0.
+                Op::Mul => a.checked_mul(b).filter(|&r| r != 0 || (a >= 0 && b >= 0)),
+                Op::Mod if a >= 0 && b > 0 => Some(a % b),
+                Op::Lt => return Ok(Value::new_imm_bool(a < b)),
+                Op::Gt => return Ok(Value::new_imm_bool(a > b)),
+                Op::Le => return Ok(Value::new_imm_bool(a <= b)),
+                Op::Ge => return Ok(Value::new_imm_bool(a >= b)),
+                Op::Eq | Op::StrictEq => return Ok(Value::new_imm_bool(a == b)),
+                Op::Ne | Op::StrictNe => return Ok(Value::new_imm_bool(a != b)),
+                _ => None
+            };
+            if let Some(ival) = result {
+                return Ok(Value::new_imm_i32(ival));
+            }
+        }
+
+        let result = match op {
+            Op::Add => return self.add(left, right),
+            Op::Sub => self.number_value(left)? - self.number_value(right)?,
+            Op::Mul => self.number_value(left)? * self.number_value(right)?,
+            Op::Div => self.number_value(left)? / self.number_value(right)?,
+            Op::Mod => self.number_value(left)? % self.number_value(right)?,
+            Op::Shl => {
+                let ival = self.int32_value(left)?;
+                let shift = self.uint32_value(right)? & 0x1F;
+                return Ok(Value::new_imm_i32(ival.wrapping_shl(shift)));
+            }
+            Op::Sar => {
+                let ival = self.int32_value(left)?;
+                let shift = self.uint32_value(right)? & 0x1F;
+                return Ok(Value::new_imm_i32(ival >> shift));
+            }
+            Op::Shr => {
+                let uval = self.uint32_value(left)?;
+                let shift = self.uint32_value(right)? & 0x1F;
+                (uval >> shift) as f64
+            }
+            Op::BitAnd => {
+                return Ok(Value::new_imm_i32(self.int32_value(left)? & self.int32_value(right)?));
+            }
+            Op::BitOr => {
+                return Ok(Value::new_imm_i32(self.int32_value(left)? | self.int32_value(right)?));
+            }
+            Op::BitXor => {
+                return Ok(Value::new_imm_i32(self.int32_value(left)? ^ self.int32_value(right)?));
+            }
+            // Comparisons with NaN are false, including the negated ones.
+            Op::Lt => return Ok(Value::new_imm_bool(self.less_than(left, right, true)? == Some(true))),
+            Op::Gt => return Ok(Value::new_imm_bool(self.less_than(right, left, false)? == Some(true))),
+            Op::Le => return Ok(Value::new_imm_bool(self.less_than(right, left, false)? == Some(false))),
+            Op::Ge => return Ok(Value::new_imm_bool(self.less_than(left, right, true)? == Some(false))),
+            Op::Eq => return Ok(Value::new_imm_bool(self.loose_equals(left, right)?)),
+            Op::Ne => return Ok(Value::new_imm_bool(! self.loose_equals(left, right)?)),
+            Op::StrictEq => return Ok(Value::new_imm_bool(left.strict_equals(right, &self.heap))),
+            Op::StrictNe => return Ok(Value::new_imm_bool(! left.strict_equals(right, &self.heap))),
+            Op::InstanceOf => {
+                if ! right.is_callable(&self.heap) {
+                    return Err(self.error(ErrorKind::TypeError, "Right-hand side of 'instanceof' is not callable"));
+                }
+                if ! object::is_object(&self.heap, left) {
+                    return Ok(Value::new_imm_bool(false));
+                }
+                let prototype_key = string::atomize_str(&mut self.heap, "prototype");
+                let prototype = self.get_property(right, prototype_key)?;
+                if ! object::is_object(&self.heap, prototype) {
+                    let message = format!("Function has non-object prototype '{}' in instanceof check",
+                                          self.display_string(prototype));
+                    return Err(self.error(ErrorKind::TypeError, &message));
+                }
+                let mut holder = object::get_prototype(&self.heap, left);
+                while ! holder.is_imm_null() {
+                    if holder == prototype {
+                        return Ok(Value::new_imm_bool(true));
+                    }
+                    holder = object::get_prototype(&self.heap, holder);
+                }
+                return Ok(Value::new_imm_bool(false));
+            }
+            Op::In => {
+                if ! object::is_object(&self.heap, right) {
+                    let message = format!("Cannot use 'in' operator to search for '{}' in {}",
+                                          self.display_string(left), self.display_string(right));
+                    return Err(self.error(ErrorKind::TypeError, &message));
+                }
+                let key = self.property_key(left)?;
+                return Ok(Value::new_imm_bool(object::has_property(&self.heap, right, key)));
+            }
+            _ => panic!("Not a binary operator: {:?}", op)
+        };
+        Ok(Value::new_number(&mut self.heap, result))
+    }
+
+    fn add(&mut self, left: Value, right: Value) -> VmResult<Value> {
+        let (left, right) = self.primitive_values(left, right, PreferredType::Default, true)?;
+        if left.is_string(&self.heap) || right.is_string(&self.heap) {
+            let left = left.to_string(&mut self.heap);
+            let right = right.to_string(&mut self.heap);
+            return Ok(string::concat(&mut self.heap, left, right));
+        }
+        let result = left.to_number(&self.heap) + right.to_number(&self.heap);
+        Ok(Value::new_number(&mut self.heap, result))
+    }
+
+    // Compare two values with `<`, giving None when either is NaN.  The
+    // operands are converted in source order, which may be right to left.
+    fn less_than(&mut self, left: Value, right: Value, left_first: bool) -> VmResult<Option<bool>> {
+        let (left, right) = self.primitive_values(left, right, PreferredType::Number, left_first)?;
+        if left.is_string(&self.heap) && right.is_string(&self.heap) {
+            let left_units = string::code_units(&self.heap, left);
+            return Ok(Some(left_units.lt(string::code_units(&self.heap, right))));
+        }
+        let (a, b) = (left.to_number(&self.heap), right.to_number(&self.heap));
+        if a.is_nan() || b.is_nan() {
+            Ok(None)
+        } else {
+            Ok(Some(a < b))
+        }
+    }
+
+    // Compare two values with the semantics of `==`.
+    fn loose_equals(&mut self, left: Value, right: Value) -> VmResult<bool> {
+        let left_type = left.value_type(&self.heap);
+        let right_type = right.value_type(&self.heap);
+        if left_type == right_type {
+            return Ok(left.strict_equals(right, &self.heap));
+        }
+        match (left_type, right_type) {
+            (ValueType::Undefined, ValueType::Null) | (ValueType::Null, ValueType::Undefined) => {
+                Ok(true)
+            }
+            (ValueType::Number, ValueType::String) | (ValueType::String, ValueType::Number) => {
+                Ok(left.to_number(&self.heap) == right.to_number(&self.heap))
+            }
+            (ValueType::Boolean, _) => {
+                let left = Value::new_imm_i32(left.to_number(&self.heap) as i32);
+                self.loose_equals(left, right)
+            }
+            (_, ValueType::Boolean) => {
+                let right = Value::new_imm_i32(right.to_number(&self.heap) as i32);
+                self.loose_equals(left, right)
+            }
+            (ValueType::Number, ValueType::Object) | (ValueType::String, ValueType::Object) => {
+                let right = self.primitive_value(right, PreferredType::Default)?;
+                self.loose_equals(left, right)
+            }
+            (ValueType::Object, ValueType::Number) | (ValueType::Object, ValueType::String) => {
+                let left = self.primitive_value(left, PreferredType::Default)?;
+                self.loose_equals(left, right)
+            }
+            _ => Ok(false)
+        }
+    }
+
+    /*************************************************************************
+     **** Conversions ********************************************************
+     *************************************************************************/
+
+    // Objects are converted by calling their valueOf and toString methods,
+    // in an order which depends on the hint.  Dates take no hint as String.
+    pub fn primitive_value(&mut self, value: Value, hint: PreferredType) -> VmResult<Value> {
+        if ! object::is_object(&self.heap, value) {
+            return Ok(value.to_primitive(&mut self.heap, hint));
+        }
+        let is_date = object::class_name(&self.heap, value) == "Date";
+        let method_names = match hint {
+            PreferredType::Default if is_date => ["toString", "valueOf"],
+            PreferredType::String => ["toString", "valueOf"],
+            PreferredType::Default | PreferredType::Number => ["valueOf", "toString"]
+        };
+        for method_name in &method_names {
+            let key = string::atomize_str(&mut self.heap, method_name);
+            let method = self.get_property(value, key)?;
+            if method.is_callable(&self.heap) {
+                let result = self.call(method, value, &[])?;
+                if result.is_primitive(&self.heap) {
+                    return Ok(result);
+                }
+            }
+        }
+        Err(self.error(ErrorKind::TypeError, "Cannot convert object to primitive value"))
+    }
+
+    // Convert a pair of rooted values to primitives, in either order.  The
+    // first result stays on the stack while the second is converted, as that
+    // may collect.
+    fn primitive_values(&mut self, left: Value, right: Value, hint: PreferredType, left_first: bool)
+        -> VmResult<(Value, Value)>
+    {
+        let (first, second) = if left_first { (left, right) } else { (right, left) };
+        let first = self.primitive_value(first, hint)?;
+        self.push(first);
+        let second = self.primitive_value(second, hint);
+        let first = self.pop();
+        let second = second?;
+        Ok(if left_first { (first, second) } else { (second, first) })
+    }
+
+    pub fn number_value(&mut self, value: Value) -> VmResult<f64> {
+        if let Some(fval) = value.to_f64(&self.heap) {
+            return Ok(fval);
+        }
+        let primitive = self.primitive_value(value, PreferredType::Number)?;
+        Ok(primitive.to_number(&self.heap))
+    }
+
+    pub fn int32_value(&mut self, value: Value) -> VmResult<i32> {
+        if let Some(ival) = value.get_imm_i32() {
+            return Ok(ival);
+        }
+        Ok(f64_to_uint32(self.number_value(value)?) as i32)
+    }
+
+    pub fn uint32_value(&mut self, value: Value) -> VmResult<u32> {
+        if let Some(ival) = value.get_imm_i32() {
+            return Ok(ival as u32);
+        }
+        Ok(f64_to_uint32(self.number_value(value)?))
+    }
+
+    pub fn string_value(&mut self, value: Value) -> VmResult<Value> {
+        let primitive = self.primitive_value(value, PreferredType::String)?;
+        Ok(primitive.to_string(&mut self.heap))
+    }
+
+    pub fn property_key(&mut self, value: Value) -> VmResult<Value> {
+        let primitive = self.primitive_value(value, PreferredType::String)?;
+        Ok(primitive.to_property_key(&mut self.heap))
+    }
+
+    /*************************************************************************
+     **** Errors *************************************************************
+     *************************************************************************/
+
+    // An exception throwing a new error object.
+    pub fn error(&mut self, kind: ErrorKind, message: &str) -> Exception {
+        let message = string::new_string(&mut self.heap, message);
+        Exception::new(error::new_error(self, kind, message))
+    }
+}
+
+fn stack_frame(frame: &Frame) -> StackFrame {
+    let code = &frame.code.code;
+    let function = if code.is_program() {
+        None
+    } else {
+        Some(code.name().unwrap_or("<anonymous>").to_string())
+    };
+    // A frame's pc is past the instruction it's running.
+    StackFrame { function, pc: frame.pc.saturating_sub(1) }
+}
+
+// The function prototype is itself a function, which does nothing.
+fn return_undefined(_interp: &mut Interpreter, _args: &CallArgs) -> VmResult<Value> {
+    Ok(Value::new_imm_undef())
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
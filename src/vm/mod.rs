@@ -1,2 +1,15 @@
-
-pub mod value;
\ No newline at end of file
+pub mod array;
+pub mod builtins;
+pub mod bytecode;
+pub mod conversions;
+pub mod function;
+pub mod heap;
+pub mod inline_cache;
+pub mod interpreter;
+pub mod number_format;
+pub mod object;
+pub mod regexp;
+pub mod shape;
+pub mod string;
+pub mod time;
+pub mod value;
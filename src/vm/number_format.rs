@@ -0,0 +1,360 @@
+/*!
+ * Number formatting, for `Number.prototype`'s `toFixed`, `toExponential`,
+ * `toPrecision` and `toString` with a radix.
+ *
+ * The decimal formats round the exact value of the double, not its shortest
+ * digits, so `(1.005).toFixed(2)` is "1.00": the double is just below 1.005.
+ * Ties between two results are broken upwards, as the spec says.  Exact
+ * values are computed with a small big-integer: every double is an integer
+ * times a power of two, so has a finite decimal expansion.
+ *
+ * Other radixes follow V8's algorithm, which gives just enough fraction
+ * digits to tell the double from its neighbours.
+ *
+ * The functions take finite numbers; callers deal with NaN and the
+ * infinities, which the spec formats as `ToString` does.
+ */
+
+use std::f64;
+use vm::conversions::{number_to_string, shortest_digits};
+
+/** The largest number of digits the decimal formats give. */
+pub const MAX_FRACTION_DIGITS: u32 = 100;
+
+// An unsigned integer of any size, in 32-bit limbs from the least significant.
+struct BigUint {
+    limbs: Vec<u32>
+}
+impl BigUint {
+    fn from_u64(value: u64) -> BigUint {
+        BigUint { limbs: vec![value as u32, (value >> 32) as u32] }
+    }
+
+    fn mul_small(&mut self, factor: u32) {
+        let mut carry: u64 = 0;
+        for limb in self.limbs.iter_mut() {
+            let product = (*limb as u64) * (factor as u64) + carry;
+            *limb = product as u32;
+            carry = product >> 32;
+        }
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    fn mul_pow5(&mut self, mut exponent: u32) {
+        // 5^13 is the largest power of five in a limb.
+        while exponent >= 13 {
+            self.mul_small(1_220_703_125);
+            exponent -= 13;
+        }
+        self.mul_small(5u32.pow(exponent));
+    }
+
+    fn shl(&mut self, bits: u32) {
+        let limb_shift = (bits / 32) as usize;
+        let bit_shift = bits % 32;
+        if bit_shift > 0 {
+            let mut carry = 0;
+            for limb in self.limbs.iter_mut() {
+                let shifted = ((*limb as u64) << bit_shift) | carry;
+                *limb = shifted as u32;
+                carry = shifted >> 32;
+            }
+            if carry > 0 {
+                self.limbs.push(carry as u32);
+            }
+        }
+        let mut limbs = vec![0; limb_shift];
+        limbs.append(&mut self.limbs);
+        self.limbs = limbs;
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&limb| limb == 0)
+    }
+
+    // Divide in place, returning the remainder.
+    fn div_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder: u64 = 0;
+        for limb in self.limbs.iter_mut().rev() {
+            let dividend = (remainder << 32) | (*limb as u64);
+            *limb = (dividend / (divisor as u64)) as u32;
+            remainder = dividend % (divisor as u64);
+        }
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        remainder as u32
+    }
+
+    // The decimal digits, most significant first, with no leading zeros.
+    fn decimal_digits(mut self) -> Vec<u8> {
+        let mut chunks: Vec<u32> = Vec::new();
+        while ! self.is_zero() {
+            chunks.push(self.div_small(1_000_000_000));
+        }
+        let mut digits: Vec<u8> = Vec::with_capacity(chunks.len() * 9);
+        for chunk in chunks.iter().rev() {
+            for power in (0..9).rev() {
+                digits.push(((chunk / 10u32.pow(power)) % 10) as u8);
+            }
+        }
+        let leading_zeros = digits.iter().take_while(|&&digit| digit == 0).count();
+        digits.drain(..leading_zeros);
+        digits
+    }
+}
+
+/**
+ * All the decimal digits of a positive finite number, with no leading or
+ * trailing zeros, and the exponent `n` for which it's `0.digits * 10^n`.
+ */
+fn exact_digits(fval: f64) -> (Vec<u8>, i32) {
+    debug_assert!(fval > 0.0 && fval.is_finite());
+    let bits = fval.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7FF) as i32;
+    let fraction = bits & 0xF_FFFF_FFFF_FFFF;
+    // The number is mantissa * 2^exponent.
+    let (mantissa, exponent) = if biased_exponent == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | (1 << 52), biased_exponent - 1075)
+    };
+
+    let mut value = BigUint::from_u64(mantissa);
+    let point_shift = if exponent >= 0 {
+        value.shl(exponent as u32);
+        0
+    } else {
+        // m * 2^-k is m * 5^k / 10^k.
+        value.mul_pow5((-exponent) as u32);
+        exponent
+    };
+    let mut digits = value.decimal_digits();
+    let n = (digits.len() as i32) + point_shift;
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    (digits, n)
+}
+
+/**
+ * The first `keep` of some digits, rounded half up by the rest, and padded
+ * with zeros to `keep` digits.  A carry out of the first digit gives one more
+ * digit, a leading 1, which is reported.  No digits are kept if `keep` isn't
+ * positive, though they may still round up to a 1.
+ */
+fn round_digits(digits: &[u8], keep: i32) -> (Vec<u8>, bool) {
+    if keep < 0 {
+        return (Vec::new(), false);
+    }
+    let keep = keep as usize;
+    let mut kept: Vec<u8> = digits.iter().cloned().take(keep).collect();
+    kept.resize(keep, 0);
+    if digits.get(keep).is_none_or(|&digit| digit < 5) {
+        return (kept, false);
+    }
+    for digit in kept.iter_mut().rev() {
+        if *digit < 9 {
+            *digit += 1;
+            return (kept, false);
+        }
+        *digit = 0;
+    }
+    kept.insert(0, 1);
+    (kept, true)
+}
+
+fn digits_string(digits: &[u8]) -> String {
+    digits.iter().map(|&digit| (b'0' + digit) as char).collect()
+}
+
+// The digits of a number to `precision` significant digits, and its decimal
+// exponent, as `d.ddd * 10^exponent`.
+fn significant_digits(fval: f64, precision: u32) -> (Vec<u8>, i32) {
+    if fval == 0.0 {
+        return (vec![0; precision as usize], 0);
+    }
+    let (digits, n) = exact_digits(fval);
+    let (mut rounded, carried) = round_digits(&digits, precision as i32);
+    if carried {
+        rounded.pop();
+        return (rounded, n);
+    }
+    (rounded, n - 1)
+}
+
+fn exponent_suffix(exponent: i32) -> String {
+    format!("e{}{}", if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/**
+ * `toFixed`: a number with `fraction_digits` digits after the point.  Numbers
+ * of 10^21 or more are formatted as `ToString` does.
+ */
+pub fn to_fixed(fval: f64, fraction_digits: u32) -> String {
+    debug_assert!(fval.is_finite() && fraction_digits <= MAX_FRACTION_DIGITS);
+    if fval.abs() >= 1e21 {
+        return number_to_string(fval);
+    }
+    let sign = if fval < 0.0 { "-" } else { "" };
+    let magnitude = fval.abs();
+    let mut integer = if magnitude == 0.0 {
+        Vec::new()
+    } else {
+        let (digits, n) = exact_digits(magnitude);
+        round_digits(&digits, n + fraction_digits as i32).0
+    };
+    // The digits of the integer n, for magnitude = n / 10^fraction_digits.
+    let leading_zeros = integer.iter().take_while(|&&digit| digit == 0).count();
+    integer.drain(..leading_zeros);
+    let fraction_digits = fraction_digits as usize;
+    if integer.len() <= fraction_digits {
+        let padding = fraction_digits + 1 - integer.len();
+        integer.splice(0..0, vec![0; padding]);
+    }
+    let point = integer.len() - fraction_digits;
+    if fraction_digits == 0 {
+        return format!("{}{}", sign, digits_string(&integer));
+    }
+    format!("{}{}.{}", sign, digits_string(&integer[..point]), digits_string(&integer[point..]))
+}
+
+/**
+ * `toExponential`: a number as one digit, a point and `fraction_digits` more
+ * digits, then its exponent.  Without a count of digits, as many are given
+ * as are needed to identify the number.
+ */
+pub fn to_exponential(fval: f64, fraction_digits: Option<u32>) -> String {
+    debug_assert!(fval.is_finite());
+    let sign = if fval < 0.0 { "-" } else { "" };
+    let magnitude = fval.abs();
+    let (digits, exponent) = match fraction_digits {
+        Some(fraction_digits) => significant_digits(magnitude, fraction_digits + 1),
+        None if magnitude == 0.0 => (vec![0], 0),
+        None => {
+            let (digits, n) = shortest_digits(magnitude);
+            (digits.bytes().map(|ch| ch - b'0').collect(), n - 1)
+        }
+    };
+    let mantissa = if digits.len() == 1 {
+        digits_string(&digits)
+    } else {
+        format!("{}.{}", digits_string(&digits[..1]), digits_string(&digits[1..]))
+    };
+    format!("{}{}{}", sign, mantissa, exponent_suffix(exponent))
+}
+
+/**
+ * `toPrecision`: a number to `precision` significant digits, in fixed
+ * notation unless its exponent is below -6, or too large for the digits.
+ */
+pub fn to_precision(fval: f64, precision: u32) -> String {
+    debug_assert!(fval.is_finite() && (1..=MAX_FRACTION_DIGITS).contains(&precision));
+    let sign = if fval < 0.0 { "-" } else { "" };
+    let (digits, exponent) = significant_digits(fval.abs(), precision);
+    let precision = precision as i32;
+    let text = if exponent < -6 || exponent >= precision {
+        let mantissa = if precision == 1 {
+            digits_string(&digits)
+        } else {
+            format!("{}.{}", digits_string(&digits[..1]), digits_string(&digits[1..]))
+        };
+        format!("{}{}", mantissa, exponent_suffix(exponent))
+    } else if exponent == precision - 1 {
+        digits_string(&digits)
+    } else if exponent >= 0 {
+        let point = (exponent + 1) as usize;
+        format!("{}.{}", digits_string(&digits[..point]), digits_string(&digits[point..]))
+    } else {
+        format!("0.{}{}", "0".repeat((-(exponent + 1)) as usize), digits_string(&digits))
+    };
+    format!("{}{}", sign, text)
+}
+
+// The exponent of a double as an integer times a power of two.
+fn binary_exponent(fval: f64) -> i32 {
+    let biased_exponent = ((fval.to_bits() >> 52) & 0x7FF) as i32;
+    if biased_exponent == 0 { -1074 } else { biased_exponent - 1075 }
+}
+
+// The next double up from a non-negative finite one.
+fn next_double(fval: f64) -> f64 {
+    f64::from_bits(fval.to_bits() + 1)
+}
+
+/**
+ * `toString` with a radix other than 10.  Fraction digits are given until
+ * they identify the number among its neighbours, with the last rounded half
+ * to even; integer digits below the double's precision are zeros.
+ */
+pub fn to_radix_string(fval: f64, radix: u32) -> String {
+    debug_assert!(fval.is_finite() && (2..=36).contains(&radix));
+    const CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let radix_f = radix as f64;
+    let negative = fval < 0.0;
+    let value = fval.abs();
+
+    let mut integer = value.floor();
+    let mut fraction = value - integer;
+    // Fraction digits are only computed to the precision of the double.
+    let mut delta = (0.5 * (next_double(value) - value)).max(next_double(0.0));
+    let mut fraction_digits: Vec<u32> = Vec::new();
+    if fraction >= delta {
+        loop {
+            fraction *= radix_f;
+            delta *= radix_f;
+            let digit = fraction as u32;
+            fraction_digits.push(digit);
+            fraction -= digit as f64;
+            let round_up = fraction > 0.5 || (fraction == 0.5 && (digit & 1) == 1);
+            if round_up && fraction + delta > 1.0 {
+                // Round up, carrying into earlier digits, and perhaps into
+                // the integer part.
+                loop {
+                    match fraction_digits.pop() {
+                        None => {
+                            integer += 1.0;
+                            break;
+                        }
+                        Some(digit) if digit + 1 < radix => {
+                            fraction_digits.push(digit + 1);
+                            break;
+                        }
+                        Some(_) => {}
+                    }
+                }
+                break;
+            }
+            if fraction < delta {
+                break;
+            }
+        }
+    }
+
+    let mut integer_digits: Vec<u8> = Vec::new();
+    while binary_exponent(integer / radix_f) > 0 {
+        integer /= radix_f;
+        integer_digits.push(b'0');
+    }
+    loop {
+        let remainder = integer % radix_f;
+        integer_digits.push(CHARS[remainder as usize]);
+        integer = (integer - remainder) / radix_f;
+        if integer <= 0.0 {
+            break;
+        }
+    }
+    if negative {
+        integer_digits.push(b'-');
+    }
+    integer_digits.reverse();
+
+    let mut text = String::from_utf8(integer_digits).unwrap();
+    if ! fraction_digits.is_empty() {
+        text.push('.');
+        text.extend(fraction_digits.iter().map(|&digit| CHARS[digit as usize] as char));
+    }
+    text
+}
@@ -0,0 +1,786 @@
+/*!
+ * Objects.
+ *
+ * An object is a heap cell holding its shape, its prototype and the values of
+ * its properties.  The shape maps each property's key to a slot and its
+ * attributes.  The first `INLINE_SLOTS` slots are stored in the cell itself,
+ * and any more in an overflow vector.
+ *
+ * The slot of an accessor property holds an accessor pair cell, with the
+ * getter and setter functions.
+ *
+ * Arrays also have an elements store, holding their indexed properties with
+ * the default attributes, and their length.  The functions here make the
+ * elements and `length` look like any other properties.
+ *
+ * Functions are objects with a `Function`, which says how to call them, and
+ * RegExp objects have their compiled `RegExp`, and Date objects their time
+ * value.  Boolean, Number and String objects wrap a primitive value, and a
+ * String object's length and characters are properties outside its shape.
+ * Other built-in kinds of object are told apart by their `ObjectClass`.
+ *
+ * The arguments object of a non-strict function maps the indices of its
+ * arguments to the function's parameters, which live in its environment.
+ * Getting and setting those properties reads and writes the parameter, until
+ * the index is deleted or redefined.
+ *
+ * The functions here only deal with the properties of objects.  Calling
+ * accessors is left to the interpreter.
+ */
+
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+use vm::array::{self, ArrayElements};
+use vm::conversions::number_to_string;
+use vm::function::Function;
+use vm::heap::{CellKind, CellType, Heap, SlotsCell, Tracer};
+use vm::regexp::RegExp;
+use vm::shape::{PropertyAttributes, Shape};
+use vm::string;
+use vm::value::Value;
+
+pub const INLINE_SLOTS: usize = 4;
+
+/**
+ * The class of an object which isn't an array or a function, as the spec's
+ * [[Class]] names it.  Built-in methods check it to find the objects they
+ * work on.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectClass {
+    Object,
+    Boolean,
+    Date,
+    Error,
+    Json,
+    Math,
+    Number,
+    RegExp,
+    String
+}
+
+/** An object, stored in a heap cell. */
+pub struct JsObject {
+    shape: Rc<Shape>,
+    // The prototype is null or an object.
+    prototype: Value,
+    inline_slots: [Value; INLINE_SLOTS],
+    overflow_slots: Vec<Value>,
+    extensible: bool,
+    class: ObjectClass,
+    // Only arrays have elements, and only functions can be called.
+    elements: Option<Box<ArrayElements>>,
+    function: Option<Box<Function>>,
+    regexp: Option<Rc<RegExp>>,
+    time_value: Option<f64>,
+    primitive_value: Option<Value>,
+    arguments_map: Option<Box<ArgumentsMap>>
+}
+impl JsObject {
+    pub fn new(shape: Rc<Shape>, prototype: Value) -> JsObject {
+        assert!(shape.is_root());
+        JsObject {
+            shape,
+            prototype,
+            inline_slots: [Value::new_imm_undef(); INLINE_SLOTS],
+            overflow_slots: Vec::new(),
+            extensible: true,
+            class: ObjectClass::Object,
+            elements: None,
+            function: None,
+            regexp: None,
+            time_value: None,
+            primitive_value: None,
+            arguments_map: None
+        }
+    }
+    pub fn new_array(shape: Rc<Shape>, prototype: Value) -> JsObject {
+        let mut obj = JsObject::new(shape, prototype);
+        obj.elements = Some(Box::new(ArrayElements::new()));
+        obj
+    }
+    pub fn new_function(shape: Rc<Shape>, prototype: Value, function: Function) -> JsObject {
+        let mut obj = JsObject::new(shape, prototype);
+        obj.function = Some(Box::new(function));
+        obj
+    }
+    pub fn new_regexp(shape: Rc<Shape>, prototype: Value, regexp: Rc<RegExp>) -> JsObject {
+        let mut obj = JsObject::new(shape, prototype);
+        obj.class = ObjectClass::RegExp;
+        obj.regexp = Some(regexp);
+        obj
+    }
+    pub fn new_date(shape: Rc<Shape>, prototype: Value, time_value: f64) -> JsObject {
+        let mut obj = JsObject::new(shape, prototype);
+        obj.class = ObjectClass::Date;
+        obj.time_value = Some(time_value);
+        obj
+    }
+    pub fn new_wrapper(shape: Rc<Shape>, prototype: Value, class: ObjectClass, primitive: Value) -> JsObject {
+        let mut obj = JsObject::new(shape, prototype);
+        obj.class = class;
+        obj.primitive_value = Some(primitive);
+        obj
+    }
+
+    pub fn shape(&self) -> &Rc<Shape> {
+        &self.shape
+    }
+    pub fn prototype(&self) -> Value {
+        self.prototype
+    }
+    pub fn is_extensible(&self) -> bool {
+        self.extensible
+    }
+    pub fn prevent_extensions(&mut self) {
+        self.extensible = false;
+    }
+    pub fn class(&self) -> ObjectClass {
+        self.class
+    }
+    pub fn set_class(&mut self, class: ObjectClass) {
+        self.class = class;
+    }
+    pub fn elements(&self) -> Option<&ArrayElements> {
+        self.elements.as_deref()
+    }
+    pub fn elements_mut(&mut self) -> Option<&mut ArrayElements> {
+        self.elements.as_deref_mut()
+    }
+    pub fn function(&self) -> Option<&Function> {
+        self.function.as_deref()
+    }
+    pub fn regexp(&self) -> Option<&Rc<RegExp>> {
+        self.regexp.as_ref()
+    }
+    // Only Date objects have a time value.
+    pub fn time_value(&self) -> Option<f64> {
+        self.time_value
+    }
+    pub fn set_time_value(&mut self, time_value: f64) {
+        assert!(self.time_value.is_some());
+        self.time_value = Some(time_value);
+    }
+    // Only Boolean, Number and String objects have a primitive value.
+    pub fn primitive_value(&self) -> Option<Value> {
+        self.primitive_value
+    }
+    // The string of a String object.
+    pub fn string_value(&self) -> Option<Value> {
+        self.primitive_value.filter(|_| self.class == ObjectClass::String)
+    }
+
+    // The environment slot an index of an arguments object is mapped to.
+    fn mapped_slot(&self, index: Option<u32>) -> Option<(Value, u16)> {
+        let map = self.arguments_map.as_ref()?;
+        let slot = (*map.slots.get(index? as usize)?)?;
+        Some((map.env, slot))
+    }
+    fn unmap(&mut self, index: Option<u32>) {
+        if let (Some(map), Some(index)) = (self.arguments_map.as_mut(), index) {
+            if let Some(slot) = map.slots.get_mut(index as usize) {
+                *slot = None;
+            }
+        }
+    }
+
+    pub fn slot(&self, slot: u32) -> Value {
+        let slot = slot as usize;
+        if slot < INLINE_SLOTS {
+            self.inline_slots[slot]
+        } else {
+            self.overflow_slots[slot - INLINE_SLOTS]
+        }
+    }
+    pub fn set_slot(&mut self, slot: u32, value: Value) {
+        let slot = slot as usize;
+        if slot < INLINE_SLOTS {
+            self.inline_slots[slot] = value;
+        } else {
+            self.overflow_slots[slot - INLINE_SLOTS] = value;
+        }
+    }
+
+    // Store a value in a slot, making room for it if it's past the last.
+    fn init_slot(&mut self, slot: u32, value: Value) {
+        while (slot as usize) >= INLINE_SLOTS + self.overflow_slots.len() {
+            self.overflow_slots.push(Value::new_imm_undef());
+        }
+        self.set_slot(slot, value);
+    }
+
+    // Change to a tree shape with one more property, whose slot gets the
+    // value.  The shape must be a child of the object's shape.
+    pub fn append_slot(&mut self, shape: Rc<Shape>, value: Value) {
+        debug_assert_eq!(shape.count(), self.shape.count() + 1);
+        let slot = self.shape.count();
+        self.shape = shape;
+        self.init_slot(slot, value);
+    }
+
+    // Add a property, whose slot gets the value.
+    fn add_slot(&mut self, key: Value, attrs: PropertyAttributes, value: Value) {
+        let shape = self.shape.add_property(key, attrs);
+        let slot = shape.lookup(key).unwrap().slot;
+        self.shape = shape;
+        self.init_slot(slot, value);
+    }
+
+    // Remove the property in a slot.  In a tree shape the later slots move
+    // down by one, and in a dictionary the slot is left empty.
+    fn remove_slot(&mut self, key: Value, slot: u32) {
+        let shape = self.shape.remove_property(key);
+        if shape.is_dictionary() {
+            self.set_slot(slot, Value::new_imm_undef());
+            self.shape = shape;
+            return;
+        }
+        let count = self.shape.count();
+        debug_assert_eq!(shape.count() + 1, count);
+        for idx in (slot + 1)..count {
+            let value = self.slot(idx);
+            self.set_slot(idx - 1, value);
+        }
+        if (count as usize) > INLINE_SLOTS {
+            self.overflow_slots.pop();
+        } else {
+            self.inline_slots[(count - 1) as usize] = Value::new_imm_undef();
+        }
+        self.shape = shape;
+    }
+}
+impl CellType for JsObject {
+    const KIND: CellKind = CellKind::Object;
+
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.trace_value(self.prototype);
+        tracer.trace_values(&self.inline_slots);
+        tracer.trace_values(&self.overflow_slots);
+        if let Some(ref elements) = self.elements {
+            elements.trace(tracer);
+        }
+        if let Some(ref function) = self.function {
+            function.trace(tracer);
+        }
+        if let Some(primitive) = self.primitive_value {
+            tracer.trace_value(primitive);
+        }
+        if let Some(ref map) = self.arguments_map {
+            tracer.trace_value(map.env);
+        }
+    }
+
+    fn extra_size(&self) -> usize {
+        let elements_size = self.elements.as_ref().map_or(0, |elements| {
+            mem::size_of::<ArrayElements>() + elements.extra_size()
+        });
+        let function_size = self.function.as_ref().map_or(0, |_| mem::size_of::<Function>());
+        self.overflow_slots.capacity() * mem::size_of::<Value>() + elements_size + function_size
+    }
+}
+
+/**
+ * The parameters the arguments object of a non-strict function aliases: the
+ * environment holding them, and the slot of the parameter each index maps to.
+ */
+struct ArgumentsMap {
+    env: Value,
+    slots: Vec<Option<u16>>
+}
+
+/** The functions of an accessor property.  Missing functions are undefined. */
+pub struct AccessorPair {
+    getter: Value,
+    setter: Value
+}
+impl AccessorPair {
+    pub fn new(getter: Value, setter: Value) -> AccessorPair {
+        AccessorPair { getter, setter }
+    }
+    pub fn getter(&self) -> Value {
+        self.getter
+    }
+    pub fn setter(&self) -> Value {
+        self.setter
+    }
+}
+impl CellType for AccessorPair {
+    const KIND: CellKind = CellKind::Accessor;
+
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.trace_value(self.getter);
+        tracer.trace_value(self.setter);
+    }
+}
+
+/** A property of an object, as a property descriptor describes it. */
+#[derive(Debug, Clone, Copy)]
+pub enum Property {
+    Data { value: Value, attrs: PropertyAttributes },
+    Accessor { getter: Value, setter: Value, attrs: PropertyAttributes }
+}
+impl Property {
+    pub fn attrs(&self) -> PropertyAttributes {
+        match *self {
+            Property::Data { attrs, .. } => attrs,
+            Property::Accessor { attrs, .. } => attrs
+        }
+    }
+}
+
+pub fn new_object(heap: &mut Heap, prototype: Value) -> Value {
+    let shape = heap.root_shape().clone();
+    heap.alloc(JsObject::new(shape, prototype))
+}
+
+pub fn new_date(heap: &mut Heap, prototype: Value, time_value: f64) -> Value {
+    let shape = heap.root_shape().clone();
+    heap.alloc(JsObject::new_date(shape, prototype, time_value))
+}
+
+/**
+ * A Boolean, Number or String object wrapping a primitive.  Boolean objects
+ * are given a prototype too, as there's no `Boolean.prototype`.
+ */
+pub fn new_wrapper(heap: &mut Heap, prototype: Value, primitive: Value) -> Value {
+    let class = if string::is_string(heap, primitive) {
+        ObjectClass::String
+    } else if primitive.is_number(heap) {
+        ObjectClass::Number
+    } else {
+        assert!(primitive.is_imm_bool());
+        ObjectClass::Boolean
+    };
+    let shape = heap.root_shape().clone();
+    heap.alloc(JsObject::new_wrapper(shape, prototype, class, primitive))
+}
+
+/** The primitive a Boolean, Number or String object wraps. */
+pub fn primitive_value(heap: &Heap, value: Value) -> Option<Value> {
+    heap.get::<JsObject>(value).and_then(JsObject::primitive_value)
+}
+
+/**
+ * Map an index of a non-strict function's arguments object to the slot of
+ * its parameter in the function's environment.  The index must be an own
+ * data property of the object.
+ */
+pub fn map_argument(heap: &mut Heap, arguments: Value, index: u32, env: Value, slot: u16) {
+    let obj = heap.get_mut::<JsObject>(arguments).expect("object");
+    let map = obj.arguments_map.get_or_insert_with(|| Box::new(ArgumentsMap { env, slots: Vec::new() }));
+    debug_assert!(map.env == env);
+    if map.slots.len() <= index as usize {
+        map.slots.resize(index as usize + 1, None);
+    }
+    map.slots[index as usize] = Some(slot);
+}
+
+pub fn is_object(heap: &Heap, value: Value) -> bool {
+    heap.get::<JsObject>(value).is_some()
+}
+
+// The name of an object's class, as `Object.prototype.toString` gives it.
+pub fn class_name(heap: &Heap, object: Value) -> &'static str {
+    let obj = heap.get::<JsObject>(object).expect("object");
+    if obj.elements().is_some() {
+        return "Array";
+    }
+    if obj.function().is_some() {
+        return "Function";
+    }
+    match obj.class {
+        ObjectClass::Object => "Object",
+        ObjectClass::Boolean => "Boolean",
+        ObjectClass::Date => "Date",
+        ObjectClass::Error => "Error",
+        ObjectClass::Json => "JSON",
+        ObjectClass::Math => "Math",
+        ObjectClass::Number => "Number",
+        ObjectClass::RegExp => "RegExp",
+        ObjectClass::String => "String"
+    }
+}
+
+pub fn get_prototype(heap: &Heap, object: Value) -> Value {
+    heap.get::<JsObject>(object).expect("object").prototype
+}
+pub fn set_prototype(heap: &mut Heap, object: Value, prototype: Value) {
+    heap.get_mut::<JsObject>(object).expect("object").prototype = prototype;
+}
+
+/**
+ * Keys must be atoms.  The heap is mutable as the characters of String
+ * objects are made as they're asked for.
+ */
+pub fn get_own_property(heap: &mut Heap, object: Value, key: Value) -> Option<Property> {
+    let index = array_index(heap, key);
+    let obj = heap.get::<JsObject>(object).expect("object");
+    if let Some(elements) = obj.elements() {
+        if key == array::length_key() {
+            return Some(length_property(elements));
+        }
+        if let Some(value) = index.and_then(|index| elements.get(index)) {
+            return Some(Property::Data { value, attrs: PropertyAttributes::DEFAULT });
+        }
+    }
+    if let Some(string) = obj.string_value() {
+        if let Some(property) = string_object_property(heap, string, key, index) {
+            return Some(property);
+        }
+    }
+    let obj = heap.get::<JsObject>(object).unwrap();
+    let entry = obj.shape.lookup(key)?;
+    let value = obj.slot(entry.slot);
+    if entry.attrs.is_accessor() {
+        let pair = heap.get::<AccessorPair>(value).unwrap();
+        return Some(Property::Accessor { getter: pair.getter, setter: pair.setter, attrs: entry.attrs });
+    }
+    let value = match obj.mapped_slot(index) {
+        Some((env, slot)) => heap.get::<SlotsCell>(env).unwrap().slots()[slot as usize],
+        None => value
+    };
+    Some(Property::Data { value, attrs: entry.attrs })
+}
+
+// The length and characters of a String object, which are read-only.
+fn string_object_property(heap: &mut Heap, string: Value, key: Value, index: Option<u32>)
+    -> Option<Property>
+{
+    let length = string::string_length(heap, string).unwrap();
+    if key == array::length_key() {
+        return Some(Property::Data { value: array::length_value(length), attrs: PropertyAttributes::NONE });
+    }
+    let index = index.filter(|&index| index < length)?;
+    let string = string::flatten(heap, string);
+    let unit = string::code_unit_at(heap, string, index).unwrap();
+    let value = string::new_string_from_units(heap, &[unit]);
+    Some(Property::Data { value, attrs: PropertyAttributes::ENUMERABLE })
+}
+
+/** Whether an object has an own property, without getting its value. */
+pub fn has_own_property(heap: &Heap, object: Value, key: Value) -> bool {
+    let obj = heap.get::<JsObject>(object).expect("object");
+    if let Some(elements) = obj.elements() {
+        if (key == array::length_key()) || array_index(heap, key).is_some_and(|index| elements.has(index)) {
+            return true;
+        }
+    }
+    is_string_object_key(heap, object, key) || obj.shape.lookup(key).is_some()
+}
+
+/**
+ * Whether a key names a property whose value isn't simply in its slot in the
+ * object's shape: an array's elements and length, a String object's length
+ * and characters, or an index an arguments object maps to a parameter.
+ * Inline caches leave these to the generic paths.
+ */
+pub fn is_virtual_key(heap: &Heap, object: Value, key: Value) -> bool {
+    let obj = match heap.get::<JsObject>(object) {
+        Some(obj) => obj,
+        None => return false
+    };
+    if obj.elements().is_none() && obj.string_value().is_none() && obj.arguments_map.is_none() {
+        return false;
+    }
+    let index = array_index(heap, key);
+    if obj.arguments_map.is_some() {
+        return obj.mapped_slot(index).is_some();
+    }
+    (key == array::length_key()) || index.is_some()
+}
+
+// Find a property on an object or its prototypes, with the object holding it.
+pub fn lookup_property(heap: &mut Heap, object: Value, key: Value) -> Option<(Value, Property)> {
+    let mut holder = object;
+    loop {
+        if let Some(property) = get_own_property(heap, holder, key) {
+            return Some((holder, property));
+        }
+        holder = get_prototype(heap, holder);
+        if holder.is_imm_null() {
+            return None;
+        }
+    }
+}
+
+pub fn has_property(heap: &Heap, object: Value, key: Value) -> bool {
+    let mut holder = object;
+    while ! holder.is_imm_null() {
+        if has_own_property(heap, holder, key) {
+            return true;
+        }
+        holder = get_prototype(heap, holder);
+    }
+    false
+}
+
+fn length_property(elements: &ArrayElements) -> Property {
+    let attrs = if elements.is_length_writable() {
+        PropertyAttributes::WRITABLE
+    } else {
+        PropertyAttributes::NONE
+    };
+    Property::Data { value: array::length_value(elements.length()), attrs }
+}
+
+/**
+ * Create or replace an own property, without checking whether the existing
+ * property allows it.  The accessor attribute of `property`'s attributes is
+ * ignored, as its kind decides it.
+ *
+ * An array's `length` can only be redefined as a data property, with a valid
+ * length, and without truncating the array.
+ */
+pub fn define_own_property(heap: &mut Heap, object: Value, key: Value, property: Property) {
+    debug_assert!(string::is_atom(heap, key));
+    if is_array_element_key(heap, object, key) && define_array_property(heap, object, key, property) {
+        return;
+    }
+    // A String object's length and characters can't change, so can only be
+    // redefined as they are.
+    if is_string_object_key(heap, object, key) {
+        return;
+    }
+    // A mapped argument takes a new value with its parameter, and is no
+    // longer mapped if it becomes an accessor or read-only.
+    let index = array_index(heap, key);
+    let obj = heap.get_mut::<JsObject>(object).expect("object");
+    if let Some((env, slot)) = obj.mapped_slot(index) {
+        match property {
+            Property::Data { attrs, .. } if attrs.is_writable() => {}
+            _ => obj.unmap(index)
+        }
+        if let Property::Data { value, .. } = property {
+            heap.get_mut::<SlotsCell>(env).unwrap().slots_mut()[slot as usize] = value;
+        }
+    }
+    let (slot_value, attrs) = match property {
+        Property::Data { value, attrs } => (value, attrs.without(PropertyAttributes::ACCESSOR)),
+        Property::Accessor { getter, setter, attrs } => {
+            let pair = heap.alloc(AccessorPair::new(getter, setter));
+            (pair, attrs.with(PropertyAttributes::ACCESSOR))
+        }
+    };
+
+    let obj = heap.get_mut::<JsObject>(object).expect("object");
+    match obj.shape.lookup(key) {
+        Some(entry) => {
+            if entry.attrs != attrs {
+                obj.shape = obj.shape.change_attributes(key, attrs);
+            }
+            obj.set_slot(entry.slot, slot_value);
+        }
+        None => obj.add_slot(key, attrs, slot_value)
+    }
+}
+
+// Whether an object is a String object, and the key is its `length` or the
+// index of one of its characters.
+fn is_string_object_key(heap: &Heap, object: Value, key: Value) -> bool {
+    match heap.get::<JsObject>(object).and_then(JsObject::string_value) {
+        Some(string) => {
+            let length = string::string_length(heap, string).unwrap();
+            (key == array::length_key()) || array_index(heap, key).is_some_and(|index| index < length)
+        }
+        None => false
+    }
+}
+
+// Whether an object is an array, and the key is `length` or an index.
+pub fn is_array_element_key(heap: &Heap, object: Value, key: Value) -> bool {
+    array::is_array(heap, object) && ((key == array::length_key()) || array_index(heap, key).is_some())
+}
+
+// Define `length` or an indexed property of an array, if it goes in the
+// elements.  Returns false if the property belongs in the shape instead, and
+// any element with its index has been removed.
+fn define_array_property(heap: &mut Heap, object: Value, key: Value, property: Property) -> bool {
+    let index = array_index(heap, key);
+    let length = match property {
+        Property::Data { value, .. } if index.is_none() => value.to_f64(heap).expect("number"),
+        _ => 0.0
+    };
+    let obj = heap.get_mut::<JsObject>(object).expect("object");
+    let in_shape = obj.shape.lookup(key).is_some();
+    let elements = obj.elements.as_mut().unwrap();
+    match (index, property) {
+        (None, Property::Data { attrs, .. }) => {
+            assert!(array::is_valid_length(length) && (length as u32) >= elements.length());
+            elements.set_length(length as u32);
+            elements.set_length_writable(attrs.is_writable());
+            true
+        }
+        (None, Property::Accessor { .. }) => panic!("array length must be a data property"),
+        (Some(index), Property::Data { value, attrs }) if (attrs == PropertyAttributes::DEFAULT) && ! in_shape => {
+            elements.set(index, value);
+            true
+        }
+        (Some(index), _) => {
+            elements.delete(index);
+            if index >= elements.length() {
+                elements.set_length(index + 1);
+            }
+            false
+        }
+    }
+}
+
+// Set the value of an own data property, or add one with the default
+// attributes.  Attributes are not checked.
+pub fn set_own_value(heap: &mut Heap, object: Value, key: Value, value: Value) {
+    let mapped_slot = heap.get::<JsObject>(object).expect("object").mapped_slot(array_index(heap, key));
+    if let Some((env, slot)) = mapped_slot {
+        heap.get_mut::<SlotsCell>(env).unwrap().slots_mut()[slot as usize] = value;
+    }
+    if is_array_element_key(heap, object, key) && (key != array::length_key()) {
+        let index = array_index(heap, key).unwrap();
+        let obj = heap.get_mut::<JsObject>(object).expect("object");
+        if obj.shape.lookup(key).is_none() {
+            obj.elements.as_mut().unwrap().set(index, value);
+            return;
+        }
+    }
+    let obj = heap.get_mut::<JsObject>(object).expect("object");
+    match obj.shape.lookup(key) {
+        Some(ref entry) if ! entry.attrs.is_accessor() => obj.set_slot(entry.slot, value),
+        _ => {
+            let property = Property::Data { value, attrs: PropertyAttributes::DEFAULT };
+            define_own_property(heap, object, key, property)
+        }
+    }
+}
+
+/** Delete an own property.  Returns false if it isn't configurable. */
+pub fn delete_own_property(heap: &mut Heap, object: Value, key: Value) -> bool {
+    if is_string_object_key(heap, object, key) {
+        return false;
+    }
+    let index = array_index(heap, key);
+    let obj = heap.get_mut::<JsObject>(object).expect("object");
+    if let Some(ref mut elements) = obj.elements {
+        if key == array::length_key() {
+            return false;
+        }
+        if let Some(index) = index.filter(|&index| elements.has(index)) {
+            elements.delete(index);
+            return true;
+        }
+    }
+    match obj.shape.lookup(key) {
+        Some(entry) => {
+            if ! entry.attrs.is_configurable() {
+                return false;
+            }
+            obj.remove_slot(key, entry.slot);
+            obj.unmap(index);
+            true
+        }
+        None => true
+    }
+}
+
+/**
+ * Set the length of an array, deleting the elements at or after the new
+ * length, from the last.  A non-configurable property stops the deletion, and
+ * the length is left just past it.  Returns false if that happened.  The
+ * length must be writable.
+ */
+pub fn set_array_length(heap: &mut Heap, array: Value, length: u32) -> bool {
+    let obj = heap.get::<JsObject>(array).expect("object");
+    debug_assert!(obj.elements().unwrap().is_length_writable());
+    let mut shape_indices: Vec<(u32, Value)> = Vec::new();
+    if obj.shape.has_index_keys(|key| array_index(heap, key).is_some()) {
+        for (key, _) in obj.shape.properties() {
+            if let Some(index) = array_index(heap, key).filter(|&index| index >= length) {
+                shape_indices.push((index, key));
+            }
+        }
+    }
+    shape_indices.sort_by_key(|&(index, _)| ! index);
+
+    let mut new_length = length;
+    for (index, key) in shape_indices {
+        if ! delete_own_property(heap, array, key) {
+            new_length = index + 1;
+            break;
+        }
+    }
+    let obj = heap.get_mut::<JsObject>(array).expect("object");
+    obj.elements.as_mut().unwrap().set_length(new_length);
+    new_length == length
+}
+
+// The array index a key names, if any: the canonical string of an integer
+// below 2^32 - 1.
+pub fn array_index(heap: &Heap, key: Value) -> Option<u32> {
+    let mut index: u64 = 0;
+    let mut length = 0;
+    for unit in string::code_units(heap, key) {
+        if ! (0x30..=0x39).contains(&unit) || (length > 0 && index == 0) || length >= 10 {
+            return None;
+        }
+        index = index * 10 + ((unit - 0x30) as u64);
+        length += 1;
+    }
+    if length == 0 || index >= 0xFFFF_FFFF {
+        return None;
+    }
+    Some(index as u32)
+}
+
+/**
+ * The keys of an object's own properties, in the order they are enumerated:
+ * array indices in ascending order, then an array's or String object's
+ * `length`, then the other keys in the order they were added.  Keys for
+ * elements and characters are atomized.
+ */
+pub fn own_keys(heap: &mut Heap, object: Value) -> Vec<Value> {
+    let obj = heap.get::<JsObject>(object).expect("object");
+    let element_indices = match obj.string_value() {
+        Some(string) => Some((0..string::string_length(heap, string).unwrap()).collect()),
+        None => obj.elements().map(ArrayElements::indices)
+    };
+    let properties = obj.shape.properties();
+    let mut indexed: Vec<(u32, Value)> = Vec::new();
+    let mut named: Vec<Value> = Vec::new();
+    if let Some(element_indices) = element_indices {
+        for index in element_indices {
+            let key = string::atomize_str(heap, &number_to_string(index as f64));
+            indexed.push((index, key));
+        }
+        named.push(array::length_key());
+    }
+    for (key, _) in properties {
+        match array_index(heap, key) {
+            Some(index) => indexed.push((index, key)),
+            None => named.push(key)
+        }
+    }
+    indexed.sort_by_key(|&(index, _)| index);
+    indexed.into_iter().map(|(_, key)| key).chain(named).collect()
+}
+
+/**
+ * The keys of the enumerable properties of an object and its prototypes,
+ * as `for-in` visits them.  Each object's keys come in the order of
+ * `own_keys`, and keys shadowed by an earlier object are skipped, whether or
+ * not the shadowing property is enumerable.
+ */
+pub fn enumerable_keys(heap: &mut Heap, object: Value) -> Vec<Value> {
+    let mut keys: Vec<Value> = Vec::new();
+    let mut seen: HashSet<Value> = HashSet::new();
+    let mut holder = object;
+    while ! holder.is_imm_null() {
+        for key in own_keys(heap, holder) {
+            if ! seen.insert(key) {
+                continue;
+            }
+            if get_own_property(heap, holder, key).unwrap().attrs().is_enumerable() {
+                keys.push(key);
+            }
+        }
+        holder = get_prototype(heap, holder);
+    }
+    keys
+}
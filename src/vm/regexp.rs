@@ -0,0 +1,1138 @@
+/*!
+ * Regular expressions.
+ *
+ * A pattern is parsed into a tree, then compiled to a program for a
+ * backtracking matcher.  The matcher keeps captures, loop counters and loop
+ * start positions in one register file.  Every register write is logged, so
+ * that backtracking to a choice point can undo the writes made since.
+ *
+ * Quantified atoms loop on a counter rather than being unrolled.  Each
+ * iteration clears the captures within the atom.  An iteration past the
+ * minimum which matches nothing fails, as the spec's RepeatMatcher says.
+ * Lookaheads run as nested matches, so they can't be backtracked into.
+ *
+ * Matching counts the instructions it runs and gives up past a limit.  This
+ * stops patterns which backtrack exponentially from hanging the engine.
+ *
+ * Patterns match code units, as in ES5.  The parser also accepts some of the
+ * web's extensions:
+ * - `]`, `{` and `}` stand for themselves where they can't mean anything
+ *   else.
+ * - Escaped numbers beyond the count of groups are octal escapes.
+ * - A class escape can be the end of a range in a class, in which case the
+ *   `-` is a literal.
+ *
+ * A RegExp object is an object with a compiled `RegExp` attached.
+ */
+
+use std::char;
+use std::fmt;
+use std::rc::Rc;
+use vm::conversions::is_str_white_space;
+use vm::heap::Heap;
+use vm::object::{self, JsObject, Property};
+use vm::shape::PropertyAttributes;
+use vm::string;
+use vm::value::Value;
+
+/** The instructions a match may run before it's abandoned, by default. */
+pub const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+// How deeply groups may nest.
+const MAX_NESTING: usize = 250;
+
+// Register value for a capture which hasn't matched.
+const UNSET: usize = usize::MAX;
+
+/** The flags a regular expression was made with. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    pub global: bool,
+    pub ignore_case: bool,
+    pub multiline: bool
+}
+impl Flags {
+    // Each flag may be given at most once.
+    pub fn parse(units: &[u16]) -> Option<Flags> {
+        let mut flags = Flags::default();
+        for &unit in units {
+            let flag = match unit {
+                0x67 => &mut flags.global,
+                0x69 => &mut flags.ignore_case,
+                0x6D => &mut flags.multiline,
+                _ => { return None; }
+            };
+            if *flag {
+                return None;
+            }
+            *flag = true;
+        }
+        Some(flags)
+    }
+
+    // The flags as they're written after a literal, in a fixed order.
+    pub fn to_str(self) -> String {
+        let mut text = String::new();
+        if self.global {
+            text.push('g');
+        }
+        if self.ignore_case {
+            text.push('i');
+        }
+        if self.multiline {
+            text.push('m');
+        }
+        text
+    }
+}
+
+/** Why a pattern couldn't be compiled. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegExpError {
+    InvalidFlags,
+    Syntax(&'static str)
+}
+impl fmt::Display for RegExpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegExpError::InvalidFlags => write!(f, "Invalid flags"),
+            RegExpError::Syntax(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+/** A match ran more instructions than its limit allowed. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepLimitExceeded;
+
+/**
+ * The extent of a match and of each capturing group within it, by code unit
+ * index.  Groups which didn't take part are None.
+ */
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+/*****************************************************************************
+ **** Character classes ******************************************************
+ *****************************************************************************/
+
+// Canonicalize, from the spec: the upper case of a code unit, unless that's
+// more than one unit, or would take a non-ASCII unit to an ASCII one.
+fn canonicalize(unit: u16) -> u16 {
+    if unit < 0x80 {
+        return (unit as u8).to_ascii_uppercase() as u16;
+    }
+    let ch = match char::from_u32(unit as u32) {
+        Some(ch) => ch,
+        None => { return unit; }
+    };
+    let mut upper = ch.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) if (upper as u32) >= 0x80 && (upper as u32) <= 0xFFFF => upper as u32 as u16,
+        _ => unit
+    }
+}
+
+fn is_line_terminator(unit: u16) -> bool {
+    unit == 0x0A || unit == 0x0D || unit == 0x2028 || unit == 0x2029
+}
+
+fn is_word_char(unit: u16) -> bool {
+    unit < 0x80 && ((unit as u8).is_ascii_alphanumeric() || unit == 0x5F)
+}
+
+/** A set of code units, as sorted, disjoint, inclusive ranges. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CharClass {
+    ranges: Vec<(u16, u16)>,
+    negated: bool
+}
+impl CharClass {
+    fn new(mut ranges: Vec<(u16, u16)>, negated: bool) -> CharClass {
+        ranges.sort();
+        let mut merged: Vec<(u16, u16)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if (start as u32) <= (last.1 as u32) + 1 => {
+                    last.1 = last.1.max(end);
+                }
+                _ => merged.push((start, end))
+            }
+        }
+        CharClass { ranges: merged, negated }
+    }
+
+    fn contains(&self, unit: u16) -> bool {
+        let found = self.ranges.binary_search_by(|&(start, end)| {
+            if end < unit {
+                ::std::cmp::Ordering::Less
+            } else if start > unit {
+                ::std::cmp::Ordering::Greater
+            } else {
+                ::std::cmp::Ordering::Equal
+            }
+        }).is_ok();
+        found != self.negated
+    }
+
+    // The same class for matching without case: its units are replaced by
+    // their canonical forms, which input units are canonicalized to match.
+    fn canonicalized(&self) -> CharClass {
+        let mut present = vec![false; 0x10000];
+        for &(start, end) in &self.ranges {
+            for unit in start..=end {
+                present[canonicalize(unit) as usize] = true;
+            }
+        }
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        let mut unit = 0;
+        while unit < 0x10000 {
+            if present[unit] {
+                let start = unit;
+                while unit + 1 < 0x10000 && present[unit + 1] {
+                    unit += 1;
+                }
+                ranges.push((start as u16, unit as u16));
+            }
+            unit += 1;
+        }
+        CharClass::new(ranges, self.negated)
+    }
+}
+
+// The ranges of a class escape: \d, \s or \w, or their complements.
+fn class_escape_ranges(escape: u16) -> Option<Vec<(u16, u16)>> {
+    let ranges = match escape | 0x20 {
+        0x64 => vec![(0x30, 0x39)],
+        0x73 => {
+            let mut ranges: Vec<(u16, u16)> = Vec::new();
+            for unit in 0..=0xFFFF {
+                if is_str_white_space(unit) {
+                    ranges.push((unit, unit));
+                }
+            }
+            CharClass::new(ranges, false).ranges
+        }
+        0x77 => vec![(0x30, 0x39), (0x41, 0x5A), (0x5F, 0x5F), (0x61, 0x7A)],
+        _ => { return None; }
+    };
+    // The upper case escapes are the complements.
+    if escape & 0x20 == 0 {
+        return Some(complement(&ranges));
+    }
+    Some(ranges)
+}
+
+fn complement(ranges: &[(u16, u16)]) -> Vec<(u16, u16)> {
+    let mut result: Vec<(u16, u16)> = Vec::new();
+    let mut next: u32 = 0;
+    for &(start, end) in ranges {
+        if (start as u32) > next {
+            result.push((next as u16, start - 1));
+        }
+        next = (end as u32) + 1;
+    }
+    if next <= 0xFFFF {
+        result.push((next as u16, 0xFFFF));
+    }
+    result
+}
+
+/*****************************************************************************
+ **** Parsing ****************************************************************
+ *****************************************************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(u16),
+    Any,
+    Class(CharClass),
+    Assert(Assertion),
+    BackRef(usize),
+    // A group, with its capture index if it captures.
+    Group(Box<Node>, Option<usize>),
+    Look(Box<Node>, bool),
+    // A quantified atom, with the range of capture indices within it.
+    Repeat {
+        atom: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+        captures: (usize, usize)
+    },
+    Concat(Vec<Node>),
+    Alt(Vec<Node>)
+}
+
+// A class atom: a single unit, which can start or end a range, or a class
+// escape.
+enum ClassAtom {
+    Unit(u16),
+    Set(Vec<(u16, u16)>)
+}
+
+struct Parser<'a> {
+    units: &'a [u16],
+    position: usize,
+    // The capture groups opened so far, and in the whole pattern.
+    group_count: usize,
+    total_groups: usize,
+    depth: usize
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u16> {
+        self.units.get(self.position).cloned()
+    }
+    fn peek_at(&self, offset: usize) -> Option<u16> {
+        self.units.get(self.position + offset).cloned()
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch as u16) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn next(&mut self) -> Option<u16> {
+        let unit = self.peek();
+        if unit.is_some() {
+            self.position += 1;
+        }
+        unit
+    }
+
+    // A decimal number, saturating rather than overflowing.
+    fn parse_decimal(&mut self) -> Option<u32> {
+        let start = self.position;
+        let mut value: u32 = 0;
+        while let Some(digit) = self.peek().and_then(digit_value) {
+            value = value.saturating_mul(10).saturating_add(digit);
+            self.position += 1;
+        }
+        if self.position == start { None } else { Some(value) }
+    }
+
+    fn parse_hex(&mut self, digits: usize) -> Option<u16> {
+        let mut value: u16 = 0;
+        for offset in 0..digits {
+            let digit = self.peek_at(offset)
+                .and_then(|unit| char::from_u32(unit as u32))
+                .and_then(|ch| ch.to_digit(16))?;
+            value = (value << 4) | (digit as u16);
+        }
+        self.position += digits;
+        Some(value)
+    }
+
+    fn parse_disjunction(&mut self) -> Result<Node, RegExpError> {
+        let mut alternatives = vec![self.parse_alternative()?];
+        while self.eat('|') {
+            alternatives.push(self.parse_alternative()?);
+        }
+        Ok(if alternatives.len() == 1 { alternatives.pop().unwrap() } else { Node::Alt(alternatives) })
+    }
+
+    fn parse_alternative(&mut self) -> Result<Node, RegExpError> {
+        let mut terms: Vec<Node> = Vec::new();
+        while let Some(unit) = self.peek() {
+            if unit == ('|' as u16) || unit == (')' as u16) {
+                break;
+            }
+            terms.push(self.parse_term()?);
+        }
+        Ok(match terms.len() {
+            0 => Node::Empty,
+            1 => terms.pop().unwrap(),
+            _ => Node::Concat(terms)
+        })
+    }
+
+    fn parse_term(&mut self) -> Result<Node, RegExpError> {
+        if self.eat('^') {
+            return Ok(Node::Assert(Assertion::Start));
+        }
+        if self.eat('$') {
+            return Ok(Node::Assert(Assertion::End));
+        }
+        if self.peek() == Some('\\' as u16) {
+            match self.peek_at(1) {
+                Some(0x62) => {
+                    self.position += 2;
+                    return Ok(Node::Assert(Assertion::WordBoundary));
+                }
+                Some(0x42) => {
+                    self.position += 2;
+                    return Ok(Node::Assert(Assertion::NotWordBoundary));
+                }
+                _ => {}
+            }
+        }
+        if self.peek() == Some('(' as u16) && self.peek_at(1) == Some('?' as u16) {
+            let negated = match self.peek_at(2) {
+                Some(0x3D) => Some(false),
+                Some(0x21) => Some(true),
+                _ => None
+            };
+            if let Some(negated) = negated {
+                self.position += 3;
+                let body = self.parse_group_body()?;
+                return Ok(Node::Look(Box::new(body), negated));
+            }
+        }
+
+        let first_capture = self.group_count;
+        let atom = self.parse_atom()?;
+        let (min, max) = match self.parse_quantifier()? {
+            Some(bounds) => bounds,
+            None => { return Ok(atom); }
+        };
+        let greedy = ! self.eat('?');
+        Ok(Node::Repeat {
+            atom: Box::new(atom),
+            min,
+            max,
+            greedy,
+            captures: (first_capture + 1, self.group_count + 1)
+        })
+    }
+
+    // A group's disjunction and its closing paren.
+    fn parse_group_body(&mut self) -> Result<Node, RegExpError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(RegExpError::Syntax("Regular expression too large"));
+        }
+        let body = self.parse_disjunction()?;
+        if ! self.eat(')') {
+            return Err(RegExpError::Syntax("Unterminated group"));
+        }
+        self.depth -= 1;
+        Ok(body)
+    }
+
+    // The bounds of a quantifier, if one follows.  A `{` which doesn't start
+    // a well-formed quantifier is left alone, to stand for itself.
+    fn parse_quantifier(&mut self) -> Result<Option<(u32, Option<u32>)>, RegExpError> {
+        let bounds = match self.peek() {
+            Some(0x2A) => (0, None),
+            Some(0x2B) => (1, None),
+            Some(0x3F) => (0, Some(1)),
+            Some(0x7B) => {
+                return match self.parse_braces() {
+                    Some((min, Some(max))) if min > max => {
+                        Err(RegExpError::Syntax("numbers out of order in {} quantifier"))
+                    }
+                    bounds => Ok(bounds)
+                };
+            }
+            _ => { return Ok(None); }
+        };
+        self.position += 1;
+        Ok(Some(bounds))
+    }
+
+    fn parse_braces(&mut self) -> Option<(u32, Option<u32>)> {
+        let start = self.position;
+        self.position += 1;
+        let bounds = self.parse_decimal().map(|min| {
+            if ! self.eat(',') {
+                return (min, Some(min));
+            }
+            (min, self.parse_decimal())
+        });
+        if bounds.is_some() && self.eat('}') {
+            return bounds;
+        }
+        self.position = start;
+        None
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegExpError> {
+        let unit = self.next().unwrap();
+        match unit {
+            0x2E => Ok(Node::Any),
+            0x28 => {
+                if self.peek() == Some('?' as u16) {
+                    if self.peek_at(1) != Some(':' as u16) {
+                        return Err(RegExpError::Syntax("Invalid group"));
+                    }
+                    self.position += 2;
+                    let body = self.parse_group_body()?;
+                    return Ok(Node::Group(Box::new(body), None));
+                }
+                self.group_count += 1;
+                let index = self.group_count;
+                let body = self.parse_group_body()?;
+                Ok(Node::Group(Box::new(body), Some(index)))
+            }
+            0x5B => self.parse_class(),
+            0x5C => self.parse_atom_escape(),
+            0x2A | 0x2B | 0x3F => Err(RegExpError::Syntax("Nothing to repeat")),
+            0x7B => {
+                self.position -= 1;
+                if self.parse_braces().is_some() {
+                    return Err(RegExpError::Syntax("Nothing to repeat"));
+                }
+                self.position += 1;
+                Ok(Node::Char(unit))
+            }
+            _ => Ok(Node::Char(unit))
+        }
+    }
+
+    fn parse_atom_escape(&mut self) -> Result<Node, RegExpError> {
+        let unit = match self.peek() {
+            Some(unit) => unit,
+            None => { return Err(RegExpError::Syntax("\\ at end of pattern")); }
+        };
+        if let Some(ranges) = class_escape_ranges(unit) {
+            self.position += 1;
+            return Ok(Node::Class(CharClass::new(ranges, false)));
+        }
+        if (0x31..=0x39).contains(&unit) {
+            let start = self.position;
+            let number = self.parse_decimal().unwrap() as usize;
+            if number <= self.total_groups {
+                return Ok(Node::BackRef(number));
+            }
+            self.position = start;
+        }
+        Ok(Node::Char(self.parse_character_escape()?))
+    }
+
+    // The unit an escape stands for, after the backslash.
+    fn parse_character_escape(&mut self) -> Result<u16, RegExpError> {
+        let unit = match self.next() {
+            Some(unit) => unit,
+            None => { return Err(RegExpError::Syntax("\\ at end of pattern")); }
+        };
+        Ok(match unit {
+            0x66 => 0x0C,
+            0x6E => 0x0A,
+            0x72 => 0x0D,
+            0x74 => 0x09,
+            0x76 => 0x0B,
+            0x63 => {
+                match self.peek() {
+                    Some(letter) if letter < 0x80 && (letter as u8).is_ascii_alphabetic() => {
+                        self.position += 1;
+                        letter % 32
+                    }
+                    // Without a letter, the backslash stands for itself.
+                    _ => {
+                        self.position -= 1;
+                        0x5C
+                    }
+                }
+            }
+            0x78 => self.parse_hex(2).unwrap_or(unit),
+            0x75 => self.parse_hex(4).unwrap_or(unit),
+            0x30..=0x37 => {
+                // Octal escapes take up to three digits, up to \377.
+                let mut value = (unit - 0x30) as u32;
+                let max_digits = if value <= 3 { 3 } else { 2 };
+                for _ in 1..max_digits {
+                    match self.peek().and_then(digit_value) {
+                        Some(digit) if digit < 8 => {
+                            value = (value << 3) | digit;
+                            self.position += 1;
+                        }
+                        _ => break
+                    }
+                }
+                value as u16
+            }
+            _ => unit
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegExpError> {
+        let negated = self.eat('^');
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        loop {
+            if self.eat(']') {
+                break;
+            }
+            let first = self.parse_class_atom()?;
+            let is_range = self.peek() == Some('-' as u16) &&
+                self.peek_at(1).is_some_and(|unit| unit != (']' as u16));
+            if ! is_range {
+                push_class_atom(&mut ranges, first);
+                continue;
+            }
+            self.position += 1;
+            let last = self.parse_class_atom()?;
+            match (first, last) {
+                (ClassAtom::Unit(start), ClassAtom::Unit(end)) => {
+                    if start > end {
+                        return Err(RegExpError::Syntax("Range out of order in character class"));
+                    }
+                    ranges.push((start, end));
+                }
+                (first, last) => {
+                    push_class_atom(&mut ranges, first);
+                    ranges.push((0x2D, 0x2D));
+                    push_class_atom(&mut ranges, last);
+                }
+            }
+        }
+        Ok(Node::Class(CharClass::new(ranges, negated)))
+    }
+
+    fn parse_class_atom(&mut self) -> Result<ClassAtom, RegExpError> {
+        let unit = match self.next() {
+            Some(unit) => unit,
+            None => { return Err(RegExpError::Syntax("Unterminated character class")); }
+        };
+        if unit != 0x5C {
+            return Ok(ClassAtom::Unit(unit));
+        }
+        match self.peek() {
+            Some(0x62) => {
+                self.position += 1;
+                Ok(ClassAtom::Unit(0x08))
+            }
+            Some(0x2D) => {
+                self.position += 1;
+                Ok(ClassAtom::Unit(0x2D))
+            }
+            Some(escape) => match class_escape_ranges(escape) {
+                Some(ranges) => {
+                    self.position += 1;
+                    Ok(ClassAtom::Set(ranges))
+                }
+                None => Ok(ClassAtom::Unit(self.parse_character_escape()?))
+            },
+            None => Err(RegExpError::Syntax("\\ at end of pattern"))
+        }
+    }
+}
+
+fn push_class_atom(ranges: &mut Vec<(u16, u16)>, atom: ClassAtom) {
+    match atom {
+        ClassAtom::Unit(unit) => ranges.push((unit, unit)),
+        ClassAtom::Set(set) => ranges.extend(set)
+    }
+}
+
+fn digit_value(unit: u16) -> Option<u32> {
+    if (0x30..=0x39).contains(&unit) { Some((unit - 0x30) as u32) } else { None }
+}
+
+// The number of capturing groups in a pattern, so that escaped numbers can
+// be told apart from backreferences before the groups they refer to.
+fn count_groups(units: &[u16]) -> usize {
+    let mut count = 0;
+    let mut in_class = false;
+    let mut i = 0;
+    while i < units.len() {
+        match units[i] {
+            0x5C => { i += 1; }
+            0x5B => { in_class = true; }
+            0x5D => { in_class = false; }
+            0x28 if ! in_class && units.get(i + 1) != Some(&0x3F) => { count += 1; }
+            _ => {}
+        }
+        i += 1;
+    }
+    count
+}
+
+/*****************************************************************************
+ **** Compiling **************************************************************
+ *****************************************************************************/
+
+#[derive(Debug, Clone)]
+enum Inst {
+    // Match one code unit, canonicalized when ignoring case.
+    Char(u16),
+    Any,
+    Class(usize),
+    Assert(Assertion),
+    BackRef(usize),
+    // Continue at the first target, or failing that the second.
+    Split(usize, usize),
+    Jump(usize),
+    // Record the position in a capture register.
+    Save(usize),
+    // Unset the capture registers in a range.
+    ClearCaptures(usize, usize),
+    // Match the following instructions, up to LookEnd, as a lookahead, then
+    // continue at `end`.
+    Look { negated: bool, end: usize },
+    LookEnd,
+    // Loops: RepeatStart zeroes the counter, and RepeatLoop decides whether
+    // to run the body again or exit.  The body starts by saving its position
+    // and ends with RepeatEnd, which checks it made progress.
+    RepeatStart { counter: usize },
+    RepeatLoop { counter: usize, min: u32, max: Option<u32>, greedy: bool, exit: usize },
+    RepeatBody { position: usize },
+    RepeatEnd { counter: usize, position: usize, min: u32, head: usize },
+    Match
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    classes: Vec<CharClass>,
+    ignore_case: bool,
+    // Registers for loop counters and positions, after the captures.
+    registers: usize
+}
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    fn new_register(&mut self) -> usize {
+        self.registers += 1;
+        self.registers - 1
+    }
+
+    fn compile(&mut self, node: &Node) {
+        match *node {
+            Node::Empty => {}
+            Node::Char(unit) => {
+                let unit = if self.ignore_case { canonicalize(unit) } else { unit };
+                self.emit(Inst::Char(unit));
+            }
+            Node::Any => { self.emit(Inst::Any); }
+            Node::Class(ref class) => {
+                let class = if self.ignore_case { class.canonicalized() } else { class.clone() };
+                self.classes.push(class);
+                let index = self.classes.len() - 1;
+                self.emit(Inst::Class(index));
+            }
+            Node::Assert(assertion) => { self.emit(Inst::Assert(assertion)); }
+            Node::BackRef(group) => { self.emit(Inst::BackRef(group)); }
+            Node::Group(ref body, None) => self.compile(body),
+            Node::Group(ref body, Some(index)) => {
+                self.emit(Inst::Save(index * 2));
+                self.compile(body);
+                self.emit(Inst::Save(index * 2 + 1));
+            }
+            Node::Look(ref body, negated) => {
+                let look = self.emit(Inst::Look { negated, end: 0 });
+                self.compile(body);
+                self.emit(Inst::LookEnd);
+                let end = self.program.len();
+                self.program[look] = Inst::Look { negated, end };
+            }
+            Node::Repeat { ref atom, min, max, greedy, captures } => {
+                let counter = self.new_register();
+                let position = self.new_register();
+                self.emit(Inst::RepeatStart { counter });
+                let head = self.emit(Inst::RepeatLoop { counter, min, max, greedy, exit: 0 });
+                self.emit(Inst::RepeatBody { position });
+                if captures.0 < captures.1 {
+                    self.emit(Inst::ClearCaptures(captures.0 * 2, captures.1 * 2));
+                }
+                self.compile(atom);
+                self.emit(Inst::RepeatEnd { counter, position, min, head });
+                let exit = self.program.len();
+                self.program[head] = Inst::RepeatLoop { counter, min, max, greedy, exit };
+            }
+            Node::Concat(ref terms) => {
+                for term in terms {
+                    self.compile(term);
+                }
+            }
+            Node::Alt(ref alternatives) => {
+                let mut end_jumps: Vec<usize> = Vec::new();
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i + 1 == alternatives.len() {
+                        self.compile(alternative);
+                        break;
+                    }
+                    let split = self.emit(Inst::Split(0, 0));
+                    self.compile(alternative);
+                    end_jumps.push(self.emit(Inst::Jump(0)));
+                    let next = self.program.len();
+                    self.program[split] = Inst::Split(split + 1, next);
+                }
+                let end = self.program.len();
+                for jump in end_jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+        }
+    }
+}
+
+/** A compiled regular expression. */
+#[derive(Debug)]
+pub struct RegExp {
+    source: Box<[u16]>,
+    flags: Flags,
+    group_count: usize,
+    program: Vec<Inst>,
+    classes: Vec<CharClass>,
+    registers: usize
+}
+impl RegExp {
+    pub fn new(pattern: &[u16], flags: &[u16]) -> Result<RegExp, RegExpError> {
+        let flags = Flags::parse(flags).ok_or(RegExpError::InvalidFlags)?;
+        let total_groups = count_groups(pattern);
+        let mut parser = Parser { units: pattern, position: 0, group_count: 0, total_groups, depth: 0 };
+        let tree = parser.parse_disjunction()?;
+        if parser.position < pattern.len() {
+            // Only an unmatched close paren stops the parse early.
+            return Err(RegExpError::Syntax("Unmatched ')'"));
+        }
+
+        let group_count = parser.group_count;
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            classes: Vec::new(),
+            ignore_case: flags.ignore_case,
+            registers: (group_count + 1) * 2
+        };
+        compiler.compile(&tree);
+        compiler.emit(Inst::Match);
+        Ok(RegExp {
+            source: pattern.into(),
+            flags,
+            group_count,
+            program: compiler.program,
+            classes: compiler.classes,
+            registers: compiler.registers
+        })
+    }
+
+    pub fn source(&self) -> &[u16] {
+        &self.source
+    }
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+    // The number of capturing groups, not counting the whole match.
+    pub fn group_count(&self) -> usize {
+        self.group_count
+    }
+
+    /**
+     * The source as a literal's body would write it: `/` and line
+     * terminators are escaped, and an empty pattern is `(?:)`.
+     */
+    pub fn escaped_source(&self) -> Vec<u16> {
+        if self.source.is_empty() {
+            return "(?:)".encode_utf16().collect();
+        }
+        let mut escaped: Vec<u16> = Vec::with_capacity(self.source.len());
+        let mut in_class = false;
+        let mut units = self.source.iter().cloned();
+        while let Some(unit) = units.next() {
+            let replacement = match unit {
+                0x5C => {
+                    escaped.push(unit);
+                    match units.next() {
+                        Some(next) if ! is_line_terminator(next) => escaped.push(next),
+                        Some(next) => escaped.extend(line_terminator_escape(next)[1..].encode_utf16()),
+                        None => {}
+                    }
+                    continue;
+                }
+                0x2F if ! in_class => Some("\\/"),
+                unit if is_line_terminator(unit) => Some(line_terminator_escape(unit)),
+                _ => None
+            };
+            match replacement {
+                Some(text) => escaped.extend(text.encode_utf16()),
+                None => escaped.push(unit)
+            }
+            if unit == 0x5B {
+                in_class = true;
+            } else if unit == 0x5D {
+                in_class = false;
+            }
+        }
+        escaped
+    }
+
+    /**
+     * Match at exactly `start`, spending at most `steps` instructions from
+     * the budget.
+     */
+    pub fn match_at(&self, input: &[u16], start: usize, steps: &mut u64)
+        -> Result<Option<Captures>, StepLimitExceeded>
+    {
+        let mut matcher = Matcher {
+            regexp: self,
+            input,
+            registers: vec![UNSET; self.registers],
+            undo: Vec::new(),
+            steps: *steps
+        };
+        let result = matcher.run(0, start);
+        *steps = matcher.steps;
+        let end = match result? {
+            Some(end) => end,
+            None => { return Ok(None); }
+        };
+        let mut captures: Captures = vec![Some((start, end))];
+        for group in 1..=self.group_count {
+            let (start, end) = (matcher.registers[group * 2], matcher.registers[group * 2 + 1]);
+            captures.push(if start == UNSET || end == UNSET { None } else { Some((start, end)) });
+        }
+        Ok(Some(captures))
+    }
+
+    /** The first match at or after `start`. */
+    pub fn search(&self, input: &[u16], start: usize, steps: &mut u64)
+        -> Result<Option<Captures>, StepLimitExceeded>
+    {
+        for index in start..=input.len() {
+            if let Some(captures) = self.match_at(input, index, steps)? {
+                return Ok(Some(captures));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn line_terminator_escape(unit: u16) -> &'static str {
+    match unit {
+        0x0A => "\\n",
+        0x0D => "\\r",
+        0x2028 => "\\u2028",
+        _ => "\\u2029"
+    }
+}
+
+/*****************************************************************************
+ **** Matching ***************************************************************
+ *****************************************************************************/
+
+struct Matcher<'a> {
+    regexp: &'a RegExp,
+    input: &'a [u16],
+    registers: Vec<usize>,
+    // The registers' previous values, in the order they were written.
+    undo: Vec<(usize, usize)>,
+    steps: u64
+}
+impl<'a> Matcher<'a> {
+    fn set(&mut self, register: usize, value: usize) {
+        self.undo.push((register, self.registers[register]));
+        self.registers[register] = value;
+    }
+
+    fn undo_to(&mut self, length: usize) {
+        while self.undo.len() > length {
+            let (register, value) = self.undo.pop().unwrap();
+            self.registers[register] = value;
+        }
+    }
+
+    fn unit_matches(&self, pos: usize, expected: u16) -> bool {
+        match self.input.get(pos) {
+            Some(&unit) if self.regexp.flags.ignore_case => canonicalize(unit) == expected,
+            Some(&unit) => unit == expected,
+            None => false
+        }
+    }
+
+    fn assertion_holds(&self, assertion: Assertion, pos: usize) -> bool {
+        let multiline = self.regexp.flags.multiline;
+        match assertion {
+            Assertion::Start => pos == 0 || (multiline && is_line_terminator(self.input[pos - 1])),
+            Assertion::End => {
+                pos == self.input.len() || (multiline && is_line_terminator(self.input[pos]))
+            }
+            Assertion::WordBoundary | Assertion::NotWordBoundary => {
+                let before = pos > 0 && is_word_char(self.input[pos - 1]);
+                let after = pos < self.input.len() && is_word_char(self.input[pos]);
+                (before != after) == (assertion == Assertion::WordBoundary)
+            }
+        }
+    }
+
+    // The position after a backreference matched at `pos`, if it does.
+    // Groups which haven't matched match the empty string.
+    fn back_reference(&self, group: usize, pos: usize) -> Option<usize> {
+        let (start, end) = (self.registers[group * 2], self.registers[group * 2 + 1]);
+        if start == UNSET || end == UNSET {
+            return Some(pos);
+        }
+        let length = end - start;
+        if pos + length > self.input.len() {
+            return None;
+        }
+        let ignore_case = self.regexp.flags.ignore_case;
+        let matches = (0..length).all(|i| {
+            let (a, b) = (self.input[start + i], self.input[pos + i]);
+            a == b || (ignore_case && canonicalize(a) == canonicalize(b))
+        });
+        if matches { Some(pos + length) } else { None }
+    }
+
+    /**
+     * Run from `pc` at `pos` until a Match or LookEnd, returning the
+     * position there, or None if every choice fails.
+     */
+    fn run(&mut self, mut pc: usize, mut pos: usize) -> Result<Option<usize>, StepLimitExceeded> {
+        // Choice points: where to continue, and the undo log's length then.
+        let mut choices: Vec<(usize, usize, usize)> = Vec::new();
+        loop {
+            if self.steps == 0 {
+                return Err(StepLimitExceeded);
+            }
+            self.steps -= 1;
+
+            let matched = match self.regexp.program[pc] {
+                Inst::Char(unit) => {
+                    let matched = self.unit_matches(pos, unit);
+                    pos += 1;
+                    matched
+                }
+                Inst::Any => {
+                    let matched = self.input.get(pos).is_some_and(|&unit| ! is_line_terminator(unit));
+                    pos += 1;
+                    matched
+                }
+                Inst::Class(index) => {
+                    let class = &self.regexp.classes[index];
+                    let matched = self.input.get(pos).is_some_and(|&unit| {
+                        class.contains(if self.regexp.flags.ignore_case { canonicalize(unit) } else { unit })
+                    });
+                    pos += 1;
+                    matched
+                }
+                Inst::Assert(assertion) => self.assertion_holds(assertion, pos),
+                Inst::BackRef(group) => match self.back_reference(group, pos) {
+                    Some(end) => {
+                        pos = end;
+                        true
+                    }
+                    None => false
+                },
+                Inst::Split(first, second) => {
+                    choices.push((second, pos, self.undo.len()));
+                    pc = first;
+                    continue;
+                }
+                Inst::Jump(target) => {
+                    pc = target;
+                    continue;
+                }
+                Inst::Save(register) => {
+                    self.set(register, pos);
+                    true
+                }
+                Inst::ClearCaptures(from, to) => {
+                    for register in from..to {
+                        if self.registers[register] != UNSET {
+                            self.set(register, UNSET);
+                        }
+                    }
+                    true
+                }
+                Inst::Look { negated, end } => {
+                    let mark = self.undo.len();
+                    let found = self.run(pc + 1, pos)?.is_some();
+                    if negated {
+                        self.undo_to(mark);
+                    }
+                    if found != negated {
+                        pc = end;
+                        continue;
+                    }
+                    false
+                }
+                Inst::LookEnd | Inst::Match => {
+                    return Ok(Some(pos));
+                }
+                Inst::RepeatStart { counter } => {
+                    self.set(counter, 0);
+                    true
+                }
+                Inst::RepeatLoop { counter, min, max, greedy, exit } => {
+                    let count = self.registers[counter] as u32;
+                    if count < min {
+                        pc += 1;
+                    } else if max == Some(count) {
+                        pc = exit;
+                    } else if greedy {
+                        choices.push((exit, pos, self.undo.len()));
+                        pc += 1;
+                    } else {
+                        choices.push((pc + 1, pos, self.undo.len()));
+                        pc = exit;
+                    }
+                    continue;
+                }
+                Inst::RepeatBody { position } => {
+                    self.set(position, pos);
+                    true
+                }
+                Inst::RepeatEnd { counter, position, min, head } => {
+                    let count = self.registers[counter];
+                    if count >= (min as usize) && self.registers[position] == pos {
+                        false
+                    } else {
+                        self.set(counter, count + 1);
+                        pc = head;
+                        continue;
+                    }
+                }
+            };
+
+            if matched {
+                pc += 1;
+                continue;
+            }
+            match choices.pop() {
+                Some((choice_pc, choice_pos, undo_length)) => {
+                    self.undo_to(undo_length);
+                    pc = choice_pc;
+                    pos = choice_pos;
+                }
+                None => { return Ok(None); }
+            }
+        }
+    }
+}
+
+/*****************************************************************************
+ **** RegExp objects *********************************************************
+ *****************************************************************************/
+
+/**
+ * A new RegExp object for a compiled expression, with its own `lastIndex`,
+ * `source`, `global`, `ignoreCase` and `multiline` properties.
+ */
+pub fn new_regexp_object(heap: &mut Heap, prototype: Value, regexp: Rc<RegExp>) -> Value {
+    let source = regexp.escaped_source();
+    let flags = regexp.flags();
+    let shape = heap.root_shape().clone();
+    let object = heap.alloc(JsObject::new_regexp(shape, prototype, regexp));
+
+    let source = string::new_string_from_units(heap, &source);
+    let properties = [
+        ("lastIndex", Value::new_imm_i32(0), PropertyAttributes::WRITABLE),
+        ("source", source, PropertyAttributes::NONE),
+        ("global", Value::new_imm_bool(flags.global), PropertyAttributes::NONE),
+        ("ignoreCase", Value::new_imm_bool(flags.ignore_case), PropertyAttributes::NONE),
+        ("multiline", Value::new_imm_bool(flags.multiline), PropertyAttributes::NONE)
+    ];
+    for &(name, value, attrs) in &properties {
+        let key = string::atomize_str(heap, name);
+        object::define_own_property(heap, object, key, Property::Data { value, attrs });
+    }
+    object
+}
+
+/** The compiled expression of a RegExp object. */
+pub fn regexp_of(heap: &Heap, value: Value) -> Option<Rc<RegExp>> {
+    heap.get::<JsObject>(value).and_then(JsObject::regexp).cloned()
+}
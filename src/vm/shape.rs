@@ -0,0 +1,434 @@
+/*!
+ * Shapes, which describe the layout of objects' properties.
+ *
+ * A shape is a node in a tree rooted at the empty shape.  Each node adds one
+ * property to its parent, with the property's key, attributes and slot, so
+ * the path from the root lists an object's properties in the order they were
+ * added.  Objects which gain the same properties in the same order share
+ * their shapes, and a shape can stand for the layout of all of them.
+ *
+ * Adding a property follows (or makes) a transition to a child shape.
+ * Transitions are held weakly, so unused branches of the tree are freed.
+ *
+ * The shapes along a path share a property table, which lists the path's
+ * properties in slot order and maps their keys to slots.  A shape uses the
+ * start of the table, up to its count.  A child extends its parent's table if
+ * no other child has, and copies the parent's part of it otherwise, so a path
+ * of any length has one table and lookups take constant time.
+ *
+ * An object with more than `MAX_TREE_PROPERTIES` properties changes to a
+ * dictionary shape, which is the object's own and is changed in place.  So
+ * does an object which removes or changes a property other than its last,
+ * unless it has few enough to rebuild its path from the root.  Removing a
+ * property from a dictionary leaves its slot free for the next one added.
+ * Dictionary shapes are never shared, so inline caches don't cache them.
+ *
+ * Keys are atoms, which are never collected, so shapes don't need tracing.
+ */
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+use vm::value::Value;
+
+// Objects with more properties than this have dictionary shapes.
+const MAX_TREE_PROPERTIES: u32 = 128;
+
+// Shapes with at most this many properties are rebuilt when one is removed or
+// changed, rather than changing to a dictionary.
+const MAX_REBUILT_PROPERTIES: u32 = 8;
+
+/** The attributes of a property. */
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PropertyAttributes(u8);
+impl PropertyAttributes {
+    pub const NONE: PropertyAttributes = PropertyAttributes(0);
+    pub const WRITABLE: PropertyAttributes = PropertyAttributes(0x1);
+    pub const ENUMERABLE: PropertyAttributes = PropertyAttributes(0x2);
+    pub const CONFIGURABLE: PropertyAttributes = PropertyAttributes(0x4);
+    // The slot holds an accessor pair rather than the property's value.
+    pub const ACCESSOR: PropertyAttributes = PropertyAttributes(0x8);
+
+    // The attributes of properties created by assignment.
+    pub const DEFAULT: PropertyAttributes = PropertyAttributes(0x7);
+
+    pub fn contains(&self, other: PropertyAttributes) -> bool {
+        (self.0 & other.0) == other.0
+    }
+    pub fn with(&self, other: PropertyAttributes) -> PropertyAttributes {
+        PropertyAttributes(self.0 | other.0)
+    }
+    pub fn without(&self, other: PropertyAttributes) -> PropertyAttributes {
+        PropertyAttributes(self.0 & ! other.0)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.contains(PropertyAttributes::WRITABLE)
+    }
+    pub fn is_enumerable(&self) -> bool {
+        self.contains(PropertyAttributes::ENUMERABLE)
+    }
+    pub fn is_configurable(&self) -> bool {
+        self.contains(PropertyAttributes::CONFIGURABLE)
+    }
+    pub fn is_accessor(&self) -> bool {
+        self.contains(PropertyAttributes::ACCESSOR)
+    }
+}
+impl fmt::Debug for PropertyAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}{}",
+               if self.is_writable() { "w" } else { "-" },
+               if self.is_enumerable() { "e" } else { "-" },
+               if self.is_configurable() { "c" } else { "-" },
+               if self.is_accessor() { "a" } else { "-" })
+    }
+}
+
+/** The slot and attributes of a property found in a shape. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShapeEntry {
+    pub slot: u32,
+    pub attrs: PropertyAttributes
+}
+
+type TransitionKey = (Value, PropertyAttributes);
+
+// Whether any key is an array index, found by checking keys in order with a
+// caller's predicate, as shapes don't know the contents of keys.  Keys are
+// checked up to the first which is one.
+struct IndexKeys {
+    checked: usize,
+    first: Option<usize>
+}
+impl IndexKeys {
+    fn new() -> IndexKeys {
+        IndexKeys { checked: 0, first: None }
+    }
+
+    // Check keys up to `end`, returning the position of the first index key.
+    fn find<F: Fn(usize) -> Option<bool>>(&mut self, end: usize, is_index_at: F) -> Option<usize> {
+        while self.first.is_none() && self.checked < end {
+            if is_index_at(self.checked) == Some(true) {
+                self.first = Some(self.checked);
+            }
+            self.checked += 1;
+        }
+        self.first
+    }
+}
+
+// The properties along a path of the tree.  The property in each slot is the
+// one the shape at that depth added.
+struct PropertyTable {
+    properties: Vec<(Value, PropertyAttributes)>,
+    slots: HashMap<Value, u32>,
+    index_keys: IndexKeys
+}
+impl PropertyTable {
+    fn new() -> PropertyTable {
+        PropertyTable {
+            properties: Vec::new(),
+            slots: HashMap::new(),
+            index_keys: IndexKeys::new()
+        }
+    }
+
+    // A copy of the first `count` properties.
+    fn prefix(&self, count: u32) -> PropertyTable {
+        let properties = self.properties[..(count as usize)].to_vec();
+        let slots = properties.iter().enumerate().map(|(slot, &(key, _))| (key, slot as u32)).collect();
+        let index_keys = IndexKeys {
+            checked: self.index_keys.checked.min(count as usize),
+            first: self.index_keys.first.filter(|&slot| slot < (count as usize))
+        };
+        PropertyTable { properties, slots, index_keys }
+    }
+
+    fn push(&mut self, key: Value, attrs: PropertyAttributes) {
+        self.slots.insert(key, self.properties.len() as u32);
+        self.properties.push((key, attrs));
+    }
+}
+
+// The properties of an object with a dictionary shape.  Removed properties
+// leave holes in the order until there are enough to compact it.
+struct Dictionary {
+    order: Vec<Option<Value>>,
+    entries: HashMap<Value, (usize, ShapeEntry)>,
+    holes: usize,
+    free_slots: Vec<u32>,
+    slot_count: u32,
+    // Positions in the order.
+    index_keys: IndexKeys
+}
+impl Dictionary {
+    fn add(&mut self, key: Value, attrs: PropertyAttributes) {
+        let slot = self.free_slots.pop().unwrap_or_else(|| {
+            self.slot_count += 1;
+            self.slot_count - 1
+        });
+        self.entries.insert(key, (self.order.len(), ShapeEntry { slot, attrs }));
+        self.order.push(Some(key));
+    }
+
+    fn remove(&mut self, key: Value) {
+        let (position, entry) = self.entries.remove(&key).expect("property");
+        self.order[position] = None;
+        self.holes += 1;
+        self.free_slots.push(entry.slot);
+        if self.index_keys.first == Some(position) {
+            self.index_keys.first = None;
+        }
+        if self.holes > self.entries.len() {
+            self.compact();
+        }
+    }
+
+    fn compact(&mut self) {
+        let checked = self.order[..self.index_keys.checked].iter().filter(|key| key.is_some()).count();
+        self.order.retain(Option::is_some);
+        for (position, key) in self.order.iter().enumerate() {
+            self.entries.get_mut(key.as_ref().unwrap()).unwrap().0 = position;
+        }
+        self.index_keys.first = self.index_keys.first.map(|_| checked - 1);
+        self.index_keys.checked = checked;
+        self.holes = 0;
+    }
+}
+
+enum Layout {
+    Tree {
+        parent: Option<Rc<Shape>>,
+        // The number of properties, which is also the number of slots used.
+        count: u32,
+        table: Rc<RefCell<PropertyTable>>,
+        transitions: RefCell<HashMap<TransitionKey, Weak<Shape>>>
+    },
+    Dictionary(RefCell<Dictionary>)
+}
+
+/** A node in the shape tree, or an object's dictionary. */
+pub struct Shape {
+    layout: Layout
+}
+impl Shape {
+    // The empty shape at the root of a tree.
+    pub fn new_root() -> Rc<Shape> {
+        Rc::new(Shape {
+            layout: Layout::Tree {
+                parent: None,
+                count: 0,
+                table: Rc::new(RefCell::new(PropertyTable::new())),
+                transitions: RefCell::new(HashMap::new())
+            }
+        })
+    }
+
+    pub fn is_root(&self) -> bool {
+        match self.layout {
+            Layout::Tree { ref parent, .. } => parent.is_none(),
+            Layout::Dictionary(_) => false
+        }
+    }
+    pub fn is_dictionary(&self) -> bool {
+        match self.layout {
+            Layout::Tree { .. } => false,
+            Layout::Dictionary(_) => true
+        }
+    }
+    // The number of properties.  In a tree shape, slots are numbered from
+    // zero up to it.
+    pub fn count(&self) -> u32 {
+        match self.layout {
+            Layout::Tree { count, .. } => count,
+            Layout::Dictionary(ref dict) => dict.borrow().entries.len() as u32
+        }
+    }
+    pub fn parent(&self) -> Option<&Rc<Shape>> {
+        match self.layout {
+            Layout::Tree { ref parent, .. } => parent.as_ref(),
+            Layout::Dictionary(_) => None
+        }
+    }
+
+    pub fn lookup(&self, key: Value) -> Option<ShapeEntry> {
+        match self.layout {
+            Layout::Tree { count, ref table, .. } => {
+                let table = table.borrow();
+                let slot = *table.slots.get(&key)?;
+                if slot >= count {
+                    return None;
+                }
+                Some(ShapeEntry { slot, attrs: table.properties[slot as usize].1 })
+            }
+            Layout::Dictionary(ref dict) => dict.borrow().entries.get(&key).map(|&(_, entry)| entry)
+        }
+    }
+
+    // Whether any key is an array index, as decided by `is_index`.  Each key
+    // is only checked once.
+    pub fn has_index_keys<F: Fn(Value) -> bool>(&self, is_index: F) -> bool {
+        match self.layout {
+            Layout::Tree { count, ref table, .. } => {
+                let table = &mut *table.borrow_mut();
+                let properties = &table.properties;
+                let first = table.index_keys.find(count as usize, |slot| Some(is_index(properties[slot].0)));
+                first.is_some_and(|slot| slot < (count as usize))
+            }
+            Layout::Dictionary(ref dict) => {
+                let dict = &mut *dict.borrow_mut();
+                let order = &dict.order;
+                dict.index_keys.find(order.len(), |position| order[position].map(&is_index)).is_some()
+            }
+        }
+    }
+
+    // The properties of the shape, in the order they were added.
+    pub fn properties(&self) -> Vec<(Value, ShapeEntry)> {
+        match self.layout {
+            Layout::Tree { count, ref table, .. } => {
+                let table = table.borrow();
+                table.properties[..(count as usize)].iter().enumerate().map(|(slot, &(key, attrs))| {
+                    (key, ShapeEntry { slot: slot as u32, attrs })
+                }).collect()
+            }
+            Layout::Dictionary(ref dict) => {
+                let dict = dict.borrow();
+                dict.order.iter().filter_map(|&key| {
+                    let key = key?;
+                    Some((key, dict.entries[&key].1))
+                }).collect()
+            }
+        }
+    }
+
+    /**
+     * The shape with a property added.  The key must not already be present.
+     * In a tree shape the property gets the next slot, and a dictionary
+     * shape is changed in place.
+     */
+    pub fn add_property(self: &Rc<Self>, key: Value, attrs: PropertyAttributes) -> Rc<Shape> {
+        debug_assert!(self.lookup(key).is_none());
+        let (count, table, transitions) = match self.layout {
+            Layout::Tree { count, ref table, ref transitions, .. } => (count, table, transitions),
+            Layout::Dictionary(ref dict) => {
+                dict.borrow_mut().add(key, attrs);
+                return self.clone();
+            }
+        };
+        if count >= MAX_TREE_PROPERTIES {
+            let dictionary = self.to_dictionary();
+            return dictionary.add_property(key, attrs);
+        }
+
+        let transition_key = (key, attrs);
+        if let Some(child) = transitions.borrow().get(&transition_key).and_then(Weak::upgrade) {
+            return child;
+        }
+
+        // Extend the table if it ends here, or already continues with this
+        // property from a child which has been freed.
+        let table = {
+            let mut shared = table.borrow_mut();
+            match shared.properties.get(count as usize) {
+                None => {
+                    shared.push(key, attrs);
+                    table.clone()
+                }
+                Some(&property) if property == transition_key => table.clone(),
+                Some(_) => {
+                    let mut copy = shared.prefix(count);
+                    copy.push(key, attrs);
+                    Rc::new(RefCell::new(copy))
+                }
+            }
+        };
+        let child = Rc::new(Shape {
+            layout: Layout::Tree {
+                parent: Some(self.clone()),
+                count: count + 1,
+                table,
+                transitions: RefCell::new(HashMap::new())
+            }
+        });
+        let mut transitions = transitions.borrow_mut();
+        transitions.retain(|_, weak| weak.strong_count() > 0);
+        transitions.insert(transition_key, Rc::downgrade(&child));
+        child
+    }
+
+    /**
+     * The shape with a property's attributes changed.  Properties keep their
+     * order and slots.
+     */
+    pub fn change_attributes(self: &Rc<Self>, key: Value, attrs: PropertyAttributes) -> Rc<Shape> {
+        if let Layout::Dictionary(ref dict) = self.layout {
+            dict.borrow_mut().entries.get_mut(&key).expect("property").1.attrs = attrs;
+            return self.clone();
+        }
+        let properties = self.properties();
+        if properties.last().is_some_and(|&(last_key, _)| last_key == key) {
+            return self.parent().unwrap().add_property(key, attrs);
+        }
+        if self.count() > MAX_REBUILT_PROPERTIES {
+            return self.to_dictionary().change_attributes(key, attrs);
+        }
+        let mut shape = self.root();
+        for (prop_key, entry) in properties {
+            let prop_attrs = if prop_key == key { attrs } else { entry.attrs };
+            shape = shape.add_property(prop_key, prop_attrs);
+        }
+        shape
+    }
+
+    /**
+     * The shape with a property removed.  In a tree shape the properties
+     * after it move down one slot, keeping their order.  In a dictionary
+     * shape its slot is left empty.
+     */
+    pub fn remove_property(self: &Rc<Self>, key: Value) -> Rc<Shape> {
+        if let Layout::Dictionary(ref dict) = self.layout {
+            dict.borrow_mut().remove(key);
+            return self.clone();
+        }
+        let properties = self.properties();
+        if properties.last().is_some_and(|&(last_key, _)| last_key == key) {
+            return self.parent().unwrap().clone();
+        }
+        if self.count() > MAX_REBUILT_PROPERTIES {
+            return self.to_dictionary().remove_property(key);
+        }
+        let mut shape = self.root();
+        for (prop_key, entry) in properties {
+            if prop_key != key {
+                shape = shape.add_property(prop_key, entry.attrs);
+            }
+        }
+        shape
+    }
+
+    // The root of a tree shape's tree.
+    fn root(self: &Rc<Self>) -> Rc<Shape> {
+        let mut shape = self;
+        while let Some(parent) = shape.parent() {
+            shape = parent;
+        }
+        shape.clone()
+    }
+
+    // A dictionary shape with the same properties, in the same slots.
+    fn to_dictionary(&self) -> Rc<Shape> {
+        let properties = self.properties();
+        let dict = Dictionary {
+            order: properties.iter().map(|&(key, _)| Some(key)).collect(),
+            entries: properties.iter().enumerate().map(|(position, &(key, entry))| (key, (position, entry))).collect(),
+            holes: 0,
+            free_slots: Vec::new(),
+            slot_count: self.count(),
+            index_keys: IndexKeys::new()
+        };
+        Rc::new(Shape { layout: Layout::Dictionary(RefCell::new(dict)) })
+    }
+}
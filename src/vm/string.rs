@@ -0,0 +1,349 @@
+/*!
+ * Strings.
+ *
+ * Strings of up to `IMM_STR_MAX_LENGTH` Latin-1 chars are immediate values.
+ * Longer strings live in heap string cells, holding either Latin-1 or UTF-16
+ * code units.  Concatenating long strings makes a rope, which refers to its
+ * two halves and is only flattened when its contents are needed.
+ *
+ * Strings compare equal when their code units are equal, whatever their
+ * representation.  Atoms are strings interned in the heap's atom table, so
+ * that equal atoms are always the same value.  They are used for property
+ * names and identifiers, and are never collected.
+ */
+
+use std::collections::HashMap;
+use std::mem;
+use vm::heap::{CellKind, CellType, Heap, Trace, Tracer};
+use vm::value::{Value, IMM_STR_MAX_LENGTH};
+
+// Concatenations shorter than this are flattened immediately.
+const ROPE_MIN_LENGTH: u32 = 24;
+
+pub const MAX_STRING_LENGTH: u32 = (1 << 30) - 1;
+
+pub enum StringContents {
+    Latin1(Box<[u8]>),
+    Utf16(Box<[u16]>),
+    Rope(Value, Value)
+}
+
+/** A string stored in a heap cell. */
+pub struct HeapString {
+    length: u32,
+    contents: StringContents,
+    atom: bool
+}
+impl HeapString {
+    fn from_units(units: &[u16]) -> HeapString {
+        assert!(units.len() <= (MAX_STRING_LENGTH as usize));
+        let contents = if units.iter().all(|&unit| unit <= 0xFF) {
+            StringContents::Latin1(units.iter().map(|&unit| unit as u8).collect())
+        } else {
+            StringContents::Utf16(units.into())
+        };
+        HeapString { length: units.len() as u32, contents, atom: false }
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+    pub fn contents(&self) -> &StringContents {
+        &self.contents
+    }
+    pub fn is_rope(&self) -> bool {
+        matches!(self.contents, StringContents::Rope(..))
+    }
+    pub fn is_atom(&self) -> bool {
+        self.atom
+    }
+}
+impl CellType for HeapString {
+    const KIND: CellKind = CellKind::String;
+
+    fn trace(&self, tracer: &mut Tracer) {
+        if let StringContents::Rope(left, right) = self.contents {
+            tracer.trace_value(left);
+            tracer.trace_value(right);
+        }
+    }
+
+    fn extra_size(&self) -> usize {
+        match self.contents {
+            StringContents::Latin1(ref chars) => chars.len(),
+            StringContents::Utf16(ref units) => units.len() * mem::size_of::<u16>(),
+            StringContents::Rope(..) => 0
+        }
+    }
+}
+
+/** The interned strings of a heap, mapped from their code units. */
+pub struct AtomTable {
+    atoms: HashMap<Box<[u16]>, Value>
+}
+impl AtomTable {
+    pub fn new() -> AtomTable {
+        AtomTable { atoms: HashMap::new() }
+    }
+    pub fn len(&self) -> usize {
+        self.atoms.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty()
+    }
+    pub fn lookup(&self, units: &[u16]) -> Option<Value> {
+        self.atoms.get(units).cloned()
+    }
+    fn insert(&mut self, units: Box<[u16]>, atom: Value) {
+        self.atoms.insert(units, atom);
+    }
+}
+impl Default for AtomTable {
+    fn default() -> AtomTable {
+        AtomTable::new()
+    }
+}
+impl Trace for AtomTable {
+    fn trace(&self, tracer: &mut Tracer) {
+        for atom in self.atoms.values() {
+            tracer.trace_value(*atom);
+        }
+    }
+}
+
+enum Segment<'a> {
+    Empty,
+    Imm(Value, u8),
+    Latin1(&'a [u8]),
+    Utf16(&'a [u16])
+}
+
+/** Iterates over the code units of a string, in any representation. */
+pub struct CodeUnits<'a> {
+    heap: &'a Heap,
+    pending: Vec<Value>,
+    segment: Segment<'a>,
+    index: usize
+}
+impl<'a> CodeUnits<'a> {
+    fn load(&mut self, value: Value) {
+        self.index = 0;
+        self.segment = if value.is_imm_str() {
+            Segment::Imm(value, unsafe { value.get_unchecked_imm_str_len() })
+        } else {
+            match self.heap.get::<HeapString>(value).expect("string value").contents {
+                StringContents::Latin1(ref chars) => Segment::Latin1(chars),
+                StringContents::Utf16(ref units) => Segment::Utf16(units),
+                StringContents::Rope(left, right) => {
+                    self.pending.push(right);
+                    self.pending.push(left);
+                    Segment::Empty
+                }
+            }
+        };
+    }
+}
+impl<'a> Iterator for CodeUnits<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        loop {
+            let unit = match self.segment {
+                Segment::Empty => None,
+                Segment::Imm(value, length) => {
+                    if self.index < (length as usize) {
+                        Some(unsafe { value.get_unchecked_imm_str_char(self.index as u8) } as u16)
+                    } else {
+                        None
+                    }
+                }
+                Segment::Latin1(chars) => chars.get(self.index).map(|&ch| ch as u16),
+                Segment::Utf16(units) => units.get(self.index).cloned()
+            };
+            if unit.is_some() {
+                self.index += 1;
+                return unit;
+            }
+
+            let value = self.pending.pop()?;
+            self.load(value);
+        }
+    }
+}
+
+pub fn is_string(heap: &Heap, value: Value) -> bool {
+    value.is_imm_str() || heap.get::<HeapString>(value).is_some()
+}
+
+pub fn string_length(heap: &Heap, value: Value) -> Option<u32> {
+    if value.is_imm_str() {
+        return Some(unsafe { value.get_unchecked_imm_str_len() } as u32);
+    }
+    heap.get::<HeapString>(value).map(|string| string.length())
+}
+
+pub fn code_units(heap: &Heap, value: Value) -> CodeUnits<'_> {
+    assert!(is_string(heap, value));
+    let mut iter = CodeUnits { heap, pending: Vec::new(), segment: Segment::Empty, index: 0 };
+    iter.load(value);
+    iter
+}
+
+pub fn to_rust_string(heap: &Heap, value: Value) -> Option<String> {
+    if ! is_string(heap, value) {
+        return None;
+    }
+    let units: Vec<u16> = code_units(heap, value).collect();
+    Some(String::from_utf16_lossy(&units))
+}
+
+// Make a string with the given code units, as an immediate if it fits.
+pub fn new_string_from_units(heap: &mut Heap, units: &[u16]) -> Value {
+    if (units.len() <= (IMM_STR_MAX_LENGTH as usize)) && units.iter().all(|&u| u <= 0xFF) {
+        let chars: Vec<u8> = units.iter().map(|&unit| unit as u8).collect();
+        return Value::new_imm_str(&chars);
+    }
+    heap.alloc(HeapString::from_units(units))
+}
+
+pub fn new_string(heap: &mut Heap, text: &str) -> Value {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    new_string_from_units(heap, &units)
+}
+
+// Make a string with the given Latin-1 chars, as an immediate if it fits.
+pub fn new_string_from_latin1(heap: &mut Heap, chars: &[u8]) -> Value {
+    if chars.len() <= (IMM_STR_MAX_LENGTH as usize) {
+        return Value::new_imm_str(chars);
+    }
+    assert!(chars.len() <= (MAX_STRING_LENGTH as usize));
+    let contents = StringContents::Latin1(chars.into());
+    heap.alloc(HeapString { length: chars.len() as u32, contents, atom: false })
+}
+
+/**
+ * The code units of a string from `start` up to `end`.  Substrings of
+ * immediates are sliced from their chars, so are immediates too.  Ropes are
+ * flattened, so that slicing them repeatedly is cheap.
+ */
+pub fn substring(heap: &mut Heap, value: Value, start: u32, end: u32) -> Value {
+    debug_assert!(start <= end && end <= string_length(heap, value).unwrap());
+    if let Some(chars) = value.get_imm_str() {
+        return Value::new_imm_str(&chars[(start as usize)..(end as usize)]);
+    }
+    if start == 0 && end == string_length(heap, value).unwrap() {
+        return value;
+    }
+    let value = flatten(heap, value);
+    let range = (start as usize)..(end as usize);
+    match heap.get::<HeapString>(value).unwrap().contents {
+        StringContents::Latin1(ref chars) => {
+            let chars = chars[range].to_vec();
+            new_string_from_latin1(heap, &chars)
+        }
+        StringContents::Utf16(ref units) => {
+            let units = units[range].to_vec();
+            new_string_from_units(heap, &units)
+        }
+        StringContents::Rope(..) => unreachable!("flattened string")
+    }
+}
+
+// The code unit at an index.  Ropes are walked, so should be flattened first
+// when indexing repeatedly.
+pub fn code_unit_at(heap: &Heap, value: Value, index: u32) -> Option<u16> {
+    if value.is_imm_str() {
+        let length = unsafe { value.get_unchecked_imm_str_len() };
+        return if index < (length as u32) {
+            Some(unsafe { value.get_unchecked_imm_str_char(index as u8) } as u16)
+        } else {
+            None
+        };
+    }
+    match heap.get::<HeapString>(value).expect("string value").contents {
+        StringContents::Latin1(ref chars) => chars.get(index as usize).map(|&ch| ch as u16),
+        StringContents::Utf16(ref units) => units.get(index as usize).cloned(),
+        StringContents::Rope(..) => code_units(heap, value).nth(index as usize)
+    }
+}
+
+pub fn concat(heap: &mut Heap, left: Value, right: Value) -> Value {
+    let left_length = string_length(heap, left).expect("string value");
+    let right_length = string_length(heap, right).expect("string value");
+    if right_length == 0 {
+        return left;
+    }
+    if left_length == 0 {
+        return right;
+    }
+
+    let length = left_length + right_length;
+    assert!(length <= MAX_STRING_LENGTH);
+    if length < ROPE_MIN_LENGTH {
+        let units: Vec<u16> = code_units(heap, left).chain(code_units(heap, right)).collect();
+        return new_string_from_units(heap, &units);
+    }
+    heap.alloc(HeapString { length, contents: StringContents::Rope(left, right), atom: false })
+}
+
+// Flatten a rope in place, so its contents can be accessed directly.
+pub fn flatten(heap: &mut Heap, value: Value) -> Value {
+    let units: Vec<u16> = match heap.get::<HeapString>(value) {
+        Some(string) if string.is_rope() => code_units(heap, value).collect(),
+        _ => { return value; }
+    };
+    let flat = HeapString::from_units(&units);
+    heap.get_mut::<HeapString>(value).unwrap().contents = flat.contents;
+    value
+}
+
+pub fn strings_equal(heap: &Heap, a: Value, b: Value) -> bool {
+    if a == b {
+        return true;
+    }
+    if is_atom(heap, a) && is_atom(heap, b) {
+        return false;
+    }
+    (string_length(heap, a) == string_length(heap, b)) &&
+        code_units(heap, a).eq(code_units(heap, b))
+}
+
+// Immediate strings need no interning, so are atoms too.
+pub fn is_atom(heap: &Heap, value: Value) -> bool {
+    value.is_imm_str() || heap.get::<HeapString>(value).is_some_and(|string| string.is_atom())
+}
+
+// Intern a string, reusing its cell as the atom when possible.
+pub fn atomize(heap: &mut Heap, value: Value) -> Value {
+    if is_atom(heap, value) {
+        return value;
+    }
+    let units: Box<[u16]> = code_units(heap, value).collect();
+    if let Some(atom) = heap.atoms().lookup(&units) {
+        return atom;
+    }
+
+    flatten(heap, value);
+    heap.get_mut::<HeapString>(value).unwrap().atom = true;
+    heap.atoms_mut().insert(units, value);
+    value
+}
+
+pub fn atomize_str(heap: &mut Heap, text: &str) -> Value {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    atomize_units(heap, &units)
+}
+
+pub fn atomize_units(heap: &mut Heap, units: &[u16]) -> Value {
+    if let Some(atom) = heap.atoms().lookup(units) {
+        return atom;
+    }
+
+    let string = new_string_from_units(heap, units);
+    if string.is_imm_str() {
+        return string;
+    }
+    heap.get_mut::<HeapString>(string).unwrap().atom = true;
+    heap.atoms_mut().insert(units.into(), string);
+    string
+}
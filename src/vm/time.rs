@@ -0,0 +1,1022 @@
+/*!
+ * Time values, for `Date`.
+ *
+ * A time value is a number of milliseconds since 1970-01-01T00:00:00Z,
+ * ignoring leap seconds, or NaN for an invalid date.  The functions here
+ * are the spec's: they split time values into years, months, days and times
+ * of day, and make them from those, on the proleptic Gregorian calendar.
+ * Like the spec's, they take and give f64s, and pass NaN through.
+ *
+ * Local time is found from a `TimeZone`.  The system's is read from `$TZ` or
+ * /etc/localtime, either a compiled zoneinfo file or a POSIX TZ rule.  A
+ * zone can also be UTC outright, so that scripts see the same local times
+ * on every machine.  The current time comes from a `Clock`, which can be
+ * fixed or replaced for the same reason.
+ *
+ * `parse` reads the ISO 8601 format of the spec, and the RFC 2822 style
+ * dates which `toString` and `toUTCString` write and the web uses.
+ */
+
+use std::env;
+use std::f64;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MS_PER_SECOND: f64 = 1000.0;
+pub const MS_PER_MINUTE: f64 = 60_000.0;
+pub const MS_PER_HOUR: f64 = 3_600_000.0;
+pub const MS_PER_DAY: f64 = 86_400_000.0;
+
+// The greatest distance of a valid time value from the epoch.
+const MAX_TIME: f64 = 8.64e15;
+
+pub const WEEK_DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+pub const MONTH_NAMES: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+                                     "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/*****************************************************************************
+ **** Time value math ********************************************************
+ *****************************************************************************/
+
+pub fn day(t: f64) -> f64 {
+    (t / MS_PER_DAY).floor()
+}
+// Remainders of negative times can be -0, so zero is added to them.
+pub fn time_within_day(t: f64) -> f64 {
+    t.rem_euclid(MS_PER_DAY) + 0.0
+}
+
+pub fn days_in_year(year: f64) -> f64 {
+    if is_leap_year(year) { 366.0 } else { 365.0 }
+}
+fn is_leap_year(year: f64) -> bool {
+    year % 4.0 == 0.0 && (year % 100.0 != 0.0 || year % 400.0 == 0.0)
+}
+
+pub fn day_from_year(year: f64) -> f64 {
+    365.0 * (year - 1970.0) + ((year - 1969.0) / 4.0).floor() - ((year - 1901.0) / 100.0).floor() +
+        ((year - 1601.0) / 400.0).floor()
+}
+pub fn time_from_year(year: f64) -> f64 {
+    MS_PER_DAY * day_from_year(year)
+}
+
+pub fn year_from_time(t: f64) -> f64 {
+    if t.is_nan() {
+        return f64::NAN;
+    }
+    // Estimate, then correct by the year or so the estimate may be out.
+    let mut year = (day(t) / 365.2425).floor() + 1970.0;
+    while time_from_year(year) > t {
+        year -= 1.0;
+    }
+    while time_from_year(year + 1.0) <= t {
+        year += 1.0;
+    }
+    year
+}
+
+// The day within the year, from 0.
+fn day_within_year(t: f64) -> f64 {
+    day(t) - day_from_year(year_from_time(t))
+}
+
+// The day within the year on which each month starts, in a common year.
+const MONTH_STARTS: [f64; 13] = [0.0, 31.0, 59.0, 90.0, 120.0, 151.0, 181.0, 212.0, 243.0, 273.0, 304.0,
+                                 334.0, 365.0];
+
+// The day within a year on which a month starts.
+fn month_start(month: usize, leap: bool) -> f64 {
+    MONTH_STARTS[month] + if leap && month >= 2 { 1.0 } else { 0.0 }
+}
+
+// Months count from 0.
+pub fn month_from_time(t: f64) -> f64 {
+    if t.is_nan() {
+        return f64::NAN;
+    }
+    let leap = is_leap_year(year_from_time(t));
+    let day = day_within_year(t);
+    (0..12).find(|&month| day < month_start(month + 1, leap)).unwrap() as f64
+}
+
+// Dates within the month count from 1.
+pub fn date_from_time(t: f64) -> f64 {
+    if t.is_nan() {
+        return f64::NAN;
+    }
+    let leap = is_leap_year(year_from_time(t));
+    day_within_year(t) - month_start(month_from_time(t) as usize, leap) + 1.0
+}
+
+// Week days count from Sunday, as 0.
+pub fn week_day(t: f64) -> f64 {
+    (day(t) + 4.0).rem_euclid(7.0) + 0.0
+}
+
+pub fn hour_from_time(t: f64) -> f64 {
+    (t / MS_PER_HOUR).floor().rem_euclid(24.0) + 0.0
+}
+pub fn min_from_time(t: f64) -> f64 {
+    (t / MS_PER_MINUTE).floor().rem_euclid(60.0) + 0.0
+}
+pub fn sec_from_time(t: f64) -> f64 {
+    (t / MS_PER_SECOND).floor().rem_euclid(60.0) + 0.0
+}
+pub fn ms_from_time(t: f64) -> f64 {
+    t.rem_euclid(MS_PER_SECOND) + 0.0
+}
+
+/** The time within a day of some hours, minutes, seconds and milliseconds. */
+pub fn make_time(hour: f64, min: f64, sec: f64, ms: f64) -> f64 {
+    if ! (hour.is_finite() && min.is_finite() && sec.is_finite() && ms.is_finite()) {
+        return f64::NAN;
+    }
+    hour.trunc() * MS_PER_HOUR + min.trunc() * MS_PER_MINUTE + sec.trunc() * MS_PER_SECOND + ms.trunc()
+}
+
+/**
+ * The day number of a date.  Months out of range move the year, and dates
+ * out of range move the day from the start of the month.
+ */
+pub fn make_day(year: f64, month: f64, date: f64) -> f64 {
+    if ! (year.is_finite() && month.is_finite() && date.is_finite()) {
+        return f64::NAN;
+    }
+    let (year, month, date) = (year.trunc(), month.trunc(), date.trunc());
+    let year = year + (month / 12.0).floor();
+    // Years this far out are beyond any valid time value, and beyond the
+    // precision of the arithmetic.
+    if year.abs() > 400_000.0 {
+        return f64::NAN;
+    }
+    let month = month.rem_euclid(12.0) as usize;
+    day_from_year(year) + month_start(month, is_leap_year(year)) + date - 1.0
+}
+
+pub fn make_date(day: f64, time: f64) -> f64 {
+    if ! (day.is_finite() && time.is_finite()) {
+        return f64::NAN;
+    }
+    day * MS_PER_DAY + time
+}
+
+/** A time value within the range of dates, as an integer, or NaN. */
+pub fn time_clip(t: f64) -> f64 {
+    if ! t.is_finite() || t.abs() > MAX_TIME {
+        return f64::NAN;
+    }
+    // Adding zero makes -0 into +0.
+    t.trunc() + 0.0
+}
+
+/*****************************************************************************
+ **** Clocks *****************************************************************
+ *****************************************************************************/
+
+/** Where the current time comes from. */
+pub struct Clock {
+    now: Box<dyn Fn() -> f64 + Send>
+}
+impl Clock {
+    pub fn system() -> Clock {
+        Clock::from_fn(|| {
+            match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(since) => since.as_millis() as f64,
+                Err(before) => -(before.duration().as_millis() as f64)
+            }
+        })
+    }
+    // A clock which is always at the same time.
+    pub fn fixed(time: f64) -> Clock {
+        Clock::from_fn(move || time)
+    }
+    pub fn from_fn<F: Fn() -> f64 + Send + 'static>(now: F) -> Clock {
+        Clock { now: Box::new(now) }
+    }
+
+    // The current time value.
+    pub fn now(&self) -> f64 {
+        time_clip((self.now)())
+    }
+}
+
+/*****************************************************************************
+ **** Time zones *************************************************************
+ *****************************************************************************/
+
+/** How local time relates to UTC. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeZone {
+    Utc,
+    Posix(PosixZone),
+    Zoneinfo(ZoneinfoZone)
+}
+
+/** The offset of local time from UTC at some time. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneOffset<'a> {
+    // In milliseconds, positive east of Greenwich.
+    pub offset: f64,
+    pub abbreviation: &'a str
+}
+
+impl TimeZone {
+    /**
+     * The system's time zone: the one `$TZ` names, or else /etc/localtime.
+     * UTC if neither can be read.
+     */
+    pub fn system() -> TimeZone {
+        match env::var("TZ") {
+            Ok(ref tz) if ! tz.is_empty() => TimeZone::from_tz_variable(tz),
+            _ => TimeZone::from_file("/etc/localtime").unwrap_or(TimeZone::Utc)
+        }
+    }
+
+    // `$TZ` is a path, the name of a zoneinfo file or a POSIX rule.
+    fn from_tz_variable(tz: &str) -> TimeZone {
+        let name = tz.trim_start_matches(':');
+        let file = if name.starts_with('/') {
+            TimeZone::from_file(name)
+        } else if ! name.contains("..") {
+            TimeZone::from_file(&format!("/usr/share/zoneinfo/{}", name))
+        } else {
+            None
+        };
+        file.or_else(|| TimeZone::from_posix(tz)).unwrap_or(TimeZone::Utc)
+    }
+
+    fn from_file(path: &str) -> Option<TimeZone> {
+        fs::read(path).ok().and_then(|bytes| TimeZone::from_zoneinfo(&bytes))
+    }
+
+    /** A zone given by a POSIX TZ rule, such as "CET-1CEST,M3.5.0,M10.5.0/3". */
+    pub fn from_posix(rule: &str) -> Option<TimeZone> {
+        PosixZone::parse(rule).map(TimeZone::Posix)
+    }
+
+    /** A zone given by a compiled zoneinfo (TZif) file. */
+    pub fn from_zoneinfo(bytes: &[u8]) -> Option<TimeZone> {
+        ZoneinfoZone::parse(bytes).map(TimeZone::Zoneinfo)
+    }
+
+    /** The offset of local time at a (finite) time value. */
+    pub fn offset_at(&self, t: f64) -> ZoneOffset<'_> {
+        match *self {
+            TimeZone::Utc => ZoneOffset { offset: 0.0, abbreviation: "UTC" },
+            TimeZone::Posix(ref zone) => zone.offset_at(t),
+            TimeZone::Zoneinfo(ref zone) => zone.offset_at(t)
+        }
+    }
+
+    /** The spec's LocalTime: a time value in local time. */
+    pub fn local_time(&self, t: f64) -> f64 {
+        if ! t.is_finite() {
+            return f64::NAN;
+        }
+        t + self.offset_at(t).offset
+    }
+
+    /**
+     * The spec's UTC: the time value of a local time.  Local times which
+     * are skipped or repeated by a change of offset are taken with the
+     * offset before the change.
+     */
+    pub fn utc_time(&self, local: f64) -> f64 {
+        if ! local.is_finite() {
+            return f64::NAN;
+        }
+        // Offsets change at most once a day, so a local time has the offset
+        // from a day before it or from a day after it.
+        let before = self.offset_at(local - MS_PER_DAY).offset;
+        let after = self.offset_at(local + MS_PER_DAY).offset;
+        if self.offset_at(local - before).offset != before && self.offset_at(local - after).offset == after {
+            return local - after;
+        }
+        local - before
+    }
+}
+
+/**
+ * A time zone by a POSIX TZ rule: a standard offset, and optionally a
+ * daylight saving offset with the dates it starts and ends each year.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PosixZone {
+    standard_name: String,
+    // Offsets are in seconds, east of Greenwich.
+    standard_offset: i32,
+    daylight: Option<DaylightRule>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DaylightRule {
+    name: String,
+    offset: i32,
+    // The days and local times of day, in seconds, of the changes.
+    start: (RuleDay, i32),
+    end: (RuleDay, i32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDay {
+    // Jn: a day from 1 to 365, never counting February 29th.
+    Julian(u32),
+    // n: a day from 0 to 365.
+    Ordinal(u32),
+    // Mm.w.d: day d of week w of month m.  Week 5 is the month's last.
+    MonthWeekDay(u32, u32, u32)
+}
+
+impl RuleDay {
+    // The day number of the rule's day in a year.
+    fn day_in(self, year: f64) -> f64 {
+        let start = day_from_year(year);
+        match self {
+            RuleDay::Julian(day) => {
+                let leap_day = if is_leap_year(year) && day >= 60 { 1.0 } else { 0.0 };
+                start + (day as f64) - 1.0 + leap_day
+            }
+            RuleDay::Ordinal(day) => start + (day as f64),
+            RuleDay::MonthWeekDay(month, week, week_day_number) => {
+                let first = make_day(year, (month - 1) as f64, 1.0);
+                let month_length = make_day(year, month as f64, 1.0) - first;
+                let first_week_day = week_day(first * MS_PER_DAY);
+                let mut day = (week_day_number as f64 - first_week_day).rem_euclid(7.0) +
+                    ((week - 1) as f64) * 7.0;
+                while day >= month_length {
+                    day -= 7.0;
+                }
+                first + day
+            }
+        }
+    }
+}
+
+// Reads a POSIX TZ rule, byte by byte.
+struct RuleReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+impl<'a> RuleReader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).cloned()
+    }
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    // A zone name: three or more letters, or anything in angle brackets.
+    fn name(&mut self) -> Option<String> {
+        let start = self.position;
+        if self.eat(b'<') {
+            while self.peek().is_some_and(|byte| byte != b'>') {
+                self.position += 1;
+            }
+            let name = String::from_utf8_lossy(&self.bytes[(start + 1)..self.position]).into_owned();
+            return if self.eat(b'>') { Some(name) } else { None };
+        }
+        while self.peek().is_some_and(|byte| byte.is_ascii_alphabetic()) {
+            self.position += 1;
+        }
+        if self.position - start < 3 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    fn number(&mut self) -> Option<i32> {
+        let start = self.position;
+        let mut value: i32 = 0;
+        while let Some(byte) = self.peek().filter(u8::is_ascii_digit) {
+            value = value.checked_mul(10)?.checked_add((byte - b'0') as i32)?;
+            self.position += 1;
+        }
+        if self.position == start { None } else { Some(value) }
+    }
+
+    // [+-]hh[:mm[:ss]], in seconds.
+    fn time(&mut self) -> Option<i32> {
+        let sign = if self.eat(b'-') { -1 } else { self.eat(b'+'); 1 };
+        let mut seconds = self.number()? * 3600;
+        if self.eat(b':') {
+            seconds += self.number()? * 60;
+            if self.eat(b':') {
+                seconds += self.number()?;
+            }
+        }
+        Some(sign * seconds)
+    }
+
+    fn rule_day(&mut self) -> Option<(RuleDay, i32)> {
+        let day = if self.eat(b'J') {
+            RuleDay::Julian(self.number().filter(|day| (1..=365).contains(day))? as u32)
+        } else if self.eat(b'M') {
+            let month = self.number().filter(|month| (1..=12).contains(month))?;
+            let week = if self.eat(b'.') { self.number().filter(|week| (1..=5).contains(week))? } else { return None };
+            let day = if self.eat(b'.') { self.number().filter(|day| (0..=6).contains(day))? } else { return None };
+            RuleDay::MonthWeekDay(month as u32, week as u32, day as u32)
+        } else {
+            RuleDay::Ordinal(self.number().filter(|day| (0..=365).contains(day))? as u32)
+        };
+        let time = if self.eat(b'/') { self.time()? } else { 2 * 3600 };
+        Some((day, time))
+    }
+}
+
+impl PosixZone {
+    fn parse(rule: &str) -> Option<PosixZone> {
+        let mut reader = RuleReader { bytes: rule.as_bytes(), position: 0 };
+        let standard_name = reader.name()?;
+        // POSIX offsets are west of Greenwich.
+        let standard_offset = -reader.time()?;
+        let mut daylight = None;
+        if reader.peek().is_some() {
+            let name = reader.name()?;
+            let offset = match reader.peek() {
+                Some(b',') | None => standard_offset + 3600,
+                _ => -reader.time()?
+            };
+            // Without dates, the US's rules are the usual default.
+            let (start, end) = if reader.eat(b',') {
+                let start = reader.rule_day()?;
+                if ! reader.eat(b',') {
+                    return None;
+                }
+                (start, reader.rule_day()?)
+            } else {
+                ((RuleDay::MonthWeekDay(3, 2, 0), 7200), (RuleDay::MonthWeekDay(11, 1, 0), 7200))
+            };
+            daylight = Some(DaylightRule { name, offset, start, end });
+        }
+        if reader.peek().is_some() {
+            return None;
+        }
+        Some(PosixZone { standard_name, standard_offset, daylight })
+    }
+
+    fn offset_at(&self, t: f64) -> ZoneOffset<'_> {
+        let standard = ZoneOffset {
+            offset: (self.standard_offset as f64) * MS_PER_SECOND,
+            abbreviation: &self.standard_name
+        };
+        let rule = match self.daylight {
+            Some(ref rule) => rule,
+            None => { return standard; }
+        };
+        let daylight_offset = (rule.offset as f64) * MS_PER_SECOND;
+        let year = year_from_time(t + standard.offset);
+        // Daylight saving starts at a time in standard time, and ends at one
+        // in daylight saving time.
+        let start = rule.start.0.day_in(year) * MS_PER_DAY + (rule.start.1 as f64) * MS_PER_SECOND -
+            standard.offset;
+        let end = rule.end.0.day_in(year) * MS_PER_DAY + (rule.end.1 as f64) * MS_PER_SECOND - daylight_offset;
+        let in_daylight = if start < end {
+            start <= t && t < end
+        } else {
+            ! (end <= t && t < start)
+        };
+        if in_daylight {
+            ZoneOffset { offset: daylight_offset, abbreviation: &rule.name }
+        } else {
+            standard
+        }
+    }
+}
+
+/**
+ * A time zone from a compiled zoneinfo file: the times at which its offset
+ * changed, and a POSIX rule for times after the last of them.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneinfoZone {
+    // Seconds since the epoch, and the index of the local time type from
+    // then on.
+    transitions: Vec<(i64, usize)>,
+    // Offsets in seconds east of Greenwich, with their abbreviations.
+    types: Vec<(i32, String)>,
+    rule: Option<PosixZone>
+}
+
+// Reads big-endian values from a zoneinfo file.
+struct ZoneinfoReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+impl<'a> ZoneinfoReader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..(self.position + length))?;
+        self.position += length;
+        Some(bytes)
+    }
+    fn int(&mut self, length: usize) -> Option<i64> {
+        let bytes = self.take(length)?;
+        let unsigned = bytes.iter().fold(0u64, |value, &byte| (value << 8) | (byte as u64));
+        // Sign-extend from the value's width.
+        let shift = 64 - 8 * length;
+        Some(((unsigned << shift) as i64) >> shift)
+    }
+}
+
+impl ZoneinfoZone {
+    fn parse(bytes: &[u8]) -> Option<ZoneinfoZone> {
+        let mut reader = ZoneinfoReader { bytes, position: 0 };
+        let (version, counts) = ZoneinfoZone::parse_header(&mut reader)?;
+        if version == 0 {
+            return ZoneinfoZone::parse_data(&mut reader, counts, 4);
+        }
+        // Version 2 and later files repeat the data with 64-bit times, then
+        // give the rule for later times.
+        let [utc_count, standard_count, leap_count, transition_count, type_count, char_count] = counts;
+        let first_length = transition_count * 5 + type_count * 6 + char_count + leap_count * 8 + standard_count +
+            utc_count;
+        reader.take(first_length)?;
+        let (_, counts) = ZoneinfoZone::parse_header(&mut reader)?;
+        let mut zone = ZoneinfoZone::parse_data(&mut reader, counts, 8)?;
+        let footer = &bytes[reader.position..];
+        if footer.first() == Some(&b'\n') {
+            let end = footer[1..].iter().position(|&byte| byte == b'\n').map_or(footer.len(), |end| end + 1);
+            zone.rule = PosixZone::parse(&String::from_utf8_lossy(&footer[1..end]));
+        }
+        Some(zone)
+    }
+
+    fn parse_header(reader: &mut ZoneinfoReader) -> Option<(u8, [usize; 6])> {
+        if reader.take(4)? != b"TZif" {
+            return None;
+        }
+        let version = reader.take(1)?[0];
+        reader.take(15)?;
+        let mut counts = [0; 6];
+        for count in counts.iter_mut() {
+            *count = reader.int(4)? as usize;
+        }
+        Some((if version == 0 { 0 } else { version - b'0' }, counts))
+    }
+
+    fn parse_data(reader: &mut ZoneinfoReader, counts: [usize; 6], time_length: usize) -> Option<ZoneinfoZone> {
+        let [utc_count, standard_count, leap_count, transition_count, type_count, char_count] = counts;
+        if type_count == 0 {
+            return None;
+        }
+        let mut times: Vec<i64> = Vec::with_capacity(transition_count);
+        for _ in 0..transition_count {
+            times.push(reader.int(time_length)?);
+        }
+        let indices = reader.take(transition_count)?;
+        let mut raw_types: Vec<(i32, usize)> = Vec::with_capacity(type_count);
+        for _ in 0..type_count {
+            let offset = reader.int(4)? as i32;
+            let bytes = reader.take(2)?;
+            raw_types.push((offset, bytes[1] as usize));
+        }
+        let chars = reader.take(char_count)?;
+        reader.take(leap_count * (time_length + 4) + standard_count + utc_count)?;
+
+        let types = raw_types.iter().map(|&(offset, index)| {
+            let name = chars.get(index..).unwrap_or(&[]);
+            let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+            (offset, String::from_utf8_lossy(&name[..end]).into_owned())
+        }).collect();
+        let mut transitions: Vec<(i64, usize)> = Vec::with_capacity(transition_count);
+        for (&time, &index) in times.iter().zip(indices.iter()) {
+            if (index as usize) >= type_count {
+                return None;
+            }
+            transitions.push((time, index as usize));
+        }
+        Some(ZoneinfoZone { transitions, types, rule: None })
+    }
+
+    fn offset_at(&self, t: f64) -> ZoneOffset<'_> {
+        let seconds = (t / MS_PER_SECOND).floor() as i64;
+        let index = match self.transitions.binary_search_by(|&(time, _)| time.cmp(&seconds)) {
+            Ok(found) => found,
+            Err(0) => {
+                let (offset, ref name) = self.types[0];
+                return ZoneOffset { offset: (offset as f64) * MS_PER_SECOND, abbreviation: name };
+            }
+            Err(after) => after - 1
+        };
+        if index + 1 == self.transitions.len() {
+            if let Some(ref rule) = self.rule {
+                return rule.offset_at(t);
+            }
+        }
+        let (offset, ref name) = self.types[self.transitions[index].1];
+        ZoneOffset { offset: (offset as f64) * MS_PER_SECOND, abbreviation: name }
+    }
+}
+
+/*****************************************************************************
+ **** Formatting *************************************************************
+ *****************************************************************************/
+
+// A year as dates write it: at least four digits, with a sign if negative.
+fn format_year(year: f64) -> String {
+    if year < 0.0 {
+        format!("-{:04}", -year)
+    } else {
+        format!("{:04}", year)
+    }
+}
+
+fn format_offset(offset: f64) -> String {
+    let minutes = (offset / MS_PER_MINUTE) as i64;
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{}{:02}{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/** The format of `toISOString`, for a finite time value. */
+pub fn to_iso_string(t: f64) -> String {
+    let year = year_from_time(t);
+    let year = if (0.0..=9999.0).contains(&year) {
+        format!("{:04}", year)
+    } else {
+        format!("{}{:06}", if year < 0.0 { '-' } else { '+' }, year.abs())
+    };
+    format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month_from_time(t) + 1.0, date_from_time(t),
+            hour_from_time(t), min_from_time(t), sec_from_time(t), ms_from_time(t))
+}
+
+/** The format of `toUTCString`: RFC 1123's. */
+pub fn to_utc_string(t: f64) -> String {
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT", WEEK_DAY_NAMES[week_day(t) as usize], date_from_time(t),
+            MONTH_NAMES[month_from_time(t) as usize], format_year(year_from_time(t)), hour_from_time(t),
+            min_from_time(t), sec_from_time(t))
+}
+
+/** The date part of `toString`, for a local time. */
+pub fn to_date_string(local: f64) -> String {
+    format!("{} {} {:02} {}", WEEK_DAY_NAMES[week_day(local) as usize], MONTH_NAMES[month_from_time(local) as usize],
+            date_from_time(local), format_year(year_from_time(local)))
+}
+
+/** The time part of `toString`, for a time value in a zone. */
+pub fn to_time_string(t: f64, zone: &TimeZone) -> String {
+    let offset = zone.offset_at(t);
+    let local = t + offset.offset;
+    format!("{:02}:{:02}:{:02} GMT{} ({})", hour_from_time(local), min_from_time(local), sec_from_time(local),
+            format_offset(offset.offset), offset.abbreviation)
+}
+
+/** The format of `toString`, for a finite time value. */
+pub fn to_date_time_string(t: f64, zone: &TimeZone) -> String {
+    format!("{} {}", to_date_string(zone.local_time(t)), to_time_string(t, zone))
+}
+
+/*****************************************************************************
+ **** Parsing ****************************************************************
+ *****************************************************************************/
+
+/**
+ * The time value a date string gives, or NaN.  Times without an offset are
+ * local, but ISO dates without a time are UTC, as engines read them.
+ */
+pub fn parse(text: &str, zone: &TimeZone) -> f64 {
+    let text = text.trim();
+    match parse_iso(text.as_bytes(), zone) {
+        Some(t) => t,
+        None => parse_legacy(text, zone)
+    }
+}
+
+// Reads fixed-width fields of an ISO date.
+struct IsoReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+impl<'a> IsoReader<'a> {
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.bytes.get(self.position) == Some(&byte) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+    fn digits(&mut self, count: usize) -> Option<f64> {
+        let digits = self.bytes.get(self.position..(self.position + count))?;
+        if ! digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.position += count;
+        Some(digits.iter().fold(0.0, |value, &digit| value * 10.0 + ((digit - b'0') as f64)))
+    }
+    fn at_end(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+}
+
+// YYYY[-MM[-DD]][THH:mm[:ss[.sss]][Z|(+|-)HH:mm]], with six digit signed
+// years allowed too.
+fn parse_iso(bytes: &[u8], zone: &TimeZone) -> Option<f64> {
+    let mut reader = IsoReader { bytes, position: 0 };
+    let year = if reader.eat(b'+') {
+        reader.digits(6)?
+    } else if reader.eat(b'-') {
+        let year = reader.digits(6)?;
+        // There's only one way to write year zero.
+        if year == 0.0 {
+            return None;
+        }
+        -year
+    } else {
+        reader.digits(4)?
+    };
+    let mut month = 1.0;
+    let mut date = 1.0;
+    if reader.eat(b'-') {
+        month = reader.digits(2).filter(|month| (1.0..=12.0).contains(month))?;
+        if reader.eat(b'-') {
+            date = reader.digits(2).filter(|date| (1.0..=31.0).contains(date))?;
+        }
+    }
+    let day = make_day(year, month - 1.0, date);
+    if date_from_time(make_date(day, 0.0)) != date {
+        return None;
+    }
+    if reader.at_end() {
+        return Some(time_clip(make_date(day, 0.0)));
+    }
+
+    if ! reader.eat(b'T') && ! reader.eat(b't') && ! reader.eat(b' ') {
+        return None;
+    }
+    let hour = reader.digits(2).filter(|hour| *hour <= 24.0)?;
+    if ! reader.eat(b':') {
+        return None;
+    }
+    let min = reader.digits(2).filter(|min| *min < 60.0)?;
+    let mut sec = 0.0;
+    let mut ms = 0.0;
+    if reader.eat(b':') {
+        sec = reader.digits(2).filter(|sec| *sec < 60.0)?;
+        if reader.eat(b'.') || reader.eat(b',') {
+            // Digits past milliseconds are allowed, and dropped.
+            let start = reader.position;
+            while reader.digits(1).is_some() {}
+            let fraction = &bytes[start..reader.position];
+            if fraction.is_empty() {
+                return None;
+            }
+            ms = fraction.iter().chain([b'0', b'0'].iter()).take(3)
+                .fold(0.0, |value, &digit| value * 10.0 + ((digit - b'0') as f64));
+        }
+    }
+    if hour == 24.0 && (min != 0.0 || sec != 0.0 || ms != 0.0) {
+        return None;
+    }
+    let local = make_date(day, make_time(hour, min, sec, ms));
+
+    if reader.at_end() {
+        return Some(time_clip(zone.utc_time(local)));
+    }
+    let offset = if reader.eat(b'Z') || reader.eat(b'z') {
+        0.0
+    } else {
+        let sign = if reader.eat(b'+') { 1.0 } else if reader.eat(b'-') { -1.0 } else { return None; };
+        let hours = reader.digits(2).filter(|hours| *hours <= 23.0)?;
+        reader.eat(b':');
+        let minutes = reader.digits(2).filter(|minutes| *minutes < 60.0)?;
+        sign * (hours * MS_PER_HOUR + minutes * MS_PER_MINUTE)
+    };
+    if ! reader.at_end() {
+        return None;
+    }
+    Some(time_clip(local - offset))
+}
+
+// The offsets of the zone names RFC 2822 allows, in minutes.
+const ZONE_NAMES: [(&str, f64); 12] = [
+    ("ut", 0.0), ("utc", 0.0), ("gmt", 0.0), ("z", 0.0),
+    ("est", -300.0), ("edt", -240.0), ("cst", -360.0), ("cdt", -300.0),
+    ("mst", -420.0), ("mdt", -360.0), ("pst", -480.0), ("pdt", -420.0)
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateToken<'a> {
+    Number(f64, usize),
+    Word(&'a str),
+    Sign(f64),
+    Punctuation(u8)
+}
+
+fn date_tokens(text: &str) -> Option<Vec<DateToken<'_>>> {
+    let bytes = text.as_bytes();
+    let mut tokens: Vec<DateToken> = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        let start = index;
+        if byte.is_ascii_digit() {
+            while index < bytes.len() && bytes[index].is_ascii_digit() {
+                index += 1;
+            }
+            let value = text[start..index].parse::<f64>().ok()?;
+            tokens.push(DateToken::Number(value, index - start));
+            continue;
+        }
+        if byte.is_ascii_alphabetic() {
+            while index < bytes.len() && bytes[index].is_ascii_alphabetic() {
+                index += 1;
+            }
+            tokens.push(DateToken::Word(&text[start..index]));
+            continue;
+        }
+        index += 1;
+        match byte {
+            b'(' => {
+                // Comments, such as a zone's name, are skipped.
+                let mut depth = 1;
+                while index < bytes.len() && depth > 0 {
+                    match bytes[index] {
+                        b'(' => depth += 1,
+                        b')' => depth -= 1,
+                        _ => {}
+                    }
+                    index += 1;
+                }
+            }
+            b'+' => tokens.push(DateToken::Sign(1.0)),
+            b'-' => tokens.push(DateToken::Sign(-1.0)),
+            b':' | b'/' | b'.' => tokens.push(DateToken::Punctuation(byte)),
+            b' ' | b'\t' | b'\n' | b'\r' | b',' => {}
+            _ => { return None; }
+        }
+    }
+    Some(tokens)
+}
+
+/**
+ * Dates as `toString` and `toUTCString` write them, RFC 2822's, and the
+ * web's variations: a month name or month/day/year, a day and a year in
+ * either order, an optional time with AM or PM, and an optional zone.
+ */
+fn parse_legacy(text: &str, zone: &TimeZone) -> f64 {
+    parse_legacy_fields(text).map_or(f64::NAN, |(local, offset)| {
+        match offset {
+            Some(offset) => time_clip(local - offset * MS_PER_MINUTE),
+            None => time_clip(zone.utc_time(local))
+        }
+    })
+}
+
+// The local time a legacy date gives, and its offset in minutes if it has
+// one.
+fn parse_legacy_fields(text: &str) -> Option<(f64, Option<f64>)> {
+    let tokens = date_tokens(text)?;
+    let mut year: Option<f64> = None;
+    let mut month: Option<f64> = None;
+    let mut date: Option<f64> = None;
+    let mut time: Option<[f64; 4]> = None;
+    let mut offset: Option<f64> = None;
+    let mut after_noon: Option<bool> = None;
+
+    let token = |at: usize| tokens.get(at).cloned();
+    let mut index = 0;
+    while index < tokens.len() {
+        match tokens[index] {
+            DateToken::Word(word) => {
+                let word = word.to_ascii_lowercase();
+                if let Some(found) = MONTH_NAMES.iter().position(|name| word.starts_with(&name.to_ascii_lowercase())) {
+                    if month.is_some() || word.len() < 3 {
+                        return None;
+                    }
+                    month = Some(found as f64);
+                } else if WEEK_DAY_NAMES.iter().any(|name| word.starts_with(&name.to_ascii_lowercase())) {
+                    // Week days are ignored, as they must follow from the date.
+                } else if word == "am" || word == "pm" {
+                    after_noon = Some(word == "pm");
+                } else if word == "t" && time.is_none() {
+                    // ISO style dates which didn't parse as ISO.
+                } else {
+                    let (_, zone_offset) = *ZONE_NAMES.iter().find(|&&(name, _)| name == word)?;
+                    offset = Some(zone_offset);
+                }
+                index += 1;
+            }
+            DateToken::Number(hour, _) if token(index + 1) == Some(DateToken::Punctuation(b':')) => {
+                if time.is_some() {
+                    return None;
+                }
+                let mut fields = [hour, 0.0, 0.0, 0.0];
+                index += 1;
+                let mut field = 1;
+                while field < 4 {
+                    let separator = if field == 3 { b'.' } else { b':' };
+                    match (token(index), token(index + 1)) {
+                        (Some(DateToken::Punctuation(punctuation)), Some(DateToken::Number(value, digits)))
+                            if punctuation == separator =>
+                        {
+                            fields[field] = if field == 3 {
+                                // A fraction of a second, to milliseconds.
+                                (value / 10f64.powi(digits as i32 - 3)).trunc()
+                            } else {
+                                value
+                            };
+                            index += 2;
+                            field += 1;
+                        }
+                        _ => break
+                    }
+                }
+                if fields[0] >= 24.0 || fields[1] >= 60.0 || fields[2] >= 60.0 {
+                    return None;
+                }
+                time = Some(fields);
+            }
+            DateToken::Number(first, _) if token(index + 1) == Some(DateToken::Punctuation(b'/')) => {
+                // month/day/year
+                match (token(index + 2), token(index + 3), token(index + 4)) {
+                    (Some(DateToken::Number(second, _)), Some(DateToken::Punctuation(b'/')),
+                     Some(DateToken::Number(third, _))) =>
+                    {
+                        if month.is_some() || date.is_some() || year.is_some() {
+                            return None;
+                        }
+                        month = Some(first - 1.0);
+                        date = Some(second);
+                        year = Some(third);
+                        index += 5;
+                    }
+                    _ => { return None; }
+                }
+            }
+            DateToken::Number(first, digits) if digits >= 3 && token(index + 1) == Some(DateToken::Sign(-1.0)) => {
+                // year-month-day
+                match (token(index + 2), token(index + 3), token(index + 4)) {
+                    (Some(DateToken::Number(second, _)), Some(DateToken::Sign(sign)),
+                     Some(DateToken::Number(third, _))) if sign < 0.0 =>
+                    {
+                        if month.is_some() || date.is_some() || year.is_some() {
+                            return None;
+                        }
+                        year = Some(first);
+                        month = Some(second - 1.0);
+                        date = Some(third);
+                        index += 5;
+                    }
+                    _ => { return None; }
+                }
+            }
+            DateToken::Number(value, digits) => {
+                if date.is_none() && digits <= 2 && value >= 1.0 && year.is_none() || (date.is_none() && year.is_some()) {
+                    date = Some(value);
+                } else if year.is_none() {
+                    year = Some(value);
+                } else {
+                    return None;
+                }
+                index += 1;
+            }
+            DateToken::Sign(sign) => {
+                // An offset, after a time or a zone: +hhmm or +hh:mm.
+                let (hours, minutes, length) = match (token(index + 1), token(index + 2), token(index + 3)) {
+                    (Some(DateToken::Number(hours, 2)), Some(DateToken::Punctuation(b':')),
+                     Some(DateToken::Number(minutes, 2))) => (hours, minutes, 4),
+                    (Some(DateToken::Number(value, 4)), _, _) => ((value / 100.0).trunc(), value % 100.0, 2),
+                    (Some(DateToken::Number(hours, digits)), _, _) if digits <= 2 => (hours, 0.0, 2),
+                    _ => { return None; }
+                };
+                if time.is_none() && offset.is_none() {
+                    return None;
+                }
+                if minutes >= 60.0 {
+                    return None;
+                }
+                offset = Some(offset.unwrap_or(0.0) + sign * (hours * 60.0 + minutes));
+                index += length;
+            }
+            DateToken::Punctuation(_) => { return None; }
+        }
+    }
+
+    let mut year = year?;
+    let month = month?;
+    let date = date.unwrap_or(1.0);
+    if ! (0.0..12.0).contains(&month) || ! (1.0..=31.0).contains(&date) {
+        return None;
+    }
+    // Two digit years are in 1950 to 2049.
+    if year < 50.0 {
+        year += 2000.0;
+    } else if year < 100.0 {
+        year += 1900.0;
+    }
+    let [mut hour, min, sec, ms] = time.unwrap_or([0.0; 4]);
+    if let Some(after_noon) = after_noon {
+        if time.is_none() || hour > 12.0 || hour == 0.0 {
+            return None;
+        }
+        hour = hour % 12.0 + if after_noon { 12.0 } else { 0.0 };
+    }
+    let day = make_day(year, month, date);
+    if date_from_time(make_date(day, 0.0)) != date {
+        return None;
+    }
+    Some((make_date(day, make_time(hour, min, sec, ms)), offset))
+}
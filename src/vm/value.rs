@@ -11,6 +11,9 @@
  * boxed-value low-bits.  Doubles falling outside of this range are heap-boxed.
  */
 
+use vm::heap::{Heap, HeapDouble, HeapValue};
+use vm::string;
+
 const MASK_TAG: u8 = 0b1111;
 
 const TAG_IMM_I32: u8 = 0b0000;
@@ -38,18 +41,22 @@ const SHIFT_IMM_BOOL: u8 = 32;
 
 const PAYLOAD_IMM_UNDEF: u32 = 1;
 const PAYLOAD_IMM_NULL: u32 = 2;
+const PAYLOAD_IMM_HOLE: u32 = 3;
 
 const SHIFT_IMM_STR_LENGTH: u8 = 4;
-const IMM_STR_MAX_LENGTH: u8 = 7;
+pub const IMM_STR_MAX_LENGTH: u8 = 7;
 
 const F64_TAG_ADJUST: u8 = 0b0111;
 const F64_ROTATE: u8 = 5;
+const F64_MAX_ROTATED_TAG: u8 = TAG_IMM_F64_MAX - F64_TAG_ADJUST;
 
-/** Opaque heap-value struct. */
-pub struct HeapValue;
 
-/** The VM's boxed value format. */
-#[derive(Debug, Clone, Copy)]
+/**
+ * The VM's boxed value format.  Comparing values with `==` compares their
+ * representations, so it is identity for heap values.  Use `strict_equals`
+ * for the language's notion of equality.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Value(u64);
 
 impl Value {
@@ -89,11 +96,19 @@ impl Value {
         Value(((PAYLOAD_IMM_NULL as u64) << SHIFT_IMM_UNDEF_NULL) | (TAG_IMM_UNDEF_NULL as u64))
     }
 
+    // The hole marks a missing element in array storage.  It is never a
+    // language value, and must not escape the storage.
+    #[inline(always)]
+    pub fn new_imm_hole() -> Value {
+        Value(((PAYLOAD_IMM_HOLE as u64) << SHIFT_IMM_UNDEF_NULL) | (TAG_IMM_UNDEF_NULL as u64))
+    }
+
     #[inline(always)]
     pub fn new_imm_bool(bval: bool) -> Value {
         Value(((bval as u64) << SHIFT_IMM_BOOL) | (TAG_IMM_BOOL as u64))
     }
 
+    // Immediate strings hold up to IMM_STR_MAX_LENGTH Latin-1 chars.
     pub fn new_imm_str(bytes: &[u8]) -> Value {
         let len = bytes.len();
         assert!(bytes.len() <= (IMM_STR_MAX_LENGTH as usize));
@@ -104,8 +119,10 @@ impl Value {
         Value(uval)
     }
 
+    // Only the heap makes pointer values, so that safe code outside the crate
+    // can't forge one for `Heap::get` to dereference.
     #[inline(always)]
-    pub fn new_ptr(ptr: *const HeapValue) -> Value {
+    pub(crate) fn new_ptr(ptr: *const HeapValue) -> Value {
         // Low 4 bits must be zero.
         let uval = ptr as u64;
         assert!(uval & 0xFu64 == 0);
@@ -116,10 +133,37 @@ impl Value {
     pub fn new_imm_f64(fval: f64) -> Value {
         let uval = Value::f64_to_u64(fval);
         let rot_uval = uval.rotate_left(F64_ROTATE as u32);
-        assert!(Value::extract_u64_tag(rot_uval) <= 0b1000);
+        assert!(Value::extract_u64_tag(rot_uval) <= F64_MAX_ROTATED_TAG);
         Value(rot_uval + (F64_TAG_ADJUST as u64))
     }
 
+    // Doubles are immediates when the high bits of their exponent are in range,
+    // i.e. when their magnitude is below 2^129.  This excludes infinities and NaNs.
+    #[inline(always)]
+    pub fn is_imm_f64_representable(fval: f64) -> bool {
+        let rot_uval = Value::f64_to_u64(fval).rotate_left(F64_ROTATE as u32);
+        Value::extract_u64_tag(rot_uval) <= F64_MAX_ROTATED_TAG
+    }
+
+    // Box a double, on the heap if it can't be an immediate.
+    pub fn from_f64(heap: &mut Heap, fval: f64) -> Value {
+        if Value::is_imm_f64_representable(fval) {
+            Value::new_imm_f64(fval)
+        } else {
+            heap.alloc(HeapDouble::new(fval))
+        }
+    }
+
+    // Box a number, as an int32 when it is one.
+    pub fn new_number(heap: &mut Heap, fval: f64) -> Value {
+        let ival = fval as i32;
+        if (ival as f64) == fval && ! (ival == 0 && fval.is_sign_negative()) {
+            Value::new_imm_i32(ival)
+        } else {
+            Value::from_f64(heap, fval)
+        }
+    }
+
     #[inline(always)]
     fn extract_tag(&self) -> u8 {
         (self.0 as u8) & MASK_TAG
@@ -158,6 +202,10 @@ impl Value {
         self.0 == Value::new_imm_null().0
     }
     #[inline(always)]
+    pub fn is_imm_hole(&self) -> bool {
+        self.0 == Value::new_imm_hole().0
+    }
+    #[inline(always)]
     pub fn is_imm_false(&self) -> bool {
         self.0 == Value::new_imm_bool(false).0
     }
@@ -223,12 +271,12 @@ impl Value {
     #[inline(always)]
     pub unsafe fn get_unchecked_ptr(&self) -> *const HeapValue {
         assert!(self.is_ptr());
-        self.0 as *const HeapValue
+        (self.0 & !(MASK_TAG as u64)) as *const HeapValue
     }
     #[inline(always)]
     pub unsafe fn get_unchecked_mut_ptr(&self) -> *mut HeapValue {
         assert!(self.is_ptr());
-        self.0 as *mut HeapValue
+        (self.0 & !(MASK_TAG as u64)) as *mut HeapValue
     }
     pub fn get_ptr(&self) -> Option<*const HeapValue> {
         if self.is_ptr() {
@@ -258,6 +306,42 @@ impl Value {
         }
     }
 
+    pub fn is_heap_f64(&self, heap: &Heap) -> bool {
+        heap.get::<HeapDouble>(*self).is_some()
+    }
+    pub fn is_number(&self, heap: &Heap) -> bool {
+        self.is_imm_i32() || self.is_imm_f64() || self.is_heap_f64(heap)
+    }
+
+    // Get the numeric value of any number representation.
+    pub fn to_f64(&self, heap: &Heap) -> Option<f64> {
+        if let Some(ival) = self.get_imm_i32() {
+            return Some(ival as f64);
+        }
+        if let Some(fval) = self.get_imm_f64() {
+            return Some(fval);
+        }
+        heap.get::<HeapDouble>(*self).map(|double| double.value())
+    }
+
+    pub fn new_string(heap: &mut Heap, text: &str) -> Value {
+        string::new_string(heap, text)
+    }
+    pub fn is_string(&self, heap: &Heap) -> bool {
+        string::is_string(heap, *self)
+    }
+
+    // Compare two values with the semantics of `===`.
+    pub fn strict_equals(&self, other: Value, heap: &Heap) -> bool {
+        if let (Some(a), Some(b)) = (self.to_f64(heap), other.to_f64(heap)) {
+            return a == b;
+        }
+        if self.is_string(heap) && other.is_string(heap) {
+            return string::strings_equal(heap, *self, other);
+        }
+        *self == other
+    }
+
     pub fn unpack(&self) -> UnpackedValue {
         match self.extract_tag() {
             TAG_IMM_I32 => {
@@ -0,0 +1,96 @@
+extern crate liboxyjs;
+
+mod common;
+
+use common::run;
+use liboxyjs::vm::array::{self, ArrayElements, MAX_DENSE_HOLE};
+use liboxyjs::vm::heap::Heap;
+use liboxyjs::vm::object::{self, JsObject, Property};
+use liboxyjs::vm::shape::PropertyAttributes;
+use liboxyjs::vm::string;
+use liboxyjs::vm::value::Value;
+
+#[test]
+fn elements_go_sparse_past_large_holes() {
+    let mut elements = ArrayElements::new();
+    for i in 0..10 {
+        elements.push(Value::new_imm_i32(i));
+    }
+    // A hole no bigger than the limit stays dense.
+    elements.set(10 + MAX_DENSE_HOLE, Value::new_imm_f64(0.5));
+    assert!(elements.is_dense());
+    assert_eq!(elements.length(), MAX_DENSE_HOLE + 11);
+    assert!(! elements.has(10) && elements.has(10 + MAX_DENSE_HOLE));
+
+    elements.delete(3);
+    elements.set(100000, Value::new_imm_bool(true));
+    assert!(! elements.is_dense());
+    assert_eq!(elements.length(), 100001);
+    assert_eq!(elements.get(9), Some(Value::new_imm_i32(9)));
+    assert_eq!(elements.get(3), None);
+    assert_eq!(elements.indices(), [0, 1, 2, 4, 5, 6, 7, 8, 9, 10 + MAX_DENSE_HOLE, 100000]);
+
+    // Truncation removes the elements past the new length, in either form.
+    elements.set_length(5);
+    assert_eq!(elements.indices(), [0, 1, 2, 4]);
+    assert_eq!(elements.length(), 5);
+    let mut dense = ArrayElements::new();
+    dense.set(3, Value::new_imm_i32(3));
+    dense.push_hole();
+    assert_eq!(dense.length(), 5);
+    dense.set_length(2);
+    assert!(dense.is_dense() && ! dense.has_elements());
+    assert_eq!(array::length_value(array::MAX_LENGTH), Value::new_imm_f64(4294967295.0));
+}
+
+#[test]
+fn truncation_stops_at_non_configurable_elements() {
+    let mut heap = Heap::new();
+    let array = array::new_array(&mut heap, Value::new_imm_null());
+    for i in 0..6 {
+        let key = string::atomize_str(&mut heap, &i.to_string());
+        object::set_own_value(&mut heap, array, key, Value::new_imm_i32(i));
+    }
+    // An element with other attributes moves into the shape.
+    let key = string::atomize_str(&mut heap, "2");
+    let property = Property::Data { value: Value::new_imm_i32(20), attrs: PropertyAttributes::WRITABLE };
+    object::define_own_property(&mut heap, array, key, property);
+    assert!(heap.get::<JsObject>(array).unwrap().shape().lookup(key).is_some());
+    assert!(! heap.get::<JsObject>(array).unwrap().elements().unwrap().has(2));
+
+    assert!(! object::set_array_length(&mut heap, array, 1));
+    let elements = heap.get::<JsObject>(array).unwrap().elements().unwrap();
+    assert_eq!((elements.length(), elements.indices()), (3, vec![0, 1]));
+    let keys: Vec<String> = object::own_keys(&mut heap, array).into_iter()
+        .map(|key| string::to_rust_string(&heap, key).unwrap())
+        .collect();
+    assert_eq!(keys, ["0", "1", "2", "length"]);
+}
+
+#[test]
+fn length_follows_elements() {
+    assert_eq!(run("var a = [1, 2, 3]; a[9] = 0; var r = [a.length]; a.length = 2; \
+                    r.push(a.length, a[2], a.join()); a.length = 4; r.concat(a.join(), 2 in a).join('|')"),
+               "10|2||1,2|1,2,,|false");
+    assert_eq!(run("var a = []; a[4294967294] = 1; a[4294967295] = 2; \
+                    [a.length, Object.keys(a).join()].join('|')"), "4294967295|4294967294,4294967295");
+    assert_eq!(run("var a = [1, 2]; a['1'] = 'one'; a['01'] = 'x'; a[1.5] = 'y'; a[-0] = 'z'; \
+                    [a.length, a[1], a.join()].join('|')"), "2|one|z,one");
+    assert_eq!(run("[].length = -1"), "threw RangeError: Invalid array length");
+    assert_eq!(run("new Array(2.5)"), "threw RangeError: Invalid array length");
+    assert_eq!(run("var a = [1, 2, 3]; Object.defineProperty(a, 'length', { writable: false }); \
+                    a.length = 0; a[5] = 1; [a.length, a.join()].join('|')"), "3|1,2,3");
+    assert_eq!(run("'use strict'; var a = Object.freeze([1]); a.length = 0"),
+               "threw TypeError: Cannot assign to read only property 'length'");
+}
+
+#[test]
+fn sparse_arrays_behave_like_dense_ones() {
+    assert_eq!(run("var a = [0]; a[1000000] = 1; a[5] = 5; var r = []; for (var k in a) r.push(k); \
+                    delete a[5]; r.concat(a.length, a.indexOf(1), a.lastIndexOf(0), 5 in a).join()"),
+               "0,5,1000000,1000001,1000000,0,false");
+    assert_eq!(run("var a = []; a[100000] = 'x'; a.length = 10; a[3] = 'y'; [a.length, a.join('-')].join('|')"),
+               "10|---y------");
+    assert_eq!(run("var a = [3, 1, 2]; a[5000] = 0; a.sort(); a.slice(0, 5).concat(a.length).join()"),
+               "0,1,2,3,,5001");
+}
@@ -0,0 +1,114 @@
+extern crate liboxyjs;
+
+mod common;
+
+use common::run;
+use liboxyjs::parser::ast_builder::AstBuilder;
+use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::vm::interpreter::Interpreter;
+
+#[test]
+fn object_functions_define_and_inspect_properties() {
+    assert_eq!(run("var o = Object.create({inherited: 1}, {a: {value: 1, enumerable: true}, b: {value: 2}}); \
+                    [Object.keys(o), Object.getPrototypeOf(o).inherited, o.b, Object.getOwnPropertyNames(o)] \
+                    .join('|')"), "a|1|2|a,b");
+    assert_eq!(run("var o = {}; Object.defineProperty(o, 'x', \
+                    {get: function () { return this.y * 2; }, configurable: true}); o.y = 4; \
+                    var d = Object.getOwnPropertyDescriptor(o, 'x'); \
+                    [o.x, typeof d.get, d.set, d.enumerable, d.configurable, 'value' in d].join()"),
+               "8,function,,false,true,false");
+    assert_eq!(run("Object.defineProperty(Object.freeze({}), 'x', {value: 1})"),
+               "threw TypeError: Cannot redefine property: x");
+    assert_eq!(run("[Object.getPrototypeOf(Object.create(null)), Object.isFrozen(Object.freeze({a: 1})), \
+                    Object.isExtensible(Object.preventExtensions({}))].join()"), ",true,false");
+}
+
+#[test]
+fn functions_call_apply_and_bind() {
+    assert_eq!(run("function f(a, b) { return [this.n, a, b].join('/'); } var t = {n: 'T'}; var g = f.bind(t, 1); \
+                    [f.call(t, 1, 2), f.apply(t, [3, 4]), g(5), g.length, \
+                    new ((function (x) { this.x = x; }).bind(null, 7))().x].join()"), "T/1/2,T/3/4,T/1/5,1,7");
+    assert_eq!(run("Math.max.apply(null, [1, 5, 3])"), "5");
+    assert_eq!(run("Function.prototype.call.call(1)"),
+               "threw TypeError: Function.prototype.call called on non-function 1");
+}
+
+#[test]
+fn array_methods_follow_es5() {
+    assert_eq!(run("var a = [1, 2, 3, 4, 5]; \
+                    JSON.stringify([a.push(6, 7), a.pop(), a.slice(1, -3), a.splice(1, 2, 'x', 'y', 'z'), a])"),
+               "[7,7,[2,3],[2,3],[1,\"x\",\"y\",\"z\",4,5,6]]");
+    assert_eq!(run("[1, 2, 3, 4].map(function (x, i) { return x * i; }).filter(function (x) { return x > 1; }) \
+                    .reduce(function (s, x) { return s + x; }, 10)"), "30");
+    assert_eq!(run("[[10, 9, 1, 2].sort(), [10, 9, 1, 2].sort(function (a, b) { return a - b; }), \
+                    ['b', undefined, 'a', , 'c'].sort(), [1, 2, 1].indexOf(1, 1), [1, 2, 1].lastIndexOf(1, -2), \
+                    [NaN].indexOf(NaN)].join('|')"), "1,10,2,9|1,2,9,10|a,b,c,,|2|0|-1");
+    assert_eq!(run("[[1, [2, [3]]].join(';'), [null, undefined, 0].join(), [].reduceRight(function () {}, 'init'), \
+                    [1, 2, 3].some(function (x) { return x > 2; }), \
+                    [1, 2, 3].every(function (x) { return x > 2; })].join('|')"), "1;2,3|,,0|init|true|false");
+    assert_eq!(run("[].reduce(function () {})"), "threw TypeError: Reduce of empty array with no initial value");
+    // The methods are generic, working on array-like objects.
+    assert_eq!(run("var o = {length: 2, 0: 'a', 1: 'b'}; Array.prototype.push.call(o, 'c'); \
+                    [o.length, Array.prototype.join.call(o, '+')].join()"), "3,a+b+c");
+}
+
+#[test]
+fn errors_have_names_messages_and_stacks() {
+    assert_eq!(run("var e = new RangeError('bad'); \
+                    [e.name, e.message, e instanceof RangeError, e instanceof Error, \
+                    Object.prototype.toString.call(e), String(e), typeof e.stack, RangeError.prototype.name, \
+                    Error('x').message].join('|')"),
+               "RangeError|bad|true|true|[object Error]|RangeError: bad|string|RangeError|x");
+    assert_eq!(run("try { null.x; } catch (e) { [e.constructor === TypeError, e instanceof Error].join(); }"),
+               "true,true");
+    assert_eq!(run("try { undefinedVar; } catch (e) { e.name }"), "ReferenceError");
+    assert_eq!(run("try { new RegExp('('); } catch (e) { e.name }"), "SyntaxError");
+    assert_eq!(run("var e = new Error(); [e.message === '', String(e), String(new TypeError('m')), \
+                    Error.prototype.toString.call({name: 'N', message: ''})].join('|')"), "true|Error|TypeError: m|N");
+    // The stack names the functions the error passed through.
+    assert_eq!(run("function inner() { return new Error('e'); } function outer() { return inner(); } \
+                    var s = outer().stack; [s.indexOf('inner') >= 0, s.indexOf('outer') > s.indexOf('inner')].join()"),
+               "true,true");
+}
+
+#[test]
+fn string_methods_work_on_code_units() {
+    assert_eq!(run("var s = 'hello, world'; [s.charAt(4), s.charAt(99) === '', s.charCodeAt(0), \
+                    s.charCodeAt(-1) !== s.charCodeAt(-1), s.indexOf('o'), s.indexOf('o', 5), s.lastIndexOf('o'), \
+                    s.lastIndexOf('o', 7), s.indexOf(''), s.lastIndexOf('', 3), s.indexOf('xyz')].join()"),
+               "o,true,104,true,4,8,8,4,0,3,-1");
+    assert_eq!(run("var s = 'abcdefgh'; [s.slice(2, -2), s.slice(-3), s.substring(5, 2), s.substring(-1, 2), \
+                    s.substr(-3, 2), s.substr(2), s.slice(5, 2) === ''].join()"), "cdef,fgh,cde,ab,fg,cdefgh,true");
+    assert_eq!(run("JSON.stringify(['a,b,,c'.split(','), 'abc'.split(''), 'a,b,c'.split(',', 2), ''.split(','), \
+                    ''.split(''), 'abc'.split(), 'a1b2c3'.split(/\\d/), 'ab'.split('ab')])"),
+               r#"[["a","b","","c"],["a","b","c"],["a","b"],[""],[],["abc"],["a","b","c",""],["",""]]"#);
+    assert_eq!(run("['Stra\u{df}e'.toUpperCase(), '\u{130}'.toLowerCase().length, '\u{c9}COLE'.toLowerCase(), \
+                    '\u{3a3}\u{3a3}'.toLowerCase(), '\u{1c6}'.toUpperCase(), 'abc\u{101}'.toUpperCase()].join('|')"),
+               "STRASSE|2|\u{e9}cole|\u{3c3}\u{3c2}|\u{1c4}|ABC\u{100}");
+    assert_eq!(run("JSON.stringify([' \u{feff}\\t x y \\n '.trim(), 'aXbXc'.replace('X', '-'), \
+                    'aXbXc'.replace('X', '[$&$$$`]'), 'abc'.replace('b', function (m, i, s) { return m + i + s; }), \
+                    'abc'.replace('z', '-'), 'a'.concat(1, null, [2, 3]), String.prototype.concat.call(5, 6)])"),
+               r#"["x y","a-bXc","a[X$a]bXc","ab1abcc","abc","a1null2,3","56"]"#);
+    assert_eq!(run("String.prototype.trim.call(null)"),
+               "threw TypeError: String.prototype.trim called on null or undefined");
+}
+
+#[test]
+fn short_string_results_are_immediates() {
+    let results: Vec<(bool, String)> = ["'a long string'.slice(2, 9)", "'a long string'.slice(2, 10)",
+                                        "'ABCDEFGHIJ'.substr(3, 7).toLowerCase()", "'x'.concat('yz', 1.5)",
+                                        "'  \u{e9}t\u{e9}  '.trim()", "'a\u{20ac}'.charAt(1)",
+                                        "'a,bb,ccc'.split(',')[2]", "'abcdefg'.replace('c', 'CCC')"]
+        .iter()
+        .map(|source| {
+            let program = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+            let mut interp = Interpreter::new();
+            let value = interp.run_program(&program).ok().unwrap();
+            (value.is_imm_str(), interp.display_string(value))
+        })
+        .collect();
+    assert_eq!(results, [(true, "long st".to_string()), (false, "long str".to_string()),
+                         (true, "defghij".to_string()), (true, "xyz1.5".to_string()),
+                         (true, "\u{e9}t\u{e9}".to_string()), (false, "\u{20ac}".to_string()),
+                         (true, "ccc".to_string()), (false, "abCCCdefg".to_string())]);
+}
@@ -0,0 +1,111 @@
+extern crate liboxyjs;
+
+use std::rc::Rc;
+use liboxyjs::parser::ast_builder::AstBuilder;
+use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::vm::bytecode::{self, compiler, Constant, FunctionCode, Op};
+
+fn compile(source: &str) -> Rc<FunctionCode> {
+    let program = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    compiler::compile_program(&program).unwrap()
+}
+
+// Each instruction's position and opcode.
+fn instructions(code: &FunctionCode) -> Vec<(usize, Op)> {
+    let mut result = Vec::new();
+    let mut pc = 0;
+    while pc < code.code().len() {
+        let op = Op::from_u8(code.code()[pc]).expect("valid opcode");
+        result.push((pc, op));
+        pc += op.length();
+    }
+    assert_eq!(pc, code.code().len());
+    result
+}
+
+fn ops(code: &FunctionCode) -> Vec<Op> {
+    instructions(code).into_iter().map(|(_, op)| op).collect()
+}
+
+fn functions(code: &FunctionCode) -> Vec<Rc<FunctionCode>> {
+    code.constants().iter().filter_map(|constant| match *constant {
+        Constant::Function(ref func) => Some(func.clone()),
+        _ => None
+    }).collect()
+}
+
+// Check that every jump in a function and those nested in it lands on an
+// instruction, or the end of the code.
+fn check_jumps(code: &FunctionCode) {
+    let instructions = instructions(code);
+    for &(pc, op) in &instructions {
+        if op.is_jump() {
+            let offset = bytecode::read_i32(code.code(), pc + 1);
+            let target = ((pc + op.length()) as isize + offset as isize) as usize;
+            assert!(instructions.iter().any(|&(start, _)| start == target) || target == code.code().len(),
+                    "jump at {} to {}\n{}", pc, target, code.disassembly_string());
+        }
+    }
+    for func in functions(code) {
+        check_jumps(&func);
+    }
+}
+
+#[test]
+fn jumps_are_patched_to_instructions() {
+    let code = compile("var x = a ? 1 : 'b'; if (x && y || !z) f(); else if (w) g(); \
+                        while (i < 10) { if (i == 5) break; i++; continue; } \
+                        do { j--; } while (j > 0); for (var k = 0; k < 3; k++) { } \
+                        try { h(); } catch (e) { } finally { l: for (;;) { break l; } } \
+                        function n() { return p || (q ? r : s); }");
+    check_jumps(&code);
+
+    // Logical operators keep the value deciding them on the stack.
+    let program_ops = ops(&code);
+    assert!(program_ops.contains(&Op::JumpIfFalseKeep));
+    assert!(program_ops.contains(&Op::JumpIfTrueKeep));
+    assert!(program_ops.contains(&Op::Gosub));
+    assert_eq!(code.handlers().len(), 2);
+}
+
+#[test]
+fn constants_are_pooled_per_function() {
+    let code = compile("f('str', 1.5, 'str', 1.5, 7); function g(a) { return 'str' + a; }");
+    let strings = code.constants().iter()
+        .filter(|c| matches!(**c, Constant::String(ref units) if units[..] == [0x73, 0x74, 0x72]))
+        .count();
+    let numbers = code.constants().iter().filter(|c| matches!(**c, Constant::Number(n) if n == 1.5)).count();
+    assert_eq!((strings, numbers), (1, 1));
+    // Small integers are operands rather than constants.
+    assert!(ops(&code).contains(&Op::Int8));
+
+    let nested = functions(&code);
+    assert_eq!(nested.len(), 1);
+    assert_eq!(nested[0].name(), Some("g"));
+    assert!(nested[0].constants().iter().any(|c| matches!(*c, Constant::String(_))));
+}
+
+#[test]
+fn functions_get_their_own_frames() {
+    let code = compile("function outer(a, b) { var local = a; var captured = b; \
+                        return function inner() { return captured; }; }");
+    assert!(code.is_program());
+    let outer = &functions(&code)[0];
+    assert_eq!(outer.name(), Some("outer"));
+    assert_eq!(outer.param_count(), 2);
+    // Parameters and the uncaptured local are registers; the captured
+    // variable is in the environment, after the link to the enclosing one.
+    assert!(outer.register_count() >= 3);
+    assert_eq!(outer.env_size(), 2);
+    assert_eq!(ops(outer)[0], Op::CreateEnv);
+
+    let inner = &functions(outer)[0];
+    assert_eq!((inner.param_count(), inner.env_size()), (0, 0));
+    assert!(ops(inner).contains(&Op::GetEnv));
+}
+
+#[test]
+fn property_accesses_get_inline_caches() {
+    let code = compile("o.a; o.b = o.a; o[k]; o[k] = 1; f.call(o)");
+    assert_eq!(code.inline_cache_count(), 6);
+}
@@ -0,0 +1,17 @@
+use liboxyjs::parser::ast_builder::AstBuilder;
+use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::vm::interpreter::{Interpreter, RunError};
+
+// The result of a program, or the message of what it threw.
+pub fn run(source: &str) -> String {
+    let program = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    let mut interp = Interpreter::new();
+    match interp.run_program(&program) {
+        Ok(value) => interp.display_string(value),
+        Err(RunError::Throw(exception)) => {
+            let text = interp.string_value(exception.value()).unwrap();
+            format!("threw {}", interp.display_string(text))
+        }
+        Err(RunError::Compile(error)) => panic!("compile error: {:?}", error)
+    }
+}
@@ -0,0 +1,95 @@
+extern crate liboxyjs;
+
+use std::f64;
+use liboxyjs::vm::conversions::{number_to_string, string_to_number};
+use liboxyjs::vm::heap::Heap;
+use liboxyjs::vm::string;
+use liboxyjs::vm::value::Value;
+
+// The expected strings are what V8 and SpiderMonkey give.
+
+fn to_number(text: &str) -> f64 {
+    string_to_number(&text.encode_utf16().collect::<Vec<u16>>())
+}
+
+fn assert_same(actual: f64, expected: f64) {
+    assert!(actual == expected && actual.is_sign_negative() == expected.is_sign_negative() ||
+            actual.is_nan() && expected.is_nan(), "{} != {}", actual, expected);
+}
+
+#[test]
+fn numbers_convert_to_their_shortest_strings() {
+    let cases: &[(f64, &str)] = &[
+        (0.0, "0"), (-0.0, "0"), (1.0, "1"), (-1.5, "-1.5"), (0.1, "0.1"),
+        (0.1 + 0.2, "0.30000000000000004"), (1.0 / 3.0, "0.3333333333333333"), (123456789.0, "123456789"),
+        (1e20, "100000000000000000000"), (1e21, "1e+21"), (123456789012345680000.0, "123456789012345680000"),
+        (0.000001, "0.000001"), (1e-7, "1e-7"), (-1.5e-10, "-1.5e-10"),
+        (1.7976931348623157e308, "1.7976931348623157e+308"), (5e-324, "5e-324"), (f64::NAN, "NaN"), (f64::INFINITY, "Infinity"), (f64::NEG_INFINITY, "-Infinity"),
+    ];
+    for &(fval, text) in cases {
+        assert_eq!(number_to_string(fval), text);
+        if fval.is_finite() {
+            // Minus zero prints as "0", which reads back as plus zero.
+            assert_same(to_number(text), if fval == 0.0 { 0.0 } else { fval });
+        }
+    }
+}
+
+#[test]
+fn strings_convert_to_numbers() {
+    let cases: &[(&str, f64)] = &[
+        ("", 0.0), ("   ", 0.0), ("\t\n\u{a0}\u{feff}\u{2028} 42 \r\u{3000}", 42.0), ("-0", -0.0), ("+5", 5.0),
+        ("007", 7.0), ("1.", 1.0), (".5", 0.5), ("1e3", 1000.0), ("1E-3", 0.001), ("-1.5e+2", -150.0),
+        ("0x1F", 31.0), ("0XfF", 255.0), ("Infinity", f64::INFINITY), ("-Infinity", f64::NEG_INFINITY),
+        ("+Infinity", f64::INFINITY), ("1e1000", f64::INFINITY), ("9007199254740993", 9007199254740992.0),
+    ];
+    for &(text, fval) in cases {
+        assert_same(to_number(text), fval);
+    }
+    for text in &[".", "e5", "1e", "1e+", "0x", "-0x10", "+0x10", "0x1g", "1_000", "infinity", "Inf", "1 2",
+                  "- 1", "0b1", "0o7"] {
+        assert!(to_number(text).is_nan(), "{}", text);
+    }
+}
+
+#[test]
+fn values_convert_by_type() {
+    let mut heap = Heap::new();
+    let text = string::new_string(&mut heap, " 12 ");
+    let empty = string::new_string(&mut heap, "");
+    let zero = string::new_string(&mut heap, "0");
+    let big = Value::new_number(&mut heap, 4294967301.0);
+
+    assert_same(Value::new_imm_undef().to_number(&heap), f64::NAN);
+    assert_same(Value::new_imm_null().to_number(&heap), 0.0);
+    assert_same(Value::new_imm_bool(true).to_number(&heap), 1.0);
+    assert_same(text.to_number(&heap), 12.0);
+
+    assert!(! empty.to_boolean(&heap) && zero.to_boolean(&heap));
+    assert!(! Value::new_number(&mut heap, f64::NAN).to_boolean(&heap));
+    assert!(! Value::new_number(&mut heap, -0.0).to_boolean(&heap));
+    assert!(big.to_boolean(&heap) && ! Value::new_imm_undef().to_boolean(&heap));
+
+    // ToInt32 and ToUint32 wrap modulo 2^32.
+    assert_eq!(big.to_int32(&heap), 5);
+    assert_eq!(Value::new_number(&mut heap, 2147483648.0).to_int32(&heap), -2147483648);
+    assert_eq!(Value::new_number(&mut heap, -1.5).to_uint32(&heap), 4294967295);
+    assert_eq!(Value::new_number(&mut heap, f64::INFINITY).to_int32(&heap), 0);
+    assert_eq!(Value::new_imm_i32(-7).to_uint32(&heap), 4294967289);
+
+    let strings: Vec<String> = [Value::new_imm_undef(), Value::new_imm_null(), Value::new_imm_bool(false),
+                                Value::new_imm_i32(-7), big, Value::new_number(&mut heap, 0.5)]
+        .iter()
+        .map(|value| {
+            let value = value.to_string(&mut heap);
+            string::to_rust_string(&heap, value).unwrap()
+        })
+        .collect();
+    assert_eq!(strings, ["undefined", "null", "false", "-7", "4294967301", "0.5"]);
+
+    // Property keys are atoms, whatever the key converted from.
+    let key = Value::new_number(&mut heap, 1e21).to_property_key(&mut heap);
+    assert!(string::is_atom(&heap, key));
+    assert_eq!(string::atomize_str(&mut heap, "1e+21"), key);
+    assert_eq!(text.to_property_key(&mut heap), text.to_property_key(&mut heap));
+}
@@ -0,0 +1,40 @@
+extern crate liboxyjs;
+
+use std::f64;
+use liboxyjs::vm::heap::{Heap, HeapDouble};
+use liboxyjs::vm::value::Value;
+
+#[test]
+fn dangling_values_point_at_no_cell() {
+    let mut heap = Heap::new();
+    let collected = Value::from_f64(&mut heap, f64::INFINITY);
+    let neg_infinity = Value::from_f64(&mut heap, f64::NEG_INFINITY);
+    let kept = heap.root(neg_infinity);
+    heap.collect();
+    assert!(heap.get::<HeapDouble>(collected).is_none());
+    assert!(heap.kind_of(collected).is_none());
+    assert_eq!(heap.get::<HeapDouble>(kept.get()).map(|double| double.value()), Some(f64::NEG_INFINITY));
+
+    // Values from another heap aren't this heap's cells either, even when
+    // they're rooted in it.
+    let mut other = Heap::new();
+    let foreign = Value::from_f64(&mut other, f64::NAN);
+    assert!(heap.get_mut::<HeapDouble>(foreign).is_none());
+    let _rooted = heap.root(foreign);
+    heap.collect();
+    drop(other);
+    heap.collect();
+    assert_eq!(heap.cell_count(), 1);
+}
+
+#[test]
+fn dead_roots_are_forgotten_without_collecting() {
+    let mut heap = Heap::new();
+    let kept = heap.root(Value::new_imm_i32(1));
+    for i in 0..100000 {
+        heap.root(Value::new_imm_i32(i));
+    }
+    assert!(heap.root_count() < 1000, "{} roots", heap.root_count());
+    assert_eq!(heap.bytes_allocated(), 0);
+    assert_eq!(kept.get(), Value::new_imm_i32(1));
+}
@@ -0,0 +1,242 @@
+extern crate liboxyjs;
+
+use std::cmp;
+use std::io;
+use liboxyjs::parser::ast::AstNode;
+use liboxyjs::parser::ast_builder::AstBuilder;
+use liboxyjs::parser::input_stream::{InputStream, ReadInputStream, Utf16InputStream, VecInputStream};
+use liboxyjs::vm::interpreter::Interpreter;
+
+// A reader which gives at most `chunk` bytes at a time.
+struct ChunkedReader {
+    data: Vec<u8>,
+    offset: usize,
+    chunk: usize
+}
+impl ChunkedReader {
+    fn new(data: &[u8], chunk: usize) -> ChunkedReader {
+        ChunkedReader { data: data.to_vec(), offset: 0, chunk }
+    }
+}
+impl io::Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = cmp::min(cmp::min(buf.len(), self.chunk), self.data.len() - self.offset);
+        buf[..count].copy_from_slice(&self.data[self.offset..(self.offset + count)]);
+        self.offset += count;
+        Ok(count)
+    }
+}
+
+// Read the rest of a stream as chars, reading non-ascii chars as the tokenizer does.
+fn read_chars<S: InputStream>(stream: &mut S, limit: usize) -> String {
+    let mut text = String::new();
+    for _ in 0..limit {
+        let ch = stream.read_ascii();
+        if ch.is_end() {
+            break;
+        }
+        if ch.is_ascii_or_end() {
+            text.push(ch.ascii_value() as char);
+            continue;
+        }
+        stream.unread_ascii(ch);
+        let ch = stream.read_non_ascii();
+        assert!(ch.is_valid());
+        text.push(std::char::from_u32(ch.code_point()).unwrap());
+    }
+    text
+}
+
+#[test]
+fn read_streams_decode_across_chunks() {
+    let text = "ascii é € 𝄞 and more ascii";
+    for chunk in 1..6 {
+        let mut stream = ReadInputStream::new(ChunkedReader::new(text.as_bytes(), chunk));
+        assert_eq!(read_chars(&mut stream, usize::MAX), text);
+        assert!(stream.read_ascii().is_end());
+        assert!(stream.read_error().is_none());
+        assert_eq!(stream.current_offset() as usize, text.len());
+    }
+}
+
+#[test]
+fn read_streams_retain_the_pinned_window() {
+    let text: String = (0..40000).map(|i| format!("{:07}\n", i)).collect();
+    let mut stream = ReadInputStream::new(ChunkedReader::new(text.as_bytes(), 1000));
+
+    // Nothing is discarded until the window start is pinned.
+    let start = stream.mark();
+    assert_eq!(read_chars(&mut stream, 200000).len(), 200000);
+    assert!(stream.is_retained_position(start));
+    stream.rewind(start);
+    assert_eq!(read_chars(&mut stream, 16), "0000000\n0000001\n");
+
+    // Rewinding within the window gives the same text.
+    assert_eq!(read_chars(&mut stream, 150000 - 16).len(), 150000 - 16);
+    let pinned = stream.mark();
+    stream.pin_window_start(pinned);
+    let first = read_chars(&mut stream, 100000);
+    stream.rewind(pinned);
+    assert_eq!(read_chars(&mut stream, 100000), first);
+    assert!(first.starts_with("0018750\n"));
+
+    // Once the window moves past it, earlier input is discarded, but the
+    // window's text can still be extracted.
+    let end = stream.mark();
+    stream.pin_window_start(end);
+    assert_eq!(read_chars(&mut stream, usize::MAX).len(), text.len() - 250000);
+    assert!(! stream.is_retained_position(start));
+    assert!(stream.is_retained_position(end));
+    assert!((stream.buffered_end_offset() - end.value()) < 200000);
+    assert_eq!(stream.extract_text(end, stream.mark()), &text[250000..]);
+}
+
+#[test]
+fn read_streams_parse_like_whole_inputs() {
+    // Blocks and object literals are rewound over as statements are parsed, and
+    // the window is pinned at each statement, so input is read and discarded in
+    // small chunks as the parse goes.
+    let source: String = (0..1000)
+        .map(|i| format!("{{ var a{} = {{ k: [{}, 'é'] }}; }}\n({{ x: a{}.k }});\nif (a{}) {{ f({}); }}\n",
+                         i, i, i, i, i))
+        .collect();
+    let expected = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    let mut builder = AstBuilder::new(ReadInputStream::new(ChunkedReader::new(source.as_bytes(), 777)));
+    let program = builder.parse_program().unwrap();
+    assert_eq!(program.tree_string(), expected.tree_string());
+}
+
+#[test]
+fn read_errors_end_the_stream() {
+    struct FailingReader(usize);
+    impl io::Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("disk on fire"));
+            }
+            self.0 -= 1;
+            buf[0] = b'x';
+            Ok(1)
+        }
+    }
+    let mut stream = ReadInputStream::new(FailingReader(3));
+    assert_eq!(read_chars(&mut stream, usize::MAX), "xxx");
+    assert!(stream.read_ascii().is_end());
+    assert_eq!(stream.read_error().unwrap().to_string(), "disk on fire");
+}
+
+fn utf16(text: &str) -> Vec<u16> {
+    text.encode_utf16().collect()
+}
+
+#[test]
+fn utf16_streams_read_surrogate_pairs_as_one_char() {
+    let units = utf16("a\u{e9}\u{1d11e}b");
+    let mut stream = Utf16InputStream::new(&units);
+    assert_eq!(stream.read_ascii().ascii_value(), b'a');
+
+    // Non-ascii chars are unread as a whole, by their length in code units.
+    let before = stream.mark();
+    let ch = stream.read_ascii();
+    assert!(! ch.is_ascii_or_end());
+    stream.unread_ascii(ch);
+    let e_acute = stream.read_non_ascii();
+    assert_eq!(e_acute.code_point(), 0xE9);
+    let clef = stream.read_non_ascii();
+    assert_eq!(clef.code_point(), 0x1D11E);
+    assert_eq!(stream.current_offset(), 4);
+    stream.unread_non_ascii(clef);
+    assert_eq!(stream.current_offset(), 2);
+    assert_eq!(stream.read_non_ascii().code_point(), 0x1D11E);
+
+    assert_eq!(stream.read_ascii().ascii_value(), b'b');
+    assert!(stream.read_ascii().is_end());
+    assert!(stream.read_non_ascii().is_end());
+    assert_eq!(stream.extract_text(before, stream.mark()), "\u{e9}\u{1d11e}b");
+    stream.rewind(before);
+    assert_eq!(read_chars(&mut stream, usize::MAX), "\u{e9}\u{1d11e}b");
+}
+
+// A tree string without its token locations, which are in the units of the
+// stream's encoding.
+fn tree_shape(program: &dyn AstNode) -> String {
+    let tree = program.tree_string();
+    let mut shape = String::new();
+    let mut chars = tree.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '@' {
+            while chars.peek().is_some_and(|&c| c.is_ascii_digit() || c == '-') {
+                chars.next();
+            }
+            continue;
+        }
+        shape.push(ch);
+    }
+    shape
+}
+
+#[test]
+fn utf16_streams_parse_like_utf8() {
+    let source = "// \u{1d11e} comment\nvar s = '\u{1d11e}\u{e9}' + \"\\u00e9\"; /* \u{2603} */ s.length + /\u{1d11e}/.source";
+    let units = utf16(source);
+    let from_utf8 = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    let from_utf16 = AstBuilder::new(Utf16InputStream::new(&units)).parse_program().unwrap();
+    assert_eq!(tree_shape(from_utf16.as_ref()), tree_shape(from_utf8.as_ref()));
+
+    for program in &[from_utf8, from_utf16] {
+        let mut interp = Interpreter::new();
+        let result = interp.run_program(program).ok().unwrap();
+        assert_eq!(interp.display_string(result), "4\u{1d11e}");
+    }
+}
+
+#[test]
+fn token_locations_count_code_units_of_the_stream() {
+    // The same token is at different offsets in UTF-8 and UTF-16 input, but
+    // its text is the same.
+    let source = "'\u{e9}\u{1d11e}' + x";
+    let units = utf16(source);
+    let from_utf8 = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    let from_utf16 = AstBuilder::new(Utf16InputStream::new(&units)).parse_program().unwrap();
+    assert!(from_utf8.tree_string().contains("string_literal@0-8"));
+    assert!(from_utf8.tree_string().contains("identifier@11-12"));
+    assert!(from_utf16.tree_string().contains("string_literal@0-5"));
+    assert!(from_utf16.tree_string().contains("identifier@8-9"));
+
+    let mut stream = Utf16InputStream::new(&units);
+    let start = stream.mark();
+    read_chars(&mut stream, 4);
+    assert_eq!(stream.mark().value(), 5);
+    assert_eq!(stream.extract_text(start, stream.mark()), "'\u{e9}\u{1d11e}'");
+}
+
+#[test]
+fn utf16_streams_allow_lone_surrogates_in_strings_and_comments() {
+    // A program whose string literals hold a lone trailing and leading
+    // surrogate, one after a backslash, and another in a comment.
+    let mut units = utf16("var s = '");
+    units.push(0xDC00);
+    units.extend(utf16("' + '\\"));
+    units.push(0xD834);
+    units.extend(utf16("'; // "));
+    units.push(0xD800);
+    units.extend(utf16("\n[s.length, s.charCodeAt(0), s.charCodeAt(1)].join()"));
+
+    let program = AstBuilder::new(Utf16InputStream::new(&units)).parse_program().unwrap();
+    let mut interp = Interpreter::new();
+    let result = interp.run_program(&program).ok().unwrap();
+    assert_eq!(interp.display_string(result), "2,56320,55348");
+
+    // Extracted text writes them as escapes, unless they're escaped already.
+    let mut stream = Utf16InputStream::new(&units);
+    let start = stream.mark();
+    assert_eq!(read_chars(&mut stream, 9), "var s = '");
+    let ch = stream.read_ascii();
+    stream.unread_ascii(ch);
+    assert_eq!(stream.read_non_ascii().code_point(), 0xDC00);
+    read_chars(&mut stream, 6);
+    assert_eq!(stream.read_non_ascii().code_point(), 0xD834);
+    read_chars(&mut stream, 6);
+    assert_eq!(stream.read_non_ascii().code_point(), 0xD800);
+    assert_eq!(stream.extract_text(start, stream.mark()), "var s = '\\udc00' + '\\ud834'; // \\ud800");
+}
@@ -0,0 +1,126 @@
+extern crate liboxyjs;
+
+mod common;
+
+use common::run;
+use liboxyjs::parser::ast_builder::AstBuilder;
+use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::vm::inline_cache::InlineCacheCounters;
+use liboxyjs::vm::interpreter::Interpreter;
+
+#[test]
+fn strict_programs_bind_their_functions() {
+    assert_eq!(run("'use strict'; var a = 1; function f() { return a; } f()"), "1");
+    assert_eq!(run("'use strict'; var r = [typeof g, g()]; function g() { return 'g'; } \
+                    var d = Object.getOwnPropertyDescriptor(this, 'g'); \
+                    r.concat(d.writable, d.enumerable, d.configurable).join()"),
+               "function,g,true,true,false");
+    assert_eq!(run("'use strict'; undeclared = 1"), "threw ReferenceError: undeclared is not defined");
+}
+
+#[test]
+fn for_in_visits_enumerable_keys() {
+    assert_eq!(run("var r = []; var o = {a: 1, b: 2, c: 3}; \
+                    for (var k in o) { r.push(k); if (k == 'a') delete o.b; } r.join()"), "a,c");
+    assert_eq!(run("function P() { this.x = 1; } P.prototype.y = 2; var r = []; \
+                    for (var k in new P()) r.push(k); r.join()"), "x,y");
+    assert_eq!(run("var r = [], a = [5, 6]; a.extra = 1; for (r[r.length] in a); \
+                    for (var k in 'ab') r.push(k); for (k in null) r.push(k); r.join()"), "0,1,extra,0,1");
+    assert_eq!(run("var r = []; outer: for (var a in {p: 1, q: 1}) { for (var b in {x: 1, y: 1}) { \
+                    if (b == 'y') continue outer; r.push(a + b); } } r.join()"), "px,qx");
+    assert_eq!(run("for (var w = 'init' in {}); w"), "init");
+}
+
+#[test]
+fn non_strict_arguments_alias_parameters() {
+    assert_eq!(run("function f(a) { arguments[0] = 9; return a } f(1)"), "9");
+    assert_eq!(run("function f(a, b) { a = 5; b = 6; return [arguments[0], arguments.length, arguments[1]].join() } \
+                    f(1)"), "5,1,");
+    assert_eq!(run("function f(a, a) { arguments[1] = 7; return a } f(1, 2)"), "7");
+    assert_eq!(run("function f(a) { return function() { arguments[0] = 1; return a }; } f(2)(0)"), "2");
+    // Deleting or redefining an index unmaps it.
+    assert_eq!(run("function f(a) { delete arguments[0]; arguments[0] = 3; return a } f(1)"), "1");
+    assert_eq!(run("function f(a) { Object.defineProperty(arguments, '0', {value: 4, writable: false}); \
+                    a = 8; return [a, arguments[0]].join() } f(1)"), "8,4");
+    assert_eq!(run("function f(a) { 'use strict'; arguments[0] = 9; return a } f(1)"), "1");
+}
+
+#[test]
+fn non_strict_this_values_are_boxed() {
+    assert_eq!(run("(function() { return typeof this }).call(5)"), "object");
+    assert_eq!(run("var g = this; [(function() { return this }).call(undefined) === g, \
+                    (function() { return this }).call(null) === g].join()"), "true,true");
+    assert_eq!(run("(function() { 'use strict'; return typeof this }).call(5)"), "number");
+    assert_eq!(run("(function() { return [this + 1, this.toFixed(1)].join() }).call(5)"), "6,5.0");
+    assert_eq!(run("(function() { this[0] = 'z'; this.length = 1; \
+                    return [this, this.length, this[1], delete this[0], Object.keys(this)].join() }).call('ab')"),
+               "ab,2,b,false,0,1");
+    assert_eq!(run("Object.prototype.toString.call(Object(true)) + JSON.stringify([Object(1), Object('s')])"),
+               "[object Boolean][1,\"s\"]");
+}
+
+#[test]
+fn finally_blocks_keep_the_completion_value() {
+    assert_eq!(run("2; try { 3 } finally { 4 }"), "3");
+    assert_eq!(run("2; try { throw 1 } catch (e) { 5 } finally { 6 }"), "5");
+    assert_eq!(run("2; try { 3; try { 8 } finally { 9 } } finally { 4 }"), "8");
+    // Leaving a finally block by break keeps its own value.
+    assert_eq!(run("l: { 1; try { 3 } finally { 4; break l; } }"), "4");
+}
+
+// The result of a program, and the inline cache counters after running it.
+fn run_counting(source: &str) -> (String, InlineCacheCounters) {
+    let program = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    let mut interp = Interpreter::new();
+    match interp.run_program(&program) {
+        Ok(value) => (interp.display_string(value), interp.inline_cache_counters()),
+        Err(_) => panic!("program failed")
+    }
+}
+
+#[test]
+fn property_sites_cache_shapes() {
+    // Each of the four sites misses once, for the first object.
+    let (result, counters) = run_counting("function P(x) { this.x = x; this.y = 2; } var s = 0; \
+                                           for (var i = 0; i < 100; i++) { var p = new P(i); s += p.x + p.y; } s");
+    assert_eq!(result, "5150");
+    assert_eq!(counters, InlineCacheCounters { hits: 396, misses: 4, megamorphic: 0 });
+
+    // Four shapes fit in a polymorphic cache, but a fifth makes the site
+    // megamorphic.
+    let (result, counters) = run_counting("var os = [{a: 1}, {b: 1, a: 2}, {c: 1, a: 3}, {d: 1, a: 4}]; \
+                                           var s = 0; for (var i = 0; i < 60; i++) s += os[i % 4].a; s");
+    assert_eq!(result, "150");
+    assert_eq!(counters, InlineCacheCounters { hits: 56, misses: 4, megamorphic: 0 });
+    let (result, counters) = run_counting("var os = [{a: 1}, {b: 1, a: 2}, {c: 1, a: 3}, {d: 1, a: 4}, {e: 1, a: 5}]; \
+                                           var s = 0; for (var i = 0; i < 60; i++) s += os[i % 5].a; s");
+    assert_eq!(result, "180");
+    assert_eq!(counters, InlineCacheCounters { hits: 0, misses: 5, megamorphic: 55 });
+    assert_eq!(counters.hit_rate(), 0.0);
+
+    // Elements, lengths and primitives aren't cached.
+    let (result, counters) = run_counting("var a = [1, 2, 3], s = 'abc', t = 0; \
+                                           for (var i = 0; i < 50; i++) t += a[i % 3] + s.length + a.length; t");
+    assert_eq!(result, "399");
+    assert_eq!(counters.accesses(), 0);
+}
+
+#[test]
+fn cached_sites_see_changed_properties() {
+    // A cached add isn't used once a prototype has a setter for the key.
+    assert_eq!(run("function P() {} var log = []; function set(o, v) { o.x = v; } \
+                    set(new P(), 1); set(new P(), 2); \
+                    Object.defineProperty(P.prototype, 'x', { set: function (v) { log.push(v); } }); \
+                    var p = new P(); set(p, 3); log.concat(p.hasOwnProperty('x')).join()"), "3,false");
+    // Nor a cached store once the property is read-only, or a cached add
+    // once the object is frozen.
+    assert_eq!(run("function set(o, v) { o.x = v; } var a = {x: 1}, b = {x: 1}; set(a, 2); \
+                    Object.defineProperty(b, 'x', { writable: false }); set(b, 3); \
+                    var c = {}, d = {}; set(c, 4); Object.freeze(d); set(d, 5); \
+                    [a.x, b.x, c.x, d.x].join()"), "2,1,4,");
+    // Cached loads miss once the property is deleted or becomes an accessor.
+    assert_eq!(run("function get(o) { return o.x; } var o = {x: 1, y: 2}; var r = [get(o), get(o)]; \
+                    delete o.x; r.push(get(o)); \
+                    o.x = 3; Object.defineProperty(o, 'x', { get: function () { return 4; } }); \
+                    r.push(get(o)); r.join()"), "1,1,,4");
+}
@@ -0,0 +1,72 @@
+extern crate liboxyjs;
+
+mod common;
+
+use common::run;
+
+#[test]
+fn parse_builds_values() {
+    assert_eq!(run(r#"JSON.stringify(JSON.parse(' {"a": [1, -2.5e3, true, null, "s\\u00e9\\n\\"x\\""],
+                                                  "b": {}, "": 0} '))"#),
+               r#"{"a":[1,-2500,true,null,"sé\n\"x\""],"b":{},"":0}"#);
+    assert_eq!(run(r#"var o = JSON.parse('{"__proto__": 1, "k": 1, "k": 2}'); [o.k, Object.keys(o)].join('|')"#),
+               "2|__proto__,k");
+
+    // The reviver sees values bottom up, and removes those it returns
+    // undefined for.
+    assert_eq!(run(r#"var seen = []; var v = JSON.parse('{"a": {"b": 1, "c": 2}, "d": [1, 2]}', function (k, v) {
+                          seen.push(k); return k === 'c' ? undefined : typeof v === 'number' ? v * 10 : v; });
+                      [JSON.stringify(v), seen.join()].join('|')"#),
+               r#"{"a":{"b":10},"d":[10,20]}|b,c,a,0,1,d,"#);
+}
+
+#[test]
+fn parse_rejects_what_isnt_json() {
+    assert_eq!(run(r#"var bad = ['{a: 1}', "['x']", '[1,]', '01', '"\t"', '1 2', '', '{"a" 1}', '"\\x"', 'nul',
+                                 '+1', '.5', '1.', '0x10', '[1', '"\\ud8"'];
+                      var r = []; for (var i = 0; i < bad.length; i++) {
+                          try { JSON.parse(bad[i]); r.push('ok'); } catch (e) { r.push(e.name); } }
+                      r.join()"#),
+               vec!["SyntaxError"; 16].join(","));
+    assert_eq!(run("JSON.parse('[1, 2')"), "threw SyntaxError: Unexpected end of JSON input");
+}
+
+#[test]
+fn stringify_indents_and_filters() {
+    assert_eq!(run("JSON.stringify({a: [1, {b: 2}], c: 'x', e: [], f: {}}, null, 2)"),
+               "{\n  \"a\": [\n    1,\n    {\n      \"b\": 2\n    }\n  ],\n  \"c\": \"x\",\n  \
+                \"e\": [],\n  \"f\": {}\n}");
+    assert_eq!(run("JSON.stringify({a: 1, b: [1, 2]}, null, '--')"),
+               "{\n--\"a\": 1,\n--\"b\": [\n----1,\n----2\n--]\n}");
+    // Indents are at most ten spaces or characters.
+    assert_eq!(run("[JSON.stringify([true], null, 20), JSON.stringify([true], null, '12345678901234')].join('|')"),
+               "[\n          true\n]|[\n1234567890true\n]");
+
+    assert_eq!(run("JSON.stringify({a: 1, b: 2, c: {a: 3, z: 4}}, ['c', 'a', 'c', 1])"), r#"{"c":{"a":3},"a":1}"#);
+    assert_eq!(run("JSON.stringify({a: 1, b: 'x', c: [1, 'y']}, \
+                    function (k, v) { return typeof v === 'string' ? v.toUpperCase() : v; })"),
+               r#"{"a":1,"b":"X","c":[1,"Y"]}"#);
+}
+
+#[test]
+fn stringify_converts_values() {
+    assert_eq!(run("JSON.stringify([undefined, function () {}, NaN, -Infinity, -0, new Number(3), new String('s'), \
+                    {toJSON: function (k) { return 'key ' + k; }}])"),
+               r#"[null,null,null,null,0,3,"s","key 7"]"#);
+    assert_eq!(run("JSON.stringify({u: undefined, f: function () {}, d: new Date(0)})"),
+               r#"{"d":"1970-01-01T00:00:00.000Z"}"#);
+    assert_eq!(run(r#"JSON.stringify('\u0000\u001f"\\/\b\f\n\r\t é')"#),
+               r#""\u0000\u001f\"\\/\b\f\n\r\t é""#);
+    assert_eq!(run("[JSON.stringify(undefined), JSON.stringify(function () {}), JSON.stringify(null), \
+                    typeof JSON.stringify(undefined)].join('|')"), "||null|undefined");
+}
+
+#[test]
+fn stringify_detects_cycles() {
+    assert_eq!(run("var o = {}; o.self = o; JSON.stringify(o)"),
+               "threw TypeError: Converting circular structure to JSON");
+    assert_eq!(run("var a = []; a.push({x: a}); try { JSON.stringify(a); } catch (e) { e.name }"), "TypeError");
+    // Values seen twice but not inside themselves are fine.
+    assert_eq!(run("var shared = {a: [1]}; JSON.stringify([shared, shared, shared.a])"),
+               r#"[{"a":[1]},{"a":[1]},[1]]"#);
+}
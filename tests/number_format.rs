@@ -0,0 +1,108 @@
+extern crate liboxyjs;
+
+use liboxyjs::vm::builtins::math::{self, Random};
+use liboxyjs::vm::number_format::{to_exponential, to_fixed, to_precision, to_radix_string};
+use std::f64;
+
+// The expected strings are what V8 and SpiderMonkey give.
+
+#[test]
+fn to_fixed_rounds_the_exact_value() {
+    assert_eq!(to_fixed(1.005, 2), "1.00");
+    assert_eq!(to_fixed(1.45, 1), "1.4");
+    assert_eq!(to_fixed(123.456, 1), "123.5");
+    assert_eq!(to_fixed(0.5, 0), "1");
+    assert_eq!(to_fixed(2.5, 0), "3");
+    assert_eq!(to_fixed(-2.5, 0), "-3");
+    assert_eq!(to_fixed(0.0, 3), "0.000");
+    assert_eq!(to_fixed(-0.0, 2), "0.00");
+    assert_eq!(to_fixed(-1e-7, 2), "-0.00");
+    assert_eq!(to_fixed(0.1, 20), "0.10000000000000000555");
+    assert_eq!(to_fixed(12345.6789, 20), "12345.67890000000079453457");
+    assert_eq!(to_fixed(1000000000000000128.0, 0), "1000000000000000128");
+    assert_eq!(to_fixed(1e21, 2), "1e+21");
+}
+
+#[test]
+fn to_exponential_formats_digits_and_exponent() {
+    assert_eq!(to_exponential(123.456, Some(2)), "1.23e+2");
+    assert_eq!(to_exponential(1.25, Some(1)), "1.3e+0");
+    assert_eq!(to_exponential(1.35, Some(1)), "1.4e+0");
+    assert_eq!(to_exponential(-1.5e300, Some(3)), "-1.500e+300");
+    assert_eq!(to_exponential(0.0, Some(2)), "0.00e+0");
+    assert_eq!(to_exponential(0.0, None), "0e+0");
+    assert_eq!(to_exponential(123456.0, None), "1.23456e+5");
+    assert_eq!(to_exponential(1e-7, None), "1e-7");
+    assert_eq!(to_exponential(5e-324, None), "5e-324");
+    assert_eq!(to_exponential(f64::MAX, None), "1.7976931348623157e+308");
+}
+
+#[test]
+fn to_precision_switches_notation() {
+    assert_eq!(to_precision(123.456, 4), "123.5");
+    assert_eq!(to_precision(123.0, 3), "123");
+    assert_eq!(to_precision(123456.0, 2), "1.2e+5");
+    assert_eq!(to_precision(0.000001234, 2), "0.0000012");
+    assert_eq!(to_precision(0.0000001234, 2), "1.2e-7");
+    assert_eq!(to_precision(1e21, 3), "1.00e+21");
+    assert_eq!(to_precision(0.0, 5), "0.0000");
+    assert_eq!(to_precision(1.0, 1), "1");
+    assert_eq!(to_precision(-99.99, 3), "-100");
+    assert_eq!(to_precision(0.1, 21), "0.100000000000000005551");
+}
+
+#[test]
+fn to_radix_string_gives_shortest_fractions() {
+    assert_eq!(to_radix_string(255.0, 16), "ff");
+    assert_eq!(to_radix_string(-255.0, 2), "-11111111");
+    assert_eq!(to_radix_string(0.0, 36), "0");
+    assert_eq!(to_radix_string(0.5, 2), "0.1");
+    assert_eq!(to_radix_string(0.1, 2), "0.0001100110011001100110011001100110011001100110011001101");
+    assert_eq!(to_radix_string(1.0 / 3.0, 2), "0.010101010101010101010101010101010101010101010101010101");
+    assert_eq!(to_radix_string(f64::consts::PI, 16), "3.243f6a8885a3");
+    assert_eq!(to_radix_string(35.0, 36), "z");
+}
+
+fn assert_same(actual: f64, expected: f64) {
+    assert_eq!(actual.to_bits(), expected.to_bits(), "{:?} is not {:?}", actual, expected);
+}
+
+#[test]
+fn math_functions_follow_the_spec() {
+    assert_same(math::round_half_up(2.5), 3.0);
+    assert_same(math::round_half_up(-2.5), -2.0);
+    assert_same(math::round_half_up(-0.5), -0.0);
+    assert_same(math::round_half_up(-0.0), -0.0);
+    assert_same(math::round_half_up(0.49999999999999994), 0.0);
+    assert!(math::round_half_up(f64::NAN).is_nan());
+
+    assert!(math::power(1.0, f64::INFINITY).is_nan());
+    assert!(math::power(-1.0, f64::NEG_INFINITY).is_nan());
+    assert!(math::power(1.0, f64::NAN).is_nan());
+    assert_same(math::power(f64::NAN, 0.0), 1.0);
+    assert_same(math::power(-0.0, -3.0), f64::NEG_INFINITY);
+
+    assert_same(math::max_of(&[]), f64::NEG_INFINITY);
+    assert_same(math::min_of(&[]), f64::INFINITY);
+    assert_same(math::max_of(&[-0.0, 0.0]), 0.0);
+    assert_same(math::max_of(&[0.0, -0.0]), 0.0);
+    assert_same(math::min_of(&[0.0, -0.0]), -0.0);
+    assert_same(math::min_of(&[-0.0, 0.0]), -0.0);
+    assert!(math::max_of(&[1.0, f64::NAN, 3.0]).is_nan());
+    assert!(math::min_of(&[f64::NAN, f64::NEG_INFINITY]).is_nan());
+}
+
+#[test]
+fn seeded_random_repeats() {
+    let mut first = Random::new(42);
+    let mut second = Random::new(42);
+    let mut other = Random::new(43);
+    let mut differs = false;
+    for _ in 0..1000 {
+        let fval = first.next_f64();
+        assert!((0.0..1.0).contains(&fval));
+        assert_same(second.next_f64(), fval);
+        differs |= other.next_f64() != fval;
+    }
+    assert!(differs);
+}
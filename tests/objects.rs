@@ -0,0 +1,196 @@
+extern crate liboxyjs;
+
+use std::rc::Rc;
+use liboxyjs::vm::heap::Heap;
+use liboxyjs::vm::object::{self, JsObject, Property};
+use liboxyjs::vm::shape::{PropertyAttributes, Shape};
+use liboxyjs::vm::string;
+use liboxyjs::vm::value::Value;
+
+fn key(heap: &mut Heap, name: &str) -> Value {
+    string::atomize_str(heap, name)
+}
+
+fn shape_of(heap: &Heap, object: Value) -> Rc<Shape> {
+    heap.get::<JsObject>(object).unwrap().shape().clone()
+}
+
+fn keys_of(heap: &mut Heap, object: Value) -> Vec<String> {
+    object::own_keys(heap, object).into_iter().map(|key| string::to_rust_string(heap, key).unwrap()).collect()
+}
+
+// An object with properties named by a prefix and a number, each holding its
+// number.
+fn numbered_object(heap: &mut Heap, prefix: &str, count: u32) -> Value {
+    let object = object::new_object(heap, Value::new_imm_null());
+    for i in 0..count {
+        let key = key(heap, &format!("{}{}", prefix, i));
+        object::set_own_value(heap, object, key, Value::new_imm_i32(i as i32));
+    }
+    object
+}
+
+fn get(heap: &mut Heap, object: Value, name: &str) -> Option<Value> {
+    let key = key(heap, name);
+    match object::get_own_property(heap, object, key) {
+        Some(Property::Data { value, .. }) => Some(value),
+        _ => None
+    }
+}
+
+#[test]
+fn shape_paths_share_tables_and_branch() {
+    let mut heap = Heap::new();
+    let first = numbered_object(&mut heap, "p", 20);
+    let second = numbered_object(&mut heap, "p", 20);
+    assert!(Rc::ptr_eq(&shape_of(&heap, first), &shape_of(&heap, second)));
+
+    // A branch off the middle of the path sees only its own properties.
+    let branch = numbered_object(&mut heap, "p", 10);
+    let other = key(&mut heap, "other");
+    object::set_own_value(&mut heap, branch, other, Value::new_imm_i32(-1));
+    let shape = shape_of(&heap, branch);
+    assert_eq!(shape.count(), 11);
+    assert_eq!(shape.lookup(other).unwrap().slot, 10);
+    let p15 = key(&mut heap, "p15");
+    assert!(shape.lookup(p15).is_none());
+    assert_eq!(shape_of(&heap, first).lookup(p15).unwrap().slot, 15);
+    assert!(shape_of(&heap, first).lookup(other).is_none());
+    assert_eq!(shape.parent().unwrap().count(), 10);
+}
+
+#[test]
+fn large_and_edited_objects_become_dictionaries() {
+    let mut heap = Heap::new();
+
+    // Removing the last property goes back to the parent shape.
+    let object = numbered_object(&mut heap, "k", 20);
+    let k19 = key(&mut heap, "k19");
+    assert!(object::delete_own_property(&mut heap, object, k19));
+    let shorter = numbered_object(&mut heap, "k", 19);
+    assert!(Rc::ptr_eq(&shape_of(&heap, object), &shape_of(&heap, shorter)));
+
+    // Removing another reuses its slot for the next property added, and keeps
+    // the order of the others.
+    let k3 = key(&mut heap, "k3");
+    let slot = shape_of(&heap, object).lookup(k3).unwrap().slot;
+    assert!(object::delete_own_property(&mut heap, object, k3));
+    assert!(shape_of(&heap, object).is_dictionary());
+    let added = key(&mut heap, "added");
+    object::set_own_value(&mut heap, object, added, Value::new_imm_i32(100));
+    assert_eq!(shape_of(&heap, object).lookup(added).unwrap().slot, slot);
+    let keys = keys_of(&mut heap, object);
+    assert_eq!(keys.len(), 19);
+    assert_eq!(keys[..4], ["k0", "k1", "k2", "k4"]);
+    assert_eq!(keys[17..], ["k18", "added"]);
+    assert_eq!(get(&mut heap, object, "k4"), Some(Value::new_imm_i32(4)));
+    assert_eq!(get(&mut heap, object, "added"), Some(Value::new_imm_i32(100)));
+
+    // Attributes change in place.
+    let k5 = key(&mut heap, "k5");
+    let property = Property::Data { value: Value::new_imm_i32(5), attrs: PropertyAttributes::NONE };
+    object::define_own_property(&mut heap, object, k5, property);
+    assert_eq!(object::get_own_property(&mut heap, object, k5).unwrap().attrs(), PropertyAttributes::NONE);
+    assert_eq!(keys_of(&mut heap, object)[4], "k5");
+
+    // Small objects stay in the tree.
+    let small = numbered_object(&mut heap, "k", 4);
+    let k1 = key(&mut heap, "k1");
+    assert!(object::delete_own_property(&mut heap, small, k1));
+    assert!(! shape_of(&heap, small).is_dictionary());
+    assert_eq!(keys_of(&mut heap, small), ["k0", "k2", "k3"]);
+
+    // Objects with many properties go straight to a dictionary.
+    let large = numbered_object(&mut heap, "k", 10000);
+    assert!(shape_of(&heap, large).is_dictionary());
+    assert_eq!(shape_of(&heap, large).count(), 10000);
+    assert_eq!(get(&mut heap, large, "k9999"), Some(Value::new_imm_i32(9999)));
+    for i in 0..9990 {
+        let key = key(&mut heap, &format!("k{}", i));
+        assert!(object::delete_own_property(&mut heap, large, key));
+    }
+    assert_eq!(keys_of(&mut heap, large).join(","), "k9990,k9991,k9992,k9993,k9994,k9995,k9996,k9997,k9998,k9999");
+    assert_eq!(get(&mut heap, large, "k9995"), Some(Value::new_imm_i32(9995)));
+}
+
+#[test]
+fn attribute_changes_and_accessors_transition_shapes() {
+    let mut heap = Heap::new();
+    let first = numbered_object(&mut heap, "a", 6);
+    let second = numbered_object(&mut heap, "a", 6);
+
+    // Changing the last property's attributes branches off its parent, and
+    // the same change on another object takes the same branch.
+    let a5 = key(&mut heap, "a5");
+    for &object in &[first, second] {
+        let property = Property::Data { value: Value::new_imm_i32(50), attrs: PropertyAttributes::WRITABLE };
+        object::define_own_property(&mut heap, object, a5, property);
+    }
+    assert!(Rc::ptr_eq(&shape_of(&heap, first), &shape_of(&heap, second)));
+    let shorter = numbered_object(&mut heap, "a", 5);
+    assert!(Rc::ptr_eq(shape_of(&heap, first).parent().unwrap(), &shape_of(&heap, shorter)));
+    assert_eq!(shape_of(&heap, first).lookup(a5).unwrap().attrs, PropertyAttributes::WRITABLE);
+
+    // Changing an earlier one rebuilds the path, keeping slots and values,
+    // including those past the inline slots.
+    let a1 = key(&mut heap, "a1");
+    let getter = object::new_object(&mut heap, Value::new_imm_null());
+    let property = Property::Accessor { getter, setter: Value::new_imm_undef(), attrs: PropertyAttributes::ENUMERABLE };
+    object::define_own_property(&mut heap, first, a1, property);
+    let shape = shape_of(&heap, first);
+    assert!(! shape.is_dictionary());
+    assert_eq!(shape.count(), 6);
+    let entry = shape.lookup(a1).unwrap();
+    assert_eq!(entry.slot, 1);
+    assert_eq!(entry.attrs, PropertyAttributes::ENUMERABLE.with(PropertyAttributes::ACCESSOR));
+    match object::get_own_property(&mut heap, first, a1) {
+        Some(Property::Accessor { getter: found, setter, attrs }) => {
+            assert_eq!(found, getter);
+            assert!(setter.is_imm_undef());
+            assert!(attrs.is_accessor() && ! attrs.is_configurable());
+        }
+        _ => panic!("not an accessor")
+    }
+    assert_eq!(get(&mut heap, first, "a4"), Some(Value::new_imm_i32(4)));
+    assert_eq!(get(&mut heap, first, "a5"), Some(Value::new_imm_i32(50)));
+    assert_eq!(keys_of(&mut heap, first), ["a0", "a1", "a2", "a3", "a4", "a5"]);
+
+    // Removing a property moves the later slots down.
+    let a2 = key(&mut heap, "a2");
+    assert!(object::delete_own_property(&mut heap, second, a2));
+    assert_eq!(shape_of(&heap, second).lookup(a5).unwrap().slot, 4);
+    assert_eq!(get(&mut heap, second, "a4"), Some(Value::new_imm_i32(4)));
+    assert_eq!(get(&mut heap, second, "a5"), Some(Value::new_imm_i32(50)));
+}
+
+#[test]
+fn keys_enumerate_in_order_through_prototypes() {
+    let mut heap = Heap::new();
+    let proto = object::new_object(&mut heap, Value::new_imm_null());
+    let object = object::new_object(&mut heap, proto);
+    for &(target, name) in &[(proto, "shadowed"), (proto, "inherited"), (proto, "hidden"), (object, "b"),
+                             (object, "10"), (object, "a"), (object, "2"), (object, "shadowed"),
+                             (object, "4294967295"), (object, "-1"), (object, "01")] {
+        let key = key(&mut heap, name);
+        object::set_own_value(&mut heap, target, key, Value::new_imm_bool(true));
+    }
+    let hidden = key(&mut heap, "hidden");
+    let property = Property::Data { value: Value::new_imm_i32(1), attrs: PropertyAttributes::WRITABLE };
+    object::define_own_property(&mut heap, proto, hidden, property);
+
+    // Array indices come first in ascending order, then the other keys in the
+    // order they were added; 2^32 - 1 isn't an index.
+    assert_eq!(keys_of(&mut heap, object), ["2", "10", "b", "a", "shadowed", "4294967295", "-1", "01"]);
+    let enumerable: Vec<String> = object::enumerable_keys(&mut heap, object).into_iter()
+        .map(|key| string::to_rust_string(&heap, key).unwrap())
+        .collect();
+    assert_eq!(enumerable, ["2", "10", "b", "a", "shadowed", "4294967295", "-1", "01", "inherited"]);
+
+    let (holder, _) = object::lookup_property(&mut heap, object, hidden).unwrap();
+    assert_eq!(holder, proto);
+    let shadowed = key(&mut heap, "shadowed");
+    assert_eq!(object::lookup_property(&mut heap, object, shadowed).unwrap().0, object);
+    let missing = key(&mut heap, "missing");
+    assert!(object::lookup_property(&mut heap, object, missing).is_none());
+    assert!(object::has_property(&heap, object, hidden) && ! object::has_own_property(&heap, object, hidden));
+}
@@ -0,0 +1,221 @@
+extern crate liboxyjs;
+
+use std::thread;
+use liboxyjs::parser::ast::{self, AstNode, CommentKind, NodeComments};
+use liboxyjs::parser::ast_builder::{AstBuilder, ParseError};
+use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::parser::token_kind::TokenKind;
+
+#[test]
+fn parsing_needs_no_initialization() {
+    assert_eq!(TokenKind::open_paren().name(), "open_paren");
+    assert_eq!(TokenKind::while_keyword().name(), "while_keyword");
+
+    // Initializing is harmless, however often it's done.
+    liboxyjs::initialize_library();
+    liboxyjs::initialize_library();
+
+    let threads: Vec<_> = (0..8).map(|i| {
+        thread::spawn(move || {
+            let source = format!("var x{} = [1, 2, {}]; while (x{}.length) {{ x{}.pop(); }}", i, i, i, i);
+            AstBuilder::new(VecInputStream::new(source.into_bytes())).parse_program().is_ok()
+        })
+    }).collect();
+    for thread in threads {
+        assert!(thread.join().unwrap());
+    }
+}
+
+fn parse(source: &str) -> Result<(), ParseError> {
+    AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().map(|_| ())
+}
+
+// The source text an early error points at.
+fn early_error_text(source: &str) -> String {
+    let location = match parse(source) {
+        Err(ParseError::InvalidAssignmentTarget(location)) |
+        Err(ParseError::StrictModeEvalOrArguments(location)) => location,
+        other => panic!("{}: unexpected result {:?}", source, other)
+    };
+    source[location.start_offset().value() as usize .. location.end_offset().value() as usize].to_string()
+}
+
+#[test]
+fn invalid_assignment_targets_are_early_errors() {
+    assert_eq!(early_error_text("1 = 2"), "1");
+    assert_eq!(early_error_text("x; f() += 3"), "f()");
+    assert_eq!(early_error_text("a = (b + c) *= 2"), "(b + c)");
+    assert_eq!(early_error_text("++this"), "this");
+    assert_eq!(early_error_text("y = --f(x)"), "f(x)");
+    assert_eq!(early_error_text("new Date()++"), "new Date()");
+    assert_eq!(early_error_text("for (1 in o);"), "1");
+    assert_eq!(early_error_text("for (a + b in o);"), "a + b");
+    assert_eq!(early_error_text("'use strict'; eval = 1"), "eval");
+    assert_eq!(early_error_text("'use strict'; arguments++"), "arguments");
+    assert_eq!(early_error_text("'use strict'; for (eval in o);"), "eval");
+
+    for source in &["a = b", "a.b += 1", "a[0]++", "--(a)", "for (var k in o);", "for (a.b in o);",
+                    "for (a[k] in o);", "for (var k = 0 in o);", "for (var i = [0 in o]; ;) break;",
+                    "for (var i = 0, n = 1; i < n; i++);", "eval = arguments = 1"] {
+        assert!(parse(source).is_ok(), "{}", source);
+    }
+    assert!(matches!(parse("for (var a, b in o);"), Err(ParseError::ExpectedCommaOrSemicolon)));
+}
+
+// The kind of error a program fails to parse with.
+fn parse_error(source: &str) -> String {
+    match parse(source) {
+        Err(error) => format!("{:?}", error).split('(').next().unwrap().to_string(),
+        Ok(()) => panic!("{}: parsed", source)
+    }
+}
+
+#[test]
+fn strict_mode_restrictions_are_early_errors() {
+    let strict_errors = [
+        ("with (o) {}", "StrictModeWith"),
+        ("x = 010", "StrictModeOctalLiteral"),
+        ("x = '\\012'", "StrictModeOctalEscape"),
+        ("delete x", "StrictModeDeleteIdentifier"),
+        ("delete ((x))", "StrictModeDeleteIdentifier"),
+        ("var eval", "StrictModeEvalOrArguments"),
+        ("function arguments() {}", "StrictModeEvalOrArguments"),
+        ("function f(eval) {}", "StrictModeEvalOrArguments"),
+        ("try {} catch (arguments) {}", "StrictModeEvalOrArguments"),
+        ("function f(a, a) {}", "StrictModeDuplicateParameter"),
+        ("var implements", "StrictModeReservedWord"),
+        ("let = 1", "StrictModeReservedWord"),
+        ("function f(yield) {}", "StrictModeReservedWord"),
+    ];
+    for &(source, error) in &strict_errors {
+        // Each is allowed in non-strict code, and an error in strict code.
+        assert!(parse(source).is_ok(), "{}", source);
+        assert_eq!(parse_error(&format!("'use strict'; {}", source)), error, "{}", source);
+        assert_eq!(parse_error(&format!("function g() {{ 'use strict'; {} }}", source)), error, "{}", source);
+    }
+
+    // Only a prologue of string literal statements can hold the directive.
+    assert!(parse("'a'; 'use strict'; with (o) {}").is_err());
+    assert!(parse("x; 'use strict'; with (o) {}").is_ok());
+    assert!(parse("('use strict'); with (o) {}").is_ok());
+    assert!(parse("'use\\x20strict'; with (o) {}").is_ok());
+    // A function's directive applies to its own parameters, but not outside it.
+    assert_eq!(parse_error("function f(a, a) { 'use strict'; }"), "StrictModeDuplicateParameter");
+    assert!(parse("function f() { 'use strict'; } with (o) {}").is_ok());
+    // Nested functions inherit strictness.
+    assert_eq!(parse_error("'use strict'; function f() { return function() { with (o) {} }; }"),
+               "StrictModeWith");
+}
+
+#[test]
+fn identifiers_ending_in_keywords_are_identifiers() {
+    for name in &["bandwith", "startsWith", "xthis", "refnull", "istrue", "showcase", "devoid", "unfunction",
+                  "funcAction", "myreturn", "isfalse", "notypeof", "awhile", "jailbreak", "mycatch", "undelete",
+                  "rethrow", "noswitch", "discontinue", "nodefault", "xinstanceof", "infinally", "orelse"] {
+        let source = format!("var {} = 1; {}++;", name, name);
+        assert!(parse(&source).is_ok(), "{}", name);
+        assert!(parse(&format!("'use strict'; {}", source)).is_ok(), "{}", name);
+    }
+    for keyword in &["this", "function", "return", "else", "null", "true", "false", "typeof", "while", "case",
+                     "break", "catch", "delete", "throw", "switch", "continue", "default", "instanceof", "void",
+                     "with", "finally"] {
+        assert_eq!(parse_error(&format!("var {} = 1", keyword)), "ExpectedVariableName", "{}", keyword);
+    }
+}
+
+#[test]
+fn duplicate_properties_are_found_by_spelling() {
+    for source in &["({a: 1, a: 2})", "({a: 1, 'a': 2})", "({\"a\": 1, 'a': 2})", "({1: 0, 1.0: 0})",
+                    "({0x10: 0, 16: 0})"] {
+        assert!(parse(source).is_ok(), "{}", source);
+        assert_eq!(parse_error(&format!("'use strict'; {}", source)), "DuplicateProperty", "{}", source);
+    }
+    assert_eq!(parse_error("({get a() {}, a: 1})"), "DuplicateProperty");
+
+    // Names are compared by their raw text, not by their values.
+    for source in &["({'a': 1, '\\x61': 2})", "({1: 0, '1': 0})", "({get 1() {}, '1': 0})"] {
+        assert!(parse(&format!("'use strict'; {}", source)).is_ok(), "{}", source);
+    }
+}
+
+#[test]
+fn programs_and_functions_record_strictness() {
+    let program = |source: &str| {
+        AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap()
+    };
+    assert!(program("'use strict'; x").is_strict());
+    assert!(! program("x; 'use strict'").is_strict());
+    assert!(! program("function f() { 'use strict'; }").is_strict());
+}
+
+fn parse_with_comments(source: &str) -> Box<ast::ProgramNode> {
+    let mut builder = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec()));
+    builder.set_collect_comments(true);
+    builder.parse_program().unwrap()
+}
+
+fn texts(comments: &[ast::Comment]) -> Vec<&str> {
+    comments.iter().map(|c| c.text()).collect()
+}
+
+// The text of each group of comments attached to a node.
+fn comment_texts(comments: &NodeComments) -> [Vec<&str>; 3] {
+    [texts(comments.leading()), texts(comments.trailing()), texts(comments.inner())]
+}
+
+fn statement_comments(stmt: &dyn AstNode) -> [Vec<&str>; 3] {
+    comment_texts(stmt.comments().unwrap())
+}
+
+#[test]
+fn comments_attach_to_the_nearest_statements() {
+    let source = "/* @license MIT */\n\
+                  // first\n\
+                  var a = 1; // after a\n\
+                  \n\
+                  /* before b */ b(/* inside */);\n\
+                  {\n\
+                  \x20 c(); /* after c */\n\
+                  \x20 // end of block\n\
+                  }\n\
+                  // end of program\n";
+    let program = parse_with_comments(source);
+    let stmts = program.source_elements();
+    assert_eq!(stmts.len(), 3);
+
+    let [leading, trailing, inner] = statement_comments(stmts[0].as_ref());
+    assert_eq!(leading, ["/* @license MIT */", "// first\n"]);
+    assert_eq!(trailing, ["// after a\n"]);
+    assert!(inner.is_empty());
+    let license = &stmts[0].comments().unwrap().leading()[0];
+    assert_eq!(license.kind(), CommentKind::Block);
+    assert_eq!(license.location().start_offset().value(), 0);
+    assert_eq!(stmts[0].comments().unwrap().leading()[1].kind(), CommentKind::Line);
+
+    let [leading, trailing, inner] = statement_comments(stmts[1].as_ref());
+    assert_eq!(leading, ["/* before b */"]);
+    assert!(trailing.is_empty());
+    assert_eq!(inner, ["/* inside */"]);
+
+    let block = stmts[2].downcast_ref::<ast::BlockStmtNode>().unwrap();
+    assert_eq!(statement_comments(block.statements()[0].as_ref())[1], ["/* after c */"]);
+    assert_eq!(statement_comments(stmts[2].as_ref())[2], ["// end of block\n"]);
+    assert_eq!(comment_texts(program.comments().unwrap())[2], ["// end of program\n"]);
+}
+
+#[test]
+fn comments_are_kept_once_across_backtracking() {
+    // Blocks and object literals, and for statements' heads, are reparsed after
+    // rewinding past comments.
+    let program = parse_with_comments("({ /* a */ x: 1 });\n{ /* b */ }\nfor (/* c */ k in o) /* d */;\n");
+    let stmts = program.source_elements();
+    assert_eq!(statement_comments(stmts[0].as_ref())[2], ["/* a */"]);
+    assert_eq!(statement_comments(stmts[1].as_ref())[2], ["/* b */"]);
+    assert_eq!(statement_comments(stmts[2].as_ref())[2], ["/* c */", "/* d */"]);
+
+    // Collecting comments doesn't change how a newline after one is seen.
+    let program = parse_with_comments("a /* x */\n++b");
+    assert_eq!(program.source_elements().len(), 2);
+    let program = parse_with_comments("return_ = a // x\n(b)");
+    assert_eq!(program.source_elements().len(), 1);
+}
@@ -0,0 +1,89 @@
+extern crate liboxyjs;
+
+use liboxyjs::vm::regexp::{Captures, RegExp, RegExpError, DEFAULT_STEP_LIMIT};
+
+fn units(text: &str) -> Vec<u16> {
+    text.encode_utf16().collect()
+}
+
+fn compile(pattern: &str, flags: &str) -> RegExp {
+    RegExp::new(&units(pattern), &units(flags)).unwrap()
+}
+
+// The texts of the first match and its groups.
+fn exec(pattern: &str, flags: &str, input: &str) -> Option<Vec<Option<String>>> {
+    let input = units(input);
+    let mut steps = DEFAULT_STEP_LIMIT;
+    let captures: Captures = compile(pattern, flags).search(&input, 0, &mut steps).unwrap()?;
+    Some(captures.iter().map(|capture| {
+        capture.map(|(start, end)| String::from_utf16(&input[start..end]).unwrap())
+    }).collect())
+}
+
+fn texts(expected: &[Option<&str>]) -> Option<Vec<Option<String>>> {
+    Some(expected.iter().map(|text| text.map(String::from)).collect())
+}
+
+// The examples are those of the spec's pattern semantics.
+#[test]
+fn quantified_groups_reset_their_captures() {
+    assert_eq!(exec("(z)((a+)?(b+)?(c))*", "", "zaacbbbcac"),
+               texts(&[Some("zaacbbbcac"), Some("z"), Some("ac"), Some("a"), None, Some("c")]));
+    assert_eq!(exec("(a*)*", "", "b"), texts(&[Some(""), None]));
+    assert_eq!(exec("(a*)b\\1+", "", "baaaac"), texts(&[Some("b"), Some("")]));
+    assert_eq!(exec("a[a-z]{2,4}", "", "abcdefghi"), texts(&[Some("abcde")]));
+    assert_eq!(exec("a[a-z]{2,4}?", "", "abcdefghi"), texts(&[Some("abc")]));
+    assert_eq!(exec("(aa|aabaac|ba|b|c)*", "", "aabaac"), texts(&[Some("aaba"), Some("ba")]));
+}
+
+#[test]
+fn lookaheads_are_atomic() {
+    assert_eq!(exec("(?=(a+))", "", "baaabac"), texts(&[Some(""), Some("aaa")]));
+    assert_eq!(exec("(?=(a+))a*b\\1", "", "baaabac"), texts(&[Some("aba"), Some("a")]));
+    assert_eq!(exec("(.*?)a(?!(a+)b\\2c)\\2(.*)", "", "baaabaac"),
+               texts(&[Some("baaabaac"), Some("ba"), None, Some("abaac")]));
+}
+
+#[test]
+fn flags_change_matching() {
+    assert_eq!(exec("[a-z]+", "i", "12HeLLo"), texts(&[Some("HeLLo")]));
+    assert_eq!(exec("\u{e9}", "i", "\u{c9}"), texts(&[Some("\u{c9}")]));
+    assert_eq!(exec("^b$", "", "a\nb\nc"), None);
+    assert_eq!(exec("^b$", "m", "a\nb\nc"), texts(&[Some("b")]));
+    assert!(compile("a", "gim").flags().global);
+    assert_eq!(RegExp::new(&units("a"), &units("gg")).err(), Some(RegExpError::InvalidFlags));
+    assert_eq!(RegExp::new(&units("a"), &units("x")).err(), Some(RegExpError::InvalidFlags));
+}
+
+#[test]
+fn web_extensions_are_accepted() {
+    assert_eq!(exec("]{}", "", "]{}"), texts(&[Some("]{}")]));
+    assert_eq!(exec("\\101\\8", "", "A8"), texts(&[Some("A8")]));
+    assert_eq!(exec("[\\d-z]+", "", "1-z"), texts(&[Some("1-z")]));
+    assert_eq!(exec("\\c", "", "\\c"), texts(&[Some("\\c")]));
+    assert!(RegExp::new(&units("a**"), &[]).is_err());
+    assert!(RegExp::new(&units("(a"), &[]).is_err());
+    assert!(RegExp::new(&units("a)"), &[]).is_err());
+    assert!(RegExp::new(&units("[z-a]"), &[]).is_err());
+    assert!(RegExp::new(&units("a{2,1}"), &[]).is_err());
+}
+
+#[test]
+fn escaped_source_can_be_reparsed() {
+    let escaped = |pattern: &str| String::from_utf16(&compile(pattern, "").escaped_source()).unwrap();
+    assert_eq!(escaped(""), "(?:)");
+    assert_eq!(escaped("a/b[/]\\/"), "a\\/b[/]\\/");
+    assert_eq!(escaped("a\nb"), "a\\nb");
+}
+
+#[test]
+fn catastrophic_patterns_hit_the_step_limit() {
+    let regexp = compile("(a*)*b", "");
+    let input = units(&"a".repeat(40));
+    let mut steps = DEFAULT_STEP_LIMIT;
+    assert!(regexp.search(&input, 0, &mut steps).is_err());
+
+    let mut steps = 1000;
+    assert_eq!(regexp.search(&units("aaab"), 0, &mut steps), Ok(Some(vec![Some((0, 4)), Some((0, 3))])));
+    assert!(steps > 0);
+}
@@ -0,0 +1,234 @@
+extern crate liboxyjs;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use liboxyjs::runtime::{Context, Error, JsValue, Runtime};
+use liboxyjs::vm::builtins::error::ErrorKind;
+
+// The message of a thrown error.
+fn thrown_message(runtime: &mut Runtime, error: Error) -> String {
+    match error {
+        Error::Throw { value, .. } => {
+            let mut cx = runtime.context();
+            let message = cx.get(&value, "message").unwrap();
+            cx.convert(&message).unwrap()
+        }
+        other => panic!("expected a throw, got {:?}", other)
+    }
+}
+
+#[test]
+fn values_convert_to_rust_types() {
+    let mut runtime = Runtime::new();
+    let result = runtime.eval("var xs = [1, '2', 3.5]; xs.length * 2").unwrap();
+    let mut cx = runtime.context();
+    assert_eq!(cx.convert::<f64>(&result).unwrap(), 6.0);
+    assert_eq!(cx.convert::<String>(&result).unwrap(), "6");
+
+    let xs = cx.get_global("xs").unwrap();
+    assert_eq!(cx.convert::<Vec<f64>>(&xs).unwrap(), vec![1.0, 2.0, 3.5]);
+    assert_eq!(cx.convert::<Vec<String>>(&xs).unwrap(), vec!["1", "2", "3.5"]);
+    let missing = cx.get_global("missing").unwrap();
+    assert_eq!(cx.convert::<Option<i32>>(&missing).unwrap(), None);
+    assert!(cx.convert::<Vec<i32>>(&missing).is_err());
+
+    cx.set_global("names", vec!["a", "b"]).unwrap();
+    cx.set_global("nothing", None::<bool>).unwrap();
+    let joined = runtime.eval("names.join('+') + ' ' + nothing").unwrap();
+    assert_eq!(runtime.context().convert::<String>(&joined).unwrap(), "a+b null");
+}
+
+#[test]
+fn properties_are_read_and_written() {
+    let mut runtime = Runtime::new();
+    let mut cx = runtime.context();
+    let point = cx.new_object();
+    cx.set(&point, "x", 3).unwrap();
+    cx.set(&point, "y", 4.5).unwrap();
+    cx.set_global("point", &point).unwrap();
+    let result = runtime.eval("Object.defineProperty(point, 'sum', { get: function() { return this.x + this.y; } });
+                               point.x = 10; point").unwrap();
+    let mut cx = runtime.context();
+    let sum = cx.get(&result, "sum").unwrap();
+    assert_eq!(cx.convert::<f64>(&sum).unwrap(), 14.5);
+
+    let frozen = runtime.eval("Object.freeze({ a: 1 })").unwrap();
+    let error = runtime.context().set(&frozen, "a", 2).unwrap_err();
+    assert!(thrown_message(&mut runtime, error).contains("a"));
+}
+
+#[test]
+fn host_functions_are_called_from_script() {
+    let mut runtime = Runtime::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    runtime.register_function("add", move |cx: &mut Context, args: &[JsValue]| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let mut sum = 0.0;
+        for arg in args {
+            sum += cx.convert::<f64>(arg)?;
+        }
+        cx.value(sum)
+    });
+    runtime.register_function("fail", |cx: &mut Context, _args: &[JsValue]| {
+        Err(cx.throw_error(ErrorKind::RangeError, "out of range"))
+    });
+    runtime.register_function("reject", |_cx: &mut Context, _args: &[JsValue]| {
+        Err(Error::Type("not acceptable".to_string()))
+    });
+
+    let result = runtime.eval("add(1, 2, '3') + add()").unwrap();
+    assert_eq!(runtime.context().convert::<f64>(&result).unwrap(), 6.0);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    let result = runtime.eval("var caught = []; \
+                               try { fail(); } catch (e) { caught.push(e instanceof RangeError, e.message); } \
+                               try { reject(); } catch (e) { caught.push(e instanceof TypeError, e.message); } \
+                               caught.join()").unwrap();
+    assert_eq!(runtime.context().convert::<String>(&result).unwrap(),
+               "true,out of range,true,not acceptable");
+
+    // Exceptions from script pass through host functions unchanged.
+    runtime.register_function("invoke", |cx: &mut Context, args: &[JsValue]| {
+        let undefined = cx.undefined();
+        cx.call(&args[0], &undefined, &args[1..])
+    });
+    let result = runtime.eval("try { invoke(function(x) { throw x; }, 'thrown'); } catch (e) { e }").unwrap();
+    assert_eq!(runtime.context().convert::<String>(&result).unwrap(), "thrown");
+}
+
+#[test]
+fn script_errors_are_returned() {
+    let mut runtime = Runtime::new();
+    match runtime.eval("var = 1") {
+        Err(Error::Parse(_)) => {}
+        other => panic!("expected a parse error, got {:?}", other)
+    }
+    let error = runtime.eval("null.x").unwrap_err();
+    assert!(thrown_message(&mut runtime, error).contains("null"));
+
+    let function = runtime.eval("(function(a, b) { if (b === 0) throw new Error('divide by zero'); return a / b; })")
+        .unwrap();
+    let mut cx = runtime.context();
+    let undefined = cx.undefined();
+    let args = [cx.value(1).unwrap(), cx.value(4).unwrap()];
+    let result = cx.call(&function, &undefined, &args).unwrap();
+    assert_eq!(cx.convert::<f64>(&result).unwrap(), 0.25);
+    let args = [cx.value(1).unwrap(), cx.value(0).unwrap()];
+    let error = cx.call(&function, &undefined, &args).unwrap_err();
+    assert_eq!(thrown_message(&mut runtime, error), "divide by zero");
+}
+
+#[test]
+fn held_values_survive_collection() {
+    let mut runtime = Runtime::new();
+    let kept = runtime.eval("({ name: 'kept', items: [1, 2, 3] })").unwrap();
+    runtime.eval("for (var i = 0; i < 100000; i++) { var garbage = { i: i, s: 'x' + i }; }").unwrap();
+    let mut cx = runtime.context();
+    let name = cx.get(&kept, "name").unwrap();
+    assert_eq!(cx.convert::<String>(&name).unwrap(), "kept");
+    let items = cx.get(&kept, "items").unwrap();
+    assert_eq!(cx.convert::<Vec<i32>>(&items).unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn host_calls_dont_accumulate_handles() {
+    let mut runtime = Runtime::new();
+    runtime.register_function("identity", |cx: &mut Context, args: &[JsValue]| {
+        Ok(args.first().cloned().unwrap_or_else(|| cx.undefined()))
+    });
+    // The loop allocates nothing, so never collects.
+    let result = runtime.eval("var sum = 0; for (var i = 0; i < 100000; i++) { sum += identity(i); } sum").unwrap();
+    assert_eq!(runtime.context().convert::<f64>(&result).unwrap(), 4999950000.0);
+    assert!(runtime.handle_count() < 1000, "{} handles", runtime.handle_count());
+}
+
+#[test]
+fn runtimes_are_isolated() {
+    let mut first = Runtime::new();
+    let mut second = Runtime::new();
+    first.eval("var name = 'first'; Object.prototype.shared = 1;").unwrap();
+    second.eval("var name = 'second';").unwrap();
+    let result = second.eval("name + ' ' + typeof ({}).shared").unwrap();
+    assert_eq!(second.context().convert::<String>(&result).unwrap(), "second undefined");
+    let result = first.eval("name + ' ' + ({}).shared").unwrap();
+    assert_eq!(first.context().convert::<String>(&result).unwrap(), "first 1");
+}
+
+#[test]
+fn values_cannot_cross_runtimes() {
+    let mut first = Runtime::new();
+    let mut second = Runtime::new();
+    let value = first.eval("({ x: 1 })").unwrap();
+    let mut cx = second.context();
+    match cx.get(&value, "x") {
+        Err(Error::ForeignValue { .. }) => {}
+        other => panic!("expected a foreign value error, got {:?}", other)
+    }
+    assert!(cx.convert::<String>(&value).is_err());
+    assert!(cx.new_array(std::slice::from_ref(&value)).is_err());
+    assert!(cx.set_global("leaked", &value).is_err());
+
+    // A host function returning one throws a TypeError to its caller.
+    second.register_function("leak", move |_cx: &mut Context, _args: &[JsValue]| Ok(value.clone()));
+    let result = second.eval("try { leak(); } catch (e) { e instanceof TypeError }").unwrap();
+    assert!(second.context().convert::<bool>(&result).unwrap());
+    let result = first.eval("({ x: 1 }).x").unwrap();
+    assert_eq!(first.context().convert::<i32>(&result).unwrap(), 1);
+}
+
+#[test]
+fn runtimes_move_between_threads() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Runtime>();
+    assert_send::<JsValue>();
+
+    let mut runtime = Runtime::new();
+    runtime.register_function("twice", |cx: &mut Context, args: &[JsValue]| {
+        let x = cx.convert::<f64>(&args[0])?;
+        cx.value(x * 2.0)
+    });
+    let kept = runtime.eval("var total = 0; ({ items: [1, 2, 3] })").unwrap();
+
+    // A value can go with its runtime, and be used on the other side.
+    let (mut runtime, total) = thread::spawn(move || {
+        let result = runtime.eval("for (var i = 0; i < 10000; i++) { total += twice(1); } total").unwrap();
+        let total = runtime.context().convert::<f64>(&result).unwrap();
+        (runtime, total)
+    }).join().unwrap();
+    assert_eq!(total, 20000.0);
+    let mut cx = runtime.context();
+    let items = cx.get(&kept, "items").unwrap();
+    assert_eq!(cx.convert::<Vec<i32>>(&items).unwrap(), vec![1, 2, 3]);
+
+    // Runtimes run alongside each other, one per thread.
+    let threads: Vec<_> = (0..4).map(|i| {
+        thread::spawn(move || {
+            let mut runtime = Runtime::new();
+            runtime.context().set_global("id", i).unwrap();
+            let result = runtime.eval("var s = ''; for (var j = 0; j < 1000; j++) { s = 'r' + id; } s").unwrap();
+            runtime.context().convert::<String>(&result).unwrap()
+        })
+    }).collect();
+    for (i, thread) in threads.into_iter().enumerate() {
+        assert_eq!(thread.join().unwrap(), format!("r{}", i));
+    }
+}
+
+#[test]
+fn values_display_as_strings() {
+    let mut runtime = Runtime::new();
+    let values = runtime.eval("[[1, [2, 3]], null, 'text', { toString: function() { return 'custom'; } }, {}]")
+        .unwrap();
+    let mut cx = runtime.context();
+    let values: Vec<JsValue> = cx.convert(&values).unwrap();
+    let shown: Vec<String> = values.iter().map(|value| cx.display(value).unwrap()).collect();
+    assert_eq!(shown, ["1,2,3", "null", "text", "custom", "[object Object]"]);
+
+    let array = cx.new_array(&values[1..3]).unwrap();
+    assert_eq!(cx.display(&array).unwrap(), ",text");
+    let failing = runtime.eval("({ toString: function() { throw new Error('no string'); } })").unwrap();
+    let error = runtime.context().display(&failing).unwrap_err();
+    assert_eq!(thrown_message(&mut runtime, error), "no string");
+}
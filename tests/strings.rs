@@ -0,0 +1,121 @@
+extern crate liboxyjs;
+
+use liboxyjs::vm::heap::Heap;
+use liboxyjs::vm::string::{self, HeapString, StringContents};
+use liboxyjs::vm::value::Value;
+
+fn text(heap: &Heap, value: Value) -> String {
+    string::to_rust_string(heap, value).unwrap()
+}
+
+fn is_rope(heap: &Heap, value: Value) -> bool {
+    heap.get::<HeapString>(value).is_some_and(HeapString::is_rope)
+}
+
+#[test]
+fn short_latin1_strings_are_immediates() {
+    let mut heap = Heap::new();
+    assert!(string::new_string(&mut heap, "").is_imm_str());
+    assert!(string::new_string(&mut heap, "abcdefg").is_imm_str());
+    assert!(string::new_string(&mut heap, "caf\u{e9}").is_imm_str());
+    assert_eq!(heap.cell_count(), 0);
+
+    let long = string::new_string(&mut heap, "abcdefgh");
+    let wide = string::new_string(&mut heap, "\u{20ac}");
+    assert!(! long.is_imm_str() && ! wide.is_imm_str());
+    match heap.get::<HeapString>(long).unwrap().contents() {
+        StringContents::Latin1(chars) => assert_eq!(&chars[..], b"abcdefgh"),
+        _ => panic!("not Latin-1")
+    }
+    match heap.get::<HeapString>(wide).unwrap().contents() {
+        StringContents::Utf16(units) => assert_eq!(&units[..], [0x20AC]),
+        _ => panic!("not UTF-16")
+    }
+
+    // Slices short enough to be immediates come back as them.
+    let slice = string::substring(&mut heap, long, 2, 5);
+    assert!(slice.is_imm_str());
+    assert_eq!(text(&heap, slice), "cde");
+}
+
+#[test]
+fn ropes_flatten_lazily() {
+    let mut heap = Heap::new();
+    let left = string::new_string(&mut heap, "the quick brown fox ");
+    let right = string::new_string(&mut heap, "jumps over the \u{1d11e} dog");
+    let short = string::concat(&mut heap, left, Value::new_imm_str(b"!"));
+    assert!(! is_rope(&heap, short));
+
+    let rope = string::concat(&mut heap, left, right);
+    let nested = string::concat(&mut heap, rope, rope);
+    assert!(is_rope(&heap, rope) && is_rope(&heap, nested));
+    assert_eq!(string::string_length(&heap, nested), Some(2 * (20 + 21)));
+    assert_eq!(string::code_unit_at(&heap, nested, 41 + 4), Some(b'q' as u16));
+    assert_eq!(string::code_unit_at(&heap, nested, 82), None);
+
+    // Ropes equal flat strings with the same code units.
+    let flat = string::new_string(&mut heap, "the quick brown fox jumps over the \u{1d11e} dog");
+    assert!(string::strings_equal(&heap, rope, flat));
+    assert!(! string::strings_equal(&heap, nested, flat));
+
+    // Flattening happens in place, keeping the value.
+    assert_eq!(string::flatten(&mut heap, nested), nested);
+    assert!(! is_rope(&heap, nested));
+    assert!(is_rope(&heap, rope));
+    match heap.get::<HeapString>(nested).unwrap().contents() {
+        StringContents::Utf16(units) => assert_eq!(units.len(), 82),
+        _ => panic!("not UTF-16")
+    }
+    let sliced = string::substring(&mut heap, rope, 4, 9);
+    assert_eq!(text(&heap, sliced), "quick");
+    assert!(! is_rope(&heap, rope));
+}
+
+#[test]
+fn atoms_are_interned() {
+    let mut heap = Heap::new();
+    let atom = string::atomize_str(&mut heap, "longer name");
+    assert!(string::is_atom(&heap, atom));
+    assert_eq!(string::atomize_str(&mut heap, "longer name"), atom);
+    assert_eq!(heap.atoms().len(), 1);
+
+    // Equal strings atomize to the existing atom.
+    let copy = string::new_string(&mut heap, "longer name");
+    assert!(! string::is_atom(&heap, copy));
+    assert!(string::strings_equal(&heap, copy, atom));
+    assert_eq!(string::atomize(&mut heap, copy), atom);
+
+    // Otherwise the string's own cell becomes the atom, flattened.
+    let left = string::new_string(&mut heap, "a rope made into ");
+    let right = string::new_string(&mut heap, "an atom");
+    let rope = string::concat(&mut heap, left, right);
+    assert_eq!(string::atomize(&mut heap, rope), rope);
+    assert!(! is_rope(&heap, rope));
+    assert_eq!(string::atomize_str(&mut heap, "a rope made into an atom"), rope);
+
+    // Immediates are atoms as they are.
+    let short = string::atomize_str(&mut heap, "length");
+    assert!(short.is_imm_str() && string::is_atom(&heap, short));
+    assert_eq!(heap.atoms().len(), 2);
+}
+
+#[test]
+fn atoms_survive_collection() {
+    let mut heap = Heap::new();
+    let atom = string::atomize_str(&mut heap, "kept forever");
+    let left = string::new_string(&mut heap, "a rope keeps ");
+    let right = string::new_string(&mut heap, "both its halves");
+    let rope = string::concat(&mut heap, left, right);
+    let kept = heap.root(rope);
+    string::new_string(&mut heap, "garbage string");
+    assert_eq!(heap.cell_count(), 5);
+
+    heap.collect();
+    assert_eq!(heap.cell_count(), 4);
+    assert_eq!(text(&heap, kept.get()), "a rope keeps both its halves");
+    assert_eq!(string::atomize_str(&mut heap, "kept forever"), atom);
+
+    drop(kept);
+    heap.collect();
+    assert_eq!(heap.cell_count(), 1);
+}
@@ -0,0 +1,131 @@
+extern crate liboxyjs;
+
+use liboxyjs::parser::ast_builder::AstBuilder;
+use liboxyjs::parser::input_stream::VecInputStream;
+use liboxyjs::vm::interpreter::Interpreter;
+use liboxyjs::vm::time::{self, Clock, TimeZone};
+
+fn utc(year: f64, month: f64, date: f64, hour: f64, min: f64, sec: f64, ms: f64) -> f64 {
+    time::make_date(time::make_day(year, month, date), time::make_time(hour, min, sec, ms))
+}
+
+fn parse(text: &str) -> f64 {
+    time::parse(text, &TimeZone::Utc)
+}
+
+#[test]
+fn time_values_split_into_fields() {
+    let t = utc(2024.0, 1.0, 29.0, 13.0, 14.0, 15.0, 16.0);
+    assert_eq!(t, 1709212455016.0);
+    assert_eq!((time::year_from_time(t), time::month_from_time(t), time::date_from_time(t)), (2024.0, 1.0, 29.0));
+    assert_eq!(time::week_day(t), 4.0);
+    assert_eq!((time::hour_from_time(t), time::min_from_time(t), time::sec_from_time(t), time::ms_from_time(t)),
+               (13.0, 14.0, 15.0, 16.0));
+
+    // Before the epoch, and around the leap days of centuries.
+    let t = -1.0;
+    assert_eq!((time::year_from_time(t), time::month_from_time(t), time::date_from_time(t)), (1969.0, 11.0, 31.0));
+    assert_eq!(time::ms_from_time(t), 999.0);
+    assert_eq!(time::date_from_time(utc(1900.0, 1.0, 29.0, 0.0, 0.0, 0.0, 0.0)), 1.0);
+    assert_eq!(time::date_from_time(utc(2000.0, 1.0, 29.0, 0.0, 0.0, 0.0, 0.0)), 29.0);
+
+    // Fields out of range carry into the next.
+    assert_eq!(time::make_day(2023.0, 13.0, 1.0), time::make_day(2024.0, 1.0, 1.0));
+    assert_eq!(time::make_day(2024.0, 0.0, 0.0), time::make_day(2023.0, 11.0, 31.0));
+    assert!(time::make_day(2024.0, f64::NAN, 1.0).is_nan());
+}
+
+#[test]
+fn time_values_are_clipped() {
+    assert_eq!(time::time_clip(8.64e15), 8.64e15);
+    assert!(time::time_clip(8.64e15 + 1.0).is_nan());
+    assert!(time::time_clip(f64::INFINITY).is_nan());
+    assert_eq!(time::time_clip(-0.5).to_bits(), 0.0f64.to_bits());
+    assert_eq!(time::time_clip(1.9), 1.0);
+}
+
+#[test]
+fn iso_dates_are_parsed() {
+    assert_eq!(parse("2024-01-02T03:04:05.006Z"), 1704164645006.0);
+    assert_eq!(parse("2024-01-02"), 1704153600000.0);
+    assert_eq!(parse("2024"), 1704067200000.0);
+    assert_eq!(parse("2024-01-02T03:04:05+01:30"), 1704159245000.0);
+    assert_eq!(parse("2024-01-02T24:00Z"), 1704240000000.0);
+    assert_eq!(parse("+275760-09-13T00:00:00Z"), 8.64e15);
+    assert_eq!(parse("-000001-01-01T00:00:00Z"), -62198755200000.0);
+    assert!(parse("-000000-01-01T00:00:00Z").is_nan());
+    assert!(parse("2023-02-29").is_nan());
+    assert!(parse("2024-01-02T24:01Z").is_nan());
+    assert!(parse("2024-13-01").is_nan());
+
+    // Date-times without an offset are local; dates alone are UTC.
+    let zone = TimeZone::from_posix("EST5").unwrap();
+    assert_eq!(time::parse("2024-01-02T00:00", &zone), 1704171600000.0);
+    assert_eq!(time::parse("2024-01-02", &zone), 1704153600000.0);
+}
+
+#[test]
+fn legacy_dates_are_parsed() {
+    assert_eq!(parse("Tue, 02 Jan 2024 03:04:05 GMT"), 1704164645000.0);
+    assert_eq!(parse("Tue Jan 02 2024 03:04:05 GMT+0100 (CET)"), 1704161045000.0);
+    assert_eq!(parse("Mon, 25 Dec 1995 13:30:00 +0430"), 819882000000.0);
+    assert_eq!(parse("December 17, 1995 03:24:00 EST"), 819188640000.0);
+    assert_eq!(parse("1/2/2024 3:04 PM"), 1704207840000.0);
+    assert_eq!(parse("12/25/95"), 819849600000.0);
+    assert_eq!(parse("2024-01-02 03:04:05"), 1704164645000.0);
+    assert!(parse("Feb 30 2024").is_nan());
+    assert!(parse("13:00 PM Jan 1 2024").is_nan());
+    assert!(parse("nonsense").is_nan());
+}
+
+#[test]
+fn dates_are_formatted() {
+    let t = 1704164645006.0;
+    assert_eq!(time::to_iso_string(t), "2024-01-02T03:04:05.006Z");
+    assert_eq!(time::to_utc_string(t), "Tue, 02 Jan 2024 03:04:05 GMT");
+    assert_eq!(time::to_date_time_string(t, &TimeZone::Utc), "Tue Jan 02 2024 03:04:05 GMT+0000 (UTC)");
+    assert_eq!(time::to_iso_string(-1.0), "1969-12-31T23:59:59.999Z");
+    assert_eq!(time::to_iso_string(-8.64e15), "-271821-04-20T00:00:00.000Z");
+    assert_eq!(time::to_iso_string(253402300800000.0), "+010000-01-01T00:00:00.000Z");
+}
+
+#[test]
+fn posix_zones_follow_their_rules() {
+    let zone = TimeZone::from_posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+    let winter = utc(2024.0, 0.0, 15.0, 12.0, 0.0, 0.0, 0.0);
+    let summer = utc(2024.0, 6.0, 15.0, 12.0, 0.0, 0.0, 0.0);
+    assert_eq!(zone.offset_at(winter).offset, 3600000.0);
+    assert_eq!(zone.offset_at(summer).abbreviation, "CEST");
+    assert_eq!(time::to_date_time_string(summer, &zone), "Mon Jul 15 2024 14:00:00 GMT+0200 (CEST)");
+
+    // Daylight saving starts at 01:00 UTC on the last Sunday of March.
+    let start = utc(2024.0, 2.0, 31.0, 1.0, 0.0, 0.0, 0.0);
+    assert_eq!(zone.offset_at(start - 1.0).offset, 3600000.0);
+    assert_eq!(zone.offset_at(start).offset, 7200000.0);
+
+    // Skipped local times move forward, and repeated ones take the earlier.
+    let skipped = utc(2024.0, 2.0, 31.0, 2.0, 30.0, 0.0, 0.0);
+    assert_eq!(zone.utc_time(skipped), utc(2024.0, 2.0, 31.0, 1.0, 30.0, 0.0, 0.0));
+    let repeated = utc(2024.0, 9.0, 27.0, 2.0, 30.0, 0.0, 0.0);
+    assert_eq!(zone.utc_time(repeated), utc(2024.0, 9.0, 27.0, 0.0, 30.0, 0.0, 0.0));
+
+    // The southern hemisphere's summer spans the new year.
+    let zone = TimeZone::from_posix("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+    assert_eq!(zone.offset_at(winter).abbreviation, "AEDT");
+    assert_eq!(zone.offset_at(summer).abbreviation, "AEST");
+
+    assert!(TimeZone::from_posix("X1").is_none());
+    assert!(TimeZone::from_posix("CET-1CEST,M3.5.0").is_none());
+}
+
+#[test]
+fn scripts_see_the_configured_clock_and_zone() {
+    let source = "var d = new Date(); [Date.now(), String(d), new Date(2024, 0, 2).getTime()].join(' | ')";
+    let program = AstBuilder::new(VecInputStream::new(source.as_bytes().to_vec())).parse_program().unwrap();
+    let mut interp = Interpreter::new();
+    interp.set_clock(Clock::fixed(1704164645006.0));
+    interp.set_time_zone(TimeZone::Utc);
+    let result = interp.run_program(&program).unwrap();
+    assert_eq!(interp.display_string(result),
+               "1704164645006 | Tue Jan 02 2024 03:04:05 GMT+0000 (UTC) | 1704153600000");
+}
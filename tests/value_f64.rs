@@ -0,0 +1,101 @@
+extern crate liboxyjs;
+
+use liboxyjs::vm::heap::Heap;
+use liboxyjs::vm::value::Value;
+use std::f64;
+
+const EXPONENT_MAX: u64 = 0x7FF;
+// Exponents from this one up have their high bits outside the immediate range.
+const FIRST_HEAP_EXPONENT: u64 = 0b1001 << 7;
+
+fn make_f64(sign: u64, exponent: u64, mantissa: u64) -> f64 {
+    f64::from_bits((sign << 63) | (exponent << 52) | mantissa)
+}
+
+fn check_round_trip(heap: &mut Heap, fval: f64) -> Value {
+    let value = Value::from_f64(heap, fval);
+    let result = value.to_f64(heap).expect("number value");
+    assert_eq!(result.to_bits(), fval.to_bits(), "round trip of {:e}", fval);
+    assert!(value.is_number(heap));
+    value
+}
+
+#[test]
+fn every_exponent_round_trips() {
+    let mut heap = Heap::new();
+    let mantissas = [0, 1, 0x8_0000_0000_0000, 0xF_FFFF_FFFF_FFFF];
+    for sign in 0..2 {
+        for exponent in 0..(EXPONENT_MAX + 1) {
+            for &mantissa in &mantissas {
+                let fval = make_f64(sign, exponent, mantissa);
+                let value = check_round_trip(&mut heap, fval);
+                let immediate = exponent < FIRST_HEAP_EXPONENT;
+                assert_eq!(Value::is_imm_f64_representable(fval), immediate);
+                assert_eq!(value.is_imm_f64(), immediate, "exponent {:#x}", exponent);
+                assert_eq!(value.is_heap_f64(&heap), ! immediate, "exponent {:#x}", exponent);
+            }
+        }
+    }
+}
+
+#[test]
+fn boundary_exponents() {
+    let mut heap = Heap::new();
+    let largest_immediate = make_f64(0, FIRST_HEAP_EXPONENT - 1, 0xF_FFFF_FFFF_FFFF);
+    let smallest_boxed = make_f64(0, FIRST_HEAP_EXPONENT, 0);
+    assert_eq!(smallest_boxed, 2f64.powi(129));
+
+    assert!(check_round_trip(&mut heap, largest_immediate).is_imm_f64());
+    assert!(check_round_trip(&mut heap, -largest_immediate).is_imm_f64());
+    assert!(check_round_trip(&mut heap, smallest_boxed).is_ptr());
+    assert!(check_round_trip(&mut heap, -smallest_boxed).is_ptr());
+}
+
+#[test]
+fn special_values() {
+    let mut heap = Heap::new();
+    for &fval in &[f64::INFINITY, f64::NEG_INFINITY, f64::MAX, f64::MIN] {
+        assert!(check_round_trip(&mut heap, fval).is_ptr());
+    }
+    for &fval in &[0.0, -0.0, f64::MIN_POSITIVE, 5e-324, -5e-324, 1.5, -1e38] {
+        assert!(check_round_trip(&mut heap, fval).is_imm_f64());
+    }
+
+    // NaNs are boxed with their payload and sign intact.
+    let nans = [f64::NAN, -f64::NAN, f64::from_bits(0x7FF0_0000_0000_0001)];
+    for &fval in &nans {
+        let value = check_round_trip(&mut heap, fval);
+        assert!(value.is_ptr());
+        assert!(value.to_f64(&heap).unwrap().is_nan());
+    }
+}
+
+#[test]
+fn non_numbers_and_int32() {
+    let mut heap = Heap::new();
+    assert_eq!(Value::new_imm_i32(-7).to_f64(&heap), Some(-7.0));
+    assert_eq!(Value::new_imm_undef().to_f64(&heap), None);
+    assert_eq!(Value::new_imm_bool(true).to_f64(&heap), None);
+    assert_eq!(Value::new_imm_str(b"abc").to_f64(&heap), None);
+    assert!(! Value::new_imm_null().is_number(&heap));
+    let value = check_round_trip(&mut heap, f64::INFINITY);
+    assert!(! value.is_imm_f64());
+}
+
+#[test]
+fn boxed_doubles_are_collected() {
+    let mut heap = Heap::new();
+    let infinity = Value::from_f64(&mut heap, f64::INFINITY);
+    let kept = heap.root(infinity);
+    Value::from_f64(&mut heap, f64::NAN);
+    Value::from_f64(&mut heap, 1.0);
+    assert_eq!(heap.cell_count(), 2);
+
+    heap.collect();
+    assert_eq!(heap.cell_count(), 1);
+    assert_eq!(kept.get().to_f64(&heap), Some(f64::INFINITY));
+
+    drop(kept);
+    heap.collect();
+    assert_eq!(heap.cell_count(), 0);
+}